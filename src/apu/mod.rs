@@ -0,0 +1,355 @@
+// src/apu/mod.rs
+// Sound (GBATEK "GBA Sound Controller"): the four PSG channels from `psg` and the two
+// DirectSound FIFOs, mixed to stereo at a fixed 32768 Hz, one sample every 512 cycles. Samples
+// are worked out lazily when the bus advances, so the output stays locked to emulated time.
+//
+// The FIFOs play 8 bit signed samples, one per overflow of their timer, and ask DMA1/DMA2 for
+// four more words once half empty. The mix follows the hardware: PSG and FIFO levels added
+// onto SOUNDBIAS and clamped to 10 bits. The bias is taken out again so silence is 0.
+
+pub mod psg;
+
+use crate::bus::Bus;
+use crate::dma::DmaTiming;
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+use psg::{Noise, Square, Wave};
+
+pub const SOUND_REGISTERS_START: u32 = 0x060;
+pub const SOUND_REGISTERS_END: u32 = 0x0A7;
+pub const SAMPLE_RATE: u32 = 32768;
+pub const CYCLES_PER_SAMPLE: u64 = 512;
+
+const REG_SOUNDCNT_L: u32 = 0x080;
+const REG_SOUNDCNT_H: u32 = 0x082;
+const REG_SOUNDCNT_X: u32 = 0x084;
+const REG_SOUNDBIAS: u32 = 0x088;
+const WAVE_RAM_START: u32 = 0x090;
+const WAVE_RAM_END: u32 = 0x09F;
+const FIFO_A: u32 = 0x0A0;
+const FIFO_B: u32 = 0x0A4;
+
+const SOUNDCNT_X_ENABLE: u8 = 1 << 7;
+const FIFO_SIZE: usize = 32;
+const FIFO_REFILL_LEVEL: usize = 16;
+const SAMPLES_PER_SEQUENCER_STEP: u64 = 64; // 512 Hz frame sequencer
+
+// One of the two DirectSound queues, 32 signed 8 bit samples
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Fifo {
+    data: [i8; FIFO_SIZE],
+    start: usize,
+    len: usize,
+    pub current: i8, // The sample playing
+}
+
+impl Fifo {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // A full FIFO drops the write
+    pub fn push(&mut self, sample: i8) {
+        if self.len < FIFO_SIZE {
+            self.data[(self.start + self.len) % FIFO_SIZE] = sample;
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> Option<i8> {
+        if self.len == 0 {
+            return None;
+        }
+        let sample = self.data[self.start];
+        self.start = (self.start + 1) % FIFO_SIZE;
+        self.len -= 1;
+        Some(sample)
+    }
+
+    fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Apu {
+    pub square1: Square,
+    pub square2: Square,
+    pub wave: Wave,
+    pub noise: Noise,
+    pub fifos: [Fifo; 2],
+    pub soundcnt_l: u16,
+    pub soundcnt_h: u16,
+    pub enabled: bool, // SOUNDCNT_X master enable
+    pub bias: u16,
+    samples_generated: u64, // Sample n is due at cycle n * 512
+    // Mixed samples, left and right, kept only while capturing so nothing piles up unread
+    pub capture: bool,
+    samples: Vec<[i16; 2]>,
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Apu::default()
+    }
+
+    /// The samples mixed since the last call, needs `capture` on.
+    pub fn take_samples(&mut self) -> Vec<[i16; 2]> {
+        std::mem::take(&mut self.samples)
+    }
+
+    // Bits 0-3 of SOUNDCNT_X
+    fn channel_status(&self) -> u8 {
+        [
+            self.square1.active,
+            self.square2.active,
+            self.wave.active,
+            self.noise.active,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |status, (bit, &active)| status | (active as u8) << bit)
+    }
+
+    /// Mixes every sample due up to cycle `now`.
+    pub fn run_until(&mut self, now: u64) {
+        while self.samples_generated * CYCLES_PER_SAMPLE <= now {
+            if self.samples_generated.is_multiple_of(SAMPLES_PER_SEQUENCER_STEP) {
+                self.clock_sequencer(self.samples_generated / SAMPLES_PER_SEQUENCER_STEP % 8);
+            }
+            let cycles = CYCLES_PER_SAMPLE as u32;
+            self.square1.advance(cycles);
+            self.square2.advance(cycles);
+            self.wave.advance(cycles);
+            self.noise.advance(cycles);
+            let sample = self.mix();
+            if self.capture {
+                self.samples.push(sample);
+            }
+            self.samples_generated += 1;
+        }
+    }
+
+    // Lengths at 256 Hz, the sweep at 128 Hz and envelopes at 64 Hz
+    fn clock_sequencer(&mut self, step: u64) {
+        if step.is_multiple_of(2) {
+            self.square1.clock_length();
+            self.square2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if step == 2 || step == 6 {
+            self.square1.clock_sweep();
+        }
+        if step == 7 {
+            self.square1.clock_envelope();
+            self.square2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+    }
+
+    fn mix(&self) -> [i16; 2] {
+        if !self.enabled {
+            return [0, 0];
+        }
+        let levels = [
+            self.square1.level(),
+            self.square2.level(),
+            self.wave.level(),
+            self.noise.level(),
+        ];
+        let bias = (self.bias & 0x3FE) as i32;
+        // SOUNDCNT_L has right in the low bits, left in the high ones
+        [1, 0].map(|side| {
+            let enables = self.soundcnt_l >> (8 + side * 4);
+            let psg: i32 = levels
+                .iter()
+                .enumerate()
+                .filter(|(channel, _)| enables & (1 << channel) != 0)
+                .map(|(_, &level)| level as i32)
+                .sum();
+            let master = ((self.soundcnt_l >> (side * 4)) & 7) as i32 + 1;
+            // 25%, 50% or 100%, 3 is prohibited and taken as 100%
+            let psg = match self.soundcnt_h & 3 {
+                0 => psg * master / 4,
+                1 => psg * master / 2,
+                _ => psg * master,
+            };
+            let direct: i32 = (0..2)
+                .filter(|fifo| self.soundcnt_h & (1 << (8 + fifo * 4 + side)) != 0)
+                .map(|fifo| {
+                    let full = self.soundcnt_h & (1 << (2 + fifo)) != 0;
+                    self.fifos[fifo].current as i32 * if full { 4 } else { 2 }
+                })
+                .sum();
+            let level = (bias + psg + direct).clamp(0, 0x3FF);
+            ((level - bias) * 64).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+        })
+    }
+}
+
+impl Bus {
+    pub(crate) fn read_sound_register(&self, offset: u32) -> u8 {
+        match offset {
+            REG_SOUNDCNT_X => self.io[offset as usize] | self.apu.channel_status(),
+            WAVE_RAM_START..=WAVE_RAM_END => {
+                self.apu.wave.ram[self.apu.wave.ram_offset((offset - WAVE_RAM_START) as usize)]
+            }
+            FIFO_A..=SOUND_REGISTERS_END => 0, // Write only
+            _ => self.io[offset as usize],
+        }
+    }
+
+    pub(crate) fn write_sound_register(&mut self, offset: u32, value: u8) {
+        // With the master enable off the PSG registers can't be written
+        if !self.apu.enabled && offset < REG_SOUNDCNT_H {
+            return;
+        }
+        match offset {
+            WAVE_RAM_START..=WAVE_RAM_END => {
+                let index = self.apu.wave.ram_offset((offset - WAVE_RAM_START) as usize);
+                self.apu.wave.ram[index] = value;
+                return;
+            }
+            FIFO_A..=SOUND_REGISTERS_END => {
+                self.apu.fifos[((offset - FIFO_A) / 4) as usize].push(value as i8);
+                return;
+            }
+            _ => self.io[offset as usize] = value,
+        }
+
+        let register = offset & !1;
+        let halfword = self.io_halfword(register);
+        let apu = &mut self.apu;
+        match register {
+            0x060 => apu.square1.sweep = halfword & 0x7F,
+            0x062 => apu.square1.write_control(halfword),
+            0x064 => apu.square1.write_frequency(halfword),
+            0x068 => apu.square2.write_control(halfword),
+            0x06C => apu.square2.write_frequency(halfword),
+            0x070 => apu.wave.write_select(halfword),
+            0x072 => apu.wave.write_control(halfword),
+            0x074 => apu.wave.write_frequency(halfword),
+            0x078 => apu.noise.write_control(halfword),
+            0x07C => apu.noise.write_frequency(halfword),
+            REG_SOUNDCNT_L => apu.soundcnt_l = halfword & 0xFF77,
+            REG_SOUNDCNT_H => {
+                // The FIFO reset bits do their thing and read back as 0
+                for fifo in 0..2 {
+                    if halfword & (1 << (11 + fifo * 4)) != 0 {
+                        apu.fifos[fifo].clear();
+                    }
+                }
+                apu.soundcnt_h = halfword & 0x770F;
+                self.io[register as usize..register as usize + 2]
+                    .copy_from_slice(&apu.soundcnt_h.to_le_bytes());
+            }
+            REG_SOUNDCNT_X if offset == REG_SOUNDCNT_X => {
+                apu.enabled = value & SOUNDCNT_X_ENABLE != 0;
+                self.io[offset as usize] = value & SOUNDCNT_X_ENABLE;
+                if !apu.enabled {
+                    // Switching off clears every PSG register
+                    apu.square1 = Square::default();
+                    apu.square2 = Square::default();
+                    apu.noise = Noise::default();
+                    apu.wave.reset();
+                    apu.soundcnt_l = 0;
+                    self.io[0x060..=0x081].fill(0);
+                }
+            }
+            REG_SOUNDBIAS => apu.bias = halfword,
+            _ => {}
+        }
+        // Restart bits are write only
+        if matches!(offset, 0x065 | 0x06D | 0x075 | 0x07D) {
+            self.io[offset as usize] &= 0x7F;
+        }
+    }
+
+    /// Timer `timer` overflowed, the FIFOs it drives move on a sample.
+    pub(crate) fn clock_sound_fifos(&mut self, timer: usize) {
+        for fifo in 0..2 {
+            let selected = (self.apu.soundcnt_h >> (10 + fifo * 4)) as usize & 1;
+            if selected != timer {
+                continue;
+            }
+            if let Some(sample) = self.apu.fifos[fifo].pop() {
+                self.apu.fifos[fifo].current = sample;
+            }
+            if self.apu.fifos[fifo].len() <= FIFO_REFILL_LEVEL {
+                self.request_fifo_dma(fifo);
+            }
+        }
+    }
+
+    // DMA1 feeds FIFO A and DMA2 FIFO B, when set to sound timing and pointed at it
+    fn request_fifo_dma(&mut self, fifo: usize) {
+        let channel = 1 + fifo;
+        let dma = self.dma[channel];
+        let fifo_address = 0x0400_0000 + [FIFO_A, FIFO_B][fifo];
+        if dma.is_enabled() && dma.timing() == DmaTiming::Special && dma.destination == fifo_address
+        {
+            self.run_dma(channel);
+        }
+    }
+}
+
+impl Snapshot for Fifo {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.len as u8);
+        for index in 0..self.len {
+            state.u8(self.data[(self.start + index) % FIFO_SIZE] as u8);
+        }
+        state.u8(self.current as u8);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        let len = state.u8()? as usize;
+        if len > FIFO_SIZE {
+            return Err(SaveStateError::Corrupt("sound FIFO overfull"));
+        }
+        self.clear();
+        for _ in 0..len {
+            self.push(state.u8()? as i8);
+        }
+        self.current = state.u8()? as i8;
+        Ok(())
+    }
+}
+
+impl Snapshot for Apu {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.square1.save_state(state);
+        self.square2.save_state(state);
+        self.wave.save_state(state);
+        self.noise.save_state(state);
+        for fifo in &self.fifos {
+            fifo.save_state(state);
+        }
+        state.u16(self.soundcnt_l);
+        state.u16(self.soundcnt_h);
+        state.bool(self.enabled);
+        state.u16(self.bias);
+        state.u64(self.samples_generated);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.square1.load_state(state)?;
+        self.square2.load_state(state)?;
+        self.wave.load_state(state)?;
+        self.noise.load_state(state)?;
+        for fifo in self.fifos.iter_mut() {
+            fifo.load_state(state)?;
+        }
+        self.soundcnt_l = state.u16()?;
+        self.soundcnt_h = state.u16()?;
+        self.enabled = state.bool()?;
+        self.bias = state.u16()?;
+        self.samples_generated = state.u64()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,453 @@
+// src/apu/psg.rs
+// The four Game Boy sound channels (GBATEK "GBA Sound Channel 1-4"): two square waves, the
+// first with a frequency sweep, a 4 bit wave table and an LFSR noise generator. Each gives a
+// signed level of -15..15, the mixer in `apu` takes care of volumes and panning.
+//
+// Channels are point sampled: they advance by a whole output sample at a time and whatever
+// level they're at is the sample. Tones above half the sample rate alias, as they would with
+// any nearest-sample resampler.
+
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+const DUTY_CYCLES: [u8; 4] = [0b0000_0001, 0b0000_0011, 0b0000_1111, 0b1111_1100];
+const LENGTH_ENABLE: u16 = 1 << 14;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Envelope {
+    pub volume: u8,
+    register: u8, // Bits 8-15 of the channel's control register
+    timer: u8,
+}
+
+impl Envelope {
+    fn initial_volume(&self) -> u8 {
+        self.register >> 4
+    }
+
+    fn increases(&self) -> bool {
+        self.register & 8 != 0
+    }
+
+    fn step_time(&self) -> u8 {
+        self.register & 7
+    }
+
+    // A channel whose envelope starts silent and only goes down has its DAC off
+    fn dac_enabled(&self) -> bool {
+        self.register & 0xF8 != 0
+    }
+
+    fn restart(&mut self) {
+        self.volume = self.initial_volume();
+        self.timer = self.step_time();
+    }
+
+    // 64 Hz
+    fn clock(&mut self) {
+        if self.step_time() == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = self.step_time();
+            if self.increases() && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increases() && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Square {
+    pub sweep: u16,     // SOUND1CNT_L, channel 1 only
+    pub control: u16,   // Length, duty and envelope
+    pub frequency: u16, // Frequency and length enable
+    pub active: bool,
+    pub envelope: Envelope,
+    length: u16,
+    phase: u8,
+    elapsed: u32, // Cycles into the current duty step
+    sweep_timer: u8,
+    sweep_frequency: u16,
+}
+
+impl Square {
+    pub fn write_control(&mut self, value: u16) {
+        self.control = value;
+        self.length = 64 - (value & 0x3F);
+        self.envelope.register = (value >> 8) as u8;
+        if !self.envelope.dac_enabled() {
+            self.active = false;
+        }
+    }
+
+    pub fn write_frequency(&mut self, value: u16) {
+        self.frequency = value & 0x47FF;
+        if value & 0x8000 != 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.active = self.envelope.dac_enabled();
+        if self.length == 0 {
+            self.length = 64;
+        }
+        self.envelope.restart();
+        self.elapsed = 0;
+        self.sweep_frequency = self.frequency & 0x7FF;
+        self.sweep_timer = self.sweep_time();
+        if self.sweep_shift() != 0 && self.sweep_target() > 0x7FF {
+            self.active = false;
+        }
+    }
+
+    fn sweep_time(&self) -> u8 {
+        (self.sweep >> 4) as u8 & 7
+    }
+
+    fn sweep_shift(&self) -> u16 {
+        self.sweep & 7
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let delta = self.sweep_frequency >> self.sweep_shift();
+        if self.sweep & 8 != 0 {
+            self.sweep_frequency - delta
+        } else {
+            self.sweep_frequency + delta
+        }
+    }
+
+    pub fn advance(&mut self, cycles: u32) {
+        let period = (2048 - (self.frequency & 0x7FF) as u32) * 16;
+        let elapsed = self.elapsed + cycles;
+        self.phase = ((self.phase as u32 + elapsed / period) % 8) as u8;
+        self.elapsed = elapsed % period;
+    }
+
+    pub fn level(&self) -> i16 {
+        if !self.active {
+            return 0;
+        }
+        let duty = DUTY_CYCLES[(self.control >> 6) as usize & 3];
+        let volume = self.envelope.volume as i16;
+        if duty & (1 << self.phase) != 0 {
+            volume
+        } else {
+            -volume
+        }
+    }
+
+    // 256 Hz
+    pub fn clock_length(&mut self) {
+        if self.frequency & LENGTH_ENABLE != 0 && self.length > 0 {
+            self.length -= 1;
+            if self.length == 0 {
+                self.active = false;
+            }
+        }
+    }
+
+    // 128 Hz
+    pub fn clock_sweep(&mut self) {
+        if self.sweep_time() == 0 {
+            return;
+        }
+        self.sweep_timer = self.sweep_timer.saturating_sub(1);
+        if self.sweep_timer > 0 {
+            return;
+        }
+        self.sweep_timer = self.sweep_time();
+        let target = self.sweep_target();
+        if target > 0x7FF {
+            self.active = false;
+        } else if self.sweep_shift() != 0 {
+            self.sweep_frequency = target;
+            self.frequency = (self.frequency & !0x7FF) | target;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Wave {
+    pub select: u16,    // SOUND3CNT_L: two banks, bank number, playback on
+    pub control: u16,   // SOUND3CNT_H: length and volume
+    pub frequency: u16, // SOUND3CNT_X
+    pub active: bool,
+    pub ram: [u8; 32], // Two banks of 32 4 bit samples, high nibble first
+    length: u16,
+    position: u8,
+    elapsed: u32,
+}
+
+impl Wave {
+    fn bank(&self) -> usize {
+        (self.select >> 6) as usize & 1
+    }
+
+    fn playing(&self) -> bool {
+        self.select & 0x80 != 0
+    }
+
+    pub fn write_select(&mut self, value: u16) {
+        self.select = value & 0xE0;
+        if !self.playing() {
+            self.active = false;
+        }
+    }
+
+    pub fn write_control(&mut self, value: u16) {
+        self.control = value & 0xE0FF;
+        self.length = 256 - (value & 0xFF);
+    }
+
+    pub fn write_frequency(&mut self, value: u16) {
+        self.frequency = value & 0x47FF;
+        if value & 0x8000 != 0 {
+            self.active = self.playing();
+            if self.length == 0 {
+                self.length = 256;
+            }
+            self.position = 0;
+            self.elapsed = 0;
+        }
+    }
+
+    // Everything but the wave RAM, which keeps its samples
+    pub fn reset(&mut self) {
+        *self = Wave {
+            ram: self.ram,
+            ..Wave::default()
+        };
+    }
+
+    // The CPU sees the bank that isn't playing
+    pub fn ram_offset(&self, index: usize) -> usize {
+        (1 - self.bank()) * 16 + index
+    }
+
+    pub fn advance(&mut self, cycles: u32) {
+        let period = (2048 - (self.frequency & 0x7FF) as u32) * 8;
+        let samples = if self.select & 0x20 != 0 { 64 } else { 32 };
+        let elapsed = self.elapsed + cycles;
+        self.position = ((self.position as u32 + elapsed / period) % samples) as u8;
+        self.elapsed = elapsed % period;
+    }
+
+    pub fn level(&self) -> i16 {
+        if !self.active {
+            return 0;
+        }
+        let index = (self.bank() * 32 + self.position as usize) % 64;
+        let byte = self.ram[index / 2];
+        let sample = if index.is_multiple_of(2) {
+            byte >> 4
+        } else {
+            byte & 0xF
+        };
+        let level = sample as i16 * 2 - 15;
+        if self.control & 0x8000 != 0 {
+            return level * 3 / 4;
+        }
+        match (self.control >> 13) & 3 {
+            0 => 0,
+            1 => level,
+            2 => level / 2,
+            _ => level / 4,
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.frequency & LENGTH_ENABLE != 0 && self.length > 0 {
+            self.length -= 1;
+            if self.length == 0 {
+                self.active = false;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Noise {
+    pub control: u16,   // SOUND4CNT_L: length and envelope
+    pub frequency: u16, // SOUND4CNT_H: divider, width, shift and length enable
+    pub active: bool,
+    pub envelope: Envelope,
+    length: u16,
+    lfsr: u16,
+    elapsed: u32,
+}
+
+impl Noise {
+    pub fn write_control(&mut self, value: u16) {
+        self.control = value & 0xFF3F;
+        self.length = 64 - (value & 0x3F);
+        self.envelope.register = (value >> 8) as u8;
+        if !self.envelope.dac_enabled() {
+            self.active = false;
+        }
+    }
+
+    pub fn write_frequency(&mut self, value: u16) {
+        self.frequency = value & 0x40FF;
+        if value & 0x8000 != 0 {
+            self.active = self.envelope.dac_enabled();
+            if self.length == 0 {
+                self.length = 64;
+            }
+            self.envelope.restart();
+            self.lfsr = if self.narrow() { 0x7F } else { 0x7FFF };
+            self.elapsed = 0;
+        }
+    }
+
+    fn narrow(&self) -> bool {
+        self.frequency & 8 != 0
+    }
+
+    // 524288 Hz / r / 2^(s+1), with r = 0 counting as 0.5
+    fn period(&self) -> u32 {
+        let divider = match self.frequency & 7 {
+            0 => 16,
+            r => 32 * r as u32,
+        };
+        divider << ((self.frequency >> 4) + 1)
+    }
+
+    pub fn advance(&mut self, cycles: u32) {
+        let period = self.period();
+        self.elapsed += cycles;
+        while self.elapsed >= period {
+            self.elapsed -= period;
+            let bit = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+            self.lfsr = (self.lfsr >> 1) | (bit << 14);
+            if self.narrow() {
+                self.lfsr = (self.lfsr & !(1 << 6)) | (bit << 6);
+            }
+        }
+    }
+
+    pub fn level(&self) -> i16 {
+        if !self.active {
+            return 0;
+        }
+        let volume = self.envelope.volume as i16;
+        if self.lfsr & 1 == 0 {
+            volume
+        } else {
+            -volume
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.frequency & LENGTH_ENABLE != 0 && self.length > 0 {
+            self.length -= 1;
+            if self.length == 0 {
+                self.active = false;
+            }
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+}
+
+impl Snapshot for Envelope {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.volume);
+        state.u8(self.register);
+        state.u8(self.timer);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.volume = state.u8()?;
+        self.register = state.u8()?;
+        self.timer = state.u8()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Square {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.sweep);
+        state.u16(self.control);
+        state.u16(self.frequency);
+        state.bool(self.active);
+        self.envelope.save_state(state);
+        state.u16(self.length);
+        state.u8(self.phase);
+        state.u32(self.elapsed);
+        state.u8(self.sweep_timer);
+        state.u16(self.sweep_frequency);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.sweep = state.u16()?;
+        self.control = state.u16()?;
+        self.frequency = state.u16()?;
+        self.active = state.bool()?;
+        self.envelope.load_state(state)?;
+        self.length = state.u16()?;
+        self.phase = state.u8()? & 7;
+        self.elapsed = state.u32()?;
+        self.sweep_timer = state.u8()?;
+        self.sweep_frequency = state.u16()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Wave {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.select);
+        state.u16(self.control);
+        state.u16(self.frequency);
+        state.bool(self.active);
+        state.raw(&self.ram);
+        state.u16(self.length);
+        state.u8(self.position);
+        state.u32(self.elapsed);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.select = state.u16()?;
+        self.control = state.u16()?;
+        self.frequency = state.u16()?;
+        self.active = state.bool()?;
+        self.ram.copy_from_slice(state.raw(32)?);
+        self.length = state.u16()?;
+        self.position = state.u8()? % 64;
+        self.elapsed = state.u32()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Noise {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.control);
+        state.u16(self.frequency);
+        state.bool(self.active);
+        self.envelope.save_state(state);
+        state.u16(self.length);
+        state.u16(self.lfsr);
+        state.u32(self.elapsed);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.control = state.u16()?;
+        self.frequency = state.u16()?;
+        self.active = state.bool()?;
+        self.envelope.load_state(state)?;
+        self.length = state.u16()?;
+        self.lfsr = state.u16()?;
+        self.elapsed = state.u32()?;
+        Ok(())
+    }
+}
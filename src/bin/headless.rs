@@ -0,0 +1,281 @@
+// src/bin/headless.rs
+// Runs a ROM without a display, for CI: a number of frames or until a condition, optionally
+// with an input script, then writes a screenshot, prints the framebuffer hash and dumps the
+// registers. Can also dump the sound as WAV and every frame as Y4M or raw RGB, both clocked off
+// emulated time so they stay in sync, and link up with other instances through a link cable.
+// The exit code says how it went:
+//   0  ran all frames, or the condition was met
+//   1  timeout, the condition was never met
+//   2  bad arguments or files
+//   3  the emulator hit an error
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use emulator::apu::SAMPLE_RATE;
+use emulator::bios;
+use emulator::debugger::{Breakpoint, Condition, Repl};
+use emulator::gba::Gba;
+use emulator::movie::frame_hash;
+use emulator::movie::script::InputScript;
+use emulator::png;
+use emulator::ppu::{FRAME_CYCLES, SCREEN_HEIGHT, SCREEN_WIDTH};
+use emulator::sio::link::Link;
+use emulator::wav::WavWriter;
+use emulator::y4m::Y4mWriter;
+
+const EXIT_TIMEOUT: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_EMULATOR_ERROR: i32 = 3;
+const DEFAULT_FRAMES: u32 = 600;
+const FRAME_RATE: (u64, u64) = (1 << 24, FRAME_CYCLES); // About 59.7275 fps
+
+const USAGE: &str = "\
+Usage: headless <rom> [options]
+  --frames N       frames to run, the time limit with --until/--until-pc (default 600)
+  --until COND     stop once COND holds after a frame, e.g. \"[0x02000000] == 1\"
+  --until-pc LOC   stop when execution reaches LOC, an address or a symbol
+  --input FILE     replay an input script
+  --bios FILE      map a BIOS dump, SWIs still go through the HLE BIOS
+  --boot-bios      with --bios, boot through it (not with the real BIOS yet)
+  --link-host ADDR N  host a link cable on ADDR (host:port or unix:PATH) for N other players
+  --link ADDR      join the link cable hosted on ADDR
+  --png FILE       write the last frame as a PNG
+  --wav FILE       dump the sound, 16 bit stereo at 32768 Hz
+  --video FILE     dump every frame, as Y4M for .y4m files, raw 240x160 RGB otherwise
+  --hash           print the framebuffer's CRC-32
+  --registers      dump the registers at the end";
+
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(EXIT_USAGE);
+}
+
+// Takes `flag` and its value out of `args`
+fn option(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == flag)?;
+    if index + 1 >= args.len() {
+        fail(format!("{} needs a value\n{}", flag, USAGE));
+    }
+    let value = args.remove(index + 1);
+    args.remove(index);
+    Some(value)
+}
+
+// Takes `flag` and its two values out of `args`
+fn option_pair(args: &mut Vec<String>, flag: &str) -> Option<(String, String)> {
+    let index = args.iter().position(|arg| arg == flag)?;
+    if index + 2 >= args.len() {
+        fail(format!("{} needs two values\n{}", flag, USAGE));
+    }
+    let second = args.remove(index + 2);
+    let first = args.remove(index + 1);
+    args.remove(index);
+    Some((first, second))
+}
+
+fn flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let index = args.iter().position(|arg| arg == flag);
+    if let Some(index) = index {
+        args.remove(index);
+    }
+    index.is_some()
+}
+
+enum Video {
+    Y4m(Y4mWriter<BufWriter<File>>),
+    Raw(BufWriter<File>),
+}
+
+impl Video {
+    fn create(path: &str) -> io::Result<Video> {
+        let (width, height) = (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+        if path.ends_with(".y4m") {
+            Ok(Video::Y4m(Y4mWriter::create(
+                path, width, height, FRAME_RATE,
+            )?))
+        } else {
+            Ok(Video::Raw(BufWriter::new(File::create(path)?)))
+        }
+    }
+
+    fn write_frame(&mut self, rgb: &[u8]) -> io::Result<()> {
+        match self {
+            Video::Y4m(writer) => writer.write_frame(rgb),
+            Video::Raw(out) => out.write_all(rgb),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Video::Y4m(writer) => writer.finish().map(|_| ()),
+            Video::Raw(mut out) => out.flush(),
+        }
+    }
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let frames = option(&mut args, "--frames").map(|frames| {
+        frames
+            .parse::<u32>()
+            .unwrap_or_else(|_| fail(format!("Bad frame count '{}'", frames)))
+    });
+    let until = option(&mut args, "--until").map(|source| {
+        Condition::parse(&source).unwrap_or_else(|err| fail(format!("Bad condition: {}", err)))
+    });
+    let until_pc = option(&mut args, "--until-pc");
+    let script = option(&mut args, "--input").map(|path| {
+        InputScript::load(&path)
+            .unwrap_or_else(|err| fail(format!("Failed to load {}: {}", path, err)))
+    });
+    let bios_image = option(&mut args, "--bios").map(|path| {
+        bios::load_image(&path)
+            .unwrap_or_else(|err| fail(format!("Failed to load {}: {}", path, err)))
+    });
+    let skip_boot = !flag(&mut args, "--boot-bios");
+    let link_host = option_pair(&mut args, "--link-host").map(|(address, players)| {
+        let players = players
+            .parse::<usize>()
+            .unwrap_or_else(|_| fail(format!("Bad player count '{}'", players)));
+        (address, players)
+    });
+    let link_address = option(&mut args, "--link");
+    let png_path = option(&mut args, "--png");
+    let wav_path = option(&mut args, "--wav");
+    let video_path = option(&mut args, "--video");
+    let print_hash = flag(&mut args, "--hash");
+    let dump_registers = flag(&mut args, "--registers");
+    let [rom] = args.as_slice() else {
+        fail(USAGE.to_string());
+    };
+
+    let (mut gba, symbols) =
+        Gba::load(rom).unwrap_or_else(|err| fail(format!("Failed to load {}: {}", rom, err)));
+    if let Some(image) = &bios_image {
+        if let Err(err) = gba.use_bios(image, skip_boot) {
+            fail(format!("Failed to load the BIOS: {}", err));
+        }
+    }
+    if let Some(location) = &until_pc {
+        let address = symbols.address_of(location).or_else(|| {
+            let digits = location.trim_start_matches("0x");
+            u32::from_str_radix(digits, 16).ok()
+        });
+        let Some(address) = address else {
+            fail(format!("Bad address '{}'", location));
+        };
+        gba.cpu.debugger.add_breakpoint(Breakpoint::at(address));
+    }
+    // The host waits here until every player has connected
+    let link = match (link_host, link_address) {
+        (Some(_), Some(_)) => fail("--link-host and --link can't be used together".to_string()),
+        (Some((address, players)), None) => Some(Link::host(&address, players)),
+        (None, Some(address)) => Some(Link::connect(&address)),
+        (None, None) => None,
+    };
+    if let Some(link) = link {
+        let link = link.unwrap_or_else(|err| fail(format!("Failed to set up the link: {}", err)));
+        gba.bus.attach_link(link);
+    }
+    let script = script.unwrap_or_default();
+    let waiting = until.is_some() || until_pc.is_some();
+    let frames = frames.unwrap_or(DEFAULT_FRAMES);
+    let mut wav = wav_path.as_ref().map(|path| {
+        WavWriter::create(path, SAMPLE_RATE)
+            .unwrap_or_else(|err| fail(format!("Failed to create {}: {}", path, err)))
+    });
+    let mut video = video_path.as_ref().map(|path| {
+        Video::create(path)
+            .unwrap_or_else(|err| fail(format!("Failed to create {}: {}", path, err)))
+    });
+    gba.bus.apu.capture = wav.is_some();
+
+    let mut outcome = if waiting { Err(EXIT_TIMEOUT) } else { Ok(()) };
+    for frame in 0..frames {
+        gba.bus.set_keys(script.keys_at(frame));
+        if let Err(err) = gba.run_frame() {
+            eprintln!("Emulator error in frame {}: {}", frame, err);
+            outcome = Err(EXIT_EMULATOR_ERROR);
+            break;
+        }
+        if let Some(wav) = &mut wav {
+            let samples = gba.bus.apu.take_samples();
+            if let Err(err) = wav.write_samples(&samples) {
+                fail(format!(
+                    "Failed to write {}: {}",
+                    wav_path.as_deref().unwrap_or_default(),
+                    err
+                ));
+            }
+        }
+        if let Some(video) = &mut video {
+            if let Err(err) = video.write_frame(&gba.bus.ppu.framebuffer_rgb()) {
+                fail(format!(
+                    "Failed to write {}: {}",
+                    video_path.as_deref().unwrap_or_default(),
+                    err
+                ));
+            }
+        }
+        let at_pc = gba.cpu.stop_reason().is_some();
+        let holds = until
+            .as_ref()
+            .is_some_and(|condition| condition.is_true(&gba.cpu.cpu_state, &mut gba.bus));
+        if at_pc || holds {
+            println!("Stopped after {} frames", frame + 1);
+            outcome = Ok(());
+            break;
+        }
+    }
+    if outcome == Err(EXIT_TIMEOUT) {
+        eprintln!("Timed out after {} frames", frames);
+    }
+    if let Some(err) = &gba.bus.sio.link_error {
+        eprintln!("Link cable disconnected: {}", err);
+    }
+    if let Some(wav) = wav {
+        if let Err(err) = wav.finish() {
+            fail(format!(
+                "Failed to write {}: {}",
+                wav_path.unwrap_or_default(),
+                err
+            ));
+        }
+    }
+    if let Some(video) = video {
+        if let Err(err) = video.finish() {
+            fail(format!(
+                "Failed to write {}: {}",
+                video_path.unwrap_or_default(),
+                err
+            ));
+        }
+    }
+
+    if let Some(path) = png_path {
+        let rgb = gba.bus.ppu.framebuffer_rgb();
+        if let Err(err) = png::write_rgb(&path, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, &rgb) {
+            fail(format!("Failed to write {}: {}", path, err));
+        }
+    }
+    if print_hash {
+        println!("Framebuffer hash: {:08X}", frame_hash(&gba));
+    }
+    if dump_registers {
+        let mut repl = Repl::new();
+        repl.symbols = symbols;
+        let result = repl.execute(
+            &mut gba.cpu,
+            &mut gba.bus,
+            "registers",
+            &mut std::io::stdout(),
+        );
+        if let Err(err) = result {
+            fail(format!("Failed to print registers: {}", err));
+        }
+    }
+    if let Err(code) = outcome {
+        std::process::exit(code);
+    }
+}
@@ -0,0 +1,28 @@
+// src/bin/trace_diff.rs
+// Compares two instruction traces, ours (text or binary), mGBA's or NanoBoyAdvance's, and
+// reports the first step where they disagree. Exits with 1 when they do.
+
+use emulator::trace::diff::first_divergence;
+use emulator::trace::format::load_steps;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [left, right] = args.as_slice() else {
+        eprintln!("Usage: trace_diff <left trace> <right trace>");
+        std::process::exit(2);
+    };
+    let load = |path: &String| {
+        load_steps(path).unwrap_or_else(|err| {
+            eprintln!("Failed to read {}: {}", path, err);
+            std::process::exit(2);
+        })
+    };
+    let (left_steps, right_steps) = (load(left), load(right));
+    match first_divergence(&left_steps, &right_steps) {
+        Some(divergence) => {
+            println!("{}", divergence);
+            std::process::exit(1);
+        }
+        None => println!("Traces match ({} steps)", left_steps.len()),
+    }
+}
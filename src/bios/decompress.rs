@@ -0,0 +1,181 @@
+// src/bios/decompress.rs
+// Decompression SWIs. Every format starts with a 32 bit header holding the type in bits 4-7
+// and the decompressed size in bits 8-31 (GBATEK "BIOS Decompression Functions").
+
+use crate::memory::MemoryBus;
+
+fn decompressed_size<M: MemoryBus>(memory: &mut M, source: u32) -> usize {
+    (memory.read_word(source) >> 8) as usize
+}
+
+// LZ77UnComp: a flag byte announces 8 blocks, MSB first. 0 = literal byte, 1 = copy of
+// 3-18 bytes from up to 4 KB back in the output.
+pub fn lz77<M: MemoryBus>(memory: &mut M, source: u32) -> Vec<u8> {
+    let size = decompressed_size(memory, source);
+    let mut output = Vec::with_capacity(size);
+    let mut address = source.wrapping_add(4);
+    while output.len() < size {
+        let flags = memory.read_byte(address);
+        address = address.wrapping_add(1);
+        for block in 0..8 {
+            if output.len() >= size {
+                break;
+            }
+            if flags & (0x80 >> block) == 0 {
+                output.push(memory.read_byte(address));
+                address = address.wrapping_add(1);
+                continue;
+            }
+            let high = memory.read_byte(address) as usize;
+            let low = memory.read_byte(address.wrapping_add(1)) as usize;
+            address = address.wrapping_add(2);
+            let length = (high >> 4) + 3;
+            let distance = (((high & 0xF) << 8) | low) + 1;
+            for _ in 0..length {
+                // Bad data reaching before the start reads zeros instead of panicking
+                let byte = output
+                    .len()
+                    .checked_sub(distance)
+                    .map_or(0, |index| output[index]);
+                output.push(byte);
+            }
+        }
+    }
+    output.truncate(size);
+    output
+}
+
+// RLUnComp: flag bit 7 set = the next byte repeated (flag & 0x7F) + 3 times,
+// clear = (flag & 0x7F) + 1 literal bytes.
+pub fn run_length<M: MemoryBus>(memory: &mut M, source: u32) -> Vec<u8> {
+    let size = decompressed_size(memory, source);
+    let mut output = Vec::with_capacity(size);
+    let mut address = source.wrapping_add(4);
+    while output.len() < size {
+        let flag = memory.read_byte(address);
+        address = address.wrapping_add(1);
+        if flag & 0x80 != 0 {
+            let byte = memory.read_byte(address);
+            address = address.wrapping_add(1);
+            for _ in 0..(flag & 0x7F) as usize + 3 {
+                output.push(byte);
+            }
+        } else {
+            for _ in 0..(flag & 0x7F) as usize + 1 {
+                output.push(memory.read_byte(address));
+                address = address.wrapping_add(1);
+            }
+        }
+    }
+    output.truncate(size);
+    output
+}
+
+// HuffUnComp: a tree of 8 bit nodes follows the header, then a bitstream in 32 bit units
+// read from bit 31 down. Output is written as whole words, so it is padded to 4 bytes.
+pub fn huffman<M: MemoryBus>(memory: &mut M, source: u32) -> Vec<u8> {
+    let header = memory.read_word(source);
+    let data_bits = match header & 0xF {
+        0 => 8,
+        bits => bits,
+    };
+    let size = (header >> 8) as usize;
+    let tree_size = memory.read_byte(source.wrapping_add(4)) as u32;
+    let root = source.wrapping_add(5);
+    let mut stream = source.wrapping_add(4 + (tree_size + 1) * 2);
+
+    let mut output = Vec::with_capacity(size + 3);
+    let mut word: u32 = 0;
+    let mut word_bits = 0;
+    let mut node_address = root;
+    let mut node = memory.read_byte(root);
+    while output.len() < size {
+        let bits = memory.read_word(stream);
+        stream = stream.wrapping_add(4);
+        for bit in (0..32).rev() {
+            let direction = (bits >> bit) & 1;
+            let offset = (node & 0x3F) as u32 * 2 + 2 + direction;
+            let child = (node_address & !1).wrapping_add(offset);
+            let is_data = node & (0x80 >> direction) != 0;
+            node_address = child;
+            node = memory.read_byte(child);
+            if !is_data {
+                continue;
+            }
+            word |= ((node as u32) & ((1 << data_bits) - 1)) << word_bits;
+            word_bits += data_bits;
+            node_address = root;
+            node = memory.read_byte(root);
+            if word_bits == 32 {
+                output.extend_from_slice(&word.to_le_bytes());
+                word = 0;
+                word_bits = 0;
+                if output.len() >= size {
+                    break;
+                }
+            }
+        }
+    }
+    output
+}
+
+// Diff8bitUnFilter: every byte is stored as the difference to the previous one.
+pub fn diff8<M: MemoryBus>(memory: &mut M, source: u32) -> Vec<u8> {
+    let size = decompressed_size(memory, source);
+    let mut output = Vec::with_capacity(size);
+    let mut previous: u8 = 0;
+    for i in 0..size as u32 {
+        previous = previous.wrapping_add(memory.read_byte(source.wrapping_add(4 + i)));
+        output.push(previous);
+    }
+    output
+}
+
+// Diff16bitUnFilter: same on halfwords. The size is in bytes.
+pub fn diff16<M: MemoryBus>(memory: &mut M, source: u32) -> Vec<u16> {
+    let size = decompressed_size(memory, source);
+    let mut output = Vec::with_capacity(size / 2);
+    let mut previous: u16 = 0;
+    for i in 0..(size / 2) as u32 {
+        previous = previous.wrapping_add(memory.read_halfword(source.wrapping_add(4 + i * 2)));
+        output.push(previous);
+    }
+    output
+}
+
+// BitUnPack: widens units of 1/2/4/8 bits to 1/2/4/8/16/32 bits, adding an offset to
+// non-zero units (or to all of them with bit 31 of the offset set). Output is whole words.
+pub fn bit_unpack<M: MemoryBus>(memory: &mut M, source: u32, info: u32) -> Vec<u32> {
+    let length = memory.read_halfword(info) as u32;
+    let source_width = memory.read_byte(info.wrapping_add(2)) as u32;
+    let destination_width = memory.read_byte(info.wrapping_add(3)) as u32;
+    let offset = memory.read_word(info.wrapping_add(4));
+    let add_to_zero = offset & 0x8000_0000 != 0;
+    let offset = offset & 0x7FFF_FFFF;
+    if !matches!(source_width, 1 | 2 | 4 | 8) || !matches!(destination_width, 1 | 2 | 4 | 8 | 16 | 32) {
+        return Vec::new();
+    }
+
+    let mut output = Vec::new();
+    let mut word: u64 = 0;
+    let mut word_bits = 0;
+    let source_mask = (1u32 << source_width) - 1;
+    for i in 0..length {
+        let byte = memory.read_byte(source.wrapping_add(i)) as u32;
+        for shift in (0..8).step_by(source_width as usize) {
+            let mut unit = (byte >> shift) & source_mask;
+            if unit != 0 || add_to_zero {
+                unit = unit.wrapping_add(offset);
+            }
+            let unit = unit as u64 & ((1u64 << destination_width) - 1);
+            word |= unit << word_bits;
+            word_bits += destination_width;
+            if word_bits == 32 {
+                output.push(word as u32);
+                word = 0;
+                word_bits = 0;
+            }
+        }
+    }
+    output
+}
@@ -0,0 +1,396 @@
+// src/bios/hle.rs
+// High level emulation of the BIOS SWI functions 0x00-0x2A (GBATEK "BIOS Functions").
+// Results, including what is left behind in other registers, follow the real BIOS.
+
+use crate::bios::{decompress, math};
+use crate::cpu::{Cpu, Mode};
+use crate::error::EmulatorError;
+use crate::memory::MemoryBus;
+
+pub const REG_DISPCNT: u32 = 0x0400_0000;
+pub const REG_SOUNDBIAS: u32 = 0x0400_0088;
+pub const REG_IE: u32 = 0x0400_0200;
+pub const REG_IF: u32 = 0x0400_0202;
+pub const REG_WAITCNT: u32 = 0x0400_0204;
+pub const REG_IME: u32 = 0x0400_0208;
+pub const REG_HALTCNT: u32 = 0x0400_0301;
+
+// Interrupt flags the game's handler acknowledges for IntrWait, at the top of IWRAM.
+pub const BIOS_IF: u32 = 0x0300_7FF8;
+// Non-zero makes SoftReset return to EWRAM (multiboot) instead of ROM.
+pub const SOFT_RESET_FLAG: u32 = 0x0300_7FFA;
+
+// Stack pointers set up by the BIOS on boot and by SoftReset.
+pub const SP_SVC: u32 = 0x0300_7FE0;
+pub const SP_IRQ: u32 = 0x0300_7FA0;
+pub const SP_SYS: u32 = 0x0300_7F00;
+
+// The BIOS interrupt handler calls the game's handler with LR pointing here, where it restores
+// the registers it saved and returns from the IRQ.
+pub const IRQ_RETURN_ADDRESS: u32 = 0x0000_0138;
+// Address of the game's interrupt handler, mirrored at the end of IWRAM.
+pub const IRQ_HANDLER: u32 = 0x03FF_FFFC;
+
+// GetBiosChecksum of the GBA BIOS (the DS one differs).
+pub const BIOS_CHECKSUM: u32 = 0xBAAE_187F;
+
+const CPU_SET_FILL: u32 = 1 << 24;
+const CPU_SET_WORDS: u32 = 1 << 26;
+
+/// Sound driver and multiboot functions run BIOS code that is not emulated, they return
+/// without doing anything. Tracing the `swi` category shows when a game calls one.
+pub fn is_stubbed(number: u8) -> bool {
+    matches!(number, 0x1A..=0x1E | 0x20..=0x25 | 0x28..=0x2A)
+}
+
+/// Runs SWI `number` on behalf of the BIOS and returns to the caller. Numbers past 0x2A have
+/// no BIOS function and stop the CPU.
+pub fn software_interrupt<M: MemoryBus>(
+    cpu: &mut Cpu,
+    memory: &mut M,
+    number: u8,
+) -> Result<(), EmulatorError> {
+    let r = |cpu: &Cpu, index: usize| cpu.cpu_state.reg(index);
+    match number {
+        0x00 => soft_reset(cpu, memory),
+        0x01 => register_ram_reset(memory, r(cpu, 0)),
+        0x02 => memory.write_byte(REG_HALTCNT, 0),
+        0x03 => memory.write_byte(REG_HALTCNT, 0x80),
+        0x04 => intr_wait(cpu, memory, r(cpu, 0) != 0, r(cpu, 1) as u16),
+        0x05 => {
+            cpu.cpu_state.set_reg(0, 1);
+            cpu.cpu_state.set_reg(1, 1);
+            intr_wait(cpu, memory, true, 1);
+        }
+        0x06 => div(cpu, r(cpu, 0) as i32, r(cpu, 1) as i32),
+        0x07 => div(cpu, r(cpu, 1) as i32, r(cpu, 0) as i32),
+        0x08 => {
+            let root = math::sqrt(r(cpu, 0));
+            cpu.cpu_state.set_reg(0, root as u32);
+        }
+        0x09 => {
+            let (angle, r1, r3) = math::arctan(r(cpu, 0) as i32);
+            cpu.cpu_state.set_reg(0, angle as i32 as u32);
+            cpu.cpu_state.set_reg(1, r1 as u32);
+            cpu.cpu_state.set_reg(3, r3 as u32);
+        }
+        0x0A => {
+            let (angle, r1) = math::arctan2(r(cpu, 0) as i32, r(cpu, 1) as i32);
+            cpu.cpu_state.set_reg(0, angle as u32);
+            if let Some(r1) = r1 {
+                cpu.cpu_state.set_reg(1, r1 as u32);
+            }
+            cpu.cpu_state.set_reg(3, 0x170);
+        }
+        0x0B => cpu_set(memory, r(cpu, 0), r(cpu, 1), r(cpu, 2)),
+        0x0C => cpu_fast_set(memory, r(cpu, 0), r(cpu, 1), r(cpu, 2)),
+        0x0D => cpu.cpu_state.set_reg(0, BIOS_CHECKSUM),
+        0x0E => bg_affine_set(memory, r(cpu, 0), r(cpu, 1), r(cpu, 2)),
+        0x0F => obj_affine_set(memory, r(cpu, 0), r(cpu, 1), r(cpu, 2), r(cpu, 3)),
+        0x10..=0x18 if r(cpu, 0) < 0x0200_0000 => {
+            // The BIOS refuses to read itself
+        }
+        0x10 => {
+            let words = decompress::bit_unpack(memory, r(cpu, 0), r(cpu, 2));
+            write_words(memory, r(cpu, 1), &words);
+        }
+        0x11 | 0x12 => {
+            let bytes = decompress::lz77(memory, r(cpu, 0));
+            write_uncompressed(memory, r(cpu, 1), &bytes, number == 0x12);
+        }
+        0x13 => {
+            let bytes = decompress::huffman(memory, r(cpu, 0));
+            write_uncompressed(memory, r(cpu, 1), &bytes, false);
+        }
+        0x14 | 0x15 => {
+            let bytes = decompress::run_length(memory, r(cpu, 0));
+            write_uncompressed(memory, r(cpu, 1), &bytes, number == 0x15);
+        }
+        0x16 | 0x17 => {
+            let bytes = decompress::diff8(memory, r(cpu, 0));
+            write_uncompressed(memory, r(cpu, 1), &bytes, number == 0x17);
+        }
+        0x18 => {
+            let halfwords = decompress::diff16(memory, r(cpu, 0));
+            for (i, halfword) in halfwords.iter().enumerate() {
+                memory.write_halfword(r(cpu, 1).wrapping_add(i as u32 * 2), *halfword);
+            }
+        }
+        0x19 => {
+            // The BIOS ramps the level over a few frames, games only wait for it to finish
+            let level = if r(cpu, 0) != 0 { 0x200 } else { 0 };
+            let bias = memory.read_halfword(REG_SOUNDBIAS);
+            memory.write_halfword(REG_SOUNDBIAS, (bias & !0x3FF) | level);
+        }
+        0x1F => {
+            let sample_rate = memory.read_word(r(cpu, 0).wrapping_add(4));
+            let frequency = midi_key_to_frequency(sample_rate, r(cpu, 1), r(cpu, 2));
+            cpu.cpu_state.set_reg(0, frequency);
+        }
+        0x26 => soft_reset(cpu, memory), // HardReset, minus the boot logo
+        0x27 => memory.write_byte(REG_HALTCNT, r(cpu, 2) as u8),
+        number if is_stubbed(number) => {}
+        _ => return Err(invalid_swi(cpu, memory)),
+    }
+    Ok(())
+}
+
+// PC has already moved past the SWI
+fn invalid_swi<M: MemoryBus>(cpu: &Cpu, memory: &mut M) -> EmulatorError {
+    let thumb = cpu.cpu_state.CPSR.is_thumb_state();
+    let pc = cpu.cpu_state.reg(15);
+    let (address, opcode) = if thumb {
+        let address = pc.wrapping_sub(2);
+        (address, memory.read_halfword(address) as u32)
+    } else {
+        let address = pc.wrapping_sub(4);
+        (address, memory.fetch_word(address))
+    };
+    EmulatorError::UnimplementedInstruction {
+        address,
+        opcode,
+        thumb,
+    }
+}
+
+// SoftReset: clears the top of IWRAM, resets the stacks and restarts the game.
+fn soft_reset<M: MemoryBus>(cpu: &mut Cpu, memory: &mut M) {
+    let to_ewram = memory.read_byte(SOFT_RESET_FLAG) != 0;
+    for address in (0x0300_7E00..0x0300_8000).step_by(4) {
+        memory.write_word(address, 0);
+    }
+    let state = &mut cpu.cpu_state;
+    for (mode, stack) in [(Mode::Irq, SP_IRQ), (Mode::Supervisor, SP_SVC)] {
+        state.set_mode(mode);
+        state.set_reg(13, stack);
+        state.set_reg(14, 0);
+        state.SPSR.value = 0;
+    }
+    state.set_mode(Mode::System);
+    state.CPSR.value = Mode::System as u32;
+    for index in 0..13 {
+        state.set_reg(index, 0);
+    }
+    let entry = if to_ewram { 0x0200_0000 } else { 0x0800_0000 };
+    state.set_reg(13, SP_SYS);
+    state.set_reg(14, entry);
+    state.set_reg(15, entry);
+    cpu.intr_wait_pending = false;
+}
+
+// RegisterRamReset: each bit of `flags` clears one memory area or group of registers.
+fn register_ram_reset<M: MemoryBus>(memory: &mut M, flags: u32) {
+    // Always forces blank
+    memory.write_halfword(REG_DISPCNT, 0x0080);
+    let areas = [
+        (0x0200_0000, 0x0204_0000), // EWRAM
+        (0x0300_0000, 0x0300_7E00), // IWRAM, except the BIOS and stack area at the top
+        (0x0500_0000, 0x0500_0400), // Palette
+        (0x0600_0000, 0x0601_8000), // VRAM
+        (0x0700_0000, 0x0700_0400), // OAM
+    ];
+    for (bit, (start, end)) in areas.iter().enumerate() {
+        if flags & (1 << bit) != 0 {
+            for address in (*start..*end).step_by(4) {
+                memory.write_word(address, 0);
+            }
+        }
+    }
+    if flags & (1 << 5) != 0 {
+        // Serial
+        clear_halfwords(memory, 0x0400_0120, 0x0400_0130);
+        memory.write_halfword(0x0400_0134, 0x8000); // RCNT, general purpose mode
+        clear_halfwords(memory, 0x0400_0140, 0x0400_0142);
+        clear_halfwords(memory, 0x0400_0150, 0x0400_015A);
+    }
+    if flags & (1 << 6) != 0 {
+        // Sound
+        clear_halfwords(memory, 0x0400_0060, 0x0400_00A8);
+        memory.write_halfword(REG_SOUNDBIAS, 0x0200);
+    }
+    if flags & (1 << 7) != 0 {
+        // Display, DMA, timers, keypad control and interrupts
+        clear_halfwords(memory, 0x0400_0004, 0x0400_0060);
+        for address in [0x0400_0020, 0x0400_0026, 0x0400_0030, 0x0400_0036] {
+            memory.write_halfword(address, 0x0100); // BG2/BG3 identity matrix
+        }
+        clear_halfwords(memory, 0x0400_00B0, 0x0400_00E0);
+        clear_halfwords(memory, 0x0400_0100, 0x0400_0110);
+        memory.write_halfword(0x0400_0132, 0);
+        memory.write_halfword(REG_IE, 0);
+        memory.write_halfword(REG_IF, 0xFFFF);
+        memory.write_halfword(REG_WAITCNT, 0);
+        memory.write_halfword(REG_IME, 0);
+    }
+}
+
+fn clear_halfwords<M: MemoryBus>(memory: &mut M, start: u32, end: u32) {
+    for address in (start..end).step_by(2) {
+        memory.write_halfword(address, 0);
+    }
+}
+
+/// The BIOS IRQ vector: pushes r0-r3, r12 and lr on the IRQ stack and calls the game's handler
+/// with r0 = 0x04000000.
+pub fn irq_entry<M: MemoryBus>(cpu: &mut Cpu, memory: &mut M) {
+    let state = &mut cpu.cpu_state;
+    let sp = state.reg(13).wrapping_sub(24);
+    for (slot, register) in [0, 1, 2, 3, 12, 14].into_iter().enumerate() {
+        memory.write_word(sp.wrapping_add(slot as u32 * 4), state.reg(register));
+    }
+    state.set_reg(13, sp);
+    state.set_reg(0, 0x0400_0000);
+    state.set_reg(14, IRQ_RETURN_ADDRESS);
+    let handler = memory.read_word(IRQ_HANDLER) & !3;
+    state.set_reg(15, handler);
+}
+
+/// Back from the game's handler: pops the registers and returns to the interrupted code.
+pub fn irq_return<M: MemoryBus>(cpu: &mut Cpu, memory: &mut M) {
+    let state = &mut cpu.cpu_state;
+    let sp = state.reg(13);
+    for (slot, register) in [0, 1, 2, 3, 12, 14].into_iter().enumerate() {
+        let value = memory.read_word(sp.wrapping_add(slot as u32 * 4));
+        state.set_reg(register, value);
+    }
+    state.set_reg(13, sp.wrapping_add(24));
+    let return_address = state.reg(14).wrapping_sub(4);
+    let spsr = state.SPSR;
+    state.set_mode(Mode::from_bits(spsr.value).unwrap_or(Mode::User));
+    state.CPSR = spsr;
+    state.set_reg(15, return_address);
+}
+
+// IntrWait: halts until one of `flags` shows up in BIOS_IF. The game's interrupt handler sets
+// BIOS_IF, so the SWI runs again after every wake up until the flag is there.
+fn intr_wait<M: MemoryBus>(cpu: &mut Cpu, memory: &mut M, discard: bool, flags: u16) {
+    if discard && !cpu.intr_wait_pending {
+        let pending = memory.read_halfword(BIOS_IF);
+        memory.write_halfword(BIOS_IF, pending & !flags);
+    }
+    memory.write_halfword(REG_IME, 1);
+    let pending = memory.read_halfword(BIOS_IF);
+    if pending & flags != 0 {
+        memory.write_halfword(BIOS_IF, pending & !flags);
+        cpu.intr_wait_pending = false;
+        return;
+    }
+    cpu.intr_wait_pending = true;
+    let instruction_size = if cpu.cpu_state.CPSR.is_thumb_state() { 2 } else { 4 };
+    let pc = cpu.cpu_state.reg(15);
+    cpu.cpu_state.set_reg(15, pc.wrapping_sub(instruction_size));
+    memory.write_byte(REG_HALTCNT, 0);
+}
+
+// Div and DivArm leave |quotient| in r3.
+fn div(cpu: &mut Cpu, numerator: i32, denominator: i32) {
+    let (quotient, remainder, absolute) = math::div(numerator, denominator);
+    cpu.cpu_state.set_reg(0, quotient as u32);
+    cpu.cpu_state.set_reg(1, remainder as u32);
+    cpu.cpu_state.set_reg(3, absolute);
+}
+
+// CpuSet: copies or fills (bit 24) count halfwords, or words with bit 26.
+fn cpu_set<M: MemoryBus>(memory: &mut M, source: u32, destination: u32, control: u32) {
+    if source < 0x0200_0000 {
+        return;
+    }
+    let count = control & 0x1F_FFFF;
+    let fill = control & CPU_SET_FILL != 0;
+    if control & CPU_SET_WORDS != 0 {
+        let (source, destination) = (source & !3, destination & !3);
+        let value = memory.read_word(source);
+        for i in 0..count {
+            let value = if fill { value } else { memory.read_word(source.wrapping_add(i * 4)) };
+            memory.write_word(destination.wrapping_add(i * 4), value);
+        }
+    } else {
+        let (source, destination) = (source & !1, destination & !1);
+        let value = memory.read_halfword(source);
+        for i in 0..count {
+            let value = if fill { value } else { memory.read_halfword(source.wrapping_add(i * 2)) };
+            memory.write_halfword(destination.wrapping_add(i * 2), value);
+        }
+    }
+}
+
+// CpuFastSet: words only, in blocks of 8.
+fn cpu_fast_set<M: MemoryBus>(memory: &mut M, source: u32, destination: u32, control: u32) {
+    let count = ((control & 0x1F_FFFF) + 7) & !7;
+    cpu_set(memory, source, destination, (control & CPU_SET_FILL) | CPU_SET_WORDS | count);
+}
+
+// BgAffineSet: 20 byte source entries (center in texture, center on screen, scale, angle)
+// into 16 byte PA-PD plus reference point entries.
+fn bg_affine_set<M: MemoryBus>(memory: &mut M, source: u32, destination: u32, count: u32) {
+    for i in 0..count {
+        let source = source.wrapping_add(i.wrapping_mul(20));
+        let destination = destination.wrapping_add(i.wrapping_mul(16));
+        let at = |offset: u32| source.wrapping_add(offset);
+        let origin_x = memory.read_word(source) as i32;
+        let origin_y = memory.read_word(at(4)) as i32;
+        let center_x = memory.read_halfword(at(8)) as i16 as i32;
+        let center_y = memory.read_halfword(at(10)) as i16 as i32;
+        let scale_x = memory.read_halfword(at(12)) as i16;
+        let scale_y = memory.read_halfword(at(14)) as i16;
+        let angle = memory.read_halfword(at(16));
+        let [pa, pb, pc, pd] = math::affine_matrix(scale_x, scale_y, angle);
+        let x = origin_x - (pa as i32 * center_x + pb as i32 * center_y);
+        let y = origin_y - (pc as i32 * center_x + pd as i32 * center_y);
+        for (j, value) in [pa, pb, pc, pd].iter().enumerate() {
+            memory.write_halfword(destination.wrapping_add(j as u32 * 2), *value as u16);
+        }
+        memory.write_word(destination.wrapping_add(8), x as u32);
+        memory.write_word(destination.wrapping_add(12), y as u32);
+    }
+}
+
+// ObjAffineSet: 8 byte source entries (scale, angle), PA-PD written `stride` bytes apart
+// so they can go straight into OAM (stride 8) or a packed array (stride 2).
+fn obj_affine_set<M: MemoryBus>(
+    memory: &mut M,
+    source: u32,
+    destination: u32,
+    count: u32,
+    stride: u32,
+) {
+    for i in 0..count {
+        let source = source.wrapping_add(i.wrapping_mul(8));
+        let scale_x = memory.read_halfword(source) as i16;
+        let scale_y = memory.read_halfword(source.wrapping_add(2)) as i16;
+        let angle = memory.read_halfword(source.wrapping_add(4));
+        let destination = destination.wrapping_add(i.wrapping_mul(stride).wrapping_mul(4));
+        for (j, value) in math::affine_matrix(scale_x, scale_y, angle).iter().enumerate() {
+            let offset = (j as u32).wrapping_mul(stride);
+            memory.write_halfword(destination.wrapping_add(offset), *value as u16);
+        }
+    }
+}
+
+// MidiKey2Freq: sample rate for playing a wave, recorded at MIDI key 180, at `key` plus a
+// fraction in 1/256ths. Sample rates are stored times 1024, past what an f32 holds exactly, so
+// this is done in f64 and truncated like the BIOS.
+fn midi_key_to_frequency(sample_rate: u32, key: u32, fraction: u32) -> u32 {
+    let exponent = (180.0 - key as f64 - fraction as f64 / 256.0) / 12.0;
+    (sample_rate as f64 / exponent.exp2()) as u32
+}
+
+// The Vram variants only write halfwords, an odd last byte is dropped.
+fn write_uncompressed<M: MemoryBus>(memory: &mut M, destination: u32, bytes: &[u8], vram: bool) {
+    if vram {
+        for (i, pair) in bytes.chunks_exact(2).enumerate() {
+            let halfword = u16::from_le_bytes([pair[0], pair[1]]);
+            memory.write_halfword(destination.wrapping_add(i as u32 * 2), halfword);
+        }
+    } else {
+        for (i, byte) in bytes.iter().enumerate() {
+            memory.write_byte(destination.wrapping_add(i as u32), *byte);
+        }
+    }
+}
+
+fn write_words<M: MemoryBus>(memory: &mut M, destination: u32, words: &[u32]) {
+    for (i, word) in words.iter().enumerate() {
+        memory.write_word(destination.wrapping_add(i as u32 * 4), *word);
+    }
+}
@@ -0,0 +1,127 @@
+// src/bios/math.rs
+// Arithmetic SWIs. These follow the BIOS algorithms rather than the exact math, games depend
+// on the rounding (and on the side effects in other registers).
+
+// Quarter of the BIOS sine table, sin(i * 2pi / 256) * 0x4000 rounded down.
+const SINE_QUARTER: [i32; 65] = [
+    0x0000, 0x0192, 0x0323, 0x04B5, 0x0645, 0x07D5, 0x0964, 0x0AF1,
+    0x0C7C, 0x0E05, 0x0F8C, 0x1111, 0x1294, 0x1413, 0x158F, 0x1708,
+    0x187D, 0x19EF, 0x1B5D, 0x1CC6, 0x1E2B, 0x1F8B, 0x20E7, 0x223D,
+    0x238E, 0x24DA, 0x261F, 0x275F, 0x2899, 0x29CD, 0x2AFA, 0x2C21,
+    0x2D41, 0x2E5A, 0x2F6B, 0x3076, 0x3179, 0x3274, 0x3367, 0x3453,
+    0x3536, 0x3612, 0x36E5, 0x37AF, 0x3871, 0x392A, 0x39DA, 0x3A82,
+    0x3B20, 0x3BB6, 0x3C42, 0x3CC5, 0x3D3E, 0x3DAE, 0x3E14, 0x3E71,
+    0x3EC5, 0x3F0E, 0x3F4E, 0x3F84, 0x3FB1, 0x3FD3, 0x3FEC, 0x3FFB,
+    0x4000,
+];
+
+// Angle in 1/256ths of a full turn, result in 1.14 fixed point.
+pub fn sine(angle: u8) -> i32 {
+    let index = (angle & 0x7F) as usize;
+    let value = if index <= 64 {
+        SINE_QUARTER[index]
+    } else {
+        SINE_QUARTER[128 - index]
+    };
+    if angle >= 128 {
+        -value
+    } else {
+        value
+    }
+}
+
+pub fn cosine(angle: u8) -> i32 {
+    sine(angle.wrapping_add(64))
+}
+
+/// Div: (quotient, remainder, |quotient|), the last one ends up in r3.
+/// The BIOS hangs dividing anything but 0 and +-1 by zero, which is not worth emulating.
+pub fn div(numerator: i32, denominator: i32) -> (i32, i32, u32) {
+    if denominator == 0 {
+        let quotient = if numerator < 0 { -1 } else { 1 };
+        return (quotient, numerator, 1);
+    }
+    if numerator == i32::MIN && denominator == -1 {
+        return (i32::MIN, 0, i32::MIN as u32);
+    }
+    let quotient = numerator / denominator;
+    (quotient, numerator % denominator, quotient.unsigned_abs())
+}
+
+// Rounds down, like the BIOS.
+pub fn sqrt(value: u32) -> u16 {
+    let mut result: u32 = 0;
+    let mut bit: u32 = 1 << 30;
+    let mut remainder = value;
+    while bit > remainder {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if remainder >= result + bit {
+            remainder -= result + bit;
+            result = (result >> 1) + bit;
+        } else {
+            result >>= 1;
+        }
+        bit >>= 2;
+    }
+    result as u16
+}
+
+/// ArcTan of a 1.14 tangent. Returns the angle (0x4000 = 90 degrees) along with the
+/// intermediate values the BIOS leaves in r1 and r3.
+pub fn arctan(tangent: i32) -> (i16, i32, i32) {
+    let a = (tangent.wrapping_mul(tangent) >> 14).wrapping_neg();
+    let mut b = (0xA9i32.wrapping_mul(a) >> 14) + 0x390;
+    for constant in [0x91C, 0xFB6, 0x16AA, 0x2081, 0x3651, 0xA2F9] {
+        b = (b.wrapping_mul(a) >> 14) + constant;
+    }
+    ((tangent.wrapping_mul(b) >> 16) as i16, a, b)
+}
+
+/// ArcTan2 for the point (x, y), 0x0000-0xFFFF for a full turn. The second value is what
+/// the BIOS leaves in r1, which is untouched when either coordinate is zero.
+pub fn arctan2(x: i32, y: i32) -> (u16, Option<i32>) {
+    if y == 0 {
+        return (if x >= 0 { 0 } else { 0x8000 }, None);
+    }
+    if x == 0 {
+        return (if y >= 0 { 0x4000 } else { 0xC000 }, None);
+    }
+    let (angle, r1) = if y >= 0 {
+        if x >= 0 && x >= y {
+            let (angle, r1, _) = arctan((y << 14).wrapping_div(x));
+            (angle as i32, r1)
+        } else if x < 0 && x.wrapping_neg() >= y {
+            let (angle, r1, _) = arctan((y << 14).wrapping_div(x));
+            (angle as i32 + 0x8000, r1)
+        } else {
+            let (angle, r1, _) = arctan((x << 14).wrapping_div(y));
+            (0x4000 - angle as i32, r1)
+        }
+    } else if x <= 0 && x.wrapping_neg() > y.wrapping_neg() {
+        let (angle, r1, _) = arctan((y << 14).wrapping_div(x));
+        (angle as i32 + 0x8000, r1)
+    } else if x > 0 && x >= y.wrapping_neg() {
+        let (angle, r1, _) = arctan((y << 14).wrapping_div(x));
+        (angle as i32 + 0x10000, r1)
+    } else {
+        let (angle, r1, _) = arctan((x << 14).wrapping_div(y));
+        (0xC000 - angle as i32, r1)
+    };
+    (angle as u16, Some(r1))
+}
+
+/// Rotation/scaling matrix (pa, pb, pc, pd) for 8.8 scale factors and an angle whose upper
+/// byte counts 1/256ths of a turn.
+pub fn affine_matrix(scale_x: i16, scale_y: i16, angle: u16) -> [i16; 4] {
+    let angle = (angle >> 8) as u8;
+    let (sin, cos) = (sine(angle), cosine(angle));
+    let (scale_x, scale_y) = (scale_x as i32, scale_y as i32);
+    [
+        ((scale_x * cos) >> 14) as i16,
+        ((-scale_x * sin) >> 14) as i16,
+        ((scale_y * sin) >> 14) as i16,
+        ((scale_y * cos) >> 14) as i16,
+    ]
+}
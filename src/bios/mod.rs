@@ -0,0 +1,83 @@
+// src/bios/mod.rs
+// BIOS support. Nintendo's BIOS cannot be shipped, so by default SWIs are serviced by `hle`.
+// A dumped gba_bios.bin can be loaded instead and booted from the reset vector.
+
+pub mod decompress;
+pub mod hle;
+pub mod math;
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::bios::hle::{SP_IRQ, SP_SVC, SP_SYS};
+use crate::bus::{Bus, BIOS_SIZE};
+use crate::cpu::{Cpu, Mode};
+
+// Last BIOS opcode fetched when the BIOS hands over to the game, which is what reads of the
+// protected BIOS return afterwards.
+pub const POST_BOOT_PREFETCH: u32 = 0xE129_F000;
+
+#[derive(Debug)]
+pub enum BiosError {
+    Io(std::io::Error),
+    WrongSize { size: usize },
+}
+
+impl fmt::Display for BiosError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BiosError::Io(err) => write!(f, "could not read BIOS file: {}", err),
+            BiosError::WrongSize { size } => write!(
+                f,
+                "BIOS image is {} bytes, expected {}",
+                size, BIOS_SIZE
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BiosError {}
+
+impl From<std::io::Error> for BiosError {
+    fn from(err: std::io::Error) -> Self {
+        BiosError::Io(err)
+    }
+}
+
+pub fn load_image<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, BiosError> {
+    let image = fs::read(path)?;
+    if image.len() != BIOS_SIZE {
+        return Err(BiosError::WrongSize { size: image.len() });
+    }
+    Ok(image)
+}
+
+/// Sum of all words, the value GetBiosChecksum returns. 0xBAAE187F for the GBA BIOS.
+pub fn checksum(image: &[u8]) -> u32 {
+    image
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .fold(0u32, |sum, word| sum.wrapping_add(word))
+}
+
+/// Puts CPU and I/O in the state the BIOS leaves them in and jumps straight to the ROM.
+pub fn skip_boot(cpu: &mut Cpu, bus: &mut Bus) {
+    cpu.reset();
+    let state = &mut cpu.cpu_state;
+    for (mode, stack) in [(Mode::Irq, SP_IRQ), (Mode::Supervisor, SP_SVC), (Mode::System, SP_SYS)] {
+        state.set_mode(mode);
+        state.set_reg(13, stack);
+    }
+    state.CPSR.value = Mode::System as u32;
+    state.set_reg(15, 0x0800_0000);
+
+    bus.write_halfword(0x0400_0000, 0x0080); // DISPCNT, forced blank
+    for address in [0x0400_0020, 0x0400_0026, 0x0400_0030, 0x0400_0036] {
+        bus.write_halfword(address, 0x0100); // BG2/BG3 identity matrix
+    }
+    bus.write_halfword(0x0400_0088, 0x0200); // SOUNDBIAS
+    bus.write_halfword(0x0400_0134, 0x8000); // RCNT
+    bus.write_byte(0x0400_0300, 1); // POSTFLG, past the first boot
+    bus.bios_prefetch = POST_BOOT_PREFETCH;
+}
@@ -0,0 +1,425 @@
+// src/bus.rs
+// GBA memory map. Every access is decoded by the top byte of the address (GBATEK "Memory Map").
+
+use crate::apu::{Apu, SOUND_REGISTERS_END, SOUND_REGISTERS_START};
+use crate::bios::{self, BiosError};
+use crate::cartridge::Cartridge;
+use crate::dma::{DmaChannel, DMA_REGISTERS_END, DMA_REGISTERS_START};
+use crate::interrupt::{Interrupt, PowerState, REG_HALTCNT, REG_IE, REG_IME, REG_POSTFLG};
+use crate::memory::MemoryBus;
+use crate::ppu::{self, Ppu};
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+use crate::scheduler::{Event, Scheduler};
+use crate::sio::{
+    Sio, JOY_REGISTERS_END, JOY_REGISTERS_START, REG_RCNT, SIO_REGISTERS_END, SIO_REGISTERS_START,
+};
+use crate::timer::{Timer, TIMER_REGISTERS_END, TIMER_REGISTERS_START};
+
+pub const BIOS_SIZE: usize = 16 * 1024;
+pub const EWRAM_SIZE: usize = 256 * 1024;
+pub const IWRAM_SIZE: usize = 32 * 1024;
+pub const IO_SIZE: usize = 0x400;
+pub const PALETTE_SIZE: usize = 1024;
+pub const VRAM_SIZE: usize = 96 * 1024;
+pub const OAM_SIZE: usize = 1024;
+
+const REG_KEYINPUT: u32 = 0x130;
+const REG_KEYCNT: u32 = 0x132;
+const KEYS_RELEASED: u16 = 0x03FF; // KEYINPUT bits are 0 while pressed
+
+pub struct Bus {
+    pub bios: Vec<u8>,
+    pub ewram: Vec<u8>,
+    pub iwram: Vec<u8>,
+    pub io: Vec<u8>,
+    pub palette: Vec<u8>,
+    pub vram: Vec<u8>,
+    pub oam: Vec<u8>,
+    pub cartridge: Option<Cartridge>,
+    pub dma: [DmaChannel; 4],
+    // The BIOS can only be read while executing from it, everyone else sees the last opcode
+    // it fetched.
+    pub bios_prefetch: u32,
+    executing_bios: bool,
+    pub ppu: Ppu,
+    pub scheduler: Scheduler,
+    pub power_state: PowerState,
+    pub sio: Sio,
+    pub timers: [Timer; 4],
+    pub apu: Apu,
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        let mut bus = Bus {
+            bios: vec![0; BIOS_SIZE],
+            ewram: vec![0; EWRAM_SIZE],
+            iwram: vec![0; IWRAM_SIZE],
+            io: vec![0; IO_SIZE],
+            palette: vec![0; PALETTE_SIZE],
+            vram: vec![0; VRAM_SIZE],
+            oam: vec![0; OAM_SIZE],
+            cartridge: None,
+            dma: [DmaChannel::default(); 4],
+            bios_prefetch: 0,
+            executing_bios: false,
+            ppu: Ppu::new(),
+            scheduler: Scheduler::new(),
+            power_state: PowerState::Running,
+            sio: Sio::new(),
+            timers: [Timer::default(); 4],
+            apu: Apu::new(),
+        };
+        bus.io[REG_KEYINPUT as usize..REG_KEYINPUT as usize + 2]
+            .copy_from_slice(&KEYS_RELEASED.to_le_bytes());
+        bus.scheduler.schedule(ppu::HDRAW_CYCLES, Event::HBlank);
+        bus.scheduler.schedule(ppu::LINE_CYCLES, Event::EndOfLine);
+        bus
+    }
+
+    pub fn with_cartridge(cartridge: Cartridge) -> Self {
+        let mut bus = Bus::new();
+        bus.cartridge = Some(cartridge);
+        bus
+    }
+
+    /// Copies a BIOS image into the BIOS region and returns its checksum.
+    pub fn load_bios(&mut self, image: &[u8]) -> Result<u32, BiosError> {
+        if image.len() != BIOS_SIZE {
+            return Err(BiosError::WrongSize { size: image.len() });
+        }
+        self.bios.copy_from_slice(image);
+        Ok(bios::checksum(image))
+    }
+
+    /// Sets the pressed buttons (bit 0 A .. bit 9 L, as in KEYINPUT but active high) and raises
+    /// the keypad interrupt when KEYCNT asks for it.
+    pub fn set_keys(&mut self, pressed: u16) {
+        let input = !pressed & KEYS_RELEASED;
+        self.io[REG_KEYINPUT as usize..REG_KEYINPUT as usize + 2].copy_from_slice(&input.to_le_bytes());
+        let keycnt = u16::from_le_bytes([
+            self.io[REG_KEYCNT as usize],
+            self.io[REG_KEYCNT as usize + 1],
+        ]);
+        let selected = keycnt & KEYS_RELEASED;
+        if keycnt & (1 << 14) == 0 || selected == 0 {
+            return;
+        }
+        let pressed = pressed & selected;
+        // Bit 15 asks for all the selected buttons at once, otherwise any of them will do
+        let condition = if keycnt & (1 << 15) != 0 { pressed == selected } else { pressed != 0 };
+        if condition {
+            self.request_interrupt(Interrupt::Keypad);
+        }
+    }
+
+    /// Runs the rest of the system for `cycles` CPU cycles, handling every event that comes due.
+    pub fn advance(&mut self, cycles: u64) {
+        if self.power_state == PowerState::Stopped {
+            return;
+        }
+        self.scheduler.advance(cycles);
+        while let Some((at, event)) = self.scheduler.pop_due() {
+            // Sound up to the event first, it may be a timer moving the FIFOs on
+            self.apu.run_until(at);
+            match event {
+                Event::HBlank | Event::EndOfLine => self.handle_ppu_event(event, at),
+                Event::SerialStart => self.start_transfer(),
+                Event::SerialTransfer => self.finish_transfer(),
+                Event::LinkSync => self.sync_link(at),
+                Event::TimerOverflow(timer) => self.timer_overflow(timer as usize, at),
+            }
+        }
+        self.apu.run_until(self.scheduler.now());
+        self.update_power_state();
+    }
+
+    /// Jumps to the next scheduled event, for a halted CPU. Returns the cycles skipped.
+    pub fn skip_to_next_event(&mut self) -> u64 {
+        let Some(at) = self.scheduler.next_event_at() else {
+            return 0;
+        };
+        let cycles = at.saturating_sub(self.scheduler.now());
+        self.advance(cycles);
+        cycles
+    }
+
+    fn read_bios(&self, address: u32) -> u8 {
+        if self.executing_bios {
+            self.bios[address as usize]
+        } else {
+            (self.bios_prefetch >> ((address & 3) * 8)) as u8
+        }
+    }
+
+    // VRAM is 96 KB mirrored in 128 KB steps, the upper 32 KB mirroring the last 32 KB.
+    fn vram_offset(address: u32) -> usize {
+        let offset = (address & 0x1FFFF) as usize;
+        if offset >= VRAM_SIZE {
+            offset - 0x8000
+        } else {
+            offset
+        }
+    }
+
+    fn is_save_region(address: u32) -> bool {
+        matches!(address >> 24, 0x0E | 0x0F)
+    }
+
+    fn read_io(&mut self, offset: u32) -> u8 {
+        match offset {
+            ppu::REG_DISPSTAT..=0x007 => self.read_ppu_register(offset),
+            SOUND_REGISTERS_START..=SOUND_REGISTERS_END => self.read_sound_register(offset),
+            DMA_REGISTERS_START..=DMA_REGISTERS_END => self.read_dma_register(offset),
+            TIMER_REGISTERS_START..=TIMER_REGISTERS_END => self.read_timer_register(offset),
+            SIO_REGISTERS_START..=SIO_REGISTERS_END
+            | REG_RCNT..=0x135
+            | JOY_REGISTERS_START..=JOY_REGISTERS_END => self.read_sio_register(offset),
+            _ => self.io[offset as usize],
+        }
+    }
+
+    fn write_io(&mut self, offset: u32, value: u8) {
+        match offset {
+            ppu::REG_DISPSTAT..=0x007 => self.write_ppu_register(offset, value),
+            SOUND_REGISTERS_START..=SOUND_REGISTERS_END => self.write_sound_register(offset, value),
+            DMA_REGISTERS_START..=DMA_REGISTERS_END => self.write_dma_register(offset, value),
+            TIMER_REGISTERS_START..=TIMER_REGISTERS_END => self.write_timer_register(offset, value),
+            SIO_REGISTERS_START..=SIO_REGISTERS_END
+            | REG_RCNT..=0x135
+            | JOY_REGISTERS_START..=JOY_REGISTERS_END => self.write_sio_register(offset, value),
+            REG_KEYINPUT | 0x131 => {} // Read only, see set_keys
+            REG_IE..=0x203 | REG_IME | REG_POSTFLG | REG_HALTCNT => {
+                self.write_interrupt_register(offset, value)
+            }
+            _ => self.io[offset as usize] = value,
+        }
+    }
+
+    // Reads take &mut self, some devices (EEPROM, I/O) change state when read.
+    pub fn read_byte(&mut self, address: u32) -> u8 {
+        match address >> 24 {
+            0x00 if (address as usize) < BIOS_SIZE => self.read_bios(address),
+            0x02 => self.ewram[address as usize % EWRAM_SIZE],
+            0x03 => self.iwram[address as usize % IWRAM_SIZE],
+            0x04 if ((address & 0xFFFFFF) as usize) < IO_SIZE => self.read_io(address & 0x3FF),
+            0x05 => self.palette[address as usize % PALETTE_SIZE],
+            0x06 => self.vram[Self::vram_offset(address)],
+            0x07 => self.oam[address as usize % OAM_SIZE],
+            0x08..=0x0D => match &mut self.cartridge {
+                Some(cartridge) if cartridge.is_eeprom_address(address) => {
+                    cartridge.read_eeprom() as u8
+                }
+                Some(cartridge) => cartridge.read_rom_byte(address & 0x01FF_FFFF),
+                None => 0,
+            },
+            0x0E | 0x0F => match &self.cartridge {
+                Some(cartridge) => cartridge.read_save(address & 0xFFFF),
+                None => 0xFF,
+            },
+            _ => 0, // Unmapped, open bus is not emulated yet
+        }
+    }
+
+    pub fn read_halfword(&mut self, address: u32) -> u16 {
+        if Self::is_save_region(address) {
+            // The save chip sits on an 8 bit bus, the byte is repeated across the lanes.
+            return self.read_byte(address) as u16 * 0x0101;
+        }
+        let address = address & !1;
+        if let Some(cartridge) = &mut self.cartridge {
+            if cartridge.is_eeprom_address(address) {
+                return cartridge.read_eeprom();
+            }
+        }
+        u16::from_le_bytes([self.read_byte(address), self.read_byte(address + 1)])
+    }
+
+    // Word accesses are split in two halfword accesses, like on the 16 bit cartridge bus.
+    pub fn read_word(&mut self, address: u32) -> u32 {
+        if Self::is_save_region(address) {
+            return self.read_byte(address) as u32 * 0x0101_0101;
+        }
+        let address = address & !3;
+        self.read_halfword(address) as u32 | ((self.read_halfword(address + 2) as u32) << 16)
+    }
+
+    pub fn write_byte(&mut self, address: u32, value: u8) {
+        match address >> 24 {
+            0x02 => self.ewram[address as usize % EWRAM_SIZE] = value,
+            0x03 => self.iwram[address as usize % IWRAM_SIZE] = value,
+            0x04 if ((address & 0xFFFFFF) as usize) < IO_SIZE => {
+                self.write_io(address & 0x3FF, value)
+            }
+            0x05 => self.palette[address as usize % PALETTE_SIZE] = value,
+            0x06 => {
+                let offset = Self::vram_offset(address);
+                self.vram[offset] = value
+            }
+            0x07 => self.oam[address as usize % OAM_SIZE] = value,
+            0x08..=0x0D => {
+                if let Some(cartridge) = &mut self.cartridge {
+                    if cartridge.is_eeprom_address(address) {
+                        cartridge.write_eeprom(value as u16);
+                    } else {
+                        cartridge.write_rom_byte(address & 0x01FF_FFFF, value);
+                    }
+                }
+            }
+            0x0E | 0x0F => {
+                if let Some(cartridge) = &mut self.cartridge {
+                    cartridge.write_save(address & 0xFFFF, value);
+                }
+            }
+            _ => {} // BIOS is read only
+        }
+    }
+
+    pub fn write_halfword(&mut self, address: u32, value: u16) {
+        if Self::is_save_region(address) {
+            // Only the byte lane selected by the address reaches the save chip.
+            let lane = (value >> ((address & 1) * 8)) as u8;
+            self.write_byte(address, lane);
+            return;
+        }
+        let address = address & !1;
+        if let Some(cartridge) = &mut self.cartridge {
+            if cartridge.is_eeprom_address(address) {
+                cartridge.write_eeprom(value);
+                return;
+            }
+        }
+        let bytes = value.to_le_bytes();
+        self.write_byte(address, bytes[0]);
+        self.write_byte(address + 1, bytes[1]);
+    }
+
+    pub fn write_word(&mut self, address: u32, value: u32) {
+        if Self::is_save_region(address) {
+            let lane = (value >> ((address & 3) * 8)) as u8;
+            self.write_byte(address, lane);
+            return;
+        }
+        let address = address & !3;
+        self.write_halfword(address, value as u16);
+        self.write_halfword(address + 2, (value >> 16) as u16);
+    }
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryBus for Bus {
+    fn read_byte(&mut self, address: u32) -> u8 {
+        Bus::read_byte(self, address)
+    }
+
+    fn read_halfword(&mut self, address: u32) -> u16 {
+        Bus::read_halfword(self, address)
+    }
+
+    fn read_word(&mut self, address: u32) -> u32 {
+        Bus::read_word(self, address)
+    }
+
+    fn write_byte(&mut self, address: u32, value: u8) {
+        Bus::write_byte(self, address, value)
+    }
+
+    fn write_halfword(&mut self, address: u32, value: u16) {
+        Bus::write_halfword(self, address, value)
+    }
+
+    fn write_word(&mut self, address: u32, value: u32) {
+        Bus::write_word(self, address, value)
+    }
+
+    fn fetch_word(&mut self, address: u32) -> u32 {
+        self.executing_bios = (address as usize) < BIOS_SIZE;
+        let word = Bus::read_word(self, address);
+        if self.executing_bios {
+            self.bios_prefetch = word;
+        }
+        word
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.advance(cycles);
+    }
+
+    fn irq_line(&self) -> bool {
+        Bus::irq_line(self)
+    }
+
+    fn power_state(&mut self) -> PowerState {
+        self.update_power_state();
+        self.power_state
+    }
+
+    fn wait_for_event(&mut self) -> u64 {
+        self.skip_to_next_event()
+    }
+
+    fn frame_number(&self) -> Option<u64> {
+        Some(self.ppu.frame)
+    }
+}
+
+impl Snapshot for Bus {
+    fn save_state(&self, state: &mut StateWriter) {
+        for memory in [&self.ewram, &self.iwram, &self.io, &self.palette, &self.vram, &self.oam] {
+            state.bytes(memory);
+        }
+        for channel in &self.dma {
+            channel.save_state(state);
+        }
+        state.u32(self.bios_prefetch);
+        state.bool(self.executing_bios);
+        self.ppu.save_state(state);
+        self.scheduler.save_state(state);
+        self.power_state.save_state(state);
+        self.sio.save_state(state);
+        for timer in &self.timers {
+            timer.save_state(state);
+        }
+        self.apu.save_state(state);
+        state.bool(self.cartridge.is_some());
+        if let Some(cartridge) = &self.cartridge {
+            cartridge.save_state(state);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        for memory in [
+            &mut self.ewram,
+            &mut self.iwram,
+            &mut self.io,
+            &mut self.palette,
+            &mut self.vram,
+            &mut self.oam,
+        ] {
+            state.bytes_into(memory)?;
+        }
+        for channel in self.dma.iter_mut() {
+            channel.load_state(state)?;
+        }
+        self.bios_prefetch = state.u32()?;
+        self.executing_bios = state.bool()?;
+        self.ppu.load_state(state)?;
+        self.scheduler.load_state(state)?;
+        self.power_state.load_state(state)?;
+        self.sio.load_state(state)?;
+        for timer in self.timers.iter_mut() {
+            timer.load_state(state)?;
+        }
+        self.apu.load_state(state)?;
+        match (state.bool()?, &mut self.cartridge) {
+            (true, Some(cartridge)) => cartridge.load_state(state),
+            (false, None) => Ok(()),
+            _ => Err(SaveStateError::NoCartridge),
+        }
+    }
+}
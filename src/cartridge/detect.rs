@@ -0,0 +1,209 @@
+// src/cartridge/detect.rs
+// Save type detection. Nintendo's save libraries embed an ID string such as "FLASH1M_V103"
+// in the ROM, which tells us which chip the cartridge was built with.
+
+use crate::cartridge::eeprom::EepromSize;
+use crate::cartridge::flash::FlashChip;
+use crate::cartridge::save::SaveType;
+
+// In order of priority, for the odd ROM that links more than one library.
+const SIGNATURES: [(&[u8], SaveType); 6] = [
+    (b"EEPROM_V", SaveType::Eeprom(EepromSize::Unknown)),
+    (b"SRAM_F_V", SaveType::Sram),
+    (b"SRAM_V", SaveType::Sram),
+    (b"FLASH1M_V", SaveType::Flash(FlashChip::Sanyo)),
+    (b"FLASH512_V", SaveType::Flash(FlashChip::Panasonic)),
+    (b"FLASH_V", SaveType::Flash(FlashChip::Panasonic)),
+];
+
+// Games whose ID string is missing or lies about the chip, keyed by game code.
+const OVERRIDES: [(&str, SaveType); 38] = [
+    // Boktai: The Sun is in Your Hand
+    ("U3IJ", SaveType::Eeprom(EepromSize::Kbit64)),
+    ("U3IE", SaveType::Eeprom(EepromSize::Kbit64)),
+    ("U3IP", SaveType::Eeprom(EepromSize::Kbit64)),
+    // Boktai 2: Solar Boy Django
+    ("U32J", SaveType::Eeprom(EepromSize::Kbit64)),
+    ("U32E", SaveType::Eeprom(EepromSize::Kbit64)),
+    ("U32P", SaveType::Eeprom(EepromSize::Kbit64)),
+    // Shin Bokura no Taiyou
+    ("U33J", SaveType::Eeprom(EepromSize::Kbit64)),
+    // Final Fantasy Tactics Advance
+    ("AFXE", SaveType::Flash(FlashChip::Panasonic)),
+    // F-Zero: Climax
+    ("BFTJ", SaveType::Flash(FlashChip::Sanyo)),
+    // Golden Sun: The Lost Age
+    ("AGFE", SaveType::Flash(FlashChip::Panasonic)),
+    // Iridion II
+    ("AI2E", SaveType::None),
+    ("AI2P", SaveType::None),
+    // Koro Koro Puzzle: Happy Panechu!
+    ("KHPJ", SaveType::Eeprom(EepromSize::Kbit4)),
+    // Mega Man Battle Network, Mega Man Zero
+    ("AREE", SaveType::Sram),
+    ("AZCE", SaveType::Sram),
+    // Metal Slug Advance
+    ("BSME", SaveType::Eeprom(EepromSize::Kbit64)),
+    // Pokemon Ruby
+    ("AXVJ", SaveType::Flash(FlashChip::Sanyo)),
+    ("AXVE", SaveType::Flash(FlashChip::Sanyo)),
+    ("AXVP", SaveType::Flash(FlashChip::Sanyo)),
+    // Pokemon Sapphire
+    ("AXPJ", SaveType::Flash(FlashChip::Sanyo)),
+    ("AXPE", SaveType::Flash(FlashChip::Sanyo)),
+    ("AXPP", SaveType::Flash(FlashChip::Sanyo)),
+    // Pokemon Emerald
+    ("BPEJ", SaveType::Flash(FlashChip::Sanyo)),
+    ("BPEE", SaveType::Flash(FlashChip::Sanyo)),
+    ("BPEP", SaveType::Flash(FlashChip::Sanyo)),
+    // Pokemon FireRed / LeafGreen
+    ("BPRE", SaveType::Flash(FlashChip::Sanyo)),
+    ("BPGE", SaveType::Flash(FlashChip::Sanyo)),
+    // Rockman EXE 4.5: Real Operation
+    ("BR4J", SaveType::Flash(FlashChip::Sanyo)),
+    // Super Mario Advance 4
+    ("AX4J", SaveType::Flash(FlashChip::Sanyo)),
+    ("AX4E", SaveType::Flash(FlashChip::Sanyo)),
+    ("AX4P", SaveType::Flash(FlashChip::Sanyo)),
+    // Top Gun: Combat Zones
+    ("A2YE", SaveType::None),
+    // WarioWare: Twisted!
+    ("RZWJ", SaveType::Sram),
+    ("RZWE", SaveType::Sram),
+    ("RZWP", SaveType::Sram),
+    // Yoshi's Universal Gravitation / Yoshi Topsy-Turvy
+    ("KYGJ", SaveType::Eeprom(EepromSize::Kbit64)),
+    ("KYGE", SaveType::Eeprom(EepromSize::Kbit64)),
+    ("KYGP", SaveType::Eeprom(EepromSize::Kbit64)),
+];
+
+pub fn save_type_override(game_code: &str) -> Option<SaveType> {
+    OVERRIDES
+        .iter()
+        .find(|(code, _)| *code == game_code)
+        .map(|(_, save_type)| *save_type)
+}
+
+// The library strings are word aligned, so only every 4th offset needs checking.
+pub fn scan_signature(rom: &[u8]) -> Option<SaveType> {
+    let mut found: Option<(usize, SaveType)> = None;
+    for offset in (0..rom.len()).step_by(4) {
+        for (priority, (signature, save_type)) in SIGNATURES.iter().enumerate() {
+            if rom[offset..].starts_with(signature)
+                && found.is_none_or(|(best, _)| priority < best)
+            {
+                found = Some((priority, *save_type));
+            }
+        }
+    }
+    found.map(|(_, save_type)| save_type)
+}
+
+/// Picks the save chip for a ROM: override table first, then the library ID strings.
+pub fn detect_save_type(rom: &[u8], game_code: &str) -> SaveType {
+    save_type_override(game_code)
+        .or_else(|| scan_signature(rom))
+        .unwrap_or(SaveType::None)
+}
+
+// Extra hardware on the cartridge beyond ROM and save chip.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct CartridgeHardware {
+    pub rtc: bool,
+    pub solar_sensor: bool,
+    pub tilt: bool, // Accelerometer in the save region, not on the GPIO port
+    pub gyro: bool,
+    pub rumble: bool,
+}
+
+impl CartridgeHardware {
+    pub const NONE: CartridgeHardware = CartridgeHardware {
+        rtc: false,
+        solar_sensor: false,
+        tilt: false,
+        gyro: false,
+        rumble: false,
+    };
+    pub const RTC: CartridgeHardware = CartridgeHardware {
+        rtc: true,
+        ..Self::NONE
+    };
+    pub const RTC_SOLAR: CartridgeHardware = CartridgeHardware {
+        rtc: true,
+        solar_sensor: true,
+        ..Self::NONE
+    };
+    pub const TILT: CartridgeHardware = CartridgeHardware {
+        tilt: true,
+        ..Self::NONE
+    };
+    pub const GYRO_RUMBLE: CartridgeHardware = CartridgeHardware {
+        gyro: true,
+        rumble: true,
+        ..Self::NONE
+    };
+    pub const RUMBLE: CartridgeHardware = CartridgeHardware {
+        rumble: true,
+        ..Self::NONE
+    };
+
+    // Devices hanging off the GPIO port, everything except the tilt sensor.
+    pub fn uses_gpio(&self) -> bool {
+        self.rtc || self.solar_sensor || self.gyro || self.rumble
+    }
+}
+
+const HARDWARE_OVERRIDES: [(&str, CartridgeHardware); 30] = [
+    // Boktai 1, 2 and Shin Bokura no Taiyou
+    ("U3IJ", CartridgeHardware::RTC_SOLAR),
+    ("U3IE", CartridgeHardware::RTC_SOLAR),
+    ("U3IP", CartridgeHardware::RTC_SOLAR),
+    ("U32J", CartridgeHardware::RTC_SOLAR),
+    ("U32E", CartridgeHardware::RTC_SOLAR),
+    ("U32P", CartridgeHardware::RTC_SOLAR),
+    ("U33J", CartridgeHardware::RTC_SOLAR),
+    // Drill Dozer
+    ("V49J", CartridgeHardware::RUMBLE),
+    ("V49E", CartridgeHardware::RUMBLE),
+    // Koro Koro Puzzle: Happy Panechu!
+    ("KHPJ", CartridgeHardware::TILT),
+    // Pokemon Ruby, Sapphire and Emerald
+    ("AXVJ", CartridgeHardware::RTC),
+    ("AXVE", CartridgeHardware::RTC),
+    ("AXVP", CartridgeHardware::RTC),
+    ("AXVI", CartridgeHardware::RTC),
+    ("AXVS", CartridgeHardware::RTC),
+    ("AXVD", CartridgeHardware::RTC),
+    ("AXVF", CartridgeHardware::RTC),
+    ("AXPJ", CartridgeHardware::RTC),
+    ("AXPE", CartridgeHardware::RTC),
+    ("AXPP", CartridgeHardware::RTC),
+    ("BPEJ", CartridgeHardware::RTC),
+    ("BPEE", CartridgeHardware::RTC),
+    ("BPEP", CartridgeHardware::RTC),
+    // Rockman EXE 4.5: Real Operation
+    ("BR4J", CartridgeHardware::RTC),
+    // WarioWare: Twisted!
+    ("RZWJ", CartridgeHardware::GYRO_RUMBLE),
+    ("RZWE", CartridgeHardware::GYRO_RUMBLE),
+    ("RZWP", CartridgeHardware::GYRO_RUMBLE),
+    // Yoshi's Universal Gravitation / Yoshi Topsy-Turvy
+    ("KYGJ", CartridgeHardware::TILT),
+    ("KYGE", CartridgeHardware::TILT),
+    ("KYGP", CartridgeHardware::TILT),
+];
+
+// Nintendo's RTC library, linked by games using the clock.
+const RTC_SIGNATURE: &[u8] = b"SIIRTC_V";
+
+pub fn detect_hardware(rom: &[u8], game_code: &str) -> CartridgeHardware {
+    if let Some((_, hardware)) = HARDWARE_OVERRIDES.iter().find(|(code, _)| *code == game_code) {
+        return *hardware;
+    }
+    CartridgeHardware {
+        rtc: (0..rom.len())
+            .step_by(4)
+            .any(|offset| rom[offset..].starts_with(RTC_SIGNATURE)),
+        ..CartridgeHardware::NONE
+    }
+}
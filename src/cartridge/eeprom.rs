@@ -0,0 +1,233 @@
+// src/cartridge/eeprom.rs
+// Serial EEPROM accessed one bit per halfword through DMA3 (GBATEK "GBA Cart Backup EEPROM").
+
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+pub const EEPROM_4K_SIZE: usize = 512;
+pub const EEPROM_64K_SIZE: usize = 8 * 1024;
+// The chip reports busy for a while after a write. Without a clock on the cartridge
+// side we approximate the ~6.6 ms programming time by a number of status reads.
+const WRITE_BUSY_READS: u32 = 8;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EepromSize {
+    Unknown, // Detected from the first DMA transfer, treated as 64 Kbit until then
+    Kbit4,   // 6 bit addresses
+    Kbit64,  // 14 bit addresses
+}
+
+impl EepromSize {
+    fn address_bits(&self) -> u32 {
+        match self {
+            EepromSize::Kbit4 => 6,
+            _ => 14,
+        }
+    }
+
+    // DMA lengths games use: read request 2+addr+1, write request 2+addr+64+1.
+    pub fn from_dma_length(length: u32) -> Option<Self> {
+        match length {
+            9 | 73 => Some(EepromSize::Kbit4),
+            17 | 81 => Some(EepromSize::Kbit64),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum EepromState {
+    Idle,
+    Start, // Got the leading 1, next bit selects read (1) or write (0)
+    ReadAddress { address: u32, bits: u32 },
+    ReadTerminator { address: u32 },
+    WriteAddress { address: u32, bits: u32 },
+    WriteData { address: u32, data: u64, bits: u32 },
+    WriteTerminator { address: u32, data: u64 },
+}
+
+pub struct Eeprom {
+    size: EepromSize,
+    data: Vec<u8>,
+    state: EepromState,
+    read_address: u32,
+    read_bit: Option<u32>, // Position in the 68 bit read reply
+    busy_reads: u32,
+}
+
+impl Eeprom {
+    pub fn new(size: EepromSize) -> Self {
+        Eeprom {
+            size,
+            data: vec![0xFF; EEPROM_64K_SIZE],
+            state: EepromState::Idle,
+            read_address: 0,
+            read_bit: None,
+            busy_reads: 0,
+        }
+    }
+
+    pub fn size(&self) -> EepromSize {
+        self.size
+    }
+
+    // Only the first DMA transfer decides the size, later ones cannot change it.
+    pub fn detect_size(&mut self, dma_length: u32) {
+        if self.size == EepromSize::Unknown {
+            if let Some(size) = EepromSize::from_dma_length(dma_length) {
+                self.size = size;
+            }
+        }
+    }
+
+    // Block addresses count 64 bit units, 64 Kbit chips only decode the low 10 bits.
+    fn block_offset(&self, address: u32) -> usize {
+        let mask = match self.size {
+            EepromSize::Kbit4 => 0x3F,
+            _ => 0x3FF,
+        };
+        (address & mask) as usize * 8
+    }
+
+    pub fn write(&mut self, value: u16) {
+        let bit = (value & 1) as u32;
+        let address_bits = self.size.address_bits();
+        self.state = match self.state {
+            EepromState::Idle if bit == 1 => EepromState::Start,
+            EepromState::Idle => EepromState::Idle,
+            EepromState::Start if bit == 1 => EepromState::ReadAddress { address: 0, bits: 0 },
+            EepromState::Start => EepromState::WriteAddress { address: 0, bits: 0 },
+            EepromState::ReadAddress { address, bits } => {
+                let address = (address << 1) | bit;
+                if bits + 1 == address_bits {
+                    EepromState::ReadTerminator { address }
+                } else {
+                    EepromState::ReadAddress {
+                        address,
+                        bits: bits + 1,
+                    }
+                }
+            }
+            EepromState::ReadTerminator { address } => {
+                self.read_address = address;
+                self.read_bit = Some(0);
+                EepromState::Idle
+            }
+            EepromState::WriteAddress { address, bits } => {
+                let address = (address << 1) | bit;
+                if bits + 1 == address_bits {
+                    EepromState::WriteData {
+                        address,
+                        data: 0,
+                        bits: 0,
+                    }
+                } else {
+                    EepromState::WriteAddress {
+                        address,
+                        bits: bits + 1,
+                    }
+                }
+            }
+            EepromState::WriteData { address, data, bits } => {
+                let data = (data << 1) | bit as u64;
+                if bits + 1 == 64 {
+                    EepromState::WriteTerminator { address, data }
+                } else {
+                    EepromState::WriteData {
+                        address,
+                        data,
+                        bits: bits + 1,
+                    }
+                }
+            }
+            EepromState::WriteTerminator { address, data } => {
+                let offset = self.block_offset(address);
+                // First bit on the wire is the MSB of the first byte, same layout other emulators use
+                self.data[offset..offset + 8].copy_from_slice(&data.to_be_bytes());
+                self.busy_reads = WRITE_BUSY_READS;
+                EepromState::Idle
+            }
+        };
+    }
+
+    pub fn read(&mut self) -> u16 {
+        if let Some(bit) = self.read_bit {
+            // 4 dummy bits followed by 64 data bits, MSB first
+            let value = if bit < 4 {
+                0
+            } else {
+                let data_bit = bit - 4;
+                let byte = self.data[self.block_offset(self.read_address) + (data_bit / 8) as usize];
+                ((byte >> (7 - data_bit % 8)) & 1) as u16
+            };
+            self.read_bit = if bit + 1 == 68 { None } else { Some(bit + 1) };
+            return value;
+        }
+        if self.busy_reads > 0 {
+            self.busy_reads -= 1;
+            return 0;
+        }
+        1 // Ready
+    }
+
+    pub fn data(&self) -> &[u8] {
+        match self.size {
+            EepromSize::Kbit4 => &self.data[..EEPROM_4K_SIZE],
+            _ => &self.data,
+        }
+    }
+
+    // The file size settles the chip size when it is not known yet.
+    pub fn load(&mut self, bytes: &[u8]) {
+        if self.size == EepromSize::Unknown {
+            match bytes.len() {
+                EEPROM_4K_SIZE => self.size = EepromSize::Kbit4,
+                EEPROM_64K_SIZE => self.size = EepromSize::Kbit64,
+                _ => {}
+            }
+        }
+        let len = bytes.len().min(self.data.len());
+        self.data[..len].copy_from_slice(&bytes[..len]);
+    }
+}
+
+impl Snapshot for Eeprom {
+    // The size is part of the save type, see SaveMemory
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.data);
+        let (tag, address, bits, data) = match self.state {
+            EepromState::Idle => (0, 0, 0, 0),
+            EepromState::Start => (1, 0, 0, 0),
+            EepromState::ReadAddress { address, bits } => (2, address, bits, 0),
+            EepromState::ReadTerminator { address } => (3, address, 0, 0),
+            EepromState::WriteAddress { address, bits } => (4, address, bits, 0),
+            EepromState::WriteData { address, data, bits } => (5, address, bits, data),
+            EepromState::WriteTerminator { address, data } => (6, address, 0, data),
+        };
+        state.u8(tag);
+        state.u32(address);
+        state.u32(bits);
+        state.u64(data);
+        state.u32(self.read_address);
+        state.option_u32(self.read_bit);
+        state.u32(self.busy_reads);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        state.bytes_into(&mut self.data)?;
+        let (tag, address, bits, data) = (state.u8()?, state.u32()?, state.u32()?, state.u64()?);
+        self.state = match tag {
+            0 => EepromState::Idle,
+            1 => EepromState::Start,
+            2 => EepromState::ReadAddress { address, bits },
+            3 => EepromState::ReadTerminator { address },
+            4 => EepromState::WriteAddress { address, bits },
+            5 => EepromState::WriteData { address, data, bits },
+            6 => EepromState::WriteTerminator { address, data },
+            _ => return Err(SaveStateError::Corrupt("unknown EEPROM state")),
+        };
+        self.read_address = state.u32()?;
+        self.read_bit = state.option_u32()?;
+        self.busy_reads = state.u32()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+// src/cartridge/flash.rs
+
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+pub const FLASH_BANK_SIZE: usize = 64 * 1024;
+const SECTOR_SIZE: usize = 4 * 1024;
+const ATMEL_PAGE_SIZE: usize = 128;
+
+// Command addresses, relative to 0x0E000000
+const COMMAND_ADDR_1: u32 = 0x5555;
+const COMMAND_ADDR_2: u32 = 0x2AAA;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FlashChip {
+    // 64 KB chips
+    Sst,
+    Macronix64,
+    Panasonic,
+    Atmel,
+    // 128 KB chips, two switchable 64 KB banks
+    Macronix128,
+    Sanyo,
+}
+
+impl FlashChip {
+    pub fn manufacturer_id(&self) -> u8 {
+        match self {
+            FlashChip::Sst => 0xBF,
+            FlashChip::Macronix64 | FlashChip::Macronix128 => 0xC2,
+            FlashChip::Panasonic => 0x32,
+            FlashChip::Atmel => 0x1F,
+            FlashChip::Sanyo => 0x62,
+        }
+    }
+
+    pub fn device_id(&self) -> u8 {
+        match self {
+            FlashChip::Sst => 0xD4,
+            FlashChip::Macronix64 => 0x1C,
+            FlashChip::Panasonic => 0x1B,
+            FlashChip::Atmel => 0x3D,
+            FlashChip::Macronix128 => 0x09,
+            FlashChip::Sanyo => 0x13,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            FlashChip::Macronix128 | FlashChip::Sanyo => 2 * FLASH_BANK_SIZE,
+            _ => FLASH_BANK_SIZE,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum FlashState {
+    Ready,
+    Unlock1, // Got 0xAA at 0x5555
+    Unlock2, // Got 0x55 at 0x2AAA, next write to 0x5555 is the command
+    EraseReady, // Got 0x80, waiting for a second unlock sequence
+    EraseUnlock1,
+    EraseUnlock2,
+    ProgramByte,
+    ProgramPage { written: usize }, // Atmel programs whole 128 byte pages
+    BankSwitch,
+}
+
+pub struct Flash {
+    chip: FlashChip,
+    data: Vec<u8>,
+    state: FlashState,
+    id_mode: bool,
+    bank: usize,
+}
+
+impl Flash {
+    pub fn new(chip: FlashChip) -> Self {
+        Flash {
+            chip,
+            data: vec![0xFF; chip.size()],
+            state: FlashState::Ready,
+            id_mode: false,
+            bank: 0,
+        }
+    }
+
+    pub fn chip(&self) -> FlashChip {
+        self.chip
+    }
+
+    pub fn read(&self, offset: u32) -> u8 {
+        let offset = offset & 0xFFFF;
+        if self.id_mode {
+            match offset {
+                0 => return self.chip.manufacturer_id(),
+                1 => return self.chip.device_id(),
+                _ => {}
+            }
+        }
+        self.data[self.bank * FLASH_BANK_SIZE + offset as usize]
+    }
+
+    pub fn write(&mut self, offset: u32, value: u8) {
+        let offset = offset & 0xFFFF;
+        self.state = match (self.state, offset, value) {
+            (FlashState::ProgramByte, _, _) => {
+                // Programming can only clear bits, an erase is needed to set them again
+                let index = self.bank * FLASH_BANK_SIZE + offset as usize;
+                self.data[index] &= value;
+                FlashState::Ready
+            }
+            (FlashState::ProgramPage { written }, _, _) => {
+                let index = self.bank * FLASH_BANK_SIZE + offset as usize;
+                self.data[index] = value;
+                if written + 1 == ATMEL_PAGE_SIZE {
+                    FlashState::Ready
+                } else {
+                    FlashState::ProgramPage {
+                        written: written + 1,
+                    }
+                }
+            }
+            (FlashState::BankSwitch, 0, _) => {
+                if self.chip.size() > FLASH_BANK_SIZE {
+                    self.bank = (value & 1) as usize;
+                }
+                FlashState::Ready
+            }
+            (FlashState::Ready, COMMAND_ADDR_1, 0xAA) => FlashState::Unlock1,
+            (FlashState::Unlock1, COMMAND_ADDR_2, 0x55) => FlashState::Unlock2,
+            (FlashState::Unlock2, COMMAND_ADDR_1, command) => self.command(command),
+            (FlashState::EraseReady, COMMAND_ADDR_1, 0xAA) => FlashState::EraseUnlock1,
+            (FlashState::EraseUnlock1, COMMAND_ADDR_2, 0x55) => FlashState::EraseUnlock2,
+            (FlashState::EraseUnlock2, COMMAND_ADDR_1, 0x10) => {
+                self.data.fill(0xFF);
+                FlashState::Ready
+            }
+            (FlashState::EraseUnlock2, sector, 0x30) => {
+                let start = self.bank * FLASH_BANK_SIZE + (sector as usize & !(SECTOR_SIZE - 1));
+                self.data[start..start + SECTOR_SIZE].fill(0xFF);
+                FlashState::Ready
+            }
+            // Anything unexpected aborts the command sequence.
+            _ => FlashState::Ready,
+        };
+    }
+
+    fn command(&mut self, command: u8) -> FlashState {
+        match command {
+            0x90 => {
+                self.id_mode = true;
+                FlashState::Ready
+            }
+            0xF0 => {
+                self.id_mode = false;
+                FlashState::Ready
+            }
+            0x80 => FlashState::EraseReady,
+            0xA0 if self.chip == FlashChip::Atmel => FlashState::ProgramPage { written: 0 },
+            0xA0 => FlashState::ProgramByte,
+            0xB0 => FlashState::BankSwitch,
+            _ => FlashState::Ready,
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn load(&mut self, bytes: &[u8]) {
+        let len = bytes.len().min(self.data.len());
+        self.data[..len].copy_from_slice(&bytes[..len]);
+    }
+}
+
+impl Snapshot for Flash {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.data);
+        let (tag, written) = match self.state {
+            FlashState::Ready => (0, 0),
+            FlashState::Unlock1 => (1, 0),
+            FlashState::Unlock2 => (2, 0),
+            FlashState::EraseReady => (3, 0),
+            FlashState::EraseUnlock1 => (4, 0),
+            FlashState::EraseUnlock2 => (5, 0),
+            FlashState::ProgramByte => (6, 0),
+            FlashState::ProgramPage { written } => (7, written),
+            FlashState::BankSwitch => (8, 0),
+        };
+        state.u8(tag);
+        state.u32(written as u32);
+        state.bool(self.id_mode);
+        state.u8(self.bank as u8);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        state.bytes_into(&mut self.data)?;
+        let (tag, written) = (state.u8()?, state.u32()? as usize);
+        self.state = match tag {
+            0 => FlashState::Ready,
+            1 => FlashState::Unlock1,
+            2 => FlashState::Unlock2,
+            3 => FlashState::EraseReady,
+            4 => FlashState::EraseUnlock1,
+            5 => FlashState::EraseUnlock2,
+            6 => FlashState::ProgramByte,
+            7 if written < ATMEL_PAGE_SIZE => FlashState::ProgramPage { written },
+            8 => FlashState::BankSwitch,
+            _ => return Err(SaveStateError::Corrupt("unknown flash state")),
+        };
+        self.id_mode = state.bool()?;
+        self.bank = state.u8()? as usize;
+        if self.bank * FLASH_BANK_SIZE >= self.data.len() {
+            return Err(SaveStateError::Corrupt("flash bank out of range"));
+        }
+        Ok(())
+    }
+}
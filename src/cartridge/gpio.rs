@@ -0,0 +1,135 @@
+// src/cartridge/gpio.rs
+// 4 bit general purpose I/O port some cartridges map over the ROM at 0x080000C4-0x080000C9.
+
+use crate::cartridge::load_device;
+use crate::cartridge::rtc::Rtc;
+use crate::cartridge::sensors::{GyroSensor, Rumble, SolarSensor};
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+pub const GPIO_DATA: u32 = 0xC4;
+pub const GPIO_DIRECTION: u32 = 0xC6;
+pub const GPIO_CONTROL: u32 = 0xC8;
+
+pub struct Gpio {
+    pins: u8,      // Last value written by the GBA
+    direction: u8, // 1 = pin driven by the GBA, 0 = driven by the cartridge
+    readable: bool, // With control bit 0 clear the registers read back as ROM
+    pub rtc: Option<Rtc>,
+    pub solar: Option<SolarSensor>,
+    pub gyro: Option<GyroSensor>,
+    pub rumble: Option<Rumble>,
+}
+
+impl Gpio {
+    pub fn new() -> Self {
+        Gpio {
+            pins: 0,
+            direction: 0,
+            readable: false,
+            rtc: None,
+            solar: None,
+            gyro: None,
+            rumble: None,
+        }
+    }
+
+    pub fn is_register(offset: u32) -> bool {
+        (GPIO_DATA..GPIO_CONTROL + 2).contains(&offset)
+    }
+
+    pub fn is_readable(&self) -> bool {
+        self.readable
+    }
+
+    // Pins the devices drive back towards the GBA.
+    fn device_pins(&self) -> u8 {
+        let mut pins = 0;
+        if let Some(rtc) = &self.rtc {
+            pins |= rtc.read_pins();
+        }
+        if let Some(solar) = &self.solar {
+            pins |= solar.read_pins();
+        }
+        if let Some(gyro) = &self.gyro {
+            pins |= gyro.read_pins();
+        }
+        pins
+    }
+
+    // Offset relative to the start of the ROM. Only the low byte of each register exists.
+    pub fn read(&self, offset: u32) -> u8 {
+        match offset {
+            GPIO_DATA => {
+                ((self.pins & self.direction) | (self.device_pins() & !self.direction)) & 0xF
+            }
+            GPIO_DIRECTION => self.direction,
+            GPIO_CONTROL => self.readable as u8,
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, offset: u32, value: u8) {
+        match offset {
+            GPIO_DATA => {
+                // Input pins keep whatever the device drives
+                self.pins = (self.pins & !self.direction) | (value & self.direction & 0xF);
+                let pins = self.pins;
+                if let Some(rtc) = &mut self.rtc {
+                    rtc.write_pins(pins);
+                }
+                if let Some(solar) = &mut self.solar {
+                    solar.write_pins(pins);
+                }
+                if let Some(gyro) = &mut self.gyro {
+                    gyro.write_pins(pins);
+                }
+                if let Some(rumble) = &mut self.rumble {
+                    rumble.write_pins(pins);
+                }
+            }
+            GPIO_DIRECTION => self.direction = value & 0xF,
+            GPIO_CONTROL => self.readable = value & 1 == 1,
+            _ => {}
+        }
+    }
+}
+
+impl Default for Gpio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Snapshot for Gpio {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.pins);
+        state.u8(self.direction);
+        state.bool(self.readable);
+        state.bool(self.rtc.is_some());
+        if let Some(rtc) = &self.rtc {
+            rtc.save_state(state);
+        }
+        state.bool(self.solar.is_some());
+        if let Some(solar) = &self.solar {
+            solar.save_state(state);
+        }
+        state.bool(self.gyro.is_some());
+        if let Some(gyro) = &self.gyro {
+            gyro.save_state(state);
+        }
+        state.bool(self.rumble.is_some());
+        if let Some(rumble) = &self.rumble {
+            rumble.save_state(state);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.pins = state.u8()?;
+        self.direction = state.u8()?;
+        self.readable = state.bool()?;
+        load_device(&mut self.rtc, state)?;
+        load_device(&mut self.solar, state)?;
+        load_device(&mut self.gyro, state)?;
+        load_device(&mut self.rumble, state)
+    }
+}
@@ -0,0 +1,98 @@
+// src/cartridge/header.rs
+
+use crate::cartridge::CartridgeError;
+
+pub const HEADER_SIZE: usize = 0xC0;
+pub const LOGO_SIZE: usize = 156;
+const FIXED_VALUE: u8 = 0x96; // Byte at 0xB2 has to be 0x96 on every licensed cart
+
+// Offsets inside the 192 byte header (GBATEK "GBA Cartridge Header")
+const ENTRY_POINT: usize = 0x00;
+const LOGO: usize = 0x04;
+const TITLE: usize = 0xA0;
+const GAME_CODE: usize = 0xAC;
+const MAKER_CODE: usize = 0xB0;
+const FIXED: usize = 0xB2;
+const UNIT_CODE: usize = 0xB3;
+const DEVICE_TYPE: usize = 0xB4;
+const VERSION: usize = 0xBC;
+const COMPLEMENT: usize = 0xBD;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CartridgeHeader {
+    pub entry_point: u32, // Usually a "B" instruction jumping over the header
+    pub logo: [u8; LOGO_SIZE],
+    pub title: String,
+    pub game_code: String,
+    pub maker_code: String,
+    pub unit_code: u8,
+    pub device_type: u8,
+    pub version: u8,
+    pub complement_check: u8,
+}
+
+impl CartridgeHeader {
+    /// Parses the first 192 bytes of a ROM image and checks the complement byte.
+    pub fn parse(rom: &[u8]) -> Result<Self, CartridgeError> {
+        if rom.len() < HEADER_SIZE {
+            return Err(CartridgeError::Truncated { size: rom.len() });
+        }
+        if rom[FIXED] != FIXED_VALUE {
+            return Err(CartridgeError::InvalidFixedValue(rom[FIXED]));
+        }
+        let computed = Self::compute_complement(rom);
+        if computed != rom[COMPLEMENT] {
+            return Err(CartridgeError::ChecksumMismatch {
+                expected: computed,
+                found: rom[COMPLEMENT],
+            });
+        }
+
+        let mut logo = [0u8; LOGO_SIZE];
+        logo.copy_from_slice(&rom[LOGO..LOGO + LOGO_SIZE]);
+        Ok(CartridgeHeader {
+            entry_point: u32::from_le_bytes([
+                rom[ENTRY_POINT],
+                rom[ENTRY_POINT + 1],
+                rom[ENTRY_POINT + 2],
+                rom[ENTRY_POINT + 3],
+            ]),
+            logo,
+            title: ascii_field(&rom[TITLE..TITLE + 12])?,
+            game_code: ascii_field(&rom[GAME_CODE..GAME_CODE + 4])?,
+            maker_code: ascii_field(&rom[MAKER_CODE..MAKER_CODE + 2])?,
+            unit_code: rom[UNIT_CODE],
+            device_type: rom[DEVICE_TYPE],
+            version: rom[VERSION],
+            complement_check: rom[COMPLEMENT],
+        })
+    }
+
+    // Complement check over 0xA0..=0xBC, same formula the BIOS uses during boot.
+    pub fn compute_complement(rom: &[u8]) -> u8 {
+        let mut chk: u8 = 0;
+        for byte in &rom[TITLE..=VERSION] {
+            chk = chk.wrapping_sub(*byte);
+        }
+        chk.wrapping_sub(0x19)
+    }
+
+    /// Target address of the branch at 0x08000000, if the entry point is a plain ARM `B`.
+    pub fn entry_address(&self) -> Option<u32> {
+        if (self.entry_point & 0x0F00_0000) != 0x0A00_0000 {
+            return None;
+        }
+        let offset = ((self.entry_point << 8) as i32) >> 6; // sign extend imm24 and multiply by 4
+        Some(0x0800_0008u32.wrapping_add(offset as u32))
+    }
+}
+
+// Title/codes are uppercase ASCII padded with zeros.
+fn ascii_field(bytes: &[u8]) -> Result<String, CartridgeError> {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    let field = &bytes[..end];
+    if !field.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        return Err(CartridgeError::InvalidHeaderText);
+    }
+    Ok(String::from_utf8_lossy(field).into_owned())
+}
@@ -0,0 +1,404 @@
+pub mod detect;
+pub mod eeprom;
+pub mod flash;
+pub mod gpio;
+pub mod header;
+pub mod rtc;
+pub mod save;
+pub mod sensors;
+pub mod sram;
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cartridge::detect::{detect_hardware, detect_save_type, CartridgeHardware};
+use crate::cartridge::gpio::Gpio;
+use crate::cartridge::header::{CartridgeHeader, HEADER_SIZE};
+use crate::cartridge::rtc::{Clock, HostClock, Rtc};
+use crate::cartridge::save::{SaveMemory, SaveType};
+use crate::cartridge::sensors::{GyroSensor, Rumble, SolarSensor, TiltSensor};
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+pub const MAX_ROM_SIZE: usize = 32 * 1024 * 1024; // 32 MB, the whole 0x08000000-0x09FFFFFF window
+// Frames without save writes before a dirty save gets flushed, so a game saving
+// in several steps is written out once it is done.
+pub const AUTOSAVE_DELAY_FRAMES: u32 = 60;
+
+#[derive(Debug)]
+pub enum CartridgeError {
+    Io(std::io::Error),
+    Truncated { size: usize }, // Smaller than the 192 byte header
+    TooLarge { size: usize },
+    InvalidFixedValue(u8),
+    InvalidHeaderText,
+    ChecksumMismatch { expected: u8, found: u8 },
+    InvalidRtcState, // The .rtc file next to the save is truncated or not ours
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CartridgeError::Io(err) => write!(f, "could not read ROM file: {}", err),
+            CartridgeError::Truncated { size } => write!(
+                f,
+                "ROM is truncated: {} bytes, header alone needs {}",
+                size, HEADER_SIZE
+            ),
+            CartridgeError::TooLarge { size } => write!(
+                f,
+                "ROM is too large: {} bytes, maximum is {}",
+                size, MAX_ROM_SIZE
+            ),
+            CartridgeError::InvalidFixedValue(value) => {
+                write!(f, "header byte 0xB2 is 0x{:02X}, expected 0x96", value)
+            }
+            CartridgeError::InvalidHeaderText => {
+                write!(f, "header title or codes contain non-ASCII bytes")
+            }
+            CartridgeError::ChecksumMismatch { expected, found } => write!(
+                f,
+                "header complement check mismatch: computed 0x{:02X}, found 0x{:02X}",
+                expected, found
+            ),
+            CartridgeError::InvalidRtcState => write!(f, "RTC state file is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {}
+
+impl From<std::io::Error> for CartridgeError {
+    fn from(err: std::io::Error) -> Self {
+        CartridgeError::Io(err)
+    }
+}
+
+pub struct Cartridge {
+    pub header: CartridgeHeader,
+    rom: Vec<u8>,
+    save: SaveMemory,
+    gpio: Option<Gpio>,
+    tilt: Option<TiltSensor>,
+    save_path: Option<PathBuf>,
+    save_dirty: bool,
+    frames_since_save_write: u32,
+}
+
+impl Cartridge {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, CartridgeError> {
+        let data = fs::read(path)?;
+        Self::from_bytes(data)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, CartridgeError> {
+        if data.len() > MAX_ROM_SIZE {
+            return Err(CartridgeError::TooLarge { size: data.len() });
+        }
+        let header = CartridgeHeader::parse(&data)?;
+        let save_type = detect_save_type(&data, &header.game_code);
+        let hardware = detect_hardware(&data, &header.game_code);
+        let mut cartridge = Cartridge {
+            header,
+            rom: data,
+            save: SaveMemory::new(save_type),
+            gpio: None,
+            tilt: None,
+            save_path: None,
+            save_dirty: false,
+            frames_since_save_write: 0,
+        };
+        cartridge.set_hardware(hardware);
+        Ok(cartridge)
+    }
+
+    /// Replaces the extra devices, e.g. for a game missing from the detection table.
+    pub fn set_hardware(&mut self, hardware: CartridgeHardware) {
+        self.tilt = hardware.tilt.then(TiltSensor::new);
+        if !hardware.uses_gpio() {
+            self.gpio = None;
+            return;
+        }
+        let mut gpio = Gpio::new();
+        if hardware.rtc {
+            gpio.rtc = Some(Rtc::new(Box::new(HostClock)));
+        }
+        gpio.solar = hardware.solar_sensor.then(SolarSensor::new);
+        gpio.gyro = hardware.gyro.then(GyroSensor::new);
+        gpio.rumble = hardware.rumble.then(Rumble::new);
+        self.gpio = Some(gpio);
+    }
+
+    pub fn hardware(&self) -> CartridgeHardware {
+        let gpio = self.gpio.as_ref();
+        CartridgeHardware {
+            rtc: gpio.is_some_and(|gpio| gpio.rtc.is_some()),
+            solar_sensor: gpio.is_some_and(|gpio| gpio.solar.is_some()),
+            tilt: self.tilt.is_some(),
+            gyro: gpio.is_some_and(|gpio| gpio.gyro.is_some()),
+            rumble: gpio.is_some_and(|gpio| gpio.rumble.is_some()),
+        }
+    }
+
+    pub fn gpio(&self) -> Option<&Gpio> {
+        self.gpio.as_ref()
+    }
+
+    pub fn gpio_mut(&mut self) -> Option<&mut Gpio> {
+        self.gpio.as_mut()
+    }
+
+    pub fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        self.gpio.as_mut().and_then(|gpio| gpio.rtc.as_mut())
+    }
+
+    // Drives the RTC from another clock, e.g. a FakeClock for reproducible tests.
+    pub fn set_rtc_clock(&mut self, clock: Box<dyn Clock>) {
+        if let Some(rtc) = self.rtc_mut() {
+            rtc.set_clock(clock);
+        }
+    }
+
+    // Host side of the sensors. Setters do nothing when the cartridge lacks the device.
+
+    /// Light reaching the solar sensor, 0 for darkness up to 0xFF for direct sunlight.
+    pub fn set_light_level(&mut self, level: u8) {
+        if let Some(solar) = self.gpio.as_mut().and_then(|gpio| gpio.solar.as_mut()) {
+            solar.set_light_level(level);
+        }
+    }
+
+    /// Tilt on both axes, as an offset from holding the console level.
+    pub fn set_tilt(&mut self, x: i16, y: i16) {
+        if let Some(tilt) = &mut self.tilt {
+            tilt.set_tilt(x, y);
+        }
+    }
+
+    /// Rotation rate around the axis through the screen, positive for clockwise.
+    pub fn set_gyro_rate(&mut self, rate: i16) {
+        if let Some(gyro) = self.gpio.as_mut().and_then(|gpio| gpio.gyro.as_mut()) {
+            gyro.set_rate(rate);
+        }
+    }
+
+    pub fn rumble_active(&self) -> bool {
+        self.gpio
+            .as_ref()
+            .and_then(|gpio| gpio.rumble.as_ref())
+            .is_some_and(|rumble| rumble.is_active())
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    pub fn rom_size(&self) -> usize {
+        self.rom.len()
+    }
+
+    // Offset is relative to the start of the ROM (0x08000000 on the bus).
+    // Reading past the end of a ROM returns the open bus pattern: the halfword address itself.
+    pub fn read_rom_byte(&self, offset: u32) -> u8 {
+        if let Some(gpio) = &self.gpio {
+            if gpio.is_readable() && Gpio::is_register(offset) {
+                return gpio.read(offset);
+            }
+        }
+        match self.rom.get(offset as usize) {
+            Some(byte) => *byte,
+            None => {
+                let halfword = (offset >> 1) as u16;
+                if offset & 1 == 0 {
+                    halfword as u8
+                } else {
+                    (halfword >> 8) as u8
+                }
+            }
+        }
+    }
+
+    // ROM itself is read only, only the GPIO registers inside it take writes.
+    pub fn write_rom_byte(&mut self, offset: u32, value: u8) {
+        if let Some(gpio) = &mut self.gpio {
+            if Gpio::is_register(offset) {
+                gpio.write(offset, value);
+                if gpio.rtc.as_mut().is_some_and(|rtc| rtc.take_changed()) {
+                    self.save_dirty = true;
+                    self.frames_since_save_write = 0;
+                }
+            }
+        }
+    }
+
+    pub fn save_type(&self) -> SaveType {
+        self.save.save_type()
+    }
+
+    /// Forces a save chip instead of the detected one. Contents of the previous chip are
+    /// carried over where they fit.
+    pub fn set_save_type(&mut self, save_type: SaveType) {
+        let mut save = SaveMemory::new(save_type);
+        save.load(self.save.data());
+        self.save = save;
+    }
+
+    pub fn save_data(&self) -> &[u8] {
+        self.save.data()
+    }
+
+    /// Replaces the save contents, as loading a .sav file would.
+    pub fn load_save_data(&mut self, bytes: &[u8]) {
+        self.save.load(bytes);
+    }
+
+    // Offset is relative to 0x0E000000. The tilt sensor shares the region with the save chip.
+    pub fn read_save(&self, offset: u32) -> u8 {
+        if let Some(tilt) = &self.tilt {
+            if TiltSensor::is_register(offset) {
+                return tilt.read(offset);
+            }
+        }
+        self.save.read(offset)
+    }
+
+    pub fn write_save(&mut self, offset: u32, value: u8) {
+        if let Some(tilt) = &mut self.tilt {
+            if TiltSensor::is_register(offset) {
+                tilt.write(offset, value);
+                return;
+            }
+        }
+        self.save.write(offset, value);
+        self.save_dirty = true;
+        self.frames_since_save_write = 0;
+    }
+
+    // EEPROM carts answer in 0x0D000000-0x0DFFFFFF, or only in the last 256 bytes of it
+    // when the ROM itself is bigger than 16 MB.
+    pub fn is_eeprom_address(&self, address: u32) -> bool {
+        if !matches!(self.save, SaveMemory::Eeprom(_)) || address >> 24 != 0x0D {
+            return false;
+        }
+        self.rom.len() <= 16 * 1024 * 1024 || address >= 0x0DFF_FF00
+    }
+
+    pub fn read_eeprom(&mut self) -> u16 {
+        match &mut self.save {
+            SaveMemory::Eeprom(eeprom) => eeprom.read(),
+            _ => 0,
+        }
+    }
+
+    pub fn write_eeprom(&mut self, value: u16) {
+        if let SaveMemory::Eeprom(eeprom) = &mut self.save {
+            eeprom.write(value);
+            self.save_dirty = true;
+            self.frames_since_save_write = 0;
+        }
+    }
+
+    // Called when DMA3 starts a transfer to or from the EEPROM with `length` units.
+    pub fn detect_eeprom_size(&mut self, length: u32) {
+        if let SaveMemory::Eeprom(eeprom) = &mut self.save {
+            eeprom.detect_size(length);
+        }
+    }
+
+    /// Uses `path` as the .sav file of this cartridge, loading it if it already exists.
+    /// RTC state, if the cartridge has a clock, lives in the matching .rtc file.
+    pub fn attach_save_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), CartridgeError> {
+        let path = path.as_ref().to_path_buf();
+        if path.exists() {
+            let bytes = fs::read(&path)?;
+            self.save.load(&bytes);
+        }
+        let rtc_path = path.with_extension("rtc");
+        if let Some(rtc) = self.rtc_mut() {
+            if rtc_path.exists() && !rtc.load_state(&fs::read(&rtc_path)?) {
+                return Err(CartridgeError::InvalidRtcState);
+            }
+        }
+        self.save_path = Some(path);
+        self.save_dirty = false;
+        Ok(())
+    }
+
+    // Writes the save out if it changed since the last flush.
+    pub fn flush_save(&mut self) -> Result<(), CartridgeError> {
+        if !self.save_dirty {
+            return Ok(());
+        }
+        if let Some(path) = &self.save_path {
+            if !self.save.data().is_empty() {
+                fs::write(path, self.save.data())?;
+            }
+            // The RTC state goes next to the save, as <name>.rtc
+            if let Some(rtc) = self.gpio.as_ref().and_then(|gpio| gpio.rtc.as_ref()) {
+                fs::write(path.with_extension("rtc"), rtc.state())?;
+            }
+        }
+        self.save_dirty = false;
+        Ok(())
+    }
+
+    /// Called once per frame, flushes the save after it has been idle for `AUTOSAVE_DELAY_FRAMES`.
+    pub fn autosave_tick(&mut self) -> Result<(), CartridgeError> {
+        if !self.save_dirty {
+            return Ok(());
+        }
+        self.frames_since_save_write += 1;
+        if self.frames_since_save_write >= AUTOSAVE_DELAY_FRAMES {
+            self.flush_save()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Cartridge {
+    // Last chance to persist the save when the emulator shuts down.
+    fn drop(&mut self) {
+        if let Err(err) = self.flush_save() {
+            eprintln!("Failed to write save file: {}", err);
+        }
+    }
+}
+
+// The ROM is left out, a state is only loaded on top of the same one. So is where the save
+// file lives.
+impl Snapshot for Cartridge {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.save.save_state(state);
+        state.bool(self.gpio.is_some());
+        if let Some(gpio) = &self.gpio {
+            gpio.save_state(state);
+        }
+        state.bool(self.tilt.is_some());
+        if let Some(tilt) = &self.tilt {
+            tilt.save_state(state);
+        }
+        state.bool(self.save_dirty);
+        state.u32(self.frames_since_save_write);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.save.load_state(state)?;
+        load_device(&mut self.gpio, state)?;
+        load_device(&mut self.tilt, state)?;
+        self.save_dirty = state.bool()?;
+        self.frames_since_save_write = state.u32()?;
+        Ok(())
+    }
+}
+
+// Optional cartridge hardware, which has to match what this cartridge has.
+pub(crate) fn load_device<T: Snapshot>(
+    device: &mut Option<T>,
+    state: &mut StateReader,
+) -> Result<(), SaveStateError> {
+    match (state.bool()?, device) {
+        (true, Some(device)) => device.load_state(state),
+        (false, None) => Ok(()),
+        _ => Err(SaveStateError::Corrupt("cartridge hardware differs")),
+    }
+}
@@ -0,0 +1,431 @@
+// src/cartridge/rtc.rs
+// Seiko S-3511 real-time clock, wired to GPIO pins 0 (SCK), 1 (SIO) and 2 (CS).
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+const PIN_SCK: u8 = 1 << 0;
+const PIN_SIO: u8 = 1 << 1;
+const PIN_CS: u8 = 1 << 2;
+
+const COMMAND_MAGIC: u8 = 0x6; // Low nibble of every command byte, as received LSB first
+
+// Command numbers and how many parameter bytes follow each of them.
+const COMMAND_RESET: u8 = 0;
+const COMMAND_ALARM: u8 = 1;
+const COMMAND_DATETIME: u8 = 2;
+const COMMAND_FORCE_IRQ: u8 = 3;
+const COMMAND_CONTROL: u8 = 4;
+const COMMAND_TIME: u8 = 6;
+const COMMAND_BYTES: [usize; 8] = [0, 2, 7, 0, 1, 0, 3, 0];
+
+const CONTROL_24_HOUR: u8 = 1 << 6;
+pub const RTC_STATE_SIZE: usize = 16;
+const RTC_STATE_MAGIC: &[u8; 4] = b"RTC1";
+
+// Source of wall clock time, in seconds since the Unix epoch. Send, so a whole emulator can
+// be moved to another thread.
+pub trait Clock: Send {
+    fn now(&self) -> u64;
+}
+
+// Host time in UTC.
+pub struct HostClock;
+
+impl Clock for HostClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+/// Clock that only moves when told to, for reproducible runs. Clones share the same time.
+#[derive(Clone)]
+pub struct FakeClock {
+    seconds: Arc<AtomicU64>,
+}
+
+impl FakeClock {
+    pub fn new(seconds: u64) -> Self {
+        FakeClock {
+            seconds: Arc::new(AtomicU64::new(seconds)),
+        }
+    }
+
+    pub fn set(&self, seconds: u64) {
+        self.seconds.store(seconds, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, seconds: u64) {
+        self.seconds.fetch_add(seconds, Ordering::SeqCst);
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> u64 {
+        self.seconds.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DateTime {
+    pub year: u32, // Full year, the RTC stores 2000-2099
+    pub month: u32,
+    pub day: u32,
+    pub weekday: u32, // 0 = Sunday
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+// Days since 1970-01-01 to a civil date (Howard Hinnant's algorithm).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+impl DateTime {
+    pub fn from_unix(seconds: i64) -> Self {
+        let days = seconds.div_euclid(86400);
+        let time = seconds.rem_euclid(86400) as u32;
+        let (year, month, day) = civil_from_days(days);
+        DateTime {
+            year: year as u32,
+            month,
+            day,
+            weekday: (days + 4).rem_euclid(7) as u32, // 1970-01-01 was a Thursday
+            hour: time / 3600,
+            minute: time / 60 % 60,
+            second: time % 60,
+        }
+    }
+
+    pub fn to_unix(&self) -> i64 {
+        days_from_civil(self.year as i64, self.month, self.day) * 86400
+            + (self.hour * 3600 + self.minute * 60 + self.second) as i64
+    }
+}
+
+fn to_bcd(value: u32) -> u8 {
+    (((value / 10) << 4) | (value % 10)) as u8
+}
+
+fn from_bcd(value: u8) -> u32 {
+    ((value >> 4) as u32) * 10 + (value & 0xF) as u32
+}
+
+pub struct Rtc {
+    clock: Box<dyn Clock>,
+    offset: i64, // Seconds between the game's idea of time and the clock
+    control: u8,
+    alarm: [u8; 2],
+    // Serial transfer state
+    transfer_started: bool,
+    last_sck: bool,
+    bits: u8,
+    bits_done: u32,
+    command: Option<u8>, // Raw command byte while parameters are being transferred
+    bytes_remaining: usize,
+    buffer: [u8; 7],
+    output: u8, // Value driven on SIO while the game reads
+    changed: bool,
+}
+
+impl Rtc {
+    pub fn new(clock: Box<dyn Clock>) -> Self {
+        Rtc {
+            clock,
+            offset: 0,
+            control: CONTROL_24_HOUR,
+            alarm: [0; 2],
+            transfer_started: false,
+            last_sck: false,
+            bits: 0,
+            bits_done: 0,
+            command: None,
+            bytes_remaining: 0,
+            buffer: [0; 7],
+            output: 0,
+            changed: false,
+        }
+    }
+
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    pub fn now(&self) -> DateTime {
+        DateTime::from_unix(self.clock.now() as i64 + self.offset)
+    }
+
+    pub fn control(&self) -> u8 {
+        self.control
+    }
+
+    // Returns true once after the game changed persistent state (control, time or alarm).
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::replace(&mut self.changed, false)
+    }
+
+    fn encode_datetime(&self) -> [u8; 7] {
+        let now = self.now();
+        let hour = if self.control & CONTROL_24_HOUR != 0 {
+            now.hour
+        } else {
+            now.hour % 12
+        };
+        let pm_flag = if now.hour >= 12 { 0x80 } else { 0 };
+        [
+            to_bcd(now.year % 100),
+            to_bcd(now.month),
+            to_bcd(now.day),
+            to_bcd(now.weekday),
+            to_bcd(hour) | pm_flag,
+            to_bcd(now.minute),
+            to_bcd(now.second),
+        ]
+    }
+
+    // A date/time write moves the game's clock, the host clock stays untouched.
+    fn apply_datetime(&mut self, bytes: &[u8; 7]) {
+        let current = self.now();
+        let mut hour = from_bcd(bytes[4] & 0x3F);
+        if self.control & CONTROL_24_HOUR == 0 && bytes[4] & 0x80 != 0 {
+            hour += 12;
+        }
+        let target = DateTime {
+            year: 2000 + from_bcd(bytes[0]),
+            month: from_bcd(bytes[1]).clamp(1, 12),
+            day: from_bcd(bytes[2]).clamp(1, 31),
+            weekday: 0,
+            hour,
+            minute: from_bcd(bytes[5]),
+            second: from_bcd(bytes[6]),
+        };
+        self.offset += target.to_unix() - current.to_unix();
+        self.changed = true;
+    }
+
+    // Writing only the time keeps today's date.
+    fn apply_time(&mut self, bytes: &[u8]) {
+        let mut datetime = self.encode_datetime();
+        datetime[4..7].copy_from_slice(bytes);
+        self.apply_datetime(&datetime);
+    }
+
+    fn start_command(&mut self, byte: u8) {
+        if byte & 0xF != COMMAND_MAGIC {
+            return; // Not a valid command, ignored like on hardware
+        }
+        let command = (byte >> 4) & 0x7;
+        let reading = byte & 0x80 != 0;
+        match command {
+            COMMAND_RESET => {
+                self.control = 0;
+                self.alarm = [0; 2];
+                self.offset = 0;
+                self.changed = true;
+            }
+            COMMAND_DATETIME | COMMAND_TIME if reading => {
+                let datetime = self.encode_datetime();
+                if command == COMMAND_DATETIME {
+                    self.buffer = datetime;
+                } else {
+                    self.buffer[..3].copy_from_slice(&datetime[4..7]);
+                }
+            }
+            COMMAND_CONTROL if reading => self.buffer[0] = self.control,
+            COMMAND_ALARM if reading => self.buffer[..2].copy_from_slice(&self.alarm),
+            COMMAND_FORCE_IRQ => {} // Would pulse the cartridge IRQ line
+            _ => {}
+        }
+        self.bytes_remaining = COMMAND_BYTES[command as usize];
+        if self.bytes_remaining > 0 {
+            self.command = Some(byte);
+        }
+    }
+
+    fn parameter_byte(&mut self, command_byte: u8, value: u8) {
+        let command = (command_byte >> 4) & 0x7;
+        let index = COMMAND_BYTES[command as usize] - self.bytes_remaining;
+        self.buffer[index] = value;
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining > 0 {
+            return;
+        }
+        self.command = None;
+        match command {
+            COMMAND_CONTROL => {
+                self.control = value;
+                self.changed = true;
+            }
+            COMMAND_ALARM => {
+                self.alarm.copy_from_slice(&self.buffer[..2]);
+                self.changed = true;
+            }
+            COMMAND_DATETIME => {
+                let bytes = self.buffer;
+                self.apply_datetime(&bytes);
+            }
+            COMMAND_TIME => {
+                let bytes = [self.buffer[0], self.buffer[1], self.buffer[2]];
+                self.apply_time(&bytes);
+            }
+            _ => {}
+        }
+    }
+
+    fn is_reading(&self) -> bool {
+        matches!(self.command, Some(byte) if byte & 0x80 != 0)
+    }
+
+    /// Pins as driven by the GBA. Bits are clocked on the rising edge of SCK, LSB first.
+    pub fn write_pins(&mut self, pins: u8) {
+        if pins & PIN_CS == 0 {
+            // Chip select dropped, the transfer is over
+            self.transfer_started = false;
+            self.command = None;
+            self.bits = 0;
+            self.bits_done = 0;
+            return;
+        }
+        let sck = pins & PIN_SCK != 0;
+        if !self.transfer_started {
+            // CS rising while SCK is high starts a transfer, it does not clock a bit
+            self.transfer_started = true;
+            self.last_sck = sck;
+            self.bits = 0;
+            self.bits_done = 0;
+            return;
+        }
+        let rising_edge = sck && !self.last_sck;
+        self.last_sck = sck;
+        if !sck {
+            // Clock low: latch the data line, or present the next bit for a read
+            if self.is_reading() {
+                let index = COMMAND_BYTES[((self.command.unwrap_or(0) >> 4) & 0x7) as usize]
+                    - self.bytes_remaining;
+                self.output = (self.buffer[index] >> self.bits_done) & 1;
+            } else {
+                let bit = (pins & PIN_SIO) >> 1;
+                self.bits = (self.bits & !(1 << self.bits_done)) | (bit << self.bits_done);
+            }
+            return;
+        }
+        // Rising edge completes a bit
+        if !rising_edge {
+            return;
+        }
+        self.bits_done += 1;
+        if self.bits_done < 8 {
+            return;
+        }
+        self.bits_done = 0;
+        match self.command {
+            None => self.start_command(self.bits),
+            Some(byte) if byte & 0x80 != 0 => {
+                self.bytes_remaining -= 1;
+                if self.bytes_remaining == 0 {
+                    self.command = None;
+                }
+            }
+            Some(byte) => self.parameter_byte(byte, self.bits),
+        }
+        self.bits = 0;
+    }
+
+    // Value of the SIO pin as seen by the GBA.
+    pub fn read_pins(&self) -> u8 {
+        self.output << 1
+    }
+
+    // Persistent part of the chip: control, alarm and the offset to the host clock.
+    pub fn state(&self) -> [u8; RTC_STATE_SIZE] {
+        let mut state = [0u8; RTC_STATE_SIZE];
+        state[0..4].copy_from_slice(RTC_STATE_MAGIC);
+        state[4] = self.control;
+        state[5..7].copy_from_slice(&self.alarm);
+        state[8..16].copy_from_slice(&self.offset.to_le_bytes());
+        state
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> bool {
+        if state.len() != RTC_STATE_SIZE || &state[0..4] != RTC_STATE_MAGIC {
+            return false;
+        }
+        self.control = state[4];
+        self.alarm.copy_from_slice(&state[5..7]);
+        let mut offset = [0u8; 8];
+        offset.copy_from_slice(&state[8..16]);
+        self.offset = i64::from_le_bytes(offset);
+        true
+    }
+}
+
+// The clock source is the host's, the offset keeps the game's time where it was relative to it.
+impl Snapshot for Rtc {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.i64(self.offset);
+        state.u8(self.control);
+        state.u8(self.alarm[0]);
+        state.u8(self.alarm[1]);
+        state.bool(self.transfer_started);
+        state.bool(self.last_sck);
+        state.u8(self.bits);
+        state.u32(self.bits_done);
+        state.bool(self.command.is_some());
+        state.u8(self.command.unwrap_or(0));
+        state.u32(self.bytes_remaining as u32);
+        for byte in self.buffer {
+            state.u8(byte);
+        }
+        state.u8(self.output);
+        state.bool(self.changed);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.offset = state.i64()?;
+        self.control = state.u8()?;
+        self.alarm = [state.u8()?, state.u8()?];
+        self.transfer_started = state.bool()?;
+        self.last_sck = state.bool()?;
+        self.bits = state.u8()?;
+        self.bits_done = state.u32()?;
+        let has_command = state.bool()?;
+        let command = state.u8()?;
+        self.command = has_command.then_some(command);
+        self.bytes_remaining = state.u32()? as usize;
+        if self.bytes_remaining > self.buffer.len() {
+            return Err(SaveStateError::Corrupt("RTC transfer out of range"));
+        }
+        for byte in self.buffer.iter_mut() {
+            *byte = state.u8()?;
+        }
+        self.output = state.u8()?;
+        self.changed = state.bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+// src/cartridge/save.rs
+
+use crate::cartridge::eeprom::{Eeprom, EepromSize};
+use crate::cartridge::flash::{Flash, FlashChip};
+use crate::cartridge::sram::Sram;
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SaveType {
+    None,
+    Sram,
+    Flash(FlashChip),
+    Eeprom(EepromSize),
+}
+
+// The save chip itself. SRAM and Flash sit at 0x0E000000, EEPROM in the ROM region.
+pub enum SaveMemory {
+    None,
+    Sram(Sram),
+    Flash(Flash),
+    Eeprom(Eeprom), // Lives in the ROM region, not at 0x0E000000
+}
+
+impl SaveMemory {
+    pub fn new(save_type: SaveType) -> Self {
+        match save_type {
+            SaveType::None => SaveMemory::None,
+            SaveType::Sram => SaveMemory::Sram(Sram::new()),
+            SaveType::Flash(chip) => SaveMemory::Flash(Flash::new(chip)),
+            SaveType::Eeprom(size) => SaveMemory::Eeprom(Eeprom::new(size)),
+        }
+    }
+
+    pub fn save_type(&self) -> SaveType {
+        match self {
+            SaveMemory::None => SaveType::None,
+            SaveMemory::Sram(_) => SaveType::Sram,
+            SaveMemory::Flash(flash) => SaveType::Flash(flash.chip()),
+            SaveMemory::Eeprom(eeprom) => SaveType::Eeprom(eeprom.size()),
+        }
+    }
+
+    // Offset is relative to 0x0E000000. Without a chip the bus floats high.
+    pub fn read(&self, offset: u32) -> u8 {
+        match self {
+            SaveMemory::None | SaveMemory::Eeprom(_) => 0xFF,
+            SaveMemory::Sram(sram) => sram.read(offset),
+            SaveMemory::Flash(flash) => flash.read(offset),
+        }
+    }
+
+    pub fn write(&mut self, offset: u32, value: u8) {
+        match self {
+            SaveMemory::None | SaveMemory::Eeprom(_) => {}
+            SaveMemory::Sram(sram) => sram.write(offset, value),
+            SaveMemory::Flash(flash) => flash.write(offset, value),
+        }
+    }
+
+    // Raw contents in the usual .sav layout.
+    pub fn data(&self) -> &[u8] {
+        match self {
+            SaveMemory::None => &[],
+            SaveMemory::Sram(sram) => sram.data(),
+            SaveMemory::Flash(flash) => flash.data(),
+            SaveMemory::Eeprom(eeprom) => eeprom.data(),
+        }
+    }
+
+    pub fn load(&mut self, bytes: &[u8]) {
+        match self {
+            SaveMemory::None => {}
+            SaveMemory::Sram(sram) => sram.load(bytes),
+            SaveMemory::Flash(flash) => flash.load(bytes),
+            SaveMemory::Eeprom(eeprom) => eeprom.load(bytes),
+        }
+    }
+}
+
+const FLASH_CHIPS: [FlashChip; 6] = [
+    FlashChip::Sst,
+    FlashChip::Macronix64,
+    FlashChip::Panasonic,
+    FlashChip::Atmel,
+    FlashChip::Macronix128,
+    FlashChip::Sanyo,
+];
+const EEPROM_SIZES: [EepromSize; 3] = [EepromSize::Unknown, EepromSize::Kbit4, EepromSize::Kbit64];
+
+// The save type goes first, the chip is replaced if it was overridden to something else since.
+impl Snapshot for SaveMemory {
+    fn save_state(&self, state: &mut StateWriter) {
+        let (kind, detail) = match self.save_type() {
+            SaveType::None => (0, 0),
+            SaveType::Sram => (1, 0),
+            SaveType::Flash(chip) => (2, FLASH_CHIPS.iter().position(|c| *c == chip).unwrap_or_default()),
+            SaveType::Eeprom(size) => (3, EEPROM_SIZES.iter().position(|s| *s == size).unwrap_or_default()),
+        };
+        state.u8(kind);
+        state.u8(detail as u8);
+        match self {
+            SaveMemory::None => {}
+            SaveMemory::Sram(sram) => sram.save_state(state),
+            SaveMemory::Flash(flash) => flash.save_state(state),
+            SaveMemory::Eeprom(eeprom) => eeprom.save_state(state),
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        let unknown = SaveStateError::Corrupt("unknown save type");
+        let (kind, detail) = (state.u8()?, state.u8()? as usize);
+        let save_type = match kind {
+            0 => SaveType::None,
+            1 => SaveType::Sram,
+            2 => SaveType::Flash(*FLASH_CHIPS.get(detail).ok_or(unknown)?),
+            3 => SaveType::Eeprom(*EEPROM_SIZES.get(detail).ok_or(unknown)?),
+            _ => return Err(unknown),
+        };
+        if save_type != self.save_type() {
+            *self = SaveMemory::new(save_type);
+        }
+        match self {
+            SaveMemory::None => Ok(()),
+            SaveMemory::Sram(sram) => sram.load_state(state),
+            SaveMemory::Flash(flash) => flash.load_state(state),
+            SaveMemory::Eeprom(eeprom) => eeprom.load_state(state),
+        }
+    }
+}
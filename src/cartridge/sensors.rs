@@ -0,0 +1,284 @@
+// src/cartridge/sensors.rs
+// Cartridge peripherals besides the RTC: Boktai's solar sensor, the tilt sensor of Yoshi and
+// Koro Koro Puzzle, and WarioWare Twisted's gyro and rumble motor. The host feeds the inputs.
+
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+// Solar sensor pins: 0 clocks the counter, 1 resets it, 2 high selects the RTC instead,
+// 3 goes high once the counter passes the light level.
+const SOLAR_CLOCK: u8 = 1 << 0;
+const SOLAR_RESET: u8 = 1 << 1;
+const SOLAR_RTC_SELECT: u8 = 1 << 2;
+const SOLAR_FLAG: u8 = 1 << 3;
+
+pub struct SolarSensor {
+    light_level: u8, // 0 = dark, 0xFF = direct sunlight
+    threshold: u8,   // Light level latched by the last reset
+    counter: u16,
+    last_clock: bool,
+}
+
+impl SolarSensor {
+    pub fn new() -> Self {
+        SolarSensor {
+            light_level: 0,
+            threshold: 0xFF,
+            counter: 0,
+            last_clock: false,
+        }
+    }
+
+    pub fn light_level(&self) -> u8 {
+        self.light_level
+    }
+
+    pub fn set_light_level(&mut self, level: u8) {
+        self.light_level = level;
+    }
+
+    pub fn write_pins(&mut self, pins: u8) {
+        if pins & SOLAR_RTC_SELECT != 0 {
+            return;
+        }
+        if pins & SOLAR_RESET != 0 {
+            // More light means fewer clocks until the comparator trips
+            self.counter = 0;
+            self.threshold = 0xFF - self.light_level;
+        }
+        let clock = pins & SOLAR_CLOCK != 0;
+        if clock && !self.last_clock {
+            self.counter = self.counter.saturating_add(1);
+        }
+        self.last_clock = clock;
+    }
+
+    pub fn read_pins(&self) -> u8 {
+        if self.counter >= self.threshold as u16 {
+            SOLAR_FLAG
+        } else {
+            0
+        }
+    }
+}
+
+impl Default for SolarSensor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The tilt sensor sits in the save region, at these offsets from 0x0E000000.
+pub const TILT_LATCH_1: u32 = 0x8000; // Write 0x55
+pub const TILT_LATCH_2: u32 = 0x8100; // Then 0xAA to sample both axes
+pub const TILT_X_LOW: u32 = 0x8200;
+pub const TILT_X_HIGH: u32 = 0x8300; // Bit 7 flags a finished sample
+pub const TILT_Y_LOW: u32 = 0x8400;
+pub const TILT_Y_HIGH: u32 = 0x8500;
+
+// 12 bit reading of an axis held level.
+const TILT_CENTER: i32 = 0x3A0;
+
+pub struct TiltSensor {
+    tilt: (i16, i16), // Host input, offset from level in sensor units
+    latched: bool,
+    sample_x: u16,
+    sample_y: u16,
+}
+
+impl TiltSensor {
+    pub fn new() -> Self {
+        TiltSensor {
+            tilt: (0, 0),
+            latched: false,
+            sample_x: TILT_CENTER as u16,
+            sample_y: TILT_CENTER as u16,
+        }
+    }
+
+    pub fn tilt(&self) -> (i16, i16) {
+        self.tilt
+    }
+
+    // Positive x tilts the right side of the console down, positive y the top.
+    pub fn set_tilt(&mut self, x: i16, y: i16) {
+        self.tilt = (x, y);
+    }
+
+    pub fn is_register(offset: u32) -> bool {
+        matches!(
+            offset,
+            TILT_LATCH_1 | TILT_LATCH_2 | TILT_X_LOW | TILT_X_HIGH | TILT_Y_LOW | TILT_Y_HIGH
+        )
+    }
+
+    pub fn read(&self, offset: u32) -> u8 {
+        match offset {
+            TILT_X_LOW => self.sample_x as u8,
+            TILT_X_HIGH => ((self.sample_x >> 8) as u8 & 0xF) | 0x80,
+            TILT_Y_LOW => self.sample_y as u8,
+            TILT_Y_HIGH => (self.sample_y >> 8) as u8 & 0xF,
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, offset: u32, value: u8) {
+        match (offset, value) {
+            (TILT_LATCH_1, 0x55) => self.latched = true,
+            (TILT_LATCH_2, 0xAA) if self.latched => {
+                self.latched = false;
+                self.sample_x = (TILT_CENTER - self.tilt.0 as i32).clamp(0, 0xFFF) as u16;
+                self.sample_y = (TILT_CENTER - self.tilt.1 as i32).clamp(0, 0xFFF) as u16;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Default for TiltSensor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Gyro pins: 0 high samples the rate, 1 clocks the sample out on pin 2 MSB first.
+const GYRO_SAMPLE: u8 = 1 << 0;
+const GYRO_CLOCK: u8 = 1 << 1;
+const GYRO_DATA_SHIFT: u8 = 2;
+
+// 12 bit reading with the console held still.
+const GYRO_CENTER: i32 = 0x6C0;
+
+pub struct GyroSensor {
+    rate: i16, // Host input, rotation around the axis through the screen, in sensor units
+    sample: u16,
+    output: u8,
+    last_clock: bool,
+}
+
+impl GyroSensor {
+    pub fn new() -> Self {
+        GyroSensor {
+            rate: 0,
+            sample: 0,
+            output: 0,
+            last_clock: false,
+        }
+    }
+
+    pub fn rate(&self) -> i16 {
+        self.rate
+    }
+
+    // Positive rates turn the console clockwise.
+    pub fn set_rate(&mut self, rate: i16) {
+        self.rate = rate;
+    }
+
+    pub fn write_pins(&mut self, pins: u8) {
+        if pins & GYRO_SAMPLE != 0 {
+            // 16 bits go out per sample, the top 4 are always clear
+            self.sample = (GYRO_CENTER + self.rate as i32).clamp(0, 0xFFF) as u16;
+        }
+        let clock = pins & GYRO_CLOCK != 0;
+        if self.last_clock && !clock {
+            self.output = (self.sample >> 15) as u8;
+            self.sample <<= 1;
+        }
+        self.last_clock = clock;
+    }
+
+    pub fn read_pins(&self) -> u8 {
+        self.output << GYRO_DATA_SHIFT
+    }
+}
+
+impl Default for GyroSensor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The rumble motor just follows pin 3.
+const RUMBLE_PIN: u8 = 1 << 3;
+
+#[derive(Default)]
+pub struct Rumble {
+    active: bool,
+}
+
+impl Rumble {
+    pub fn new() -> Self {
+        Rumble { active: false }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn write_pins(&mut self, pins: u8) {
+        self.active = pins & RUMBLE_PIN != 0;
+    }
+}
+
+impl Snapshot for SolarSensor {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.light_level);
+        state.u8(self.threshold);
+        state.u16(self.counter);
+        state.bool(self.last_clock);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.light_level = state.u8()?;
+        self.threshold = state.u8()?;
+        self.counter = state.u16()?;
+        self.last_clock = state.bool()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for TiltSensor {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.i16(self.tilt.0);
+        state.i16(self.tilt.1);
+        state.bool(self.latched);
+        state.u16(self.sample_x);
+        state.u16(self.sample_y);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.tilt = (state.i16()?, state.i16()?);
+        self.latched = state.bool()?;
+        self.sample_x = state.u16()?;
+        self.sample_y = state.u16()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for GyroSensor {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.i16(self.rate);
+        state.u16(self.sample);
+        state.u8(self.output);
+        state.bool(self.last_clock);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.rate = state.i16()?;
+        self.sample = state.u16()?;
+        self.output = state.u8()?;
+        self.last_clock = state.bool()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Rumble {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.active);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.active = state.bool()?;
+        Ok(())
+    }
+}
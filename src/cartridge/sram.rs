@@ -0,0 +1,52 @@
+// src/cartridge/sram.rs
+
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+pub const SRAM_SIZE: usize = 32 * 1024;
+
+// Battery backed 32 KB SRAM, mapped byte-wide at 0x0E000000 and mirrored every 32 KB.
+pub struct Sram {
+    data: Vec<u8>,
+}
+
+impl Sram {
+    pub fn new() -> Self {
+        Sram {
+            data: vec![0xFF; SRAM_SIZE],
+        }
+    }
+
+    pub fn read(&self, offset: u32) -> u8 {
+        self.data[offset as usize % SRAM_SIZE]
+    }
+
+    pub fn write(&mut self, offset: u32, value: u8) {
+        self.data[offset as usize % SRAM_SIZE] = value;
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    // Shorter files (e.g. an 8 KB dump) fill the start of the chip, the rest stays erased.
+    pub fn load(&mut self, bytes: &[u8]) {
+        let len = bytes.len().min(SRAM_SIZE);
+        self.data[..len].copy_from_slice(&bytes[..len]);
+    }
+}
+
+impl Default for Sram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Snapshot for Sram {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.data);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        state.bytes_into(&mut self.data)
+    }
+}
@@ -1,8 +1,80 @@
 use crate::cpu_instructions::branch_ops::BranchOps;
 use crate::cpu_instructions::instruction_decoding::{decode_arm, Instruction, ShiftType};
-use crate::memory::Memory;
+use crate::debugger::watch::WatchBus;
+use crate::debugger::{Debugger, StopReason};
+use crate::error::EmulatorError;
+use crate::interrupt::PowerState;
+use crate::memory::MemoryBus;
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+use crate::trace::{Category, TraceRecord, Tracer};
 
-const HALT_INSTRUCTION: u32 = 0xFFFFFFFF;
+// What the BIOS runs at IRQ_RETURN_ADDRESS: LDMFD sp!, {r0-r3, r12, lr}
+const BIOS_IRQ_RETURN_OPCODE: u32 = 0xE8BD_500F;
+
+/// One instruction as run by `Cpu::step`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct StepInfo {
+    pub address: u32,
+    pub opcode: u32,
+    pub instruction: Instruction,
+    pub cycles: u64,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Step {
+    Executed(StepInfo),
+    Halted { cycles: u64 }, // Waited for an event instead of running anything
+    Stopped,                // Nothing runs until a keypad, serial or Game Pak interrupt
+    Break(StopReason),      // The debugger stopped execution
+}
+
+// Processor modes, as encoded in CPSR bits 0-4.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Mode {
+    User = 0x10,
+    Fiq = 0x11,
+    Irq = 0x12,
+    Supervisor = 0x13,
+    Abort = 0x17,
+    Undefined = 0x1B,
+    System = 0x1F,
+}
+
+impl Mode {
+    pub fn from_bits(bits: u32) -> Option<Mode> {
+        match bits & 0x1F {
+            0x10 => Some(Mode::User),
+            0x11 => Some(Mode::Fiq),
+            0x12 => Some(Mode::Irq),
+            0x13 => Some(Mode::Supervisor),
+            0x17 => Some(Mode::Abort),
+            0x1B => Some(Mode::Undefined),
+            0x1F => Some(Mode::System),
+            _ => None,
+        }
+    }
+
+    // User and System share their registers, every other mode has its own r13, r14 and SPSR.
+    fn bank(self) -> usize {
+        match self {
+            Mode::User | Mode::System => 0,
+            Mode::Fiq => 1,
+            Mode::Irq => 2,
+            Mode::Supervisor => 3,
+            Mode::Abort => 4,
+            Mode::Undefined => 5,
+        }
+    }
+}
+
+// Registers of the modes not currently active.
+#[derive(Debug, Default)]
+pub struct BankedRegisters {
+    pub r13_r14: [[u32; 2]; 6], // Indexed by Mode::bank
+    pub spsr: [Cpsr; 6],
+    pub user_r8_r12: [u32; 5], // Swapped out while in FIQ mode
+    pub fiq_r8_r12: [u32; 5],
+}
 
 #[allow(dead_code)]
 #[allow(non_snake_case)]
@@ -11,31 +83,115 @@ pub struct CpuState {
     pub registers: [u32; 16], // Regular registers (r0-r15)
     pub CPSR: Cpsr,           // Current Program Status Register
     pub SPSR: Cpsr,           // Saved Program Status Register
+    pub banked: BankedRegisters,
 }
 #[allow(dead_code)]
 impl CpuState {
-    pub fn get_register(&self, reg_num: usize) -> u32 {
-        if reg_num < 16 {
-            self.registers[reg_num]
-        } else {
-            panic!("Invalid register number requested: {}", reg_num);
+    // Mode bits that do not name a mode (like the all zero reset value here) act as User.
+    pub fn mode(&self) -> Mode {
+        Mode::from_bits(self.CPSR.value).unwrap_or(Mode::User)
+    }
+
+    /// Switches mode, swapping the banked registers in and out of `registers` and `SPSR`.
+    pub fn set_mode(&mut self, mode: Mode) {
+        let old = self.mode();
+        self.CPSR.value = (self.CPSR.value & !0x1F) | mode as u32;
+        if old.bank() == mode.bank() {
+            return;
+        }
+        let (old_bank, new_bank) = (old.bank(), mode.bank());
+        self.banked.r13_r14[old_bank] = [self.registers[13], self.registers[14]];
+        self.banked.spsr[old_bank] = self.SPSR;
+        if old == Mode::Fiq {
+            self.banked.fiq_r8_r12.copy_from_slice(&self.registers[8..13]);
+            self.registers[8..13].copy_from_slice(&self.banked.user_r8_r12);
+        } else if mode == Mode::Fiq {
+            self.banked.user_r8_r12.copy_from_slice(&self.registers[8..13]);
+            self.registers[8..13].copy_from_slice(&self.banked.fiq_r8_r12);
+        }
+        [self.registers[13], self.registers[14]] = self.banked.r13_r14[new_bank];
+        self.SPSR = self.banked.spsr[new_bank];
+    }
+
+    /// Register `reg_num` as `mode` sees it, whether or not `mode` is the current one.
+    pub fn mode_register(&self, mode: Mode, reg_num: usize) -> u32 {
+        let current = self.mode();
+        match reg_num {
+            8..=12 if (mode == Mode::Fiq) != (current == Mode::Fiq) => {
+                let bank = if mode == Mode::Fiq { &self.banked.fiq_r8_r12 } else { &self.banked.user_r8_r12 };
+                bank[reg_num - 8]
+            }
+            13 | 14 if mode.bank() != current.bank() => self.banked.r13_r14[mode.bank()][reg_num - 13],
+            _ => self.registers[reg_num],
+        }
+    }
+
+    pub fn set_mode_register(&mut self, mode: Mode, reg_num: usize, value: u32) {
+        let current = self.mode();
+        match reg_num {
+            8..=12 if (mode == Mode::Fiq) != (current == Mode::Fiq) => {
+                let bank = if mode == Mode::Fiq {
+                    &mut self.banked.fiq_r8_r12
+                } else {
+                    &mut self.banked.user_r8_r12
+                };
+                bank[reg_num - 8] = value;
+            }
+            13 | 14 if mode.bank() != current.bank() => {
+                self.banked.r13_r14[mode.bank()][reg_num - 13] = value
+            }
+            _ => self.registers[reg_num] = value,
         }
     }
 
-    pub fn set_register(&mut self, reg_num: usize, value: u32) {
-        if reg_num < 16 {
-            self.registers[reg_num] = value;
+    // User and System have no SPSR, theirs reads as whatever is in the slot.
+    pub fn mode_spsr(&self, mode: Mode) -> Cpsr {
+        if mode.bank() == self.mode().bank() {
+            self.SPSR
         } else {
-            panic!("Invalid register number: {}", reg_num)
+            self.banked.spsr[mode.bank()]
         }
     }
 
-    pub fn fetch_instruction(&mut self, memory: &Memory) -> (u32, bool) {
-        let pc = self.get_register(15);
-        let instruction = memory.read_word(pc);
-        if instruction != HALT_INSTRUCTION {
-            self.set_register(15, pc.wrapping_add(4));
+    pub fn set_mode_spsr(&mut self, mode: Mode, value: Cpsr) {
+        if mode.bank() == self.mode().bank() {
+            self.SPSR = value;
+        } else {
+            self.banked.spsr[mode.bank()] = value;
         }
+    }
+
+    pub fn get_register(&self, reg_num: usize) -> Result<u32, EmulatorError> {
+        self.registers
+            .get(reg_num)
+            .copied()
+            .ok_or(EmulatorError::InvalidRegister { index: reg_num })
+    }
+
+    pub fn set_register(&mut self, reg_num: usize, value: u32) -> Result<(), EmulatorError> {
+        let register = self
+            .registers
+            .get_mut(reg_num)
+            .ok_or(EmulatorError::InvalidRegister { index: reg_num })?;
+        *register = value;
+        Ok(())
+    }
+
+    // Register numbers decoded from an instruction are 4 bits, these can't go out of range.
+    #[inline(always)]
+    pub(crate) fn reg(&self, reg_num: usize) -> u32 {
+        self.registers[reg_num]
+    }
+
+    #[inline(always)]
+    pub(crate) fn set_reg(&mut self, reg_num: usize, value: u32) {
+        self.registers[reg_num] = value;
+    }
+
+    pub fn fetch_instruction<M: MemoryBus>(&mut self, memory: &mut M) -> (u32, bool) {
+        let pc = self.reg(15);
+        let instruction = memory.fetch_word(pc);
+        self.set_reg(15, pc.wrapping_add(4));
         (instruction, self.CPSR.is_thumb_state())
     }
 }
@@ -52,7 +208,7 @@ impl Cpsr {
     const I_BIT: u32 = 7; //IRQ disable
     const F_BIT: u32 = 8; //FIQ disable
     const T_BIT: u32 = 5; //Thumb state bit
-                          //Later implementation of mode bits (0-4)
+    const MODE_MASK: u32 = 0x1F;
 
     //------flag access methods getters and setters------
 
@@ -67,24 +223,11 @@ impl Cpsr {
     #[inline(always)]
     #[allow(dead_code)]
     pub fn set_negative(&mut self, set: bool) {
-        println!("set_negative called with set = {}", set);
-        println!("Value before: 0x{:08X}", self.value);
         if set {
             self.value |= 1u32 << Self::NEGATIVE_BIT;
-            println!(
-                "Setting bit {} (mask: 0x{:08X})",
-                Self::NEGATIVE_BIT,
-                1 << Self::NEGATIVE_BIT
-            );
         } else {
             self.value &= !(1u32 << Self::NEGATIVE_BIT);
-            println!(
-                "Clearing bit {} (mask: 0x{:08X})",
-                Self::NEGATIVE_BIT,
-                !(1 << Self::NEGATIVE_BIT)
-            );
         }
-        println!("Value after: 0x{:08X}", self.value);
     }
 
     //  ---Zero flag(Z)---
@@ -153,7 +296,7 @@ impl Cpsr {
         if set {
             self.value |= 1 << Self::I_BIT;
         } else {
-            self.value &= !1 << Self::I_BIT;
+            self.value &= !(1 << Self::I_BIT);
         }
     }
 
@@ -170,7 +313,7 @@ impl Cpsr {
         if set {
             self.value |= 1 << Self::F_BIT;
         } else {
-            self.value &= !1 << Self::F_BIT;
+            self.value &= !(1 << Self::F_BIT);
         }
     }
 
@@ -189,13 +332,26 @@ impl Cpsr {
         if set {
             self.value |= 1 << Self::T_BIT;
         } else {
-            self.value &= !1 << Self::T_BIT;
+            self.value &= !(1 << Self::T_BIT);
         }
     }
+    // --- Mode bits (0-4) ---
+    // Changing them here does not bank any registers, use CpuState::set_mode for that.
+
+    #[inline(always)]
+    pub fn mode_bits(&self) -> u32 {
+        self.value & Self::MODE_MASK
+    }
+
     #[inline(always)]
     #[allow(dead_code)]
     pub fn display_all_flags(&self) -> () {
-        println!("Is negative: {}\nIs zero: {}\nIs carry: {}\nIs overflow: {} \nIs IRQ disabled: {} \nIs FIQ disabled: {} \nIs Thumb state: {} ",
+        println!("{}", self.all_flags())
+    }
+
+    // What display_all_flags prints, for frontends writing somewhere other than stdout.
+    pub fn all_flags(&self) -> String {
+        format!("Is negative: {}\nIs zero: {}\nIs carry: {}\nIs overflow: {} \nIs IRQ disabled: {} \nIs FIQ disabled: {} \nIs Thumb state: {} ",
                 self.is_negative(),
                 self.is_zero(),
                 self.is_carry(),
@@ -209,26 +365,246 @@ impl Cpsr {
 
 pub struct Cpu {
     pub cpu_state: CpuState,
+    pub hle_bios: bool, // Service SWIs in Rust instead of jumping into a BIOS image
+    pub(crate) intr_wait_pending: bool, // IntrWait is halted and will run again on wake up
+    pub cycles: u64,                    // Run since power on, halted time included
+    pub tracer: Option<Tracer>,
+    pub debugger: Debugger,
 }
 #[allow(dead_code)]
 impl Cpu {
     pub fn new() -> Self {
         Cpu {
             cpu_state: CpuState::default(),
+            hle_bios: true,
+            intr_wait_pending: false,
+            cycles: 0,
+            tracer: None,
+            debugger: Debugger::new(),
+        }
+    }
+
+    /// Power-on state: Supervisor mode, interrupts disabled, ARM state, executing the reset vector.
+    pub fn reset(&mut self) {
+        self.cpu_state = CpuState::default();
+        self.cpu_state.set_mode(Mode::Supervisor);
+        self.cpu_state.CPSR.set_irq_disabled(true);
+        self.cpu_state.CPSR.set_fiq_disabled(true);
+        self.intr_wait_pending = false;
+    }
+
+    /// Enters an exception: saves CPSR to the new mode's SPSR, sets LR and jumps to the vector.
+    pub fn enter_exception(&mut self, mode: Mode, vector: u32, return_address: u32) {
+        let cpsr = self.cpu_state.CPSR;
+        self.cpu_state.set_mode(mode);
+        self.cpu_state.SPSR = cpsr;
+        self.cpu_state.set_reg(14, return_address);
+        self.cpu_state.CPSR.set_irq_disabled(true);
+        self.cpu_state.CPSR.set_thumb_state(false);
+        self.cpu_state.set_reg(15, vector);
+    }
+
+    /// Takes the IRQ exception. With the HLE BIOS the BIOS interrupt handler runs right away and
+    /// the CPU continues in the game's handler.
+    pub fn irq<M: MemoryBus>(&mut self, memory: &mut M) {
+        self.trace(Category::Irq, self.cpu_state.reg(15), 0);
+        let return_address = self.cpu_state.reg(15).wrapping_add(4);
+        self.enter_exception(Mode::Irq, 0x18, return_address);
+        if self.hle_bios {
+            crate::bios::hle::irq_entry(self, memory);
+        }
+    }
+
+    /// Runs the system for one instruction: wakes up from Halt, takes a pending IRQ, executes
+    /// and lets the rest of the system catch up. Bad memory accesses made along the way are
+    /// reported once the instruction is done.
+    pub fn step<M: MemoryBus>(&mut self, memory: &mut M) -> Result<Step, EmulatorError> {
+        self.debugger.begin_step();
+        match memory.power_state() {
+            PowerState::Stopped => return Ok(Step::Stopped),
+            PowerState::Halted => {
+                let cycles = memory.wait_for_event();
+                self.cycles += cycles;
+                return Ok(Step::Halted { cycles });
+            }
+            PowerState::Running => {}
+        }
+        if memory.irq_line() && !self.cpu_state.CPSR.is_irq_disabled() {
+            let return_address = self.cpu_state.reg(15);
+            self.irq(memory);
+            if self.debugger.break_on_irq() {
+                let reason = StopReason::Irq { return_address };
+                return Ok(Step::Break(self.debugger.stopped(reason)));
+            }
+        }
+        if self.debugger.is_active() {
+            return self.debug_step(memory);
+        }
+        let info = self.execute_next(memory)?;
+        // Instruction timings are not modelled yet, every instruction takes one cycle
+        memory.tick(info.cycles);
+        Ok(Step::Executed(info))
+    }
+
+    // `step` with breakpoints or watchpoints set.
+    fn debug_step<M: MemoryBus>(&mut self, memory: &mut M) -> Result<Step, EmulatorError> {
+        if let Some(reason) = self.debugger.check_breakpoints(&self.cpu_state, memory) {
+            return Ok(Step::Break(reason));
+        }
+        if !self.debugger.watching() {
+            let info = self.execute_next(memory)?;
+            memory.tick(info.cycles);
+            return Ok(Step::Executed(info));
+        }
+        let ranges = self.debugger.take_ranges();
+        let mut watched = WatchBus::new(memory, &ranges);
+        let result = self.execute_next(&mut watched);
+        let hits = std::mem::take(&mut watched.hits);
+        self.debugger.restore_ranges(ranges);
+        let info = result?;
+        memory.tick(info.cycles);
+        match self.debugger.check_hits(&hits, info.address, &self.cpu_state, memory) {
+            Some(reason) => Ok(Step::Break(reason)),
+            None => Ok(Step::Executed(info)),
+        }
+    }
+
+    /// Why the last step stopped in the debugger, if it did.
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.debugger.stop_reason()
+    }
+
+    fn execute_next<M: MemoryBus>(&mut self, memory: &mut M) -> Result<StepInfo, EmulatorError> {
+        let address = self.cpu_state.reg(15);
+        let mut info = StepInfo {
+            address,
+            opcode: BIOS_IRQ_RETURN_OPCODE,
+            instruction: decode_arm(BIOS_IRQ_RETURN_OPCODE),
+            cycles: 1,
+        };
+        self.cycles += info.cycles;
+        if self.hle_bios && address == crate::bios::hle::IRQ_RETURN_ADDRESS {
+            self.trace(Category::Instruction, address, BIOS_IRQ_RETURN_OPCODE);
+            crate::bios::hle::irq_return(self, memory);
+            return memory.take_fault().map_or(Ok(info), Err);
+        }
+        let (opcode, is_thumb) = self.cpu_state.fetch_instruction(memory);
+        if is_thumb {
+            return Err(EmulatorError::UnimplementedInstruction {
+                address,
+                opcode,
+                thumb: true,
+            });
+        }
+        info.opcode = opcode;
+        info.instruction = decode_arm(opcode);
+        self.trace(Category::of(&info.instruction), address, opcode);
+        self.interpret_instruction(opcode, memory)?;
+        memory.take_fault().map_or(Ok(info), Err)
+    }
+
+    // Hands the state before the instruction at `pc` to the tracer. A sink that fails to write
+    // turns tracing off rather than stopping the emulation.
+    fn trace(&mut self, category: Category, pc: u32, opcode: u32) {
+        let Some(tracer) = self.tracer.as_mut() else {
+            return;
+        };
+        if !tracer.wants(category, pc) {
+            return;
+        }
+        let mut registers = self.cpu_state.registers;
+        registers[15] = pc;
+        let record = TraceRecord {
+            category,
+            pc,
+            opcode,
+            registers,
+            cpsr: self.cpu_state.CPSR.value,
+            cycles: self.cycles,
+        };
+        if let Err(err) = tracer.record(&record) {
+            eprintln!("Trace write failed, tracing stopped: {}", err);
+            self.tracer = None;
+        }
+    }
+
+    /// Runs at least `cycles` cycles and returns how many ran, fewer if the system stopped.
+    pub fn run_for_cycles<M: MemoryBus>(
+        &mut self,
+        memory: &mut M,
+        cycles: u64,
+    ) -> Result<u64, EmulatorError> {
+        let mut ran = 0;
+        while ran < cycles {
+            match self.step(memory)? {
+                Step::Executed(info) => ran += info.cycles,
+                Step::Halted { cycles: 0 } | Step::Stopped | Step::Break(_) => break,
+                Step::Halted { cycles } => ran += cycles,
+            }
+        }
+        Ok(ran)
+    }
+
+    /// Runs until PC is `pc`, checked before every instruction, or `limit` cycles have passed.
+    /// Returns whether `pc` was reached.
+    pub fn run_until_pc<M: MemoryBus>(
+        &mut self,
+        memory: &mut M,
+        pc: u32,
+        limit: u64,
+    ) -> Result<bool, EmulatorError> {
+        let mut ran = 0;
+        while ran < limit {
+            if self.cpu_state.reg(15) == pc && memory.power_state() == PowerState::Running {
+                return Ok(true);
+            }
+            match self.step(memory)? {
+                Step::Executed(info) => ran += info.cycles,
+                Step::Halted { cycles: 0 } | Step::Stopped | Step::Break(_) => return Ok(false),
+                Step::Halted { cycles } => ran += cycles,
+            }
+        }
+        Ok(false)
+    }
+
+    /// Runs until the display starts the next frame. Without a display, one frame's worth of
+    /// cycles.
+    pub fn run_frame<M: MemoryBus>(&mut self, memory: &mut M) -> Result<u64, EmulatorError> {
+        let Some(frame) = memory.frame_number() else {
+            return self.run_for_cycles(memory, crate::ppu::FRAME_CYCLES);
+        };
+        let mut ran = 0;
+        while memory.frame_number() == Some(frame) {
+            match self.step(memory)? {
+                Step::Executed(info) => ran += info.cycles,
+                Step::Halted { cycles: 0 } | Step::Stopped | Step::Break(_) => break,
+                Step::Halted { cycles } => ran += cycles,
+            }
+        }
+        Ok(ran)
+    }
+
+    fn software_interrupt<M: MemoryBus>(
+        &mut self,
+        comment: u32,
+        memory: &mut M,
+    ) -> Result<(), EmulatorError> {
+        if self.hle_bios {
+            // GBA code passes the function number in the upper byte of the ARM comment field
+            crate::bios::hle::software_interrupt(self, memory, (comment >> 16) as u8)
+        } else {
+            let return_address = self.cpu_state.reg(15);
+            self.enter_exception(Mode::Supervisor, 0x08, return_address);
+            Ok(())
         }
     }
 
     // Helper function to update flags after arithmetic operations.
     pub fn update_arithmetic_flags(&mut self, result: u32, carry: bool, overflow: bool) {
-        println!("CPSR address before update: {:p}", &self.cpu_state.CPSR);
-        println!("CPSR before update: 0x{:08X}", self.cpu_state.CPSR.value);
-
         self.cpu_state.CPSR.set_zero(result == 0);
         self.cpu_state.CPSR.set_negative((result as i32) < 0);
         self.cpu_state.CPSR.set_carry(carry);
         self.cpu_state.CPSR.set_overflow(overflow);
-        println!("CPSR address after update: {:p}", &self.cpu_state.CPSR);
-        println!("CPSR after update:  0x{:08X}", self.cpu_state.CPSR.value);
     }
 
     // Helper function to update flags after logical operations.
@@ -296,7 +672,11 @@ impl Cpu {
         }
     }
     // Placeholder for interpreting a single instruction.
-    fn interpret_instruction(&mut self, instruction: u32, memory: &mut Memory) {
+    fn interpret_instruction<M: MemoryBus>(
+        &mut self,
+        instruction: u32,
+        memory: &mut M,
+    ) -> Result<(), EmulatorError> {
         // Perform the condition check *here*
         let condition_passed = match decode_arm(instruction) {
             Instruction::Nop => true, // NOP always passes
@@ -344,7 +724,7 @@ impl Cpu {
                     imm12,
                     set_flags,
                 } => {
-                    self.mov_immediete(rd, imm12);
+                    self.mov_immediete(rd, imm12, set_flags);
                 }
                 Instruction::MovRegister {
                     rd,
@@ -607,35 +987,81 @@ impl Cpu {
                 => {self.load_register_byte(rt, rn, offset, pre_index, add, write_back, memory);}
                 Instruction::Ldrd { rt, rn, offset, pre_index, add, write_back }
                 => {self.load_doubleword(rt, rn, offset, pre_index, add, write_back, memory);}
-                Instruction::Unknown(instruction) => {
-                    // Handle unknown instructions (e.g., raise an exception).
-                    panic!("Unknown instruction: 0x{:X}", instruction);
+                Instruction::SoftwareInterrupt { comment } => {
+                    self.software_interrupt(comment, memory)?;
                 }
-                Instruction::Nop => {} // Do nothing for NOP
-                _ => {
-                    todo!("unreached");
+                Instruction::Unknown(opcode) => {
+                    // PC has already moved past the instruction
+                    let address = self.cpu_state.reg(15).wrapping_sub(4);
+                    return Err(EmulatorError::from_unknown_arm(address, opcode));
                 }
+                Instruction::Nop => {} // Do nothing for NOP
             }
         }
+        Ok(())
     }
     #[allow(dead_code)]
-    pub fn run_program(&mut self, memory: &mut Memory) {
+    pub fn run_program<M: MemoryBus>(&mut self, memory: &mut M) -> Result<(), EmulatorError> {
         const HALT_INSTRUCTION: u32 = 0xFFFFFFFF;
         loop {
-            let (instruction, is_thumb) = self.cpu_state.fetch_instruction(memory);
-            if is_thumb {
-                todo!("Implement Thumb mode")
-            }
-            if instruction == HALT_INSTRUCTION {
+            let pc = self.cpu_state.reg(15);
+            if memory.fetch_word(pc) == HALT_INSTRUCTION {
                 // Print registers and halt.
                 for (i, register) in self.cpu_state.registers.iter().enumerate() {
                     println!("R{}: 0x{:X}", i, register);
                 }
                 println!("End of program (halt instruction encountered).");
-                break;
+                return memory.take_fault().map_or(Ok(()), Err);
             }
             // Decode and execute the instruction.
-            self.interpret_instruction(instruction, memory);
+            self.execute_next(memory)?;
+        }
+    }
+}
+
+impl Snapshot for Cpu {
+    fn save_state(&self, state: &mut StateWriter) {
+        let cpu = &self.cpu_state;
+        for value in cpu.registers {
+            state.u32(value);
+        }
+        state.u32(cpu.CPSR.value);
+        state.u32(cpu.SPSR.value);
+        for [r13, r14] in cpu.banked.r13_r14 {
+            state.u32(r13);
+            state.u32(r14);
+        }
+        for spsr in &cpu.banked.spsr {
+            state.u32(spsr.value);
+        }
+        for value in cpu.banked.user_r8_r12.iter().chain(&cpu.banked.fiq_r8_r12) {
+            state.u32(*value);
+        }
+        state.bool(self.hle_bios);
+        state.bool(self.intr_wait_pending);
+        state.u64(self.cycles);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        let cpu = &mut self.cpu_state;
+        for value in cpu.registers.iter_mut() {
+            *value = state.u32()?;
+        }
+        cpu.CPSR.value = state.u32()?;
+        cpu.SPSR.value = state.u32()?;
+        for pair in cpu.banked.r13_r14.iter_mut() {
+            *pair = [state.u32()?, state.u32()?];
+        }
+        for spsr in cpu.banked.spsr.iter_mut() {
+            spsr.value = state.u32()?;
+        }
+        let banked = &mut cpu.banked;
+        for value in banked.user_r8_r12.iter_mut().chain(banked.fiq_r8_r12.iter_mut()) {
+            *value = state.u32()?;
         }
+        self.hle_bios = state.bool()?;
+        self.intr_wait_pending = state.bool()?;
+        self.cycles = state.u64()?;
+        Ok(())
     }
 }
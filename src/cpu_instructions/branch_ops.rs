@@ -23,7 +23,7 @@ pub trait BranchOps {
 }
 #[allow(dead_code)]
 impl BranchOps for Cpu {
-    fn execute_branch(&mut self, branch_type: BranchType, imm24: u32, raw_instr: u32) {
+    fn execute_branch(&mut self, branch_type: BranchType, imm24: u32, _raw_instr: u32) {
         // Compute the branch offset: shift the 24-bit immediate left by 2.
         let mut offset = (imm24 << 2) as i32;
         // Sign-extend the 26-bit result to 32 bits.
@@ -31,36 +31,30 @@ impl BranchOps for Cpu {
             offset |= !0x03FF_FFFF; // set upper 6 bits to 1.
         }
         // Get the current PC, which is already advanced by 4 from fetch_instruction.
-        let current_pc = self.cpu_state.get_register(15);
-        // Compute target_pc as current_pc + offset (without adding extra 4).
-        let target_pc = current_pc.wrapping_add(offset as u32);
+        let current_pc = self.cpu_state.reg(15);
+        // The offset is relative to the instruction address + 8, one more word than current_pc.
+        let target_pc = current_pc.wrapping_add(4).wrapping_add(offset as u32);
         
         // For Branch with Link, set LR (r14) to the current PC.
         if branch_type == BranchType::BL {
-            self.cpu_state.set_register(14, current_pc);
+            self.cpu_state.set_reg(14, current_pc);
         }
         
         // Set the PC to the computed target.
-        self.cpu_state.set_register(15, target_pc);
-        println!(
-            "Branch executed: raw 0x{:08X}, type: {:?}, imm24: 0x{:06X}, offset: {}, target_pc set to: 0x{:08X}",
-            raw_instr, branch_type, imm24, offset, target_pc
-        );  
+        self.cpu_state.set_reg(15, target_pc);
     }
     fn branch_exchange(&mut self, rm:usize){
-        let target = self.cpu_state.get_register(rm);
+        let target = self.cpu_state.reg(rm);
         if (target & 1) == 1{
             self.cpu_state.CPSR.set_thumb_state(true);
         }else{
             self.cpu_state.CPSR.set_thumb_state(false);
         }
-        self.cpu_state.set_register(15, target&!1);
-        println!("BX executed: Branching to 0x{:09X}", (target &!1))
+        self.cpu_state.set_reg(15, target&!1);
     }
     fn branch_link_exchange(&mut self, rm:usize) {
-        let return_address = self.cpu_state.get_register(15);
-        self.cpu_state.set_register(14, return_address);
-        println!("BLX executed: Saving return address 0x{:08X} in R14", return_address);
+        let return_address = self.cpu_state.reg(15);
+        self.cpu_state.set_reg(14, return_address);
         self.branch_exchange(rm);
     }
 }
@@ -244,6 +244,9 @@ pub enum Instruction {
         add: bool,
         write_back: bool,
     },
+    SoftwareInterrupt {
+        comment: u32, // 24 bit field, GBA code keeps the BIOS function number in bits 16-23
+    },
     Unknown(u32),
     Nop,
 }
@@ -262,6 +265,12 @@ pub fn decode_arm(instruction: u32) -> Instruction {
         return Instruction::BranchExchange { rm: (instruction & 0xF) as usize };
     } else if (instruction & 0x0FFFFFF0) == 0x012FFF30 {
         return Instruction::BranchLinkExchange { rm: (instruction & 0xF) as usize };
+    } else if (instruction >> 24) & 0xF == 0xF && instruction >> 28 != 0xF {
+        // SWI, the NV condition space is undefined on the ARM7
+        return Instruction::SoftwareInterrupt { comment: instruction & 0x00FF_FFFF };
+    } else if instruction & 0x0E00_0010 == 0x0600_0010 {
+        // Undefined instruction space, a register offset load/store can't have bit 4 set
+        return Instruction::Unknown(instruction);
     }
     
     let cond = (instruction >> 28) & 0xF;
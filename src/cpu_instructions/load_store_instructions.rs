@@ -3,10 +3,10 @@ use std::fmt::write;
 use crate::cpu::Cpu;
 use crate::cpu_instructions::instruction_decoding::Instruction;
 use crate::cpu_instructions::instruction_decoding::ShiftType; // Import ShiftType
-use crate::memory::Memory;
+use crate::memory::MemoryBus;
 
 impl Cpu {
-    pub fn load_register(
+    pub fn load_register<M: MemoryBus>(
         //LDR
         &mut self,
         rt: usize,
@@ -15,9 +15,9 @@ impl Cpu {
         pre_index: bool,
         add: bool,
         write_back: bool,
-        memory: &Memory,
+        memory: &mut M,
     ) {
-        let base = self.cpu_state.get_register(rn);
+        let base = self.cpu_state.reg(rn);
         let effective_address = if add {
             base.wrapping_add(offset)
         } else {
@@ -26,12 +26,12 @@ impl Cpu {
         let addr = if pre_index { effective_address } else { base };
 
         let value = memory.read_word(addr);
-        self.cpu_state.set_register(rt, value);
+        self.cpu_state.set_reg(rt, value);
         if write_back {
-            self.cpu_state.set_register(rn, effective_address);
+            self.cpu_state.set_reg(rn, effective_address);
         }
     }
-    pub fn load_multiple(
+    pub fn load_multiple<M: MemoryBus>(
         //LDM
         &mut self,
         rn: usize,
@@ -39,9 +39,9 @@ impl Cpu {
         pre_index: bool,
         add: bool,
         write_back: bool,
-        memory: &Memory,
+        memory: &mut M,
     ) {
-        let base = self.cpu_state.get_register(rn);
+        let base = self.cpu_state.reg(rn);
         let mut addr = if pre_index {
             if add {
                 base.wrapping_add(4)
@@ -54,7 +54,7 @@ impl Cpu {
         for reg in 0..16 {
             if (register_list >> reg) & 1 == 1 {
                 let value = memory.read_word(addr);
-                self.cpu_state.set_register(reg, value);
+                self.cpu_state.set_reg(reg, value);
                 addr = addr.wrapping_add(4);
             }
         }
@@ -65,10 +65,10 @@ impl Cpu {
             } else {
                 base.wrapping_sub(num_regs * 4)
             };
-            self.cpu_state.set_register(rn, new_base);
+            self.cpu_state.set_reg(rn, new_base);
         }
     }
-    pub fn load_register_byte( //LDRB
+    pub fn load_register_byte<M: MemoryBus>( //LDRB
         &mut self,
         rt: usize,
         rn: usize,
@@ -76,9 +76,9 @@ impl Cpu {
         pre_index: bool,
         add: bool,
         write_back: bool,
-        memory: &Memory,
+        memory: &mut M,
     ) {
-        let base = self.cpu_state.get_register(rn);
+        let base = self.cpu_state.reg(rn);
         let effective_address = if add {
             base.wrapping_add(offset)
         } else {
@@ -87,12 +87,12 @@ impl Cpu {
         let addr = if pre_index { effective_address } else { base };
         let byte_val = memory.read_byte(addr);
         let value = byte_val as u32;
-        self.cpu_state.set_register(rt, value);
+        self.cpu_state.set_reg(rt, value);
         if write_back{
-            self.cpu_state.set_register(rn, effective_address);
+            self.cpu_state.set_reg(rn, effective_address);
         }
     }
-    pub fn load_doubleword( //LDRD
+    pub fn load_doubleword<M: MemoryBus>( //LDRD
         &mut self, 
         rt: usize,
         rn: usize,
@@ -100,9 +100,9 @@ impl Cpu {
         pre_index: bool,
         add: bool,
         write_back: bool,
-        memory: &Memory,
+        memory: &mut M,
     ){
-        let base = self.cpu_state.get_register(rn);
+        let base = self.cpu_state.reg(rn);
         let effective_address = if add{
             base.wrapping_add(offset)
         }else{
@@ -111,10 +111,10 @@ impl Cpu {
         let addr = if pre_index {effective_address} else {base};
         let lower_word = memory.read_word(addr);
         let upper_word = memory.read_word(addr.wrapping_add(4));
-        self.cpu_state.set_register(rt, lower_word);
-        self.cpu_state.set_register(rt + 1 , upper_word);
+        self.cpu_state.set_reg(rt, lower_word);
+        self.cpu_state.set_reg(rt + 1 , upper_word);
         if write_back {
-            self.cpu_state.set_register(rn, effective_address);
+            self.cpu_state.set_reg(rn, effective_address);
         }
     }
 }
@@ -0,0 +1,337 @@
+// src/debugger/expr.rs
+// Break conditions, C-like expressions over registers, flags and memory:
+//   r0 == 0x1234 && cpsr.z
+//   [sp + 4] != 0 || (lr & 1)
+// Values are u32, comparisons and logic give 1 or 0, anything non-zero is true. `[expr]` reads
+// the word at that address through the bus.
+
+use std::fmt;
+
+use crate::cpu::CpuState;
+use crate::memory::MemoryBus;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseError {
+    pub position: usize, // Byte offset into the source
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.position + 1)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum BinaryOp {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Add,
+    Sub,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Expr {
+    Number(u32),
+    Register(usize),
+    Cpsr,
+    Spsr,
+    Flag(char), // One of the CPSR flags, by its letter
+    Memory(Box<Expr>),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Token {
+    Number(u32),
+    Name(String),
+    Symbol(&'static str),
+}
+
+// Longest first so "==" is not read as "=" "="
+const SYMBOLS: [&str; 19] = [
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "(", ")", "[", "]", "+", "-", "&", "|",
+    "^", "~",
+];
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let mut tokens = Vec::new();
+    let bytes = source.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i] as char;
+        if c.is_ascii_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let start = i;
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_' || bytes[i] == b'.') {
+                i += 1;
+            }
+            let word = &source[start..i];
+            let token = if c.is_ascii_digit() {
+                let value = match word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16),
+                    None => word.parse::<u32>(),
+                };
+                Token::Number(value.map_err(|_| ParseError {
+                    position: start,
+                    message: format!("bad number '{}'", word),
+                })?)
+            } else {
+                Token::Name(word.to_ascii_lowercase())
+            };
+            tokens.push((start, token));
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| source[i..].starts_with(**symbol))
+                .ok_or_else(|| ParseError {
+                    position: i,
+                    message: format!("unexpected '{}'", c),
+                })?;
+            tokens.push((i, Token::Symbol(symbol)));
+            i += symbol.len();
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    end: usize, // Position reported for a missing token at the end
+}
+
+// Lowest precedence first
+const LEVELS: [&[(&str, BinaryOp)]; 7] = [
+    &[("||", BinaryOp::Or)],
+    &[("&&", BinaryOp::And)],
+    &[
+        ("==", BinaryOp::Equal),
+        ("!=", BinaryOp::NotEqual),
+        ("<=", BinaryOp::LessEqual),
+        (">=", BinaryOp::GreaterEqual),
+        ("<", BinaryOp::Less),
+        (">", BinaryOp::Greater),
+    ],
+    &[("|", BinaryOp::BitOr)],
+    &[("^", BinaryOp::BitXor)],
+    &[("&", BinaryOp::BitAnd)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+];
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, token)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.next).map_or(self.end, |(position, _)| *position)
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, ParseError> {
+        Err(ParseError {
+            position: self.position(),
+            message: message.to_string(),
+        })
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(Token::Symbol(s)) if *s == symbol => {
+                self.next += 1;
+                Ok(())
+            }
+            _ => self.error(&format!("expected '{}'", symbol)),
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, ParseError> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol(s)) => LEVELS[level].iter().find(|(name, _)| name == s).map(|(_, op)| *op),
+                _ => None,
+            };
+            let Some(op) = op else {
+                return Ok(left);
+            };
+            self.next += 1;
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        let position = self.position();
+        let Some(token) = self.peek().cloned() else {
+            return self.error("expected a value");
+        };
+        self.next += 1;
+        match token {
+            Token::Number(value) => Ok(Expr::Number(value)),
+            Token::Symbol("!") => Ok(Expr::Not(Box::new(self.unary()?))),
+            Token::Symbol("-") => Ok(Expr::Negate(Box::new(self.unary()?))),
+            Token::Symbol("~") => Ok(Expr::Binary(
+                BinaryOp::BitXor,
+                Box::new(self.unary()?),
+                Box::new(Expr::Number(!0)),
+            )),
+            Token::Symbol("(") => {
+                let inner = self.binary(0)?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            Token::Symbol("[") => {
+                let inner = self.binary(0)?;
+                self.expect("]")?;
+                Ok(Expr::Memory(Box::new(inner)))
+            }
+            Token::Name(name) => name_value(&name).ok_or_else(|| ParseError {
+                position,
+                message: format!("unknown name '{}'", name),
+            }),
+            Token::Symbol(symbol) => Err(ParseError {
+                position,
+                message: format!("unexpected '{}'", symbol),
+            }),
+        }
+    }
+}
+
+fn name_value(name: &str) -> Option<Expr> {
+    let register = match name {
+        "sp" => Some(13),
+        "lr" => Some(14),
+        "pc" => Some(15),
+        _ => name.strip_prefix('r').and_then(|n| n.parse::<usize>().ok()).filter(|&n| n < 16),
+    };
+    if let Some(register) = register {
+        return Some(Expr::Register(register));
+    }
+    match name {
+        "cpsr" => Some(Expr::Cpsr),
+        "spsr" => Some(Expr::Spsr),
+        _ => {
+            let flag = name.strip_prefix("cpsr.")?;
+            match flag {
+                "n" | "z" | "c" | "v" | "i" | "f" | "t" => flag.chars().next().map(Expr::Flag),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// A parsed break condition.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Condition, ParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            next: 0,
+            end: source.len(),
+        };
+        let expr = parser.binary(0)?;
+        if parser.peek().is_some() {
+            return parser.error("unexpected input");
+        }
+        Ok(Condition {
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn evaluate<M: MemoryBus>(&self, state: &CpuState, memory: &mut M) -> u32 {
+        let value = evaluate(&self.expr, state, memory);
+        // A condition reading somewhere unmapped is not the program's fault
+        let _ = memory.take_fault();
+        value
+    }
+
+    pub fn is_true<M: MemoryBus>(&self, state: &CpuState, memory: &mut M) -> bool {
+        self.evaluate(state, memory) != 0
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+fn evaluate<M: MemoryBus>(expr: &Expr, state: &CpuState, memory: &mut M) -> u32 {
+    match expr {
+        Expr::Number(value) => *value,
+        Expr::Register(i) => state.registers[*i],
+        Expr::Cpsr => state.CPSR.value,
+        Expr::Spsr => state.SPSR.value,
+        Expr::Flag(flag) => {
+            let cpsr = &state.CPSR;
+            let set = match flag {
+                'n' => cpsr.is_negative(),
+                'z' => cpsr.is_zero(),
+                'c' => cpsr.is_carry(),
+                'v' => cpsr.is_overflow(),
+                'i' => cpsr.is_irq_disabled(),
+                'f' => cpsr.is_fiq_disabled(),
+                _ => cpsr.is_thumb_state(),
+            };
+            set as u32
+        }
+        Expr::Memory(address) => {
+            let address = evaluate(address, state, memory);
+            memory.read_word(address)
+        }
+        Expr::Not(inner) => (evaluate(inner, state, memory) == 0) as u32,
+        Expr::Negate(inner) => evaluate(inner, state, memory).wrapping_neg(),
+        Expr::Binary(op, left, right) => {
+            let left = evaluate(left, state, memory);
+            // && and || short-circuit, the right side may read memory
+            match op {
+                BinaryOp::And if left == 0 => return 0,
+                BinaryOp::Or if left != 0 => return 1,
+                _ => {}
+            }
+            let right = evaluate(right, state, memory);
+            match op {
+                BinaryOp::Or | BinaryOp::And => (right != 0) as u32,
+                BinaryOp::Equal => (left == right) as u32,
+                BinaryOp::NotEqual => (left != right) as u32,
+                BinaryOp::Less => (left < right) as u32,
+                BinaryOp::LessEqual => (left <= right) as u32,
+                BinaryOp::Greater => (left > right) as u32,
+                BinaryOp::GreaterEqual => (left >= right) as u32,
+                BinaryOp::BitOr => left | right,
+                BinaryOp::BitXor => left ^ right,
+                BinaryOp::BitAnd => left & right,
+                BinaryOp::Add => left.wrapping_add(right),
+                BinaryOp::Sub => left.wrapping_sub(right),
+            }
+        }
+    }
+}
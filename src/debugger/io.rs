@@ -0,0 +1,138 @@
+// src/debugger/io.rs
+// Names of the I/O registers, as GBATEK spells them, so they can be looked at by name.
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct IoRegister {
+    pub name: &'static str,
+    pub address: u32,
+    pub size: u32, // In bytes
+}
+
+const fn reg(name: &'static str, offset: u32, size: u32) -> IoRegister {
+    IoRegister {
+        name,
+        address: 0x0400_0000 + offset,
+        size,
+    }
+}
+
+pub const IO_REGISTERS: &[IoRegister] = &[
+    // LCD
+    reg("DISPCNT", 0x000, 2),
+    reg("GREENSWAP", 0x002, 2),
+    reg("DISPSTAT", 0x004, 2),
+    reg("VCOUNT", 0x006, 2),
+    reg("BG0CNT", 0x008, 2),
+    reg("BG1CNT", 0x00A, 2),
+    reg("BG2CNT", 0x00C, 2),
+    reg("BG3CNT", 0x00E, 2),
+    reg("BG0HOFS", 0x010, 2),
+    reg("BG0VOFS", 0x012, 2),
+    reg("BG1HOFS", 0x014, 2),
+    reg("BG1VOFS", 0x016, 2),
+    reg("BG2HOFS", 0x018, 2),
+    reg("BG2VOFS", 0x01A, 2),
+    reg("BG3HOFS", 0x01C, 2),
+    reg("BG3VOFS", 0x01E, 2),
+    reg("BG2PA", 0x020, 2),
+    reg("BG2PB", 0x022, 2),
+    reg("BG2PC", 0x024, 2),
+    reg("BG2PD", 0x026, 2),
+    reg("BG2X", 0x028, 4),
+    reg("BG2Y", 0x02C, 4),
+    reg("BG3PA", 0x030, 2),
+    reg("BG3PB", 0x032, 2),
+    reg("BG3PC", 0x034, 2),
+    reg("BG3PD", 0x036, 2),
+    reg("BG3X", 0x038, 4),
+    reg("BG3Y", 0x03C, 4),
+    reg("WIN0H", 0x040, 2),
+    reg("WIN1H", 0x042, 2),
+    reg("WIN0V", 0x044, 2),
+    reg("WIN1V", 0x046, 2),
+    reg("WININ", 0x048, 2),
+    reg("WINOUT", 0x04A, 2),
+    reg("MOSAIC", 0x04C, 2),
+    reg("BLDCNT", 0x050, 2),
+    reg("BLDALPHA", 0x052, 2),
+    reg("BLDY", 0x054, 2),
+    // Sound
+    reg("SOUND1CNT_L", 0x060, 2),
+    reg("SOUND1CNT_H", 0x062, 2),
+    reg("SOUND1CNT_X", 0x064, 2),
+    reg("SOUND2CNT_L", 0x068, 2),
+    reg("SOUND2CNT_H", 0x06C, 2),
+    reg("SOUND3CNT_L", 0x070, 2),
+    reg("SOUND3CNT_H", 0x072, 2),
+    reg("SOUND3CNT_X", 0x074, 2),
+    reg("SOUND4CNT_L", 0x078, 2),
+    reg("SOUND4CNT_H", 0x07C, 2),
+    reg("SOUNDCNT_L", 0x080, 2),
+    reg("SOUNDCNT_H", 0x082, 2),
+    reg("SOUNDCNT_X", 0x084, 2),
+    reg("SOUNDBIAS", 0x088, 2),
+    reg("FIFO_A", 0x0A0, 4),
+    reg("FIFO_B", 0x0A4, 4),
+    // DMA
+    reg("DMA0SAD", 0x0B0, 4),
+    reg("DMA0DAD", 0x0B4, 4),
+    reg("DMA0CNT_L", 0x0B8, 2),
+    reg("DMA0CNT_H", 0x0BA, 2),
+    reg("DMA1SAD", 0x0BC, 4),
+    reg("DMA1DAD", 0x0C0, 4),
+    reg("DMA1CNT_L", 0x0C4, 2),
+    reg("DMA1CNT_H", 0x0C6, 2),
+    reg("DMA2SAD", 0x0C8, 4),
+    reg("DMA2DAD", 0x0CC, 4),
+    reg("DMA2CNT_L", 0x0D0, 2),
+    reg("DMA2CNT_H", 0x0D2, 2),
+    reg("DMA3SAD", 0x0D4, 4),
+    reg("DMA3DAD", 0x0D8, 4),
+    reg("DMA3CNT_L", 0x0DC, 2),
+    reg("DMA3CNT_H", 0x0DE, 2),
+    // Timers
+    reg("TM0CNT_L", 0x100, 2),
+    reg("TM0CNT_H", 0x102, 2),
+    reg("TM1CNT_L", 0x104, 2),
+    reg("TM1CNT_H", 0x106, 2),
+    reg("TM2CNT_L", 0x108, 2),
+    reg("TM2CNT_H", 0x10A, 2),
+    reg("TM3CNT_L", 0x10C, 2),
+    reg("TM3CNT_H", 0x10E, 2),
+    // Serial and keypad
+    reg("SIODATA32", 0x120, 4),
+    reg("SIOMULTI0", 0x120, 2),
+    reg("SIOMULTI1", 0x122, 2),
+    reg("SIOMULTI2", 0x124, 2),
+    reg("SIOMULTI3", 0x126, 2),
+    reg("SIOCNT", 0x128, 2),
+    reg("SIODATA8", 0x12A, 2),
+    reg("KEYINPUT", 0x130, 2),
+    reg("KEYCNT", 0x132, 2),
+    reg("RCNT", 0x134, 2),
+    reg("JOYCNT", 0x140, 2),
+    reg("JOY_RECV", 0x150, 4),
+    reg("JOY_TRANS", 0x154, 4),
+    reg("JOYSTAT", 0x158, 2),
+    // Interrupts, waitstates and power
+    reg("IE", 0x200, 2),
+    reg("IF", 0x202, 2),
+    reg("WAITCNT", 0x204, 2),
+    reg("IME", 0x208, 2),
+    reg("POSTFLG", 0x300, 1),
+    reg("HALTCNT", 0x301, 1),
+];
+
+/// Looks a register up by name, ignoring case and an optional `REG_` prefix.
+pub fn io_register(name: &str) -> Option<&'static IoRegister> {
+    let name = name.to_ascii_uppercase();
+    let name = name.strip_prefix("REG_").unwrap_or(&name);
+    IO_REGISTERS.iter().find(|register| register.name == name)
+}
+
+/// The register at `address`, the first one listed where two share an address.
+pub fn io_register_at(address: u32) -> Option<&'static IoRegister> {
+    IO_REGISTERS
+        .iter()
+        .find(|register| register.address == address)
+}
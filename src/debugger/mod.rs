@@ -0,0 +1,256 @@
+// src/debugger/mod.rs
+// Debugger core, kept on the Cpu and shared by every frontend (GDB stub, command line).
+// Breakpoints stop before the instruction at their address runs, watchpoints and I/O
+// breakpoints after the instruction that made the access, IRQ breaks once the CPU has entered
+// the exception. `Cpu::step` hands back `Step::Break` and the reason stays queryable until the
+// next step. With nothing set the CPU only checks a single flag per instruction.
+
+pub mod expr;
+pub mod io;
+pub mod repl;
+pub mod watch;
+
+use crate::cpu::{CpuState, Mode};
+use crate::memory::MemoryBus;
+pub use expr::{Condition, ParseError};
+pub use repl::Repl;
+use watch::WatchHit;
+pub use watch::{WatchKind, Watchpoint};
+
+const IO_START: u32 = 0x0400_0000;
+const IO_END: u32 = 0x0400_03FF;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum InstructionSet {
+    Arm,
+    Thumb,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Breakpoint {
+    pub address: u32,
+    pub state: Option<InstructionSet>, // Only in ARM or only in Thumb state
+    pub mode: Option<Mode>,            // Only in this processor mode
+    pub condition: Option<Condition>,
+}
+
+impl Breakpoint {
+    pub fn at(address: u32) -> Self {
+        Breakpoint {
+            address,
+            state: None,
+            mode: None,
+            condition: None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Watch {
+    pub range: Watchpoint,
+    pub condition: Option<Condition>,
+    pub io: bool, // An I/O register write breakpoint
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StopReason {
+    Breakpoint { id: usize, address: u32 },
+    Watchpoint { id: usize, pc: u32, address: u32, write: bool, value: u32 },
+    IoWrite { id: usize, pc: u32, address: u32, value: u32 },
+    Irq { return_address: u32 }, // Where the interrupted code continues
+}
+
+#[derive(Debug, Default)]
+pub struct Debugger {
+    next_id: usize,
+    breakpoints: Vec<(usize, Breakpoint)>,
+    watches: Vec<(usize, Watch)>,
+    ranges: Vec<Watchpoint>, // watches[i].range, in a slice for the WatchBus
+    break_on_irq: bool,
+    active: bool,
+    resume_from: Option<u32>, // Stopped at a breakpoint here, it doesn't stop us again
+    stop: Option<StopReason>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger::default()
+    }
+
+    fn update(&mut self) {
+        self.ranges = self.watches.iter().map(|(_, watch)| watch.range).collect();
+        self.active = !self.breakpoints.is_empty() || !self.watches.is_empty() || self.break_on_irq;
+    }
+
+    fn allocate_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// Returns an id for `remove`.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        let id = self.allocate_id();
+        self.breakpoints.push((id, breakpoint));
+        self.update();
+        id
+    }
+
+    pub fn add_watchpoint(
+        &mut self,
+        kind: WatchKind,
+        address: u32,
+        length: u32,
+        condition: Option<Condition>,
+    ) -> usize {
+        let range = Watchpoint {
+            kind,
+            address,
+            length: length.max(1),
+        };
+        self.add_watch(Watch {
+            range,
+            condition,
+            io: false,
+        })
+    }
+
+    /// Stops after a write to the I/O register at `address`, 0x04000000-0x040003FF.
+    pub fn add_io_breakpoint(&mut self, address: u32, condition: Option<Condition>) -> Option<usize> {
+        if !(IO_START..=IO_END).contains(&address) {
+            return None;
+        }
+        let range = Watchpoint {
+            kind: WatchKind::Write,
+            address,
+            length: 2, // I/O registers are halfwords
+        };
+        Some(self.add_watch(Watch {
+            range,
+            condition,
+            io: true,
+        }))
+    }
+
+    fn add_watch(&mut self, watch: Watch) -> usize {
+        let id = self.allocate_id();
+        self.watches.push((id, watch));
+        self.update();
+        id
+    }
+
+    /// Removes a breakpoint or watchpoint, returns whether `id` was one.
+    pub fn remove(&mut self, id: usize) -> bool {
+        let before = self.breakpoints.len() + self.watches.len();
+        self.breakpoints.retain(|(other, _)| *other != id);
+        self.watches.retain(|(other, _)| *other != id);
+        self.update();
+        before != self.breakpoints.len() + self.watches.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watches.clear();
+        self.update();
+    }
+
+    pub fn breakpoints(&self) -> &[(usize, Breakpoint)] {
+        &self.breakpoints
+    }
+
+    pub fn watches(&self) -> &[(usize, Watch)] {
+        &self.watches
+    }
+
+    pub fn set_break_on_irq(&mut self, enabled: bool) {
+        self.break_on_irq = enabled;
+        self.update();
+    }
+
+    pub fn break_on_irq(&self) -> bool {
+        self.break_on_irq
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub(crate) fn watching(&self) -> bool {
+        !self.ranges.is_empty()
+    }
+
+    /// Why the last step stopped, if it did.
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.stop
+    }
+
+    pub(crate) fn begin_step(&mut self) {
+        self.stop = None;
+    }
+
+    pub(crate) fn stopped(&mut self, reason: StopReason) -> StopReason {
+        self.stop = Some(reason);
+        reason
+    }
+
+    // Before the instruction at PC runs.
+    pub(crate) fn check_breakpoints<M: MemoryBus>(
+        &mut self,
+        state: &CpuState,
+        memory: &mut M,
+    ) -> Option<StopReason> {
+        let pc = state.registers[15];
+        if self.resume_from.take() == Some(pc) {
+            return None;
+        }
+        let thumb = state.CPSR.is_thumb_state();
+        let mode = state.mode();
+        let (id, _) = self.breakpoints.iter().find(|(_, breakpoint)| {
+            breakpoint.address == pc
+                && breakpoint.state.is_none_or(|set| (set == InstructionSet::Thumb) == thumb)
+                && breakpoint.mode.is_none_or(|m| m == mode)
+                && breakpoint.condition.as_ref().is_none_or(|c| c.is_true(state, memory))
+        })?;
+        self.resume_from = Some(pc);
+        Some(self.stopped(StopReason::Breakpoint { id: *id, address: pc }))
+    }
+
+    pub(crate) fn take_ranges(&mut self) -> Vec<Watchpoint> {
+        std::mem::take(&mut self.ranges)
+    }
+
+    pub(crate) fn restore_ranges(&mut self, ranges: Vec<Watchpoint>) {
+        self.ranges = ranges;
+    }
+
+    // After the instruction at `pc` made the accesses in `hits`.
+    pub(crate) fn check_hits<M: MemoryBus>(
+        &mut self,
+        hits: &[WatchHit],
+        pc: u32,
+        state: &CpuState,
+        memory: &mut M,
+    ) -> Option<StopReason> {
+        let (hit, id, io) = hits.iter().find_map(|hit| {
+            let (id, watch) = &self.watches[hit.index];
+            let passes = watch.condition.as_ref().is_none_or(|c| c.is_true(state, memory));
+            passes.then_some((hit, *id, watch.io))
+        })?;
+        let reason = if io {
+            StopReason::IoWrite {
+                id,
+                pc,
+                address: hit.address,
+                value: hit.value,
+            }
+        } else {
+            StopReason::Watchpoint {
+                id,
+                pc,
+                address: hit.address,
+                write: hit.write,
+                value: hit.value,
+            }
+        };
+        Some(self.stopped(reason))
+    }
+}
@@ -0,0 +1,527 @@
+// src/debugger/repl.rs
+// Command-line debugger, for when there is no GDB around. Reads one command per line, an empty
+// line repeats the last one. Addresses are numbers (0x for hex), symbols or break condition
+// expressions such as `sp + 8` or `[r0]`.
+
+use std::io::{self, BufRead, Write};
+
+use super::io::{io_register, io_register_at, IO_REGISTERS};
+use super::{Breakpoint, Condition, StopReason, WatchKind};
+use crate::cpu::{Cpu, Step};
+use crate::disasm::{annotate, disassemble_arm, disassemble_thumb};
+use crate::memory::MemoryBus;
+use crate::symbols::SymbolTable;
+
+const HELP: &str = "\
+step [n]                       run n instructions (s)
+continue [n]                   run until something stops us, at most n instructions (c)
+break LOC [if COND]            stop before the instruction at LOC runs (b)
+watch|rwatch|awatch LOC [LEN] [if COND]
+                               stop after a write, read or either touches LOC..LOC+LEN
+iobreak REG [if COND]          stop after a write to an I/O register
+irq on|off                     stop when an IRQ is taken
+delete [ID]                    remove a breakpoint or watchpoint, or all of them
+breakpoints                    list breakpoints and watchpoints (bp)
+registers                      show registers and CPSR flags (r)
+x LOC [LEN]                    hex dump memory
+disassemble [LOC] [COUNT]      disassemble in the current instruction set (dis)
+io [REG]                       show I/O registers, all or one
+symbols FILE                   load symbols from a .sym or .elf file (sym)
+quit                           (q)";
+
+#[derive(Debug, Default)]
+pub struct Repl {
+    pub symbols: SymbolTable,
+    last_command: String,
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Repl::default()
+    }
+
+    /// Reads commands from `input` until it ends or `quit`.
+    pub fn run<M: MemoryBus, R: BufRead, W: Write>(
+        &mut self,
+        cpu: &mut Cpu,
+        memory: &mut M,
+        mut input: R,
+        output: &mut W,
+    ) -> io::Result<()> {
+        self.show_next(cpu, memory, output)?;
+        loop {
+            write!(output, "(gba) ")?;
+            output.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            if !self.execute(cpu, memory, line.trim(), output)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Runs one command, returns false for `quit`.
+    pub fn execute<M: MemoryBus, W: Write>(
+        &mut self,
+        cpu: &mut Cpu,
+        memory: &mut M,
+        line: &str,
+        out: &mut W,
+    ) -> io::Result<bool> {
+        let line = if line.is_empty() {
+            self.last_command.clone()
+        } else {
+            line.to_string()
+        };
+        if line.is_empty() {
+            return Ok(true);
+        }
+        self.last_command = line.clone();
+        // `... if COND` puts a condition on breakpoints and watchpoints
+        let (line, condition) = match line.split_once(" if ") {
+            Some((command, condition)) => match Condition::parse(condition.trim()) {
+                Ok(condition) => (command.to_string(), Some(condition)),
+                Err(err) => {
+                    writeln!(out, "Bad condition: {}", err)?;
+                    return Ok(true);
+                }
+            },
+            None => (line, None),
+        };
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+        match self.command(cpu, memory, command, &args, condition, out) {
+            Ok(keep_going) => Ok(keep_going),
+            Err(Failure::Io(err)) => Err(err),
+            Err(Failure::Usage(message)) => {
+                writeln!(out, "{}", message)?;
+                Ok(true)
+            }
+        }
+    }
+
+    fn command<M: MemoryBus, W: Write>(
+        &mut self,
+        cpu: &mut Cpu,
+        memory: &mut M,
+        command: &str,
+        args: &[&str],
+        condition: Option<Condition>,
+        out: &mut W,
+    ) -> Result<bool, Failure> {
+        match command {
+            "help" | "h" | "?" => writeln!(out, "{}", HELP)?,
+            "quit" | "q" => return Ok(false),
+            "step" | "s" => {
+                let count = self.count(cpu, memory, args.first(), 1)?;
+                self.resume(cpu, memory, Some(count), out)?;
+            }
+            "continue" | "c" => {
+                let limit = match args.first() {
+                    Some(_) => Some(self.count(cpu, memory, args.first(), 0)?),
+                    None => None,
+                };
+                self.resume(cpu, memory, limit, out)?;
+            }
+            "break" | "b" => {
+                let address = self.location(cpu, memory, args.first())?;
+                let id = cpu.debugger.add_breakpoint(Breakpoint {
+                    condition,
+                    ..Breakpoint::at(address)
+                });
+                writeln!(out, "Breakpoint {} at {}", id, self.describe(address))?;
+            }
+            "watch" | "rwatch" | "awatch" => {
+                let kind = match command {
+                    "watch" => WatchKind::Write,
+                    "rwatch" => WatchKind::Read,
+                    _ => WatchKind::Access,
+                };
+                let address = self.location(cpu, memory, args.first())?;
+                let length = self.count(cpu, memory, args.get(1), 4)?;
+                let id = cpu
+                    .debugger
+                    .add_watchpoint(kind, address, length, condition);
+                writeln!(
+                    out,
+                    "Watchpoint {} at {}, {} bytes",
+                    id,
+                    self.describe(address),
+                    length
+                )?;
+            }
+            "iobreak" => {
+                let name = args
+                    .first()
+                    .ok_or(Failure::Usage("iobreak needs a register".into()))?;
+                let address = match io_register(name) {
+                    Some(register) => register.address,
+                    None => self.location(cpu, memory, Some(name))?,
+                };
+                let id = cpu
+                    .debugger
+                    .add_io_breakpoint(address, condition)
+                    .ok_or_else(|| {
+                        Failure::Usage(format!("0x{:08X} is not an I/O register", address))
+                    })?;
+                writeln!(out, "I/O breakpoint {} on {}", id, io_name(address))?;
+            }
+            "irq" => {
+                let enabled = match args.first().copied() {
+                    Some("on") => true,
+                    Some("off") => false,
+                    _ => return Err(Failure::Usage("irq needs on or off".into())),
+                };
+                cpu.debugger.set_break_on_irq(enabled);
+            }
+            "delete" | "d" => match args.first() {
+                None => cpu.debugger.clear(),
+                Some(id) => {
+                    let id = id
+                        .parse()
+                        .map_err(|_| Failure::Usage(format!("Bad id '{}'", id)))?;
+                    if !cpu.debugger.remove(id) {
+                        writeln!(out, "No breakpoint or watchpoint {}", id)?;
+                    }
+                }
+            },
+            "breakpoints" | "bp" => self.list_breakpoints(cpu, out)?,
+            "registers" | "r" => show_registers(cpu, out)?,
+            "x" => {
+                let address = self.location(cpu, memory, args.first())?;
+                let length = self.count(cpu, memory, args.get(1), 64)?;
+                hex_dump(memory, address, length, out)?;
+            }
+            "disassemble" | "dis" => {
+                let address = match args.first() {
+                    Some(_) => self.location(cpu, memory, args.first())?,
+                    None => cpu.cpu_state.reg(15),
+                };
+                let count = self.count(cpu, memory, args.get(1), 8)?;
+                let thumb = cpu.cpu_state.CPSR.is_thumb_state();
+                let mut address = address;
+                for _ in 0..count {
+                    let (line, size) = self.instruction_line(memory, address, thumb);
+                    writeln!(out, "{}", line)?;
+                    address = address.wrapping_add(size);
+                }
+            }
+            "io" => match args.first() {
+                None => {
+                    for register in IO_REGISTERS {
+                        let value = read_sized(memory, register.address, register.size);
+                        writeln!(
+                            out,
+                            "{:<12}{:08X}  {:0width$X}",
+                            register.name,
+                            register.address,
+                            value,
+                            width = register.size as usize * 2
+                        )?;
+                    }
+                }
+                Some(name) => {
+                    let register = io_register(name)
+                        .ok_or_else(|| Failure::Usage(format!("No I/O register {}", name)))?;
+                    let value = read_sized(memory, register.address, register.size);
+                    writeln!(
+                        out,
+                        "{} (0x{:08X}) = 0x{:0width$X}",
+                        register.name,
+                        register.address,
+                        value,
+                        width = register.size as usize * 2
+                    )?;
+                }
+            },
+            "symbols" | "sym" => {
+                let path = args
+                    .first()
+                    .ok_or(Failure::Usage("symbols needs a file".into()))?;
+                match SymbolTable::load(path) {
+                    Ok(table) => {
+                        writeln!(out, "Loaded {} symbols from {}", table.len(), path)?;
+                        self.symbols.extend(table);
+                    }
+                    Err(err) => writeln!(out, "Failed to load {}: {}", path, err)?,
+                }
+            }
+            _ => writeln!(out, "Unknown command '{}', try help", command)?,
+        }
+        Ok(true)
+    }
+
+    // Steps until a break, an error, a stopped system or `limit` instructions.
+    fn resume<M: MemoryBus, W: Write>(
+        &self,
+        cpu: &mut Cpu,
+        memory: &mut M,
+        limit: Option<u32>,
+        out: &mut W,
+    ) -> io::Result<()> {
+        let mut count = 0;
+        while limit.is_none_or(|limit| count < limit) {
+            count += 1;
+            match cpu.step(memory) {
+                Ok(Step::Executed(_)) | Ok(Step::Halted { .. }) => {}
+                Ok(Step::Break(reason)) => {
+                    writeln!(out, "{}", self.stop_message(reason))?;
+                    break;
+                }
+                Ok(Step::Stopped) => {
+                    writeln!(out, "The system is stopped")?;
+                    break;
+                }
+                Err(err) => {
+                    writeln!(out, "Error: {}", err)?;
+                    break;
+                }
+            }
+        }
+        self.show_next(cpu, memory, out)
+    }
+
+    fn show_next<M: MemoryBus, W: Write>(
+        &self,
+        cpu: &Cpu,
+        memory: &mut M,
+        out: &mut W,
+    ) -> io::Result<()> {
+        let thumb = cpu.cpu_state.CPSR.is_thumb_state();
+        let (line, _) = self.instruction_line(memory, cpu.cpu_state.reg(15), thumb);
+        writeln!(out, "=> {}", line)
+    }
+
+    fn stop_message(&self, reason: StopReason) -> String {
+        match reason {
+            StopReason::Breakpoint { id, address } => {
+                format!("Breakpoint {} at {}", id, self.describe(address))
+            }
+            StopReason::Watchpoint {
+                id,
+                pc,
+                address,
+                write,
+                value,
+            } => {
+                let access = if write { "Write" } else { "Read" };
+                format!(
+                    "Watchpoint {}: {} of 0x{:X} at {} by {}",
+                    id,
+                    access,
+                    value,
+                    self.describe(address),
+                    self.describe(pc)
+                )
+            }
+            StopReason::IoWrite {
+                id,
+                pc,
+                address,
+                value,
+            } => format!(
+                "I/O breakpoint {}: {} = 0x{:X} written by {}",
+                id,
+                io_name(address),
+                value,
+                self.describe(pc)
+            ),
+            StopReason::Irq { return_address } => {
+                format!("IRQ taken, returns to {}", self.describe(return_address))
+            }
+        }
+    }
+
+    fn list_breakpoints<W: Write>(&self, cpu: &Cpu, out: &mut W) -> io::Result<()> {
+        for (id, breakpoint) in cpu.debugger.breakpoints() {
+            write!(
+                out,
+                "{:<4}break   {}",
+                id,
+                self.describe(breakpoint.address)
+            )?;
+            if let Some(condition) = &breakpoint.condition {
+                write!(out, " if {}", condition)?;
+            }
+            writeln!(out)?;
+        }
+        for (id, watch) in cpu.debugger.watches() {
+            let kind = match (watch.io, watch.range.kind) {
+                (true, _) => "iobreak",
+                (false, WatchKind::Write) => "watch",
+                (false, WatchKind::Read) => "rwatch",
+                (false, WatchKind::Access) => "awatch",
+            };
+            let at = if watch.io {
+                io_name(watch.range.address)
+            } else {
+                format!(
+                    "{}, {} bytes",
+                    self.describe(watch.range.address),
+                    watch.range.length
+                )
+            };
+            write!(out, "{:<4}{:<8}{}", id, kind, at)?;
+            if let Some(condition) = &watch.condition {
+                write!(out, " if {}", condition)?;
+            }
+            writeln!(out)?;
+        }
+        if cpu.debugger.break_on_irq() {
+            writeln!(out, "    irq")?;
+        }
+        Ok(())
+    }
+
+    // `0x08000000 <main+0x4>`, or just the address without a symbol
+    fn describe(&self, address: u32) -> String {
+        match self.symbols.label(address) {
+            Some(label) => format!("0x{:08X} <{}>", address, label),
+            None => format!("0x{:08X}", address),
+        }
+    }
+
+    fn instruction_line<M: MemoryBus>(
+        &self,
+        memory: &mut M,
+        address: u32,
+        thumb: bool,
+    ) -> (String, u32) {
+        let (opcode, text, size) = if thumb {
+            let opcode = memory.read_halfword(address);
+            let next = memory.read_halfword(address.wrapping_add(2));
+            let (text, size) = disassemble_thumb(opcode, next, address);
+            let opcode = if size == 4 {
+                format!("{:04x} {:04x}", opcode, next)
+            } else {
+                format!("{:04x}", opcode)
+            };
+            (opcode, text, size)
+        } else {
+            let opcode = memory.read_word(address);
+            (
+                format!("{:08x}", opcode),
+                disassemble_arm(opcode, address),
+                4,
+            )
+        };
+        let _ = memory.take_fault();
+        let text = annotate(&text, &self.symbols);
+        (
+            format!("{}:  {:<9}  {}", self.describe(address), opcode, text),
+            size,
+        )
+    }
+
+    fn location<M: MemoryBus>(
+        &self,
+        cpu: &Cpu,
+        memory: &mut M,
+        arg: Option<&&str>,
+    ) -> Result<u32, Failure> {
+        let arg = arg.ok_or(Failure::Usage("missing address".into()))?;
+        if let Some(address) = self.symbols.address_of(arg) {
+            return Ok(address);
+        }
+        let expression = Condition::parse(arg)
+            .map_err(|err| Failure::Usage(format!("Bad address '{}': {}", arg, err)))?;
+        Ok(expression.evaluate(&cpu.cpu_state, memory))
+    }
+
+    fn count<M: MemoryBus>(
+        &self,
+        cpu: &Cpu,
+        memory: &mut M,
+        arg: Option<&&str>,
+        default: u32,
+    ) -> Result<u32, Failure> {
+        match arg {
+            Some(_) => self.location(cpu, memory, arg),
+            None => Ok(default),
+        }
+    }
+}
+
+enum Failure {
+    Io(io::Error),
+    Usage(String), // Told to the user, who can try again
+}
+
+impl From<io::Error> for Failure {
+    fn from(err: io::Error) -> Self {
+        Failure::Io(err)
+    }
+}
+
+fn io_name(address: u32) -> String {
+    match io_register_at(address).or_else(|| io_register_at(address & !1)) {
+        Some(register) if register.address == address => register.name.to_string(),
+        Some(register) => format!("{}+{}", register.name, address - register.address),
+        None => format!("0x{:08X}", address),
+    }
+}
+
+fn read_sized<M: MemoryBus>(memory: &mut M, address: u32, size: u32) -> u32 {
+    let value = match size {
+        1 => memory.read_byte(address) as u32,
+        2 => memory.read_halfword(address) as u32,
+        _ => memory.read_word(address),
+    };
+    let _ = memory.take_fault();
+    value
+}
+
+fn show_registers<W: Write>(cpu: &Cpu, out: &mut W) -> io::Result<()> {
+    let state = &cpu.cpu_state;
+    for row in 0..4 {
+        let line: Vec<String> = (row * 4..row * 4 + 4)
+            .map(|i| format!("{:<4}{:08X}", format!("r{}", i), state.reg(i)))
+            .collect();
+        writeln!(out, "{}", line.join("  "))?;
+    }
+    writeln!(
+        out,
+        "cpsr {:08X}  spsr {:08X}  mode {:?}  cycles {}",
+        state.CPSR.value,
+        state.SPSR.value,
+        state.mode(),
+        cpu.cycles
+    )?;
+    writeln!(out, "{}", state.CPSR.all_flags())
+}
+
+fn hex_dump<M: MemoryBus, W: Write>(
+    memory: &mut M,
+    address: u32,
+    length: u32,
+    out: &mut W,
+) -> io::Result<()> {
+    let bytes: Vec<u8> = (0..length)
+        .map(|i| memory.read_byte(address.wrapping_add(i)))
+        .collect();
+    let _ = memory.take_fault();
+    for (row, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let text: String = chunk
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        writeln!(
+            out,
+            "{:08X}  {:<47}  |{}|",
+            address.wrapping_add(row as u32 * 16),
+            hex.join(" "),
+            text
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,136 @@
+// src/debugger/watch.rs
+// Watchpoints. The CPU runs against this wrapper instead of the bus while any are set, it
+// passes everything through and remembers the accesses that touched a watched range.
+
+use crate::error::EmulatorError;
+use crate::interrupt::PowerState;
+use crate::memory::MemoryBus;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum WatchKind {
+    Write,
+    Read,
+    Access, // Either
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Watchpoint {
+    pub kind: WatchKind,
+    pub address: u32,
+    pub length: u32,
+}
+
+impl Watchpoint {
+    fn matches(&self, write: bool, address: u32, size: u32) -> bool {
+        let kind_matches = match self.kind {
+            WatchKind::Write => write,
+            WatchKind::Read => !write,
+            WatchKind::Access => true,
+        };
+        // Overlap of [address, address + size) with the watched range
+        kind_matches
+            && address < self.address.wrapping_add(self.length)
+            && self.address < address.wrapping_add(size)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct WatchHit {
+    pub index: usize, // Into the slice the WatchBus was given
+    pub address: u32,
+    pub write: bool,
+    pub value: u32, // Written, or read back
+}
+
+pub struct WatchBus<'a, M: MemoryBus> {
+    inner: &'a mut M,
+    watchpoints: &'a [Watchpoint],
+    pub hits: Vec<WatchHit>,
+}
+
+impl<'a, M: MemoryBus> WatchBus<'a, M> {
+    pub fn new(inner: &'a mut M, watchpoints: &'a [Watchpoint]) -> Self {
+        WatchBus {
+            inner,
+            watchpoints,
+            hits: Vec::new(),
+        }
+    }
+
+    fn check(&mut self, write: bool, address: u32, size: u32, value: u32) {
+        for (index, watchpoint) in self.watchpoints.iter().enumerate() {
+            if watchpoint.matches(write, address, size) {
+                self.hits.push(WatchHit {
+                    index,
+                    address,
+                    write,
+                    value,
+                });
+            }
+        }
+    }
+}
+
+impl<M: MemoryBus> MemoryBus for WatchBus<'_, M> {
+    fn read_byte(&mut self, address: u32) -> u8 {
+        let value = self.inner.read_byte(address);
+        self.check(false, address, 1, value as u32);
+        value
+    }
+
+    fn read_halfword(&mut self, address: u32) -> u16 {
+        let value = self.inner.read_halfword(address);
+        self.check(false, address, 2, value as u32);
+        value
+    }
+
+    fn read_word(&mut self, address: u32) -> u32 {
+        let value = self.inner.read_word(address);
+        self.check(false, address, 4, value);
+        value
+    }
+
+    fn write_byte(&mut self, address: u32, value: u8) {
+        self.check(true, address, 1, value as u32);
+        self.inner.write_byte(address, value)
+    }
+
+    fn write_halfword(&mut self, address: u32, value: u16) {
+        self.check(true, address, 2, value as u32);
+        self.inner.write_halfword(address, value)
+    }
+
+    fn write_word(&mut self, address: u32, value: u32) {
+        self.check(true, address, 4, value);
+        self.inner.write_word(address, value)
+    }
+
+    // Opcode fetches are not data accesses, breakpoints cover those
+    fn fetch_word(&mut self, address: u32) -> u32 {
+        self.inner.fetch_word(address)
+    }
+
+    fn take_fault(&mut self) -> Option<EmulatorError> {
+        self.inner.take_fault()
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.inner.tick(cycles)
+    }
+
+    fn irq_line(&self) -> bool {
+        self.inner.irq_line()
+    }
+
+    fn power_state(&mut self) -> PowerState {
+        self.inner.power_state()
+    }
+
+    fn wait_for_event(&mut self) -> u64 {
+        self.inner.wait_for_event()
+    }
+
+    fn frame_number(&self) -> Option<u64> {
+        self.inner.frame_number()
+    }
+}
@@ -0,0 +1,339 @@
+// src/disasm/arm.rs
+// ARM state (ARMv4T as the ARM7TDMI runs it), straight from the opcode bits. Encodings that
+// mean nothing come out as ".word".
+
+use crate::cpu_instructions::instruction_decoding::decode_rotated_immediate;
+
+use super::{
+    block_mode, condition, immediate, immediate_address, reg, register_list, shift_name,
+    shift_type, shifted_register,
+};
+
+const DATA_PROCESSING: [&str; 16] = [
+    "and", "eor", "sub", "rsb", "add", "adc", "sbc", "rsc", "tst", "teq", "cmp", "cmn", "orr",
+    "mov", "bic", "mvn",
+];
+
+fn bit(opcode: u32, n: u32) -> bool {
+    (opcode >> n) & 1 == 1
+}
+
+fn field(opcode: u32, shift: u32) -> usize {
+    ((opcode >> shift) & 0xF) as usize
+}
+
+/// Disassembles the ARM instruction `opcode` found at `address`.
+pub fn disassemble_arm(opcode: u32, address: u32) -> String {
+    let cond = condition(opcode >> 28);
+    if opcode >> 28 == 0xF {
+        // The NV space, nothing in ARMv4
+        return word(opcode);
+    }
+    if opcode & 0x0FFF_FFF0 == 0x012F_FF10 {
+        return format!("bx{} {}", cond, reg(field(opcode, 0)));
+    }
+    if opcode & 0x0FFF_FFF0 == 0x012F_FF30 {
+        return format!("blx{} {}", cond, reg(field(opcode, 0)));
+    }
+    match (opcode >> 25) & 7 {
+        0b000 => {
+            if opcode & 0x0FC0_00F0 == 0x0000_0090 {
+                multiply(opcode, cond)
+            } else if opcode & 0x0F80_00F0 == 0x0080_0090 {
+                multiply_long(opcode, cond)
+            } else if opcode & 0x0FB0_0FF0 == 0x0100_0090 {
+                let b = if bit(opcode, 22) { "b" } else { "" };
+                format!(
+                    "swp{}{} {}, {}, [{}]",
+                    b,
+                    cond,
+                    reg(field(opcode, 12)),
+                    reg(field(opcode, 0)),
+                    reg(field(opcode, 16))
+                )
+            } else if opcode & 0x0E00_0090 == 0x0000_0090 {
+                halfword_transfer(opcode, cond, address)
+            } else if opcode & 0x0190_0000 == 0x0100_0000 {
+                // TST, TEQ, CMP and CMN without S are the PSR transfers
+                psr_transfer(opcode, cond)
+            } else {
+                data_processing(opcode, cond, address)
+            }
+        }
+        0b001 => {
+            if opcode & 0x0190_0000 == 0x0100_0000 {
+                psr_transfer(opcode, cond)
+            } else {
+                data_processing(opcode, cond, address)
+            }
+        }
+        0b010 => single_transfer(opcode, cond, address),
+        0b011 => {
+            if bit(opcode, 4) {
+                word(opcode)
+            } else {
+                single_transfer(opcode, cond, address)
+            }
+        }
+        0b100 => block_transfer(opcode, cond),
+        0b101 => {
+            let link = if bit(opcode, 24) { "l" } else { "" };
+            let offset = ((opcode << 8) as i32 >> 6) as u32;
+            let target = address.wrapping_add(8).wrapping_add(offset);
+            format!("b{}{} 0x{:08X}", link, cond, target)
+        }
+        0b110 => coprocessor_transfer(opcode, cond),
+        _ => {
+            if bit(opcode, 24) {
+                format!("svc{} 0x{:08X}", cond, opcode & 0x00FF_FFFF)
+            } else {
+                coprocessor_operation(opcode, cond)
+            }
+        }
+    }
+}
+
+fn word(opcode: u32) -> String {
+    format!(".word 0x{:08X}", opcode)
+}
+
+fn literal(address: u32) -> String {
+    format!(" ; 0x{:08X}", address)
+}
+
+fn operand2(opcode: u32) -> String {
+    if bit(opcode, 25) {
+        return immediate(decode_rotated_immediate(opcode));
+    }
+    let rm = field(opcode, 0);
+    let shift = shift_type(opcode >> 5);
+    if bit(opcode, 4) {
+        format!("{}, {} {}", reg(rm), shift_name(shift), reg(field(opcode, 8)))
+    } else {
+        shifted_register(rm, shift, (opcode >> 7) & 0x1F)
+    }
+}
+
+fn data_processing(opcode: u32, cond: &str, address: u32) -> String {
+    let op = ((opcode >> 21) & 0xF) as usize;
+    let name = DATA_PROCESSING[op];
+    let s = if bit(opcode, 20) { "s" } else { "" };
+    let rd = reg(field(opcode, 12));
+    let rn = field(opcode, 16);
+    match op {
+        // Compares always set the flags, the S is implied
+        0x8..=0xB => format!("{}{} {}, {}", name, cond, reg(rn), operand2(opcode)),
+        0xD | 0xF => format!("{}{}{} {}, {}", name, s, cond, rd, operand2(opcode)),
+        _ => {
+            let mut text = format!("{}{}{} {}, {}, {}", name, s, cond, rd, reg(rn), operand2(opcode));
+            // ADD/SUB rd, pc, #imm is how a PC-relative address gets built
+            if rn == 15 && bit(opcode, 25) && (op == 0x2 || op == 0x4) {
+                let imm = decode_rotated_immediate(opcode);
+                let base = address.wrapping_add(8);
+                let target = if op == 0x4 { base.wrapping_add(imm) } else { base.wrapping_sub(imm) };
+                text.push_str(&literal(target));
+            }
+            text
+        }
+    }
+}
+
+fn psr_transfer(opcode: u32, cond: &str) -> String {
+    let psr = if bit(opcode, 22) { "spsr" } else { "cpsr" };
+    if !bit(opcode, 21) {
+        if opcode & 0x0FBF_0FFF == 0x010F_0000 {
+            return format!("mrs{} {}, {}", cond, reg(field(opcode, 12)), psr);
+        }
+        return word(opcode);
+    }
+    let mut fields = String::new();
+    for (n, name) in [(19, 'f'), (18, 's'), (17, 'x'), (16, 'c')] {
+        if bit(opcode, n) {
+            fields.push(name);
+        }
+    }
+    let source = if bit(opcode, 25) {
+        immediate(decode_rotated_immediate(opcode))
+    } else {
+        reg(field(opcode, 0)).to_string()
+    };
+    format!("msr{} {}_{}, {}", cond, psr, fields, source)
+}
+
+fn multiply(opcode: u32, cond: &str) -> String {
+    let s = if bit(opcode, 20) { "s" } else { "" };
+    let rd = reg(field(opcode, 16));
+    let rm = reg(field(opcode, 0));
+    let rs = reg(field(opcode, 8));
+    if bit(opcode, 21) {
+        format!("mla{}{} {}, {}, {}, {}", s, cond, rd, rm, rs, reg(field(opcode, 12)))
+    } else {
+        format!("mul{}{} {}, {}, {}", s, cond, rd, rm, rs)
+    }
+}
+
+fn multiply_long(opcode: u32, cond: &str) -> String {
+    let sign = if bit(opcode, 22) { "s" } else { "u" };
+    let op = if bit(opcode, 21) { "mlal" } else { "mull" };
+    let s = if bit(opcode, 20) { "s" } else { "" };
+    format!(
+        "{}{}{}{} {}, {}, {}, {}",
+        sign,
+        op,
+        s,
+        cond,
+        reg(field(opcode, 12)),
+        reg(field(opcode, 16)),
+        reg(field(opcode, 0)),
+        reg(field(opcode, 8))
+    )
+}
+
+// [rn, ±rm{, shift}]{!} or [rn], ±rm{, shift}
+fn register_address(opcode: u32, rm_text: String) -> String {
+    let rn = reg(field(opcode, 16));
+    let sign = if bit(opcode, 23) { "" } else { "-" };
+    if bit(opcode, 24) {
+        let bang = if bit(opcode, 21) { "!" } else { "" };
+        format!("[{}, {}{}]{}", rn, sign, rm_text, bang)
+    } else {
+        format!("[{}], {}{}", rn, sign, rm_text)
+    }
+}
+
+fn pc_relative(opcode: u32, offset: u32, address: u32) -> String {
+    // Only a pre-indexed immediate offset from the PC points at a fixed address
+    if field(opcode, 16) != 15 || !bit(opcode, 24) {
+        return String::new();
+    }
+    let base = address.wrapping_add(8);
+    literal(if bit(opcode, 23) { base.wrapping_add(offset) } else { base.wrapping_sub(offset) })
+}
+
+fn single_transfer(opcode: u32, cond: &str, address: u32) -> String {
+    let name = if bit(opcode, 20) { "ldr" } else { "str" };
+    let b = if bit(opcode, 22) { "b" } else { "" };
+    // Post-indexed with W set is the user mode access
+    let t = if !bit(opcode, 24) && bit(opcode, 21) { "t" } else { "" };
+    let rd = reg(field(opcode, 12));
+    if bit(opcode, 25) {
+        let rm = shifted_register(field(opcode, 0), shift_type(opcode >> 5), (opcode >> 7) & 0x1F);
+        return format!("{}{}{}{} {}, {}", name, b, t, cond, rd, register_address(opcode, rm));
+    }
+    let offset = opcode & 0xFFF;
+    let pre_index = bit(opcode, 24);
+    let addressing = immediate_address(
+        field(opcode, 16),
+        offset,
+        pre_index,
+        bit(opcode, 23),
+        pre_index && bit(opcode, 21),
+    );
+    let note = pc_relative(opcode, offset, address);
+    format!("{}{}{}{} {}, {}{}", name, b, t, cond, rd, addressing, note)
+}
+
+fn halfword_transfer(opcode: u32, cond: &str, address: u32) -> String {
+    let load = bit(opcode, 20);
+    let name = match ((opcode >> 5) & 3, load) {
+        (1, false) => "strh",
+        (1, true) => "ldrh",
+        (2, false) => "ldrd",
+        (2, true) => "ldrsb",
+        (3, false) => "strd",
+        (3, true) => "ldrsh",
+        _ => return word(opcode),
+    };
+    let rd = reg(field(opcode, 12));
+    if !bit(opcode, 22) {
+        let rm = reg(field(opcode, 0)).to_string();
+        return format!("{}{} {}, {}", name, cond, rd, register_address(opcode, rm));
+    }
+    let offset = ((opcode >> 4) & 0xF0) | (opcode & 0xF);
+    let pre_index = bit(opcode, 24);
+    let addressing = immediate_address(
+        field(opcode, 16),
+        offset,
+        pre_index,
+        bit(opcode, 23),
+        pre_index && bit(opcode, 21),
+    );
+    let note = pc_relative(opcode, offset, address);
+    format!("{}{} {}, {}{}", name, cond, rd, addressing, note)
+}
+
+fn block_transfer(opcode: u32, cond: &str) -> String {
+    let load = bit(opcode, 20);
+    let pre_index = bit(opcode, 24);
+    let add = bit(opcode, 23);
+    let user = if bit(opcode, 22) { "^" } else { "" };
+    let write_back = bit(opcode, 21);
+    let rn = field(opcode, 16);
+    let list = register_list((opcode & 0xFFFF) as u16);
+    // The stack idiom: LDMIA sp! and STMDB sp!
+    if rn == 13 && write_back && user.is_empty() && add != pre_index && load == add {
+        let name = if load { "pop" } else { "push" };
+        return format!("{}{} {}", name, cond, list);
+    }
+    let name = if load { "ldm" } else { "stm" };
+    let mode = match block_mode(pre_index, add) {
+        "ia" => "",
+        mode => mode,
+    };
+    let bang = if write_back { "!" } else { "" };
+    format!("{}{}{} {}{}, {}{}", name, mode, cond, reg(rn), bang, list, user)
+}
+
+fn coprocessor_transfer(opcode: u32, cond: &str) -> String {
+    let name = if bit(opcode, 20) { "ldc" } else { "stc" };
+    let long = if bit(opcode, 22) { "l" } else { "" };
+    let pre_index = bit(opcode, 24);
+    let addressing = immediate_address(
+        field(opcode, 16),
+        (opcode & 0xFF) * 4,
+        pre_index,
+        bit(opcode, 23),
+        pre_index && bit(opcode, 21),
+    );
+    format!(
+        "{}{}{} p{}, c{}, {}",
+        name,
+        long,
+        cond,
+        field(opcode, 8),
+        field(opcode, 12),
+        addressing
+    )
+}
+
+fn coprocessor_operation(opcode: u32, cond: &str) -> String {
+    let cp = field(opcode, 8);
+    let crn = field(opcode, 16);
+    let crm = field(opcode, 0);
+    let op2 = (opcode >> 5) & 7;
+    if bit(opcode, 4) {
+        let name = if bit(opcode, 20) { "mrc" } else { "mcr" };
+        format!(
+            "{}{} p{}, {}, {}, c{}, c{}, {{{}}}",
+            name,
+            cond,
+            cp,
+            (opcode >> 21) & 7,
+            reg(field(opcode, 12)),
+            crn,
+            crm,
+            op2
+        )
+    } else {
+        format!(
+            "cdp{} p{}, {}, c{}, c{}, c{}, {{{}}}",
+            cond,
+            cp,
+            (opcode >> 20) & 0xF,
+            field(opcode, 12),
+            crn,
+            crm,
+            op2
+        )
+    }
+}
@@ -0,0 +1,270 @@
+// src/disasm/mod.rs
+// Disassembler, GNU/UAL syntax as objdump prints it: condition and S suffixes, shifted
+// operands, register lists, absolute branch targets and the address a PC-relative load reads
+// from as a comment. Works from the raw opcode, which has the condition field the decoded
+// `Instruction` leaves out. `Instruction` itself gets a Display in the same syntax.
+
+pub mod arm;
+pub mod thumb;
+
+use std::fmt;
+
+use crate::cpu_instructions::instruction_decoding::{Instruction, ShiftType};
+use crate::symbols::SymbolTable;
+
+pub use arm::disassemble_arm;
+pub use thumb::disassemble_thumb;
+
+const CONDITIONS: [&str; 16] = [
+    "eq", "ne", "cs", "cc", "mi", "pl", "vs", "vc", "hi", "ls", "ge", "lt", "gt", "le", "", "nv",
+];
+
+pub(crate) fn condition(cond: u32) -> &'static str {
+    CONDITIONS[(cond & 0xF) as usize]
+}
+
+pub(crate) fn reg(index: usize) -> &'static str {
+    const NAMES: [&str; 16] = [
+        "r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7", "r8", "r9", "r10", "r11", "r12", "sp",
+        "lr", "pc",
+    ];
+    NAMES[index & 0xF]
+}
+
+pub(crate) fn immediate(value: u32) -> String {
+    if value < 0x100 {
+        format!("#{}", value)
+    } else {
+        format!("#0x{:X}", value)
+    }
+}
+
+/// Adds the symbol for a line's branch target or literal address, objdump style:
+/// `bl 0x08000240 <main>`, `ldr r0, [pc, #8] ; 0x08000130 <table+0x4>`.
+pub fn annotate(text: &str, symbols: &SymbolTable) -> String {
+    let target = match text.split_once("; ") {
+        Some((_, literal)) => Some(literal),
+        None => match text.split_once(' ') {
+            Some((mnemonic, operand)) if mnemonic.starts_with('b') => Some(operand),
+            _ => None,
+        },
+    };
+    let address = target
+        .and_then(|target| target.strip_prefix("0x"))
+        .filter(|hex| hex.len() == 8)
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok());
+    match address.and_then(|address| symbols.label(address)) {
+        Some(label) => format!("{} <{}>", text, label),
+        None => text.to_string(),
+    }
+}
+
+pub(crate) fn signed_immediate(value: u32, add: bool) -> String {
+    if add {
+        immediate(value)
+    } else if value < 0x100 {
+        format!("#-{}", value)
+    } else {
+        format!("#-0x{:X}", value)
+    }
+}
+
+pub(crate) fn shift_name(shift: ShiftType) -> &'static str {
+    match shift {
+        ShiftType::LSL => "lsl",
+        ShiftType::LSR => "lsr",
+        ShiftType::ASR => "asr",
+        ShiftType::ROR => "ror",
+    }
+}
+
+pub(crate) fn shift_type(bits: u32) -> ShiftType {
+    match bits & 3 {
+        0 => ShiftType::LSL,
+        1 => ShiftType::LSR,
+        2 => ShiftType::ASR,
+        _ => ShiftType::ROR,
+    }
+}
+
+/// `rm` shifted by an immediate, as encoded: LSR/ASR #0 mean #32 and ROR #0 is RRX.
+pub(crate) fn shifted_register(rm: usize, shift: ShiftType, amount: u32) -> String {
+    match (shift, amount) {
+        (ShiftType::LSL, 0) => reg(rm).to_string(),
+        (ShiftType::ROR, 0) => format!("{}, rrx", reg(rm)),
+        (ShiftType::LSR | ShiftType::ASR, 0) => format!("{}, {} #32", reg(rm), shift_name(shift)),
+        _ => format!("{}, {} #{}", reg(rm), shift_name(shift), amount),
+    }
+}
+
+/// "{r0, r4, lr}", every register spelled out like objdump does.
+pub(crate) fn register_list(list: u16) -> String {
+    let names: Vec<&str> = (0..16).filter(|i| list & (1 << i) != 0).map(reg).collect();
+    format!("{{{}}}", names.join(", "))
+}
+
+pub(crate) fn block_mode(pre_index: bool, add: bool) -> &'static str {
+    match (pre_index, add) {
+        (false, true) => "ia",
+        (true, true) => "ib",
+        (false, false) => "da",
+        (true, false) => "db",
+    }
+}
+
+// [rn, #offset]{!} or [rn], #offset
+pub(crate) fn immediate_address(
+    rn: usize,
+    offset: u32,
+    pre_index: bool,
+    add: bool,
+    write_back: bool,
+) -> String {
+    if !pre_index {
+        format!("[{}], {}", reg(rn), signed_immediate(offset, add))
+    } else if offset == 0 && add {
+        format!("[{}]{}", reg(rn), if write_back { "!" } else { "" })
+    } else {
+        let bang = if write_back { "!" } else { "" };
+        format!("[{}, {}]{}", reg(rn), signed_immediate(offset, add), bang)
+    }
+}
+
+// The decoded instruction has no condition or address, so branches show their target relative
+// to the instruction, ".+N", which GNU as reads the same way.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = |set_flags: bool| if set_flags { "s" } else { "" };
+        let op2 = |rm: usize, shift: ShiftType, amount: u8| shifted_register(rm, shift, amount as u32);
+        match *self {
+            Instruction::MovImmediate { rd, imm12, set_flags } => {
+                write!(f, "mov{} {}, {}", s(set_flags), reg(rd), immediate(imm12))
+            }
+            Instruction::MvnImmediate { rd, imm12, set_flags } => {
+                write!(f, "mvn{} {}, {}", s(set_flags), reg(rd), immediate(imm12))
+            }
+            Instruction::MovRegister { rd, rm, shift, shift_amount, set_flags } => {
+                write!(f, "mov{} {}, {}", s(set_flags), reg(rd), op2(rm, shift, shift_amount))
+            }
+            Instruction::MvnRegister { rd, rm, shift, shift_amount, set_flags } => {
+                write!(f, "mvn{} {}, {}", s(set_flags), reg(rd), op2(rm, shift, shift_amount))
+            }
+            Instruction::CmpImmediate { rn, imm12 } => write!(f, "cmp {}, {}", reg(rn), immediate(imm12)),
+            Instruction::CmnImmediate { rn, imm12 } => write!(f, "cmn {}, {}", reg(rn), immediate(imm12)),
+            Instruction::CmpRegister { rn, rm, shift, shift_amount } => {
+                write!(f, "cmp {}, {}", reg(rn), op2(rm, shift, shift_amount))
+            }
+            Instruction::CmnRegister { rn, rm, shift, shift_amount } => {
+                write!(f, "cmn {}, {}", reg(rn), op2(rm, shift, shift_amount))
+            }
+            Instruction::AddImmediate { rd, rn, imm12, set_flags }
+            | Instruction::SubImmediate { rd, rn, imm12, set_flags }
+            | Instruction::AndImmediate { rd, rn, imm12, set_flags }
+            | Instruction::OrrImmediate { rd, rn, imm12, set_flags }
+            | Instruction::AdcImmediate { rd, rn, imm12, set_flags }
+            | Instruction::SbcImmediate { rd, rn, imm12, set_flags }
+            | Instruction::EorImmediate { rd, rn, imm12, set_flags }
+            | Instruction::BicImmediate { rd, rn, imm12, set_flags }
+            | Instruction::RsbImmediate { rd, rn, imm12, set_flags }
+            | Instruction::RscImmediate { rd, rn, imm12, set_flags } => write!(
+                f,
+                "{}{} {}, {}, {}",
+                self.mnemonic(),
+                s(set_flags),
+                reg(rd),
+                reg(rn),
+                immediate(imm12)
+            ),
+            Instruction::AddRegister { rd, rn, rm, shift, shift_amount, set_flags }
+            | Instruction::SubRegister { rd, rn, rm, shift, shift_amount, set_flags }
+            | Instruction::AndRegister { rd, rn, rm, shift, shift_amount, set_flags }
+            | Instruction::OrrRegister { rd, rn, rm, shift, shift_amount, set_flags }
+            | Instruction::AdcRegister { rd, rn, rm, shift, shift_amount, set_flags }
+            | Instruction::SbcRegister { rd, rn, rm, shift, shift_amount, set_flags }
+            | Instruction::EorRegister { rd, rn, rm, shift, shift_amount, set_flags }
+            | Instruction::BicRegister { rd, rn, rm, shift, shift_amount, set_flags }
+            | Instruction::RsbRegister { rd, rn, rm, shift, shift_amount, set_flags }
+            | Instruction::RscRegister { rd, rn, rm, shift, shift_amount, set_flags } => write!(
+                f,
+                "{}{} {}, {}, {}",
+                self.mnemonic(),
+                s(set_flags),
+                reg(rd),
+                reg(rn),
+                op2(rm, shift, shift_amount)
+            ),
+            Instruction::Branch { branch_type, imm24 } => {
+                let offset = ((imm24 << 8) as i32 >> 6).wrapping_add(8);
+                let name = match branch_type {
+                    crate::cpu_instructions::branch_ops::BranchType::B => "b",
+                    crate::cpu_instructions::branch_ops::BranchType::BL => "bl",
+                };
+                write!(f, "{} .{:+}", name, offset)
+            }
+            Instruction::BranchExchange { rm } => write!(f, "bx {}", reg(rm)),
+            Instruction::BranchLinkExchange { rm } => write!(f, "blx {}", reg(rm)),
+            Instruction::Ldr { rt, rn, offset, pre_index, add, write_back } => write!(
+                f,
+                "ldr {}, {}",
+                reg(rt),
+                immediate_address(rn, offset, pre_index, add, write_back)
+            ),
+            Instruction::Ldrb { rt, rn, offset, pre_index, add, write_back } => write!(
+                f,
+                "ldrb {}, {}",
+                reg(rt),
+                immediate_address(rn, offset, pre_index, add, write_back)
+            ),
+            Instruction::Ldrd { rt, rn, offset, pre_index, add, write_back } => write!(
+                f,
+                "ldrd {}, {}, {}",
+                reg(rt),
+                reg(rt + 1),
+                immediate_address(rn, offset, pre_index, add, write_back)
+            ),
+            Instruction::Ldm { rn, register_list: list, pre_index, add, write_back } => {
+                if rn == 13 && write_back && !pre_index && add {
+                    return write!(f, "pop {}", register_list(list));
+                }
+                let mode = block_mode(pre_index, add);
+                let bang = if write_back { "!" } else { "" };
+                let mode = if mode == "ia" { "" } else { mode };
+                write!(f, "ldm{} {}{}, {}", mode, reg(rn), bang, register_list(list))
+            }
+            Instruction::SoftwareInterrupt { comment } => write!(f, "svc 0x{:08X}", comment),
+            Instruction::Unknown(opcode) => write!(f, "{}", disassemble_arm(opcode, 0)),
+            Instruction::Nop => write!(f, "nop"),
+        }
+    }
+}
+
+impl Instruction {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::AddImmediate { .. } | Instruction::AddRegister { .. } => "add",
+            Instruction::SubImmediate { .. } | Instruction::SubRegister { .. } => "sub",
+            Instruction::AndImmediate { .. } | Instruction::AndRegister { .. } => "and",
+            Instruction::OrrImmediate { .. } | Instruction::OrrRegister { .. } => "orr",
+            Instruction::AdcImmediate { .. } | Instruction::AdcRegister { .. } => "adc",
+            Instruction::SbcImmediate { .. } | Instruction::SbcRegister { .. } => "sbc",
+            Instruction::EorImmediate { .. } | Instruction::EorRegister { .. } => "eor",
+            Instruction::BicImmediate { .. } | Instruction::BicRegister { .. } => "bic",
+            Instruction::RsbImmediate { .. } | Instruction::RsbRegister { .. } => "rsb",
+            Instruction::RscImmediate { .. } | Instruction::RscRegister { .. } => "rsc",
+            Instruction::MovImmediate { .. } | Instruction::MovRegister { .. } => "mov",
+            Instruction::MvnImmediate { .. } | Instruction::MvnRegister { .. } => "mvn",
+            Instruction::CmpImmediate { .. } | Instruction::CmpRegister { .. } => "cmp",
+            Instruction::CmnImmediate { .. } | Instruction::CmnRegister { .. } => "cmn",
+            Instruction::Branch { .. } => "b",
+            Instruction::BranchExchange { .. } => "bx",
+            Instruction::BranchLinkExchange { .. } => "blx",
+            Instruction::Ldr { .. } => "ldr",
+            Instruction::Ldrb { .. } => "ldrb",
+            Instruction::Ldrd { .. } => "ldrd",
+            Instruction::Ldm { .. } => "ldm",
+            Instruction::SoftwareInterrupt { .. } => "svc",
+            Instruction::Unknown(_) => ".word",
+            Instruction::Nop => "nop",
+        }
+    }
+}
@@ -0,0 +1,176 @@
+// src/disasm/thumb.rs
+// Thumb state. The CPU does not run Thumb code yet, but game code is full of it and the
+// debugger needs to show it. BL is a pair of halfwords, so the one after `opcode` is passed in
+// as well and the caller is told how many bytes were used.
+
+use super::{condition, immediate, reg, register_list};
+
+const ALU: [&str; 16] = [
+    "ands", "eors", "lsls", "lsrs", "asrs", "adcs", "sbcs", "rors", "tst", "negs", "cmp", "cmn",
+    "orrs", "muls", "bics", "mvns",
+];
+
+fn low(opcode: u16, shift: u32) -> usize {
+    ((opcode >> shift) & 7) as usize
+}
+
+/// Disassembles the Thumb instruction `opcode` found at `address`, `next` being the halfword
+/// after it. Returns the text and the instruction's size in bytes, 2 or 4.
+pub fn disassemble_thumb(opcode: u16, next: u16, address: u32) -> (String, u32) {
+    let op = opcode as u32;
+    let text = match opcode >> 11 {
+        // Move shifted register, LSL #0 is a plain MOVS
+        0b00000 if (op >> 6) & 0x1F == 0 => {
+            format!("movs {}, {}", reg(low(opcode, 0)), reg(low(opcode, 3)))
+        }
+        0b00000..=0b00010 => {
+            let name = ["lsls", "lsrs", "asrs"][(op >> 11) as usize];
+            let amount = match (op >> 6) & 0x1F {
+                0 => 32, // LSR/ASR #0 shift by 32
+                n => n,
+            };
+            format!("{} {}, {}, #{}", name, reg(low(opcode, 0)), reg(low(opcode, 3)), amount)
+        }
+        0b00011 => {
+            let name = if (op >> 9) & 1 == 1 { "subs" } else { "adds" };
+            let operand = if (op >> 10) & 1 == 1 {
+                immediate((op >> 6) & 7)
+            } else {
+                reg(low(opcode, 6)).to_string()
+            };
+            format!("{} {}, {}, {}", name, reg(low(opcode, 0)), reg(low(opcode, 3)), operand)
+        }
+        0b00100..=0b00111 => {
+            let name = ["movs", "cmp", "adds", "subs"][((op >> 11) & 3) as usize];
+            format!("{} {}, {}", name, reg(low(opcode, 8)), immediate(op & 0xFF))
+        }
+        0b01000 if (op >> 10) & 1 == 0 => {
+            let name = ALU[((op >> 6) & 0xF) as usize];
+            format!("{} {}, {}", name, reg(low(opcode, 0)), reg(low(opcode, 3)))
+        }
+        0b01000 => hi_register(op),
+        0b01001 => {
+            let offset = (op & 0xFF) * 4;
+            let target = (address.wrapping_add(4) & !2).wrapping_add(offset);
+            format!(
+                "ldr {}, [pc, {}] ; 0x{:08X}",
+                reg(low(opcode, 8)),
+                immediate(offset),
+                target
+            )
+        }
+        0b01010 | 0b01011 => {
+            let name = ["str", "strh", "strb", "ldrsb", "ldr", "ldrh", "ldrb", "ldrsh"]
+                [((op >> 9) & 7) as usize];
+            format!(
+                "{} {}, [{}, {}]",
+                name,
+                reg(low(opcode, 0)),
+                reg(low(opcode, 3)),
+                reg(low(opcode, 6))
+            )
+        }
+        0b01100..=0b01111 => {
+            let byte = (op >> 12) & 1 == 1;
+            let name = match ((op >> 11) & 1 == 1, byte) {
+                (false, false) => "str",
+                (true, false) => "ldr",
+                (false, true) => "strb",
+                (true, true) => "ldrb",
+            };
+            let offset = ((op >> 6) & 0x1F) * if byte { 1 } else { 4 };
+            offset_address(name, opcode, offset)
+        }
+        0b10000 | 0b10001 => {
+            let name = if (op >> 11) & 1 == 1 { "ldrh" } else { "strh" };
+            offset_address(name, opcode, ((op >> 6) & 0x1F) * 2)
+        }
+        0b10010 | 0b10011 => {
+            let name = if (op >> 11) & 1 == 1 { "ldr" } else { "str" };
+            format!("{} {}, [sp, {}]", name, reg(low(opcode, 8)), immediate((op & 0xFF) * 4))
+        }
+        0b10100 => {
+            let offset = (op & 0xFF) * 4;
+            let target = (address.wrapping_add(4) & !2).wrapping_add(offset);
+            format!(
+                "add {}, pc, {} ; 0x{:08X}",
+                reg(low(opcode, 8)),
+                immediate(offset),
+                target
+            )
+        }
+        0b10101 => format!("add {}, sp, {}", reg(low(opcode, 8)), immediate((op & 0xFF) * 4)),
+        0b10110 | 0b10111 => miscellaneous(op),
+        0b11000 | 0b11001 => {
+            let load = (op >> 11) & 1 == 1;
+            let rb = low(opcode, 8);
+            // A load that includes the base does not write it back
+            let bang = if load && op & (1 << rb) != 0 { "" } else { "!" };
+            let name = if load { "ldmia" } else { "stmia" };
+            format!("{} {}{}, {}", name, reg(rb), bang, register_list((op & 0xFF) as u16))
+        }
+        0b11010 | 0b11011 => match (op >> 8) & 0xF {
+            0xF => format!("svc {}", immediate(op & 0xFF)),
+            0xE => format!(".short 0x{:04X}", opcode),
+            cond => {
+                let offset = ((op << 24) as i32 >> 23) as u32;
+                format!("b{} 0x{:08X}", condition(cond), address.wrapping_add(4).wrapping_add(offset))
+            }
+        },
+        0b11100 => {
+            let offset = ((op << 21) as i32 >> 20) as u32;
+            format!("b 0x{:08X}", address.wrapping_add(4).wrapping_add(offset))
+        }
+        0b11110 if next >> 11 == 0b11111 => {
+            let high = ((op << 21) as i32 >> 9) as u32;
+            let target = address
+                .wrapping_add(4)
+                .wrapping_add(high)
+                .wrapping_add((next as u32 & 0x7FF) << 1);
+            return (format!("bl 0x{:08X}", target), 4);
+        }
+        // Half a BL on its own, or the ARMv5 BLX suffix
+        _ => format!(".short 0x{:04X}", opcode),
+    };
+    (text, 2)
+}
+
+fn offset_address(name: &str, opcode: u16, offset: u32) -> String {
+    let rb = reg(low(opcode, 3));
+    if offset == 0 {
+        format!("{} {}, [{}]", name, reg(low(opcode, 0)), rb)
+    } else {
+        format!("{} {}, [{}, {}]", name, reg(low(opcode, 0)), rb, immediate(offset))
+    }
+}
+
+fn hi_register(op: u32) -> String {
+    let rd = ((op & 7) | ((op >> 4) & 8)) as usize;
+    let rs = ((op >> 3) & 0xF) as usize;
+    match (op >> 8) & 3 {
+        0 => format!("add {}, {}", reg(rd), reg(rs)),
+        1 => format!("cmp {}, {}", reg(rd), reg(rs)),
+        2 if rd == 8 && rs == 8 => String::from("nop"),
+        2 => format!("mov {}, {}", reg(rd), reg(rs)),
+        _ => format!("bx {}", reg(rs)),
+    }
+}
+
+fn miscellaneous(op: u32) -> String {
+    match (op >> 8) & 0xF {
+        0b0000 => {
+            let name = if (op >> 7) & 1 == 1 { "sub" } else { "add" };
+            format!("{} sp, {}", name, immediate((op & 0x7F) * 4))
+        }
+        0b0100 | 0b0101 | 0b1100 | 0b1101 => {
+            let pop = (op >> 11) & 1 == 1;
+            let mut list = (op & 0xFF) as u16;
+            if (op >> 8) & 1 == 1 {
+                list |= if pop { 1 << 15 } else { 1 << 14 };
+            }
+            let name = if pop { "pop" } else { "push" };
+            format!("{} {}", name, register_list(list))
+        }
+        _ => format!(".short 0x{:04X}", op),
+    }
+}
@@ -0,0 +1,236 @@
+// src/dma.rs
+// The four DMA channels (GBATEK "GBA DMA Transfers").
+
+use crate::bus::Bus;
+use crate::interrupt::Interrupt;
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+pub const DMA_REGISTERS_START: u32 = 0xB0;
+pub const DMA_REGISTERS_END: u32 = 0xDF;
+const CHANNEL_STRIDE: u32 = 12;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DmaTiming {
+    Immediate,
+    VBlank,
+    HBlank,
+    Special, // Sound FIFO for DMA1/2, video capture for DMA3
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AddressControl {
+    Increment,
+    Decrement,
+    Fixed,
+    IncrementReload, // Destination only, reloaded on every repeat
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DmaChannel {
+    pub source: u32,
+    pub destination: u32,
+    pub count: u16,
+    pub control: u16,
+    // Latched when the channel gets enabled
+    internal_source: u32,
+    internal_destination: u32,
+}
+
+impl DmaChannel {
+    pub fn is_enabled(&self) -> bool {
+        (self.control >> 15) & 1 == 1
+    }
+
+    pub fn timing(&self) -> DmaTiming {
+        match (self.control >> 12) & 0b11 {
+            0 => DmaTiming::Immediate,
+            1 => DmaTiming::VBlank,
+            2 => DmaTiming::HBlank,
+            _ => DmaTiming::Special,
+        }
+    }
+
+    pub fn is_word_transfer(&self) -> bool {
+        (self.control >> 10) & 1 == 1
+    }
+
+    pub fn repeat(&self) -> bool {
+        (self.control >> 9) & 1 == 1
+    }
+
+    pub fn irq_on_end(&self) -> bool {
+        (self.control >> 14) & 1 == 1
+    }
+
+    fn address_control(bits: u16) -> AddressControl {
+        match bits & 0b11 {
+            0 => AddressControl::Increment,
+            1 => AddressControl::Decrement,
+            2 => AddressControl::Fixed,
+            _ => AddressControl::IncrementReload,
+        }
+    }
+
+    pub fn destination_control(&self) -> AddressControl {
+        Self::address_control(self.control >> 5)
+    }
+
+    pub fn source_control(&self) -> AddressControl {
+        Self::address_control(self.control >> 7)
+    }
+}
+
+// Only DMA3 can reach the cartridge with its 28 bit addresses, the rest are limited to 27 bits.
+fn source_mask(channel: usize) -> u32 {
+    if channel == 0 {
+        0x07FF_FFFF
+    } else {
+        0x0FFF_FFFF
+    }
+}
+
+fn destination_mask(channel: usize) -> u32 {
+    if channel == 3 {
+        0x0FFF_FFFF
+    } else {
+        0x07FF_FFFF
+    }
+}
+
+// DMA0-2 only have a 14 bit count. A count of zero means the maximum length.
+fn transfer_length(channel: usize, count: u16) -> u32 {
+    match (channel, count) {
+        (3, 0) => 0x10000,
+        (3, count) => count as u32,
+        (_, count) if count & 0x3FFF == 0 => 0x4000,
+        (_, count) => (count & 0x3FFF) as u32,
+    }
+}
+
+fn step(control: AddressControl, unit: u32) -> u32 {
+    match control {
+        AddressControl::Increment | AddressControl::IncrementReload => unit,
+        AddressControl::Decrement => unit.wrapping_neg(),
+        AddressControl::Fixed => 0,
+    }
+}
+
+impl Bus {
+    // Called for every byte written into 0x040000B0-0x040000DF.
+    pub fn write_dma_register(&mut self, offset: u32, value: u8) {
+        let channel = ((offset - DMA_REGISTERS_START) / CHANNEL_STRIDE) as usize;
+        let register = (offset - DMA_REGISTERS_START) % CHANNEL_STRIDE;
+        let dma = &mut self.dma[channel];
+        let was_enabled = dma.is_enabled();
+        let shift = (register % 4) * 8;
+        match register {
+            0..=3 => dma.source = (dma.source & !(0xFF << shift)) | ((value as u32) << shift),
+            4..=7 => {
+                dma.destination = (dma.destination & !(0xFF << shift)) | ((value as u32) << shift)
+            }
+            8 | 9 => {
+                let shift = (register - 8) * 8;
+                dma.count = (dma.count & !(0xFF << shift)) | ((value as u16) << shift)
+            }
+            _ => {
+                let shift = (register - 10) * 8;
+                dma.control = (dma.control & !(0xFF << shift)) | ((value as u16) << shift)
+            }
+        }
+        if !was_enabled && dma.is_enabled() {
+            dma.internal_source = dma.source & source_mask(channel);
+            dma.internal_destination = dma.destination & destination_mask(channel);
+            if dma.timing() == DmaTiming::Immediate {
+                self.run_dma(channel);
+            }
+        }
+    }
+
+    pub fn read_dma_register(&self, offset: u32) -> u8 {
+        let channel = ((offset - DMA_REGISTERS_START) / CHANNEL_STRIDE) as usize;
+        let register = (offset - DMA_REGISTERS_START) % CHANNEL_STRIDE;
+        match register {
+            // Addresses and count are write only
+            10 => self.dma[channel].control as u8,
+            11 => (self.dma[channel].control >> 8) as u8,
+            _ => 0,
+        }
+    }
+
+    // Starts every enabled channel waiting for `timing`, in priority order.
+    pub fn trigger_dma(&mut self, timing: DmaTiming) {
+        for channel in 0..4 {
+            let dma = self.dma[channel];
+            if dma.is_enabled() && dma.timing() == timing {
+                self.run_dma(channel);
+            }
+        }
+    }
+
+    pub fn run_dma(&mut self, channel: usize) {
+        let mut dma = self.dma[channel];
+        // Sound FIFO refills are always four words into the same address, whatever the settings
+        let sound = matches!(channel, 1 | 2) && dma.timing() == DmaTiming::Special;
+        let length = if sound { 4 } else { transfer_length(channel, dma.count) };
+        let unit = if sound || dma.is_word_transfer() { 4 } else { 2 };
+        let source_step = step(dma.source_control(), unit);
+        let destination_step = if sound { 0 } else { step(dma.destination_control(), unit) };
+
+        // The EEPROM size is guessed from the length of the first request sent to it.
+        if channel == 3 {
+            if let Some(cartridge) = &mut self.cartridge {
+                if cartridge.is_eeprom_address(dma.internal_source)
+                    || cartridge.is_eeprom_address(dma.internal_destination)
+                {
+                    cartridge.detect_eeprom_size(length);
+                }
+            }
+        }
+
+        for _ in 0..length {
+            if unit == 4 {
+                let value = self.read_word(dma.internal_source & !3);
+                self.write_word(dma.internal_destination & !3, value);
+            } else {
+                let value = self.read_halfword(dma.internal_source & !1);
+                self.write_halfword(dma.internal_destination & !1, value);
+            }
+            dma.internal_source = dma.internal_source.wrapping_add(source_step);
+            dma.internal_destination = dma.internal_destination.wrapping_add(destination_step);
+        }
+
+        if dma.repeat() && dma.timing() != DmaTiming::Immediate {
+            if dma.destination_control() == AddressControl::IncrementReload {
+                dma.internal_destination = dma.destination & destination_mask(channel);
+            }
+        } else {
+            dma.control &= !(1 << 15);
+        }
+        self.dma[channel] = dma;
+        if dma.irq_on_end() {
+            let interrupt = [Interrupt::Dma0, Interrupt::Dma1, Interrupt::Dma2, Interrupt::Dma3];
+            self.request_interrupt(interrupt[channel]);
+        }
+    }
+}
+
+impl Snapshot for DmaChannel {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u32(self.source);
+        state.u32(self.destination);
+        state.u16(self.count);
+        state.u16(self.control);
+        state.u32(self.internal_source);
+        state.u32(self.internal_destination);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.source = state.u32()?;
+        self.destination = state.u32()?;
+        self.count = state.u16()?;
+        self.control = state.u16()?;
+        self.internal_source = state.u32()?;
+        self.internal_destination = state.u32()?;
+        Ok(())
+    }
+}
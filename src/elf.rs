@@ -0,0 +1,257 @@
+// src/elf.rs
+// ELF32 loader for homebrew, the `.elf` devkitARM leaves next to the `.gba`. PT_LOAD segments
+// go to their load address (the ROM copy of .data, not where crt0 copies it to), ROM segments
+// become the cartridge and RAM ones are written into EWRAM/IWRAM. The symbol table comes along
+// for the debugger, the disassembler and traces.
+
+use std::fmt;
+use std::path::Path;
+
+use crate::bios;
+use crate::cartridge::header::{CartridgeHeader, HEADER_SIZE};
+use crate::cartridge::{Cartridge, CartridgeError, MAX_ROM_SIZE};
+use crate::gba::Gba;
+use crate::symbols::SymbolTable;
+
+const MACHINE_ARM: u16 = 40;
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const STT_FUNC: u8 = 2;
+const STT_SECTION: u8 = 3;
+const STT_FILE: u8 = 4;
+
+const ROM_START: u32 = 0x0800_0000;
+const ROM_END: u32 = 0x0E00_0000; // All three waitstate mirrors
+                                  // Where a segment may go besides ROM: EWRAM and IWRAM
+const RAM_REGIONS: [(u32, u32); 2] = [(0x0200_0000, 0x0204_0000), (0x0300_0000, 0x0300_8000)];
+
+#[derive(Debug)]
+pub enum ElfError {
+    Io(std::io::Error),
+    NotElf,
+    Unsupported(&'static str), // Not a 32-bit little-endian ARM executable
+    Truncated,                 // A header or segment points past the end of the file
+    BadSegment { address: u32, size: u32 },
+    Cartridge(CartridgeError),
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ElfError::Io(err) => write!(f, "could not read ELF file: {}", err),
+            ElfError::NotElf => write!(f, "not an ELF file"),
+            ElfError::Unsupported(what) => write!(f, "unsupported ELF file: {}", what),
+            ElfError::Truncated => write!(f, "ELF file is truncated"),
+            ElfError::BadSegment { address, size } => write!(
+                f,
+                "segment at 0x{:08X}, {} bytes, is outside ROM, EWRAM and IWRAM",
+                address, size
+            ),
+            ElfError::Cartridge(err) => write!(f, "bad ROM image: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ElfError {}
+
+impl From<std::io::Error> for ElfError {
+    fn from(err: std::io::Error) -> Self {
+        ElfError::Io(err)
+    }
+}
+
+impl From<CartridgeError> for ElfError {
+    fn from(err: CartridgeError) -> Self {
+        ElfError::Cartridge(err)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Segment {
+    pub address: u32,
+    pub data: Vec<u8>,
+}
+
+impl Segment {
+    fn in_rom(&self) -> bool {
+        (ROM_START..ROM_END).contains(&self.address)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Elf {
+    pub entry: u32, // Bit 0 set for Thumb code
+    pub segments: Vec<Segment>,
+    pub symbols: SymbolTable,
+}
+
+// Little-endian field reads that fail instead of panicking on a short file
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, ElfError> {
+    let bytes = data.get(offset..offset + 2).ok_or(ElfError::Truncated)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, ElfError> {
+    let bytes = data.get(offset..offset + 4).ok_or(ElfError::Truncated)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn slice(data: &[u8], offset: u32, size: u32) -> Result<&[u8], ElfError> {
+    let start = offset as usize;
+    data.get(start..start + size as usize)
+        .ok_or(ElfError::Truncated)
+}
+
+fn c_string(table: &[u8], offset: u32) -> &str {
+    let bytes = table.get(offset as usize..).unwrap_or_default();
+    let end = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(bytes.len());
+    std::str::from_utf8(&bytes[..end]).unwrap_or_default()
+}
+
+impl Elf {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Elf, ElfError> {
+        Elf::parse(&std::fs::read(path)?)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Elf, ElfError> {
+        if !data.starts_with(b"\x7FELF") {
+            return Err(ElfError::NotElf);
+        }
+        if data.get(4) != Some(&1) {
+            return Err(ElfError::Unsupported("not 32-bit"));
+        }
+        if data.get(5) != Some(&1) {
+            return Err(ElfError::Unsupported("not little-endian"));
+        }
+        if u16_at(data, 0x12)? != MACHINE_ARM {
+            return Err(ElfError::Unsupported("not ARM"));
+        }
+        let entry = u32_at(data, 0x18)?;
+        let program_headers = u32_at(data, 0x1C)? as usize;
+        let program_header_size = u16_at(data, 0x2A)? as usize;
+        let program_header_count = u16_at(data, 0x2C)? as usize;
+
+        let mut segments = Vec::new();
+        for i in 0..program_header_count {
+            let header = program_headers + i * program_header_size;
+            if u32_at(data, header)? != PT_LOAD {
+                continue;
+            }
+            let offset = u32_at(data, header + 4)?;
+            let address = u32_at(data, header + 12)?; // p_paddr, the load address
+            let file_size = u32_at(data, header + 16)?;
+            // Zero-filled .bss has nothing to load, RAM starts out cleared
+            if file_size == 0 {
+                continue;
+            }
+            let segment = Segment {
+                address,
+                data: slice(data, offset, file_size)?.to_vec(),
+            };
+            let end = address as u64 + file_size as u64;
+            let fits = segment.in_rom() && end <= ROM_START as u64 + MAX_ROM_SIZE as u64
+                || RAM_REGIONS
+                    .iter()
+                    .any(|&(start, limit)| address >= start && end <= limit as u64);
+            if !fits {
+                return Err(ElfError::BadSegment {
+                    address,
+                    size: file_size,
+                });
+            }
+            segments.push(segment);
+        }
+
+        Ok(Elf {
+            entry,
+            segments,
+            symbols: Elf::parse_symbols(data)?,
+        })
+    }
+
+    fn parse_symbols(data: &[u8]) -> Result<SymbolTable, ElfError> {
+        let mut symbols = SymbolTable::new();
+        let section_headers = u32_at(data, 0x20)? as usize;
+        let section_header_size = u16_at(data, 0x2E)? as usize;
+        let section_count = u16_at(data, 0x30)? as usize;
+        let section = |index: usize| section_headers + index * section_header_size;
+        for i in 0..section_count {
+            if u32_at(data, section(i) + 4)? != SHT_SYMTAB {
+                continue;
+            }
+            let table = slice(
+                data,
+                u32_at(data, section(i) + 16)?,
+                u32_at(data, section(i) + 20)?,
+            )?;
+            let strings_index = u32_at(data, section(i) + 24)? as usize;
+            let strings = slice(
+                data,
+                u32_at(data, section(strings_index) + 16)?,
+                u32_at(data, section(strings_index) + 20)?,
+            )?;
+            for symbol in table.chunks_exact(16) {
+                let name = c_string(strings, u32_at(symbol, 0)?);
+                let mut value = u32_at(symbol, 4)?;
+                let size = u32_at(symbol, 8)?;
+                let kind = symbol[12] & 0xF;
+                let section_index = u16_at(symbol, 14)?;
+                // Undefined symbols, section and file names and the $a/$t/$d mapping symbols
+                // don't name anything worth showing
+                if name.is_empty()
+                    || name.starts_with('$')
+                    || section_index == 0
+                    || kind == STT_SECTION
+                    || kind == STT_FILE
+                {
+                    continue;
+                }
+                if kind == STT_FUNC {
+                    value &= !1; // The Thumb bit
+                }
+                symbols.insert_sized(value, size, name);
+            }
+        }
+        Ok(symbols)
+    }
+
+    /// The ROM segments laid out as a cartridge image, None for a multiboot program that lives
+    /// in EWRAM.
+    pub fn rom_image(&self) -> Option<Vec<u8>> {
+        let rom_segments = || self.segments.iter().filter(|segment| segment.in_rom());
+        let size = rom_segments()
+            .map(|segment| {
+                (segment.address - ROM_START) as usize % MAX_ROM_SIZE + segment.data.len()
+            })
+            .max()?;
+        let mut rom = vec![0u8; size.max(HEADER_SIZE)];
+        for segment in rom_segments() {
+            let offset = (segment.address - ROM_START) as usize % MAX_ROM_SIZE;
+            rom[offset..offset + segment.data.len()].copy_from_slice(&segment.data);
+        }
+        Some(rom)
+    }
+
+    /// A system ready to run the program: the ROM as its cartridge, RAM segments loaded, the
+    /// boot skipped and PC at the entry point.
+    pub fn build_gba(&self) -> Result<Gba, ElfError> {
+        let mut rom = self.rom_image().unwrap_or_else(|| vec![0u8; HEADER_SIZE]);
+        // Linked ELFs haven't been through gbafix yet, do what it does to the header
+        rom[0xB2] = 0x96;
+        rom[0xBD] = CartridgeHeader::compute_complement(&rom);
+        let mut gba = Gba::new(Cartridge::from_bytes(rom)?);
+        bios::skip_boot(&mut gba.cpu, &mut gba.bus);
+        for segment in self.segments.iter().filter(|segment| !segment.in_rom()) {
+            for (i, byte) in segment.data.iter().enumerate() {
+                gba.bus.write_byte(segment.address + i as u32, *byte);
+            }
+        }
+        let state = &mut gba.cpu.cpu_state;
+        state.CPSR.set_thumb_state(self.entry & 1 != 0);
+        state.set_reg(15, self.entry & !1);
+        Ok(gba)
+    }
+}
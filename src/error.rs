@@ -0,0 +1,70 @@
+// src/error.rs
+// Faults caused by the program being emulated. They stop the emulated CPU and are handed back
+// to whoever is driving it, the host process keeps running.
+
+use std::fmt;
+
+use crate::disasm::{disassemble_arm, disassemble_thumb};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EmulatorError {
+    UnmappedAccess { address: u32, write: bool },
+    UndefinedInstruction { address: u32, opcode: u32 },
+    UnimplementedInstruction { address: u32, opcode: u32, thumb: bool },
+    InvalidRegister { index: usize },
+}
+
+impl EmulatorError {
+    /// Sorts an ARM opcode the decoder did not recognise: the architecturally undefined space
+    /// (bits 25-27 = 011 with bit 4 set) or something not implemented yet.
+    pub fn from_unknown_arm(address: u32, opcode: u32) -> Self {
+        if opcode & 0x0E00_0010 == 0x0600_0010 {
+            EmulatorError::UndefinedInstruction { address, opcode }
+        } else {
+            EmulatorError::UnimplementedInstruction {
+                address,
+                opcode,
+                thumb: false,
+            }
+        }
+    }
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulatorError::UnmappedAccess { address, write } => {
+                let access = if *write { "write to" } else { "read from" };
+                write!(f, "{} unmapped address 0x{:08X}", access, address)
+            }
+            EmulatorError::UndefinedInstruction { address, opcode } => {
+                write!(f, "undefined instruction 0x{:08X} at 0x{:08X}", opcode, address)
+            }
+            EmulatorError::UnimplementedInstruction {
+                address,
+                opcode,
+                thumb: true,
+            } => {
+                // Fetched as a word, the halfword after it is in the top half
+                let (text, _) = disassemble_thumb(*opcode as u16, (*opcode >> 16) as u16, *address);
+                write!(
+                    f,
+                    "unimplemented Thumb instruction 0x{:04X} ({}) at 0x{:08X}",
+                    *opcode as u16, text, address
+                )
+            }
+            EmulatorError::UnimplementedInstruction { address, opcode, .. } => write!(
+                f,
+                "unimplemented ARM instruction 0x{:08X} ({}) at 0x{:08X}",
+                opcode,
+                disassemble_arm(*opcode, *address),
+                address
+            ),
+            EmulatorError::InvalidRegister { index } => {
+                write!(f, "invalid register r{}", index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {}
@@ -0,0 +1,84 @@
+// src/gba.rs
+// The whole system: CPU plus everything behind the bus, stepped together.
+
+use std::path::Path;
+
+use crate::bios::{self, BiosError};
+use crate::bus::Bus;
+use crate::cartridge::Cartridge;
+use crate::cpu::{Cpu, Step};
+use crate::elf::{Elf, ElfError};
+use crate::error::EmulatorError;
+use crate::savestate::{self, SaveStateError};
+use crate::symbols::SymbolTable;
+
+pub struct Gba {
+    pub cpu: Cpu,
+    pub bus: Bus,
+}
+
+impl Gba {
+    pub fn new(cartridge: Cartridge) -> Self {
+        Gba {
+            cpu: Cpu::new(),
+            bus: Bus::with_cartridge(cartridge),
+        }
+    }
+
+    /// Boots a .gba ROM or a devkitARM .elf past the BIOS, with the ELF's symbols or those of a
+    /// .sym file next to the ROM. A bad ROM comes back as `ElfError::Cartridge`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<(Gba, SymbolTable), ElfError> {
+        let path = path.as_ref();
+        if path.extension().is_some_and(|ext| ext == "elf") {
+            let elf = Elf::load(path)?;
+            return Ok((elf.build_gba()?, elf.symbols));
+        }
+        let mut gba = Gba::new(Cartridge::load(path)?);
+        bios::skip_boot(&mut gba.cpu, &mut gba.bus);
+        let symbols = SymbolTable::load(path.with_extension("sym")).unwrap_or_default();
+        Ok((gba, symbols))
+    }
+
+    /// Maps a BIOS dump. With `skip_boot` the game keeps running and SWIs and interrupts stay
+    /// with the HLE BIOS, since the core can't run the real BIOS code yet (no MSR/MRS, stores or
+    /// Thumb). Otherwise restarts from the reset vector to boot through the dump, which only
+    /// gets anywhere with simple images.
+    pub fn use_bios(&mut self, image: &[u8], skip_boot: bool) -> Result<u32, BiosError> {
+        let checksum = self.bus.load_bios(image)?;
+        self.cpu.hle_bios = skip_boot;
+        if !skip_boot {
+            self.cpu.reset();
+            // The BIOS tells a power on from a reset by POSTFLG, which the skipped boot set
+            self.bus.write_byte(0x0400_0300, 0);
+        }
+        Ok(checksum)
+    }
+
+    /// Runs one instruction, or while halted skips to the next event.
+    pub fn step(&mut self) -> Result<Step, EmulatorError> {
+        self.cpu.step(&mut self.bus)
+    }
+
+    /// Runs for at least `cycles` cycles. Stops early if the system is stopped.
+    pub fn run_cycles(&mut self, cycles: u64) -> Result<u64, EmulatorError> {
+        self.cpu.run_for_cycles(&mut self.bus, cycles)
+    }
+
+    pub fn run_until_pc(&mut self, pc: u32, limit: u64) -> Result<bool, EmulatorError> {
+        self.cpu.run_until_pc(&mut self.bus, pc, limit)
+    }
+
+    pub fn run_frame(&mut self) -> Result<u64, EmulatorError> {
+        self.cpu.run_frame(&mut self.bus)
+    }
+
+    /// The whole machine as a save state, see `savestate`.
+    pub fn save_state(&self) -> Result<Vec<u8>, SaveStateError> {
+        savestate::save(self)
+    }
+
+    /// Restores a save state of this ROM. Leaves the machine as it was if the state is refused.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        savestate::load(self, data)
+    }
+}
@@ -0,0 +1,352 @@
+// src/gdb/mod.rs
+// GDB remote stub, so `arm-none-eabi-gdb` can attach with `target remote :PORT`. Memory goes
+// through the bus like the CPU's own accesses do. Breakpoints go into the CPU's debugger rather
+// than being patched into memory, which makes software and hardware ones the same thing and
+// works in ROM too.
+
+pub mod packet;
+pub mod target;
+
+use std::io;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::cpu::{Cpu, Step};
+use crate::debugger::{Breakpoint, StopReason, WatchKind};
+use crate::error::EmulatorError;
+use crate::memory::MemoryBus;
+use packet::{from_hex, parse_hex, read_packet, to_hex, write_packet, Incoming, INTERRUPT};
+
+// Instructions run between checks for a Ctrl-C from GDB
+const INTERRUPT_POLL_INTERVAL: u64 = 4096;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+pub struct GdbStub {
+    stream: TcpStream,
+    ack: bool,
+    stop_acking: bool,
+    swbreak: bool, // GDB understands swbreak/hwbreak stop reasons
+    points: Vec<Point>,
+}
+
+// A Z packet's breakpoint or watchpoint and its id in the debugger.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Point {
+    kind: u8, // Z type, 0-4
+    address: u32,
+    length: u32,
+    id: usize,
+}
+
+enum Resume {
+    Continue,
+    Step,
+}
+
+impl GdbStub {
+    /// Waits on `address` for GDB to connect.
+    pub fn listen<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let (stream, _) = listener.accept()?;
+        Ok(GdbStub::new(stream))
+    }
+
+    pub fn new(stream: TcpStream) -> Self {
+        let _ = stream.set_nodelay(true);
+        GdbStub {
+            stream,
+            ack: true,
+            stop_acking: false,
+            swbreak: false,
+            points: Vec::new(),
+        }
+    }
+
+    /// Serves GDB until it detaches, kills the target or goes away. The CPU only runs while
+    /// GDB has it continuing or stepping.
+    pub fn run<M: MemoryBus>(&mut self, cpu: &mut Cpu, memory: &mut M) -> io::Result<()> {
+        let result = self.serve(cpu, memory);
+        // Leave no breakpoints behind for whoever runs the CPU next
+        for point in self.points.drain(..) {
+            cpu.debugger.remove(point.id);
+        }
+        result
+    }
+
+    fn serve<M: MemoryBus>(&mut self, cpu: &mut Cpu, memory: &mut M) -> io::Result<()> {
+        loop {
+            let packet = match read_packet(&mut self.stream, self.ack) {
+                Ok(Incoming::Packet(packet)) => packet,
+                Ok(Incoming::Interrupt) => {
+                    self.send(format!("S{:02x}", SIGINT).as_bytes())?;
+                    continue;
+                }
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(err) => return Err(err),
+            };
+            match packet.first() {
+                Some(b'D') => return self.send(b"OK"),
+                Some(b'k') => return Ok(()),
+                _ => {}
+            }
+            let reply = self.handle(&packet, cpu, memory)?;
+            self.send(&reply)?;
+            // The OK to QStartNoAckMode still gets acked, nothing after it
+            if self.stop_acking {
+                self.ack = false;
+            }
+        }
+    }
+
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        write_packet(&mut self.stream, data, self.ack)
+    }
+
+    fn handle<M: MemoryBus>(
+        &mut self,
+        packet: &[u8],
+        cpu: &mut Cpu,
+        memory: &mut M,
+    ) -> io::Result<Vec<u8>> {
+        let (&command, args) = match packet.split_first() {
+            Some(split) => split,
+            None => return Ok(Vec::new()),
+        };
+        let reply = match command {
+            b'?' => format!("S{:02x}", SIGTRAP).into_bytes(),
+            b'g' => self.read_registers(cpu).into_bytes(),
+            b'G' => ok_or_error(self.write_registers(cpu, args)),
+            b'p' => match parse_hex(args).and_then(|n| target::registers().get(n as usize).cloned()) {
+                Some((_, register)) => {
+                    to_hex(&target::read(&cpu.cpu_state, register).to_le_bytes()).into_bytes()
+                }
+                None => b"E00".to_vec(),
+            },
+            b'P' => ok_or_error(self.write_register(cpu, args)),
+            b'm' => self.read_memory(memory, args),
+            b'M' => ok_or_error(self.write_memory_hex(memory, args)),
+            b'X' => ok_or_error(self.write_memory_binary(memory, args)),
+            b'c' | b's' => {
+                if let Some(address) = parse_hex(args) {
+                    cpu.cpu_state.set_reg(15, address);
+                }
+                let resume = if command == b'c' { Resume::Continue } else { Resume::Step };
+                self.resume(cpu, memory, resume)?
+            }
+            b'Z' | b'z' => ok_or_error(self.set_point(cpu, command == b'Z', args)),
+            b'H' | b'T' => b"OK".to_vec(),
+            b'q' => self.query(args),
+            b'Q' if args == b"StartNoAckMode" => {
+                self.stop_acking = true;
+                b"OK".to_vec()
+            }
+            _ => Vec::new(), // Not supported, an empty reply tells GDB so
+        };
+        Ok(reply)
+    }
+
+    fn query(&mut self, args: &[u8]) -> Vec<u8> {
+        let text = String::from_utf8_lossy(args);
+        if let Some(features) = text.strip_prefix("Supported") {
+            self.swbreak = features.contains("swbreak+");
+            return b"PacketSize=4000;qXfer:features:read+;QStartNoAckMode+;swbreak+;hwbreak+"
+                .to_vec();
+        }
+        if let Some(range) = text.strip_prefix("Xfer:features:read:target.xml:") {
+            let xml = target::target_xml();
+            let Some((offset, length)) = range.split_once(',') else {
+                return b"E00".to_vec();
+            };
+            let (Some(offset), Some(length)) = (parse_hex(offset.as_bytes()), parse_hex(length.as_bytes())) else {
+                return b"E00".to_vec();
+            };
+            let bytes = xml.as_bytes();
+            let start = (offset as usize).min(bytes.len());
+            let end = (start + length as usize).min(bytes.len());
+            let mut reply = vec![if end == bytes.len() { b'l' } else { b'm' }];
+            reply.extend_from_slice(&bytes[start..end]);
+            return reply;
+        }
+        match text.as_ref() {
+            "Attached" => b"1".to_vec(),
+            "C" => b"QC1".to_vec(),
+            "fThreadInfo" => b"m1".to_vec(),
+            "sThreadInfo" => b"l".to_vec(),
+            "Symbol::" => b"OK".to_vec(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn read_registers(&self, cpu: &Cpu) -> String {
+        target::registers()
+            .iter()
+            .map(|(_, register)| to_hex(&target::read(&cpu.cpu_state, *register).to_le_bytes()))
+            .collect()
+    }
+
+    fn write_registers(&self, cpu: &mut Cpu, args: &[u8]) -> Option<()> {
+        let bytes = from_hex(args)?;
+        for ((_, register), value) in target::registers().iter().zip(bytes.chunks_exact(4)) {
+            let value = u32::from_le_bytes([value[0], value[1], value[2], value[3]]);
+            target::write(&mut cpu.cpu_state, *register, value);
+        }
+        Some(())
+    }
+
+    fn write_register(&self, cpu: &mut Cpu, args: &[u8]) -> Option<()> {
+        let split = args.iter().position(|&byte| byte == b'=')?;
+        let number = parse_hex(&args[..split])? as usize;
+        let (_, register) = target::registers().get(number).cloned()?;
+        let bytes: [u8; 4] = from_hex(&args[split + 1..])?.try_into().ok()?;
+        target::write(&mut cpu.cpu_state, register, u32::from_le_bytes(bytes));
+        Some(())
+    }
+
+    fn read_memory<M: MemoryBus>(&self, memory: &mut M, args: &[u8]) -> Vec<u8> {
+        let Some((address, length)) = address_length(args) else {
+            return b"E00".to_vec();
+        };
+        let bytes: Vec<u8> = (0..length)
+            .map(|i| memory.read_byte(address.wrapping_add(i)))
+            .collect();
+        if memory.take_fault().is_some() {
+            return b"E0e".to_vec();
+        }
+        to_hex(&bytes).into_bytes()
+    }
+
+    fn write_memory_hex<M: MemoryBus>(&self, memory: &mut M, args: &[u8]) -> Option<()> {
+        let split = args.iter().position(|&byte| byte == b':')?;
+        let (address, _) = address_length(&args[..split])?;
+        let data = from_hex(&args[split + 1..])?;
+        write_memory(memory, address, &data)
+    }
+
+    fn write_memory_binary<M: MemoryBus>(&self, memory: &mut M, args: &[u8]) -> Option<()> {
+        let split = args.iter().position(|&byte| byte == b':')?;
+        let (address, _) = address_length(&args[..split])?;
+        write_memory(memory, address, &args[split + 1..])
+    }
+
+    // Z/z type,address,kind. Kind is the breakpoint size (2 Thumb, 4 ARM) or watched length.
+    fn set_point(&mut self, cpu: &mut Cpu, insert: bool, args: &[u8]) -> Option<()> {
+        let mut fields = args.split(|&byte| byte == b',');
+        let kind = match fields.next()? {
+            [digit @ b'0'..=b'4'] => digit - b'0',
+            _ => return None,
+        };
+        let address = parse_hex(fields.next()?)?;
+        let length = parse_hex(fields.next()?)?;
+        if !insert {
+            let index = self
+                .points
+                .iter()
+                .position(|p| p.kind == kind && p.address == address && p.length == length)?;
+            cpu.debugger.remove(self.points.remove(index).id);
+            return Some(());
+        }
+        let id = match kind {
+            0 | 1 => cpu.debugger.add_breakpoint(Breakpoint::at(address)),
+            _ => {
+                let watch = [WatchKind::Write, WatchKind::Read, WatchKind::Access][kind as usize - 2];
+                cpu.debugger.add_watchpoint(watch, address, length, None)
+            }
+        };
+        self.points.push(Point { kind, address, length, id });
+        Some(())
+    }
+
+    fn stop_reply(&self, reason: StopReason) -> Vec<u8> {
+        let kind_of = |id| self.points.iter().find(|p| p.id == id).map(|p| p.kind);
+        let reply = match reason {
+            StopReason::Breakpoint { id, .. } if self.swbreak => match kind_of(id) {
+                Some(1) => format!("T{:02x}hwbreak:;", SIGTRAP),
+                _ => format!("T{:02x}swbreak:;", SIGTRAP),
+            },
+            StopReason::Watchpoint { id, address, .. } => {
+                let name = match kind_of(id) {
+                    Some(3) => "rwatch",
+                    Some(4) => "awatch",
+                    _ => "watch",
+                };
+                format!("T{:02x}{}:{:x};", SIGTRAP, name, address)
+            }
+            StopReason::IoWrite { address, .. } => format!("T{:02x}watch:{:x};", SIGTRAP, address),
+            _ => format!("S{:02x}", SIGTRAP),
+        };
+        reply.into_bytes()
+    }
+
+    // Ctrl-C arrives as a single byte while the target runs.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut byte = [0u8; 1];
+        let result = self.stream.peek(&mut byte);
+        self.stream.set_nonblocking(false)?;
+        match result {
+            Ok(1) if byte[0] == INTERRUPT => {
+                let _ = io::Read::read(&mut self.stream, &mut byte)?;
+                Ok(true)
+            }
+            Ok(_) => Ok(false),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn resume<M: MemoryBus>(
+        &mut self,
+        cpu: &mut Cpu,
+        memory: &mut M,
+        resume: Resume,
+    ) -> io::Result<Vec<u8>> {
+        let mut count = 0u64;
+        loop {
+            let step = match cpu.step(memory) {
+                Ok(step) => step,
+                Err(err) => {
+                    let signal = match err {
+                        EmulatorError::UnmappedAccess { .. } => SIGSEGV,
+                        _ => SIGILL,
+                    };
+                    return Ok(format!("S{:02x}", signal).into_bytes());
+                }
+            };
+            if let Step::Break(reason) = step {
+                return Ok(self.stop_reply(reason));
+            }
+            if let Resume::Step = resume {
+                return Ok(format!("S{:02x}", SIGTRAP).into_bytes());
+            }
+            count += 1;
+            if count.is_multiple_of(INTERRUPT_POLL_INTERVAL) && self.interrupted()? {
+                return Ok(format!("S{:02x}", SIGINT).into_bytes());
+            }
+        }
+    }
+}
+
+fn ok_or_error(result: Option<()>) -> Vec<u8> {
+    match result {
+        Some(()) => b"OK".to_vec(),
+        None => b"E00".to_vec(),
+    }
+}
+
+fn address_length(args: &[u8]) -> Option<(u32, u32)> {
+    let split = args.iter().position(|&byte| byte == b',')?;
+    Some((parse_hex(&args[..split])?, parse_hex(&args[split + 1..])?))
+}
+
+fn write_memory<M: MemoryBus>(memory: &mut M, address: u32, data: &[u8]) -> Option<()> {
+    for (i, byte) in data.iter().enumerate() {
+        memory.write_byte(address.wrapping_add(i as u32), *byte);
+    }
+    match memory.take_fault() {
+        Some(_) => None,
+        None => Some(()),
+    }
+}
@@ -0,0 +1,121 @@
+// src/gdb/packet.rs
+// Remote Serial Protocol framing: $data#checksum, acknowledged with + or - until the client
+// switches acks off. A lone 0x03 outside a packet is GDB asking to stop the target.
+
+use std::io::{self, Read, Write};
+
+pub const INTERRUPT: u8 = 0x03;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Incoming {
+    Packet(Vec<u8>),
+    Interrupt,
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn read_byte<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut byte = [0u8; 1];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn hex_digit(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|digit| digit as u8)
+}
+
+/// Waits for the next packet or interrupt, acking packets when `ack` is set. Packets with a bad
+/// checksum are nacked and skipped.
+pub fn read_packet<S: Read + Write>(stream: &mut S, ack: bool) -> io::Result<Incoming> {
+    loop {
+        match read_byte(stream)? {
+            b'$' => {}
+            INTERRUPT => return Ok(Incoming::Interrupt),
+            _ => continue, // Acks for our replies and line noise
+        }
+        let mut raw = Vec::new();
+        loop {
+            match read_byte(stream)? {
+                b'#' => break,
+                byte => raw.push(byte),
+            }
+        }
+        let high = hex_digit(read_byte(stream)?);
+        let low = hex_digit(read_byte(stream)?);
+        // The checksum covers the bytes as sent, escapes included
+        let valid = matches!((high, low), (Some(h), Some(l)) if h << 4 | l == checksum(&raw));
+        if ack {
+            stream.write_all(if valid { b"+" } else { b"-" })?;
+        }
+        if valid {
+            return Ok(Incoming::Packet(unescape(&raw)));
+        }
+    }
+}
+
+fn unescape(raw: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(raw.len());
+    let mut bytes = raw.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'}' => data.push(bytes.next().map_or(byte, |next| next ^ 0x20)),
+            _ => data.push(byte),
+        }
+    }
+    data
+}
+
+fn escape(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for &byte in data {
+        if matches!(byte, b'$' | b'#' | b'}' | b'*') {
+            escaped.push(b'}');
+            escaped.push(byte ^ 0x20);
+        } else {
+            escaped.push(byte);
+        }
+    }
+    escaped
+}
+
+/// Sends a packet, resending it until it is acked when `ack` is set.
+pub fn write_packet<S: Read + Write>(stream: &mut S, data: &[u8], ack: bool) -> io::Result<()> {
+    let escaped = escape(data);
+    let mut frame = Vec::with_capacity(escaped.len() + 4);
+    frame.push(b'$');
+    frame.extend_from_slice(&escaped);
+    frame.extend_from_slice(format!("#{:02x}", checksum(&escaped)).as_bytes());
+    loop {
+        stream.write_all(&frame)?;
+        stream.flush()?;
+        if !ack {
+            return Ok(());
+        }
+        loop {
+            match read_byte(stream)? {
+                b'+' => return Ok(()),
+                b'-' => break,
+                _ => {}
+            }
+        }
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn from_hex(text: &[u8]) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    text.chunks(2)
+        .map(|pair| Some(hex_digit(pair[0])? << 4 | hex_digit(pair[1])?))
+        .collect()
+}
+
+pub fn parse_hex(text: &[u8]) -> Option<u32> {
+    u32::from_str_radix(std::str::from_utf8(text).ok()?, 16).ok()
+}
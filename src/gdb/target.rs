@@ -0,0 +1,110 @@
+// src/gdb/target.rs
+// The registers GDB gets to see, in the order of the `g` packet, and the target description
+// XML that tells it so. Besides the core set that includes CPSR, from which GDB tells ARM and
+// Thumb code apart, the current SPSR and every mode's banked registers.
+
+use crate::cpu::{CpuState, Cpsr, Mode};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Register {
+    Core(usize), // r0-r15 of the current mode
+    Cpsr,
+    Spsr,
+    Banked(Mode, usize), // r8-r14 of a mode
+    BankedSpsr(Mode),
+}
+
+fn mode_suffix(mode: Mode) -> &'static str {
+    match mode {
+        Mode::User | Mode::System => "usr",
+        Mode::Fiq => "fiq",
+        Mode::Irq => "irq",
+        Mode::Supervisor => "svc",
+        Mode::Abort => "abt",
+        Mode::Undefined => "und",
+    }
+}
+
+/// Every register with its name, index = GDB register number.
+pub fn registers() -> Vec<(String, Register)> {
+    const CORE: [&str; 16] = [
+        "r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7", "r8", "r9", "r10", "r11", "r12", "sp",
+        "lr", "pc",
+    ];
+    let mut list: Vec<(String, Register)> = CORE
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (name.to_string(), Register::Core(i)))
+        .collect();
+    list.push(("cpsr".to_string(), Register::Cpsr));
+    list.push(("spsr".to_string(), Register::Spsr));
+    for mode in [Mode::User, Mode::Fiq] {
+        for reg in 8..15 {
+            let name = format!("r{}_{}", reg, mode_suffix(mode));
+            list.push((name, Register::Banked(mode, reg)));
+        }
+    }
+    for mode in [Mode::Irq, Mode::Supervisor, Mode::Abort, Mode::Undefined] {
+        for reg in 13..15 {
+            let name = format!("r{}_{}", reg, mode_suffix(mode));
+            list.push((name, Register::Banked(mode, reg)));
+        }
+    }
+    for mode in [Mode::Fiq, Mode::Irq, Mode::Supervisor, Mode::Abort, Mode::Undefined] {
+        list.push((format!("spsr_{}", mode_suffix(mode)), Register::BankedSpsr(mode)));
+    }
+    list
+}
+
+pub fn read(state: &CpuState, register: Register) -> u32 {
+    match register {
+        Register::Core(i) => state.registers[i],
+        Register::Cpsr => state.CPSR.value,
+        Register::Spsr => state.SPSR.value,
+        Register::Banked(mode, reg) => state.mode_register(mode, reg),
+        Register::BankedSpsr(mode) => state.mode_spsr(mode).value,
+    }
+}
+
+pub fn write(state: &mut CpuState, register: Register, value: u32) {
+    match register {
+        Register::Core(i) => state.registers[i] = value,
+        Register::Cpsr => {
+            // A new mode needs its registers swapped in
+            let mode = Mode::from_bits(value).unwrap_or(Mode::User);
+            state.set_mode(mode);
+            state.CPSR.value = value;
+        }
+        Register::Spsr => state.SPSR.value = value,
+        Register::Banked(mode, reg) => state.set_mode_register(mode, reg, value),
+        Register::BankedSpsr(mode) => state.set_mode_spsr(mode, Cpsr { value }),
+    }
+}
+
+pub fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n<architecture>arm</architecture>\n\
+         <feature name=\"org.gnu.gdb.arm.core\">\n",
+    );
+    for (number, (name, register)) in registers().iter().enumerate() {
+        if number == 17 {
+            xml.push_str("</feature>\n<feature name=\"org.gba.arm.banked\">\n");
+        }
+        let kind = match register {
+            Register::Core(13) => "data_ptr",
+            Register::Core(15) => "code_ptr",
+            _ => "uint32",
+        };
+        let group = match register {
+            Register::Core(_) | Register::Cpsr => "general",
+            _ => "system",
+        };
+        xml.push_str(&format!(
+            "<reg name=\"{}\" bitsize=\"32\" regnum=\"{}\" type=\"{}\" group=\"{}\"/>\n",
+            name, number, kind, group
+        ));
+    }
+    xml.push_str("</feature>\n</target>\n");
+    xml
+}
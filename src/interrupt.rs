@@ -0,0 +1,118 @@
+// src/interrupt.rs
+// Interrupt controller: IE (0x4000200), IF (0x4000202, write 1 to acknowledge) and IME
+// (0x4000208), plus the HALTCNT low power states.
+
+use crate::bus::Bus;
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+pub const REG_IE: u32 = 0x200;
+pub const REG_IF: u32 = 0x202;
+pub const REG_IME: u32 = 0x208;
+pub const REG_POSTFLG: u32 = 0x300;
+pub const REG_HALTCNT: u32 = 0x301;
+
+// Bit numbers in IE and IF.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Interrupt {
+    VBlank = 0,
+    HBlank = 1,
+    VCount = 2,
+    Timer0 = 3,
+    Timer1 = 4,
+    Timer2 = 5,
+    Timer3 = 6,
+    Serial = 7,
+    Dma0 = 8,
+    Dma1 = 9,
+    Dma2 = 10,
+    Dma3 = 11,
+    Keypad = 12,
+    GamePak = 13,
+}
+
+// Only these can end Stop mode, everything clocked is stopped with the CPU.
+const STOP_WAKE_MASK: u16 =
+    (1 << Interrupt::Serial as u16) | (1 << Interrupt::Keypad as u16) | (1 << Interrupt::GamePak as u16);
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PowerState {
+    Running,
+    Halted,  // CPU stopped until IE & IF != 0, the rest keeps running
+    Stopped, // Everything stopped until a keypad, serial or Game Pak interrupt
+}
+
+impl Bus {
+    pub(crate) fn io_halfword(&self, offset: u32) -> u16 {
+        u16::from_le_bytes([self.io[offset as usize], self.io[offset as usize + 1]])
+    }
+
+    pub fn interrupt_enable(&self) -> u16 {
+        self.io_halfword(REG_IE) & 0x3FFF
+    }
+
+    pub fn interrupt_flags(&self) -> u16 {
+        self.io_halfword(REG_IF) & 0x3FFF
+    }
+
+    pub fn request_interrupt(&mut self, interrupt: Interrupt) {
+        let bit = 1u16 << interrupt as u16;
+        self.io[REG_IF as usize] |= bit as u8;
+        self.io[REG_IF as usize + 1] |= (bit >> 8) as u8;
+    }
+
+    /// Some enabled interrupt is flagged. Wakes up a halted CPU even with IME off.
+    pub fn interrupt_pending(&self) -> bool {
+        self.interrupt_enable() & self.interrupt_flags() != 0
+    }
+
+    /// State of the CPU's IRQ input, before the CPSR I bit.
+    pub fn irq_line(&self) -> bool {
+        self.io[REG_IME as usize] & 1 == 1 && self.interrupt_pending()
+    }
+
+    /// Leaves Halt or Stop once the interrupts that end it are flagged.
+    pub fn update_power_state(&mut self) {
+        let wake = match self.power_state {
+            PowerState::Running => return,
+            PowerState::Halted => self.interrupt_pending(),
+            PowerState::Stopped => self.interrupt_enable() & self.interrupt_flags() & STOP_WAKE_MASK != 0,
+        };
+        if wake {
+            self.power_state = PowerState::Running;
+        }
+    }
+
+    // Interrupt registers as seen by write_io.
+    pub(crate) fn write_interrupt_register(&mut self, offset: u32, value: u8) {
+        match offset {
+            // Writing 1 acknowledges the interrupt
+            0x202 | 0x203 => self.io[offset as usize] &= !value,
+            REG_POSTFLG => self.io[offset as usize] = value & 1,
+            REG_HALTCNT => {
+                self.io[offset as usize] = value;
+                self.power_state = if value & 0x80 != 0 {
+                    PowerState::Stopped
+                } else {
+                    PowerState::Halted
+                };
+            }
+            _ => self.io[offset as usize] = value,
+        }
+    }
+}
+
+impl Snapshot for PowerState {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(*self as u8);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        *self = match state.u8()? {
+            0 => PowerState::Running,
+            1 => PowerState::Halted,
+            2 => PowerState::Stopped,
+            _ => return Err(SaveStateError::Corrupt("unknown power state")),
+        };
+        Ok(())
+    }
+}
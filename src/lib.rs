@@ -1,3 +1,26 @@
+pub mod apu;
+pub mod bios;
+pub mod bus;
+pub mod cartridge;
 pub mod cpu;
 pub mod memory;
-pub mod cpu_instructions;
\ No newline at end of file
+pub mod cpu_instructions;
+pub mod debugger;
+pub mod disasm;
+pub mod dma;
+pub mod elf;
+pub mod error;
+pub mod gba;
+pub mod gdb;
+pub mod interrupt;
+pub mod movie;
+pub mod png;
+pub mod ppu;
+pub mod savestate;
+pub mod scheduler;
+pub mod sio;
+pub mod symbols;
+pub mod timer;
+pub mod trace;
+pub mod wav;
+pub mod y4m;
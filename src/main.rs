@@ -1,11 +1,292 @@
 #[allow(unused_imports)]
 use emulator::cpu::Cpu;
+use emulator::bios::{self, hle::BIOS_CHECKSUM};
+use emulator::cartridge::Cartridge;
+use emulator::debugger::Repl;
+use emulator::elf::Elf;
+use emulator::gba::Gba;
+use emulator::gdb::GdbStub;
 use emulator::memory::Memory;
+use emulator::movie::{vbm, Movie, MovieError};
+use emulator::savestate;
+use emulator::symbols::SymbolTable;
+use emulator::trace::{TraceFormat, Tracer};
+
+// Boots a .gba ROM or a devkitARM .elf, see `Gba::load`, adding `extra` to its symbols. With a
+// BIOS dump it boots through the BIOS unless `skip_boot`, see `Gba::use_bios`.
+fn load_system(
+    path: &str,
+    extra: SymbolTable,
+    bios: Option<&[u8]>,
+    skip_boot: bool,
+) -> (Gba, SymbolTable) {
+    let (mut gba, mut symbols) = Gba::load(path).unwrap_or_else(|err| {
+        eprintln!("Failed to load {}: {}", path, err);
+        std::process::exit(1);
+    });
+    if let Some(image) = bios {
+        gba.use_bios(image, skip_boot).unwrap_or_else(|err| {
+            eprintln!("Failed to load the BIOS: {}", err);
+            std::process::exit(1);
+        });
+    }
+    symbols.extend(extra);
+    (gba, symbols)
+}
+
+fn load_movie(path: &str, rom: &str) -> Result<(Movie, Cartridge), MovieError> {
+    let cartridge = Cartridge::load(rom).unwrap_or_else(|err| {
+        eprintln!("Failed to load {}: {}", rom, err);
+        std::process::exit(1);
+    });
+    let movie = if path.ends_with(".vbm") {
+        vbm::import(&std::fs::read(path)?, &cartridge)?
+    } else {
+        Movie::load(path)?
+    };
+    Ok((movie, cartridge))
+}
+
+fn restore_state(gba: &mut Gba, path: Option<&str>) {
+    let Some(path) = path else {
+        return;
+    };
+    if let Err(err) = savestate::load_from_file(gba, path) {
+        eprintln!("Failed to load state {}: {}", path, err);
+        std::process::exit(1);
+    }
+}
 
-mod memory;
-mod cpu;
-mod cpu_instructions;
 fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    // --trace <file> writes an instruction trace of the run, binary if the file ends in .bin.
+    // --trace-format text|binary|mgba|nba picks the format explicitly.
+    let mut trace_format = None;
+    if let Some(index) = args.iter().position(|arg| arg == "--trace-format") {
+        let Some(format) = args.get(index + 1).and_then(|name| TraceFormat::from_name(name)) else {
+            eprintln!("--trace-format needs one of text, binary, mgba or nba");
+            std::process::exit(1);
+        };
+        trace_format = Some(format);
+        args.drain(index..index + 2);
+    }
+    let mut tracer = None;
+    if let Some(index) = args.iter().position(|arg| arg == "--trace") {
+        let Some(path) = args.get(index + 1).cloned() else {
+            eprintln!("--trace needs a file");
+            std::process::exit(1);
+        };
+        let format = trace_format.unwrap_or(if path.ends_with(".bin") {
+            TraceFormat::Binary
+        } else {
+            TraceFormat::Text
+        });
+        match Tracer::to_file(&path, format) {
+            Ok(t) => tracer = Some(t),
+            Err(err) => {
+                eprintln!("Failed to create {}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+        args.drain(index..index + 2);
+    }
+    // --bios <file> checks a BIOS dump and prints its checksum. --gdb and --debug map it and
+    // start the game right away. With --boot-bios they boot through it instead, which doesn't
+    // work with the real BIOS yet, the core is missing instructions its boot code needs.
+    let skip_boot = !args.iter().any(|arg| arg == "--boot-bios");
+    args.retain(|arg| arg != "--boot-bios");
+    let mut bios_image = None;
+    if let Some(index) = args.iter().position(|arg| arg == "--bios") {
+        let Some(path) = args.get(index + 1).cloned() else {
+            eprintln!("--bios needs a file");
+            std::process::exit(1);
+        };
+        match bios::load_image(&path) {
+            Ok(image) => {
+                let checksum = bios::checksum(&image);
+                let known = if checksum == BIOS_CHECKSUM { " (GBA BIOS)" } else { " (unknown BIOS)" };
+                println!("BIOS checksum: 0x{:08X}{}", checksum, known);
+                bios_image = Some(image);
+            }
+            Err(err) => {
+                eprintln!("Failed to load {}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+        args.drain(index..index + 2);
+        if args.is_empty() {
+            return;
+        }
+    }
+    // --symbols <file> adds symbols from a no$gba .sym file or an ELF, for --debug and traces.
+    // A .sym file next to the ROM is picked up without it.
+    let mut extra_symbols = SymbolTable::new();
+    if let Some(index) = args.iter().position(|arg| arg == "--symbols") {
+        let Some(path) = args.get(index + 1).cloned() else {
+            eprintln!("--symbols needs a file");
+            std::process::exit(1);
+        };
+        match SymbolTable::load(&path) {
+            Ok(symbols) => extra_symbols = symbols,
+            Err(err) => {
+                eprintln!("Failed to load {}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+        args.drain(index..index + 2);
+    }
+    // --load-state <file> starts --gdb or --debug from a save state of the same ROM,
+    // --save-state <file> writes one when the debugger quits.
+    let mut state_files = [None, None];
+    for (flag, file) in ["--load-state", "--save-state"].iter().zip(&mut state_files) {
+        if let Some(index) = args.iter().position(|arg| arg == flag) {
+            let Some(path) = args.get(index + 1).cloned() else {
+                eprintln!("{} needs a file", flag);
+                std::process::exit(1);
+            };
+            *file = Some(path);
+            args.drain(index..index + 2);
+        }
+    }
+    let [load_state, save_state] = state_files;
+    // --movie <movie> <rom> plays a movie (ours or a VBA .vbm) and checks its checkpoints,
+    // --convert-movie <in> <out> <rom> converts between the two by extension.
+    if let Some(index) = args.iter().position(|arg| arg == "--movie") {
+        let (Some(movie_path), Some(rom)) = (args.get(index + 1), args.get(index + 2)) else {
+            eprintln!("--movie needs a movie and a ROM");
+            std::process::exit(1);
+        };
+        let result = load_movie(movie_path, rom).and_then(|(movie, cartridge)| {
+            let mut gba = movie.boot(cartridge, bios_image.as_deref())?;
+            movie.play(&mut gba)?;
+            Ok(movie)
+        });
+        match result {
+            Ok(movie) => println!(
+                "Played {} frames, {} checkpoints matched",
+                movie.frames.len(),
+                movie.checkpoints.len()
+            ),
+            Err(err) => {
+                eprintln!("Movie failed: {}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--convert-movie") {
+        let (Some(input), Some(output), Some(rom)) =
+            (args.get(index + 1), args.get(index + 2), args.get(index + 3))
+        else {
+            eprintln!("--convert-movie needs an input, an output and a ROM");
+            std::process::exit(1);
+        };
+        let result = load_movie(input, rom).and_then(|(movie, cartridge)| {
+            if output.ends_with(".vbm") {
+                std::fs::write(output, vbm::export(&movie, &cartridge)?)?;
+                Ok(())
+            } else {
+                movie.save(output)
+            }
+        });
+        if let Err(err) = result {
+            eprintln!("Failed to convert {}: {}", input, err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    // --gdb <port> <rom> waits for GDB to attach and runs the ROM under its control.
+    if let Some(index) = args.iter().position(|arg| arg == "--gdb") {
+        let (Some(port), Some(path)) = (args.get(index + 1), args.get(index + 2)) else {
+            eprintln!("--gdb needs a port and a ROM");
+            std::process::exit(1);
+        };
+        let Ok(port) = port.parse::<u16>() else {
+            eprintln!("Bad port '{}'", port);
+            std::process::exit(1);
+        };
+        let (mut gba, symbols) =
+            load_system(path, extra_symbols, bios_image.as_deref(), skip_boot);
+        restore_state(&mut gba, load_state.as_deref());
+        gba.cpu.tracer = tracer;
+        if let Some(tracer) = &mut gba.cpu.tracer {
+            tracer.set_symbols(symbols);
+        }
+        println!("Waiting for GDB on port {}", port);
+        let result = GdbStub::listen(("127.0.0.1", port))
+            .and_then(|mut stub| stub.run(&mut gba.cpu, &mut gba.bus));
+        gba.cpu.tracer = None;
+        if let Err(err) = result {
+            eprintln!("GDB connection failed: {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    // --debug <rom> runs the ROM under the command-line debugger.
+    if let Some(index) = args.iter().position(|arg| arg == "--debug") {
+        let Some(path) = args.get(index + 1) else {
+            eprintln!("--debug needs a ROM");
+            std::process::exit(1);
+        };
+        let (mut gba, symbols) =
+            load_system(path, extra_symbols, bios_image.as_deref(), skip_boot);
+        restore_state(&mut gba, load_state.as_deref());
+        gba.cpu.tracer = tracer;
+        if let Some(tracer) = &mut gba.cpu.tracer {
+            tracer.set_symbols(symbols.clone());
+        }
+        let mut repl = Repl::new();
+        repl.symbols = symbols;
+        let stdin = std::io::stdin();
+        let result = repl.run(&mut gba.cpu, &mut gba.bus, stdin.lock(), &mut std::io::stdout());
+        gba.cpu.tracer = None;
+        if let Err(err) = result {
+            eprintln!("Debugger failed: {}", err);
+            std::process::exit(1);
+        }
+        if let Some(path) = save_state {
+            if let Err(err) = savestate::save_to_file(&gba, &path) {
+                eprintln!("Failed to save state {}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    // With a ROM path given, load the cartridge and print its header.
+    if let Some(path) = args.first().cloned() {
+        if path.ends_with(".elf") {
+            match Elf::load(&path) {
+                Ok(elf) => {
+                    println!("Entry point: 0x{:08X}", elf.entry);
+                    for segment in &elf.segments {
+                        println!("Segment: 0x{:08X}, {} bytes", segment.address, segment.data.len());
+                    }
+                    println!("Symbols: {}", elf.symbols.len());
+                }
+                Err(err) => {
+                    eprintln!("Failed to load {}: {}", path, err);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        match Cartridge::load(&path) {
+            Ok(cartridge) => {
+                let header = &cartridge.header;
+                println!("Title: {}", header.title);
+                println!("Game code: {}", header.game_code);
+                println!("Maker code: {}", header.maker_code);
+                println!("Version: {}", header.version);
+                println!("ROM size: {} bytes", cartridge.rom_size());
+                println!("Save type: {:?}", cartridge.save_type());
+            }
+            Err(err) => {
+                eprintln!("Failed to load {}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
     #[allow(unused_variables)]
     let mut victor:Vec<u8> = vec![];
     // Create a memory instance with 1024 bytes.
@@ -14,22 +295,29 @@ fn main() {
     // Load sample instructions into memory.
     // 1. MOV R5, #123: Encoded in little-endian as [0x7B, 0x50, 0xA0, 0xE3]
     let mov_imm: [u8; 4] = [0x7B, 0x50, 0xA0, 0xE3];
-    memory.write_bytes(0, &mov_imm);
+    memory.write_bytes(0, &mov_imm).unwrap();
 
     // 2. MOV R3, R5
     let mov_reg: [u8; 4] = [0x05, 0x30, 0xA0, 0xE1];
-    memory.write_bytes(4, &mov_reg);
+    memory.write_bytes(4, &mov_reg).unwrap();
 
     // 3. Unknown instruction to halt execution: [0xFF, 0xFF, 0xFF, 0xFF]
     let unknown: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
-    memory.write_bytes(8, &unknown);
+    memory.write_bytes(8, &unknown).unwrap();
 
     // Create a CPU instance.
     let mut cpu = Cpu::new();
+    cpu.tracer = tracer;
 
     // Initialize the program counter (PC) to the start of the program.
-    cpu.cpu_state.set_register(15, 0);
+    cpu.cpu_state.set_register(15, 0).unwrap();
 
     // Run the program. The CPU will fetch, decode, and execute instructions in a loop.
-    cpu.run_program(&mut memory);
+    let result = cpu.run_program(&mut memory);
+    // Drop the tracer so it gets flushed, process::exit skips destructors
+    cpu.tracer = None;
+    if let Err(err) = result {
+        eprintln!("Emulation stopped: {}", err);
+        std::process::exit(1);
+    }
 }
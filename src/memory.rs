@@ -1,46 +1,132 @@
+use crate::error::EmulatorError;
+use crate::interrupt::PowerState;
+
 pub struct Memory{
     data: Vec<u8>, //Byte array
+    fault: Option<EmulatorError>, // First bad access made through MemoryBus, see take_fault
 }
 impl Memory{
     #[allow(dead_code)]
     pub fn new(size: usize) -> Self{
         Memory{
             data: vec![0;size], //initialising memory with size number of bytes
+            fault: None,
         }
     }
     
-    pub fn read_byte(&self, address: u32) -> u8{
-        if address < self.data.len() as u32{
-            self.data[address as usize]
-        }else{
-            panic!("memory usage read out of bound at address 0x{:X} ", address);
-        }
+    pub fn read_byte(&self, address: u32) -> Result<u8, EmulatorError>{
+        self.data
+            .get(address as usize)
+            .copied()
+            .ok_or(EmulatorError::UnmappedAccess { address, write: false })
     }
 
     // Function to read 4 bytes (a word) from memory at a given address (Little-Endian)
 
-    pub fn read_word(&self, address: u32) -> u32{
-        if address + 3 < self.data.len() as u32{ //Check for bounds
-            let byte0 = self.read_byte(address) as u32;
-            let byte1 = self.read_byte(address +1) as u32;
-            let byte2 = self.read_byte(address +2) as u32;
-            let byte3 = self.read_byte(address +3) as u32;
-            byte0 | (byte1 <<8) | (byte2 <<16) | (byte3 <<24)
-        }else{
-            panic!("Memory word out of bounds at address 0x{:X}", address);
+    pub fn read_word(&self, address: u32) -> Result<u32, EmulatorError>{
+        let end = address as usize + 4;
+        if end > self.data.len() {
+            return Err(EmulatorError::UnmappedAccess { address, write: false });
         }
+        let bytes = &self.data[address as usize..end];
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
     }
     #[allow(dead_code)]
-    pub fn write_bytes(&mut self, address: u32, bytes: &[u8]) {
+    pub fn write_bytes(&mut self, address: u32, bytes: &[u8]) -> Result<(), EmulatorError> {
         let end = address as usize + bytes.len();
         if end > self.data.len() {
-            panic!("Memory write out of bounds at address 0x{:X}", address);
+            return Err(EmulatorError::UnmappedAccess { address, write: true });
         }
         self.data[address as usize .. end].copy_from_slice(bytes);
+        Ok(())
     }
-    pub fn write_word(&mut self, addr: u32, word: u32) {
+    pub fn write_word(&mut self, addr: u32, word: u32) -> Result<(), EmulatorError> {
         let bytes = word.to_le_bytes();
-        self.write_bytes(addr, &bytes);
+        self.write_bytes(addr, &bytes)
+    }
+
+    // Accesses through MemoryBus can't fail, a bad one reads 0 and is kept for take_fault.
+    fn record<T: Default>(&mut self, result: Result<T, EmulatorError>) -> T {
+        result.unwrap_or_else(|fault| {
+            self.fault.get_or_insert(fault);
+            T::default()
+        })
+    }
+
+}
+
+// What the CPU needs from memory, so it can run against this flat test memory or the full Bus.
+pub trait MemoryBus {
+    fn read_byte(&mut self, address: u32) -> u8;
+    fn read_halfword(&mut self, address: u32) -> u16;
+    fn read_word(&mut self, address: u32) -> u32;
+    fn write_byte(&mut self, address: u32, value: u8);
+    fn write_halfword(&mut self, address: u32, value: u16);
+    fn write_word(&mut self, address: u32, value: u32);
+
+    // Opcode fetches, separate from data reads for the BIOS read protection.
+    fn fetch_word(&mut self, address: u32) -> u32 {
+        self.read_word(address)
+    }
+
+    // The first access since the last call that had nowhere to go, if any.
+    fn take_fault(&mut self) -> Option<EmulatorError> {
+        None
     }
 
+    // The rest of the system, for Cpu::step. Plain memory has no clock, interrupts or display.
+    fn tick(&mut self, _cycles: u64) {}
+
+    fn irq_line(&self) -> bool {
+        false
+    }
+
+    fn power_state(&mut self) -> PowerState {
+        PowerState::Running
+    }
+
+    // Halted: skips ahead to whatever can wake the CPU, returns the cycles that took.
+    fn wait_for_event(&mut self) -> u64 {
+        0
+    }
+
+    fn frame_number(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl MemoryBus for Memory {
+    fn read_byte(&mut self, address: u32) -> u8 {
+        let result = Memory::read_byte(self, address);
+        self.record(result)
+    }
+
+    fn read_halfword(&mut self, address: u32) -> u16 {
+        let low = MemoryBus::read_byte(self, address) as u16;
+        low | ((MemoryBus::read_byte(self, address.wrapping_add(1)) as u16) << 8)
+    }
+
+    fn read_word(&mut self, address: u32) -> u32 {
+        let result = Memory::read_word(self, address);
+        self.record(result)
+    }
+
+    fn write_byte(&mut self, address: u32, value: u8) {
+        let result = self.write_bytes(address, &[value]);
+        self.record(result)
+    }
+
+    fn write_halfword(&mut self, address: u32, value: u16) {
+        let result = self.write_bytes(address, &value.to_le_bytes());
+        self.record(result)
+    }
+
+    fn write_word(&mut self, address: u32, value: u32) {
+        let result = Memory::write_word(self, address, value);
+        self.record(result)
+    }
+
+    fn take_fault(&mut self) -> Option<EmulatorError> {
+        self.fault.take()
+    }
 }
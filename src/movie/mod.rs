@@ -0,0 +1,378 @@
+// src/movie/mod.rs
+// Input movies: the keys held in every frame from power-on, or from an embedded save state,
+// replayed bit-exactly. Everything else that could differ between two runs is pinned down by
+// the movie: the ROM (by CRC-32), the BIOS (HLE or a dump, by checksum) and the RTC, which
+// runs on emulated time from a recorded start instead of the host clock. Checkpoints record a
+// hash of what's on screen at given frames, so a checked-in movie doubles as a regression test.
+//
+//   "GBAMOVIE" | format version u32 | emulator version | ROM CRC-32 u32 | game code |
+//   BIOS checksum (option u32) | skip BIOS bool | RTC start u64 | rerecords u32 | author |
+//   start (u8 tag + data) | frame count u32 + keys u16 each | checkpoints u32 + (frame, hash)
+//
+// Strings and start data are u32 length-prefixed, all little-endian. `vbm` converts to and from
+// VBA's movie format, `script` reads hand-written input scripts.
+
+pub mod script;
+pub mod vbm;
+
+use std::fmt;
+use std::path::Path;
+
+use crate::bios;
+use crate::cartridge::rtc::FakeClock;
+use crate::cartridge::Cartridge;
+use crate::error::EmulatorError;
+use crate::gba::Gba;
+use crate::ppu::FRAME_CYCLES;
+use crate::savestate::{self, crc32, SaveStateError, StateReader, StateWriter};
+
+const MAGIC: &[u8; 8] = b"GBAMOVIE";
+pub const FORMAT_VERSION: u32 = 2; // 2: checkpoints hash the rendered framebuffer
+const CPU_CLOCK: u64 = 1 << 24; // Cycles per second, for the RTC
+
+// The buttons in a frame's keys, KEYINPUT's bits but active high as set_keys takes them
+pub const KEYS_MASK: u16 = 0x03FF;
+
+#[derive(Debug)]
+pub enum MovieError {
+    Io(std::io::Error),
+    NotAMovie,
+    UnsupportedVersion {
+        found: u32,
+    },
+    RomMismatch {
+        expected: u32,
+        found: u32,
+    },
+    GameMismatch {
+        expected: String,
+        found: String,
+    }, // Game codes, for imports without a CRC
+    BiosMismatch {
+        expected: Option<u32>,
+        found: Option<u32>,
+    }, // None for the HLE BIOS
+    Unsupported(&'static str), // Something an imported movie needs that we can't do
+    Corrupt(&'static str),
+    State(SaveStateError),
+    Emulator(EmulatorError),
+    Desync {
+        frame: u32,
+        expected: u32,
+        found: u32,
+    }, // A checkpoint's hash didn't match
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bios = |checksum: &Option<u32>| match checksum {
+            Some(checksum) => format!("BIOS {:08X}", checksum),
+            None => "the HLE BIOS".to_string(),
+        };
+        match self {
+            MovieError::Io(err) => write!(f, "could not access movie: {}", err),
+            MovieError::NotAMovie => write!(f, "not a movie file"),
+            MovieError::UnsupportedVersion { found } => write!(
+                f,
+                "movie format {} is not supported, this is format {}",
+                found, FORMAT_VERSION
+            ),
+            MovieError::RomMismatch { expected, found } => write!(
+                f,
+                "movie is for a different ROM: made with CRC-32 {:08X}, loaded ROM is {:08X}",
+                expected, found
+            ),
+            MovieError::GameMismatch { expected, found } => {
+                write!(f, "movie is for game {}, loaded ROM is {}", expected, found)
+            }
+            MovieError::BiosMismatch { expected, found } => write!(
+                f,
+                "movie was made with {}, running with {}",
+                bios(expected),
+                bios(found)
+            ),
+            MovieError::Unsupported(what) => write!(f, "unsupported movie: {}", what),
+            MovieError::Corrupt(what) => write!(f, "movie is corrupt: {}", what),
+            MovieError::State(err) => write!(f, "movie start state: {}", err),
+            MovieError::Emulator(err) => write!(f, "{}", err),
+            MovieError::Desync {
+                frame,
+                expected,
+                found,
+            } => write!(
+                f,
+                "desync at frame {}: screen hash is {:08X}, the movie has {:08X}",
+                frame, found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<std::io::Error> for MovieError {
+    fn from(err: std::io::Error) -> Self {
+        MovieError::Io(err)
+    }
+}
+
+impl From<SaveStateError> for MovieError {
+    fn from(err: SaveStateError) -> Self {
+        match err {
+            // What the movie reader runs into, rather than a bad state inside it
+            SaveStateError::Corrupt(what) => MovieError::Corrupt(what),
+            err => MovieError::State(err),
+        }
+    }
+}
+
+impl From<EmulatorError> for MovieError {
+    fn from(err: EmulatorError) -> Self {
+        MovieError::Emulator(err)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Start {
+    PowerOn,
+    SaveData(Vec<u8>),  // Power-on with this in the cartridge's save memory
+    SaveState(Vec<u8>), // A save state, see `savestate`
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Checkpoint {
+    pub frame: u32, // Frames of the movie run by then
+    pub hash: u32,  // frame_hash
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Movie {
+    pub emulator_version: String, // Of whoever recorded it, for information
+    pub rom_crc32: u32,
+    pub game_code: String,
+    pub bios: Option<u32>, // Checksum of the BIOS dump, None for HLE
+    pub skip_bios: bool,   // With a dump, whether the boot intro was skipped
+    pub rtc_start: u64,    // Unix time the RTC shows at the first frame
+    pub rerecords: u32,
+    pub author: String,
+    pub start: Start,
+    pub frames: Vec<u16>,
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+/// CRC-32 of the framebuffer, its BGR555 pixels little-endian.
+pub fn frame_hash(gba: &Gba) -> u32 {
+    let pixels: Vec<u8> = gba
+        .bus
+        .ppu
+        .framebuffer
+        .iter()
+        .flat_map(|color| color.to_le_bytes())
+        .collect();
+    crc32(&pixels)
+}
+
+impl Movie {
+    /// An empty movie of `cartridge` from power-on with the HLE BIOS, the RTC starting at the
+    /// host's time.
+    pub fn new(cartridge: &Cartridge) -> Self {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        Movie {
+            emulator_version: savestate::EMULATOR_VERSION.to_string(),
+            rom_crc32: crc32(cartridge.rom()),
+            game_code: cartridge.header.game_code.clone(),
+            bios: None,
+            skip_bios: true,
+            rtc_start: now,
+            rerecords: 0,
+            author: String::new(),
+            start: Start::PowerOn,
+            frames: Vec::new(),
+            checkpoints: Vec::new(),
+        }
+    }
+
+    /// Builds the machine the movie starts on. `bios` is the dump it was made with, if any.
+    pub fn boot(&self, mut cartridge: Cartridge, bios: Option<&[u8]>) -> Result<Gba, MovieError> {
+        let rom = crc32(cartridge.rom());
+        if rom != self.rom_crc32 {
+            return Err(MovieError::RomMismatch {
+                expected: self.rom_crc32,
+                found: rom,
+            });
+        }
+        let found = bios.map(bios::checksum);
+        if found != self.bios {
+            return Err(MovieError::BiosMismatch {
+                expected: self.bios,
+                found,
+            });
+        }
+        if let Start::SaveData(data) = &self.start {
+            cartridge.load_save_data(data);
+        }
+        let mut gba = Gba::new(cartridge);
+        bios::skip_boot(&mut gba.cpu, &mut gba.bus);
+        if let Some(image) = bios {
+            gba.use_bios(image, self.skip_bios)
+                .map_err(|_| MovieError::Unsupported("BIOS dump of the wrong size"))?;
+        }
+        if let Start::SaveState(state) = &self.start {
+            gba.load_state(state)?;
+        }
+        self.set_clock(&mut gba, 0);
+        Ok(gba)
+    }
+
+    // The RTC follows emulated time, counted from the first frame
+    fn set_clock(&self, gba: &mut Gba, frame: usize) {
+        let seconds = self.rtc_start + frame as u64 * FRAME_CYCLES / CPU_CLOCK;
+        if let Some(cartridge) = &mut gba.bus.cartridge {
+            cartridge.set_rtc_clock(Box::new(FakeClock::new(seconds)));
+        }
+    }
+
+    fn run_frame(&self, gba: &mut Gba, frame: usize) -> Result<(), MovieError> {
+        self.set_clock(gba, frame);
+        gba.bus.set_keys(self.frames[frame] & KEYS_MASK);
+        gba.run_frame()?;
+        Ok(())
+    }
+
+    /// Plays frame `frame`, counting from 0, and checks a checkpoint at its end.
+    pub fn play_frame(&self, gba: &mut Gba, frame: usize) -> Result<(), MovieError> {
+        if frame >= self.frames.len() {
+            return Err(MovieError::Corrupt("frame past the end"));
+        }
+        self.run_frame(gba, frame)?;
+        let done = frame as u32 + 1;
+        for checkpoint in self.checkpoints.iter().filter(|c| c.frame == done) {
+            let found = frame_hash(gba);
+            if found != checkpoint.hash {
+                return Err(MovieError::Desync {
+                    frame: done,
+                    expected: checkpoint.hash,
+                    found,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Plays the whole movie on a machine from `boot`.
+    pub fn play(&self, gba: &mut Gba) -> Result<(), MovieError> {
+        (0..self.frames.len()).try_for_each(|frame| self.play_frame(gba, frame))
+    }
+
+    /// Runs one more frame with `keys` held and adds it to the movie.
+    pub fn record_frame(&mut self, gba: &mut Gba, keys: u16) -> Result<(), MovieError> {
+        self.frames.push(keys);
+        self.run_frame(gba, self.frames.len() - 1)
+    }
+
+    /// Records the screen hash as it is now, after the frames recorded so far.
+    pub fn add_checkpoint(&mut self, gba: &Gba) {
+        self.checkpoints.push(Checkpoint {
+            frame: self.frames.len() as u32,
+            hash: frame_hash(gba),
+        });
+    }
+
+    /// Cuts the movie back to `frames` frames to record from there again, after loading a state
+    /// made at that point.
+    pub fn rerecord_from(&mut self, frames: usize) {
+        self.frames.truncate(frames);
+        self.checkpoints.retain(|c| c.frame as usize <= frames);
+        self.rerecords += 1;
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = StateWriter::new();
+        out.raw(MAGIC);
+        out.u32(FORMAT_VERSION);
+        out.bytes(self.emulator_version.as_bytes());
+        out.u32(self.rom_crc32);
+        out.bytes(self.game_code.as_bytes());
+        out.option_u32(self.bios);
+        out.bool(self.skip_bios);
+        out.u64(self.rtc_start);
+        out.u32(self.rerecords);
+        out.bytes(self.author.as_bytes());
+        match &self.start {
+            Start::PowerOn => out.u8(0),
+            Start::SaveData(data) => {
+                out.u8(1);
+                out.bytes(data);
+            }
+            Start::SaveState(state) => {
+                out.u8(2);
+                out.bytes(state);
+            }
+        }
+        out.u32(self.frames.len() as u32);
+        for keys in &self.frames {
+            out.u16(*keys);
+        }
+        out.u32(self.checkpoints.len() as u32);
+        for checkpoint in &self.checkpoints {
+            out.u32(checkpoint.frame);
+            out.u32(checkpoint.hash);
+        }
+        out.into_bytes()
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Movie, MovieError> {
+        if !data.starts_with(MAGIC) {
+            return Err(MovieError::NotAMovie);
+        }
+        let mut reader = StateReader::new(&data[MAGIC.len()..]);
+        let found = reader.u32()?;
+        if found != FORMAT_VERSION {
+            return Err(MovieError::UnsupportedVersion { found });
+        }
+        let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+        let mut movie = Movie {
+            emulator_version: text(reader.bytes()?),
+            rom_crc32: reader.u32()?,
+            game_code: text(reader.bytes()?),
+            bios: reader.option_u32()?,
+            skip_bios: reader.bool()?,
+            rtc_start: reader.u64()?,
+            rerecords: reader.u32()?,
+            author: text(reader.bytes()?),
+            start: match reader.u8()? {
+                0 => Start::PowerOn,
+                1 => Start::SaveData(reader.bytes()?.to_vec()),
+                2 => Start::SaveState(reader.bytes()?.to_vec()),
+                _ => return Err(MovieError::Corrupt("unknown start")),
+            },
+            frames: Vec::new(),
+            checkpoints: Vec::new(),
+        };
+        for _ in 0..reader.u32()? {
+            movie.frames.push(reader.u16()?);
+        }
+        for _ in 0..reader.u32()? {
+            movie.checkpoints.push(Checkpoint {
+                frame: reader.u32()?,
+                hash: reader.u32()?,
+            });
+        }
+        if !reader.is_at_end() {
+            return Err(MovieError::Corrupt("trailing data"));
+        }
+        Ok(movie)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Movie, MovieError> {
+        Movie::parse(&std::fs::read(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), MovieError> {
+        std::fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+}
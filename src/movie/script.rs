@@ -0,0 +1,114 @@
+// src/movie/script.rs
+// Input scripts, a hand-written alternative to recording a movie. One press per line, the
+// frame (or an inclusive range of frames) and the buttons held, joined by `+`:
+//
+//   # Skip the title screen and walk right for a second
+//   120 START
+//   200-259 RIGHT
+//   260-265 A+RIGHT
+//
+// Frames count from 0, presses that overlap add up. `#` starts a comment.
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+const BUTTONS: [&str; 10] = [
+    "A", "B", "SELECT", "START", "RIGHT", "LEFT", "UP", "DOWN", "R", "L",
+];
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ScriptError {
+    pub line: usize, // 1-based
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Press {
+    pub first: u32,
+    pub last: u32,
+    pub keys: u16, // Active high, as Bus::set_keys takes them
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct InputScript {
+    pub presses: Vec<Press>,
+}
+
+/// Button bits for `A+START`-style names, case-insensitive. `none` is no buttons.
+pub fn parse_keys(text: &str) -> Option<u16> {
+    if text.eq_ignore_ascii_case("none") {
+        return Some(0);
+    }
+    text.split('+').try_fold(0, |keys, name| {
+        let bit = BUTTONS
+            .iter()
+            .position(|button| button.eq_ignore_ascii_case(name.trim()))?;
+        Some(keys | 1 << bit)
+    })
+}
+
+impl InputScript {
+    pub fn parse(text: &str) -> Result<InputScript, ScriptError> {
+        let mut script = InputScript::default();
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| ScriptError {
+                line: index + 1,
+                message,
+            };
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (frames, buttons) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| error("expected a frame and buttons".to_string()))?;
+            let frame = |text: &str| {
+                text.parse::<u32>()
+                    .map_err(|_| error(format!("bad frame '{}'", text)))
+            };
+            let (first, last) = match frames.split_once('-') {
+                Some((first, last)) => (frame(first)?, frame(last)?),
+                None => (frame(frames)?, frame(frames)?),
+            };
+            if last < first {
+                return Err(error(format!("range {} ends before it starts", frames)));
+            }
+            let buttons = buttons.trim();
+            let keys =
+                parse_keys(buttons).ok_or_else(|| error(format!("bad buttons '{}'", buttons)))?;
+            script.presses.push(Press { first, last, keys });
+        }
+        Ok(script)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<InputScript> {
+        InputScript::parse(&std::fs::read_to_string(path)?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// The buttons held during `frame`.
+    pub fn keys_at(&self, frame: u32) -> u16 {
+        self.presses
+            .iter()
+            .filter(|press| (press.first..=press.last).contains(&frame))
+            .fold(0, |keys, press| keys | press.keys)
+    }
+
+    /// The frame after the last press.
+    pub fn length(&self) -> u32 {
+        self.presses
+            .iter()
+            .map(|press| press.last + 1)
+            .max()
+            .unwrap_or(0)
+    }
+}
@@ -0,0 +1,183 @@
+// src/movie/vbm.rs
+// VisualBoyAdvance's .vbm movies. A 64 byte header, 192 bytes of author, then the start SRAM
+// or snapshot and two bytes of keys per frame and controller:
+//
+//   0x00 "VBM\x1A" | 0x04 version 1 | 0x08 UID, the recording time | 0x0C frames |
+//   0x10 rerecords | 0x14 start flags | 0x15 controllers | 0x16 system | 0x17 options |
+//   0x18 save type | 0x1C flash size | 0x20 GB emulator type | 0x24 title (12) |
+//   0x30 minor version | 0x31 header complement | 0x32 BIOS CRC-16 | 0x34 game code |
+//   0x38 offset of the start data | 0x3C offset of the keys
+//
+// The keys are KEYINPUT's bits active high, like ours, plus a reset in bit 10 and VBA's motion
+// sensor in 12-15. VBM has no ROM checksum, so both directions take the cartridge: import checks
+// it against the game code and header complement and fills in our CRC-32 from it. Snapshot starts
+// are VBA's own save states and can't be converted, neither can resets.
+
+use crate::cartridge::save::SaveType;
+use crate::cartridge::Cartridge;
+use crate::movie::{Movie, MovieError, Start, KEYS_MASK};
+use crate::savestate::crc32;
+
+pub const MAGIC: &[u8; 4] = b"VBM\x1A";
+const HEADER_SIZE: usize = 0x40;
+const AUTHOR_SIZE: usize = 192;
+
+const START_SNAPSHOT: u8 = 1 << 0;
+const START_SRAM: u8 = 1 << 1;
+const SYSTEM_GBA: u8 = 1 << 0;
+const OPTION_BIOS_FILE: u8 = 1 << 0;
+const OPTION_SKIP_BIOS: u8 = 1 << 1;
+const OPTION_RTC: u8 = 1 << 2;
+const OPTION_LAG_REDUCTION: u8 = 1 << 4; // The current GBA timing rather than the old laggy one
+const KEY_RESET: u16 = 1 << 10;
+
+fn put32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, MovieError> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .ok_or(MovieError::Corrupt("VBM ends early"))?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+pub fn import(data: &[u8], cartridge: &Cartridge) -> Result<Movie, MovieError> {
+    if !data.starts_with(MAGIC) {
+        return Err(MovieError::NotAMovie);
+    }
+    let version = u32_at(data, 0x04)?;
+    if version != 1 {
+        return Err(MovieError::UnsupportedVersion { found: version });
+    }
+    if data.len() < HEADER_SIZE + AUTHOR_SIZE {
+        return Err(MovieError::Corrupt("VBM ends early"));
+    }
+    let (start_flags, controllers, system, options) =
+        (data[0x14], data[0x15], data[0x16], data[0x17]);
+    if system & SYSTEM_GBA == 0 {
+        return Err(MovieError::Unsupported("not a GBA movie"));
+    }
+    if start_flags & START_SNAPSHOT != 0 {
+        return Err(MovieError::Unsupported("starts from a VBA save state"));
+    }
+    if options & OPTION_BIOS_FILE != 0 && options & OPTION_SKIP_BIOS == 0 {
+        return Err(MovieError::Unsupported("plays the BIOS intro"));
+    }
+    if controllers & 1 == 0 {
+        return Err(MovieError::Unsupported("no first controller"));
+    }
+    let rom = cartridge.rom();
+    if data[0x34..0x38] != rom[0xAC..0xB0] || data[0x31] != rom[0xBD] {
+        return Err(MovieError::GameMismatch {
+            expected: String::from_utf8_lossy(&data[0x34..0x38]).into_owned(),
+            found: cartridge.header.game_code.clone(),
+        });
+    }
+
+    let frame_count = u32_at(data, 0x0C)? as usize;
+    let start_offset = u32_at(data, 0x38)? as usize;
+    let keys_offset = u32_at(data, 0x3C)? as usize;
+    let start = if start_flags & START_SRAM != 0 {
+        let sram = data
+            .get(start_offset..keys_offset)
+            .ok_or(MovieError::Corrupt("bad SRAM offset"))?;
+        Start::SaveData(sram.to_vec())
+    } else {
+        Start::PowerOn
+    };
+    // Only the first controller's keys are ours, the others are for the Super Game Boy
+    let stride = 2 * (controllers & 0x0F).count_ones() as usize;
+    let keys = data
+        .get(keys_offset..keys_offset + frame_count * stride)
+        .ok_or(MovieError::Corrupt("VBM ends early"))?;
+    let mut frames = Vec::with_capacity(frame_count);
+    for frame in keys.chunks_exact(stride) {
+        let keys = u16::from_le_bytes([frame[0], frame[1]]);
+        if keys & KEY_RESET != 0 {
+            return Err(MovieError::Unsupported("resets during the movie"));
+        }
+        frames.push(keys & KEYS_MASK);
+    }
+
+    let author = &data[HEADER_SIZE..HEADER_SIZE + AUTHOR_SIZE];
+    let author_end = author
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(AUTHOR_SIZE);
+    Ok(Movie {
+        emulator_version: "VBA".to_string(),
+        rom_crc32: crc32(rom),
+        game_code: cartridge.header.game_code.clone(),
+        bios: None,
+        skip_bios: true,
+        // VBA's RTC runs on the host clock, the recording time is the best guess there is
+        rtc_start: u32_at(data, 0x08)? as u64,
+        rerecords: u32_at(data, 0x10)?,
+        author: String::from_utf8_lossy(&author[..author_end]).into_owned(),
+        start,
+        frames,
+        checkpoints: Vec::new(),
+    })
+}
+
+pub fn export(movie: &Movie, cartridge: &Cartridge) -> Result<Vec<u8>, MovieError> {
+    let (start_flags, start) = match &movie.start {
+        Start::PowerOn => (0, &[][..]),
+        Start::SaveData(data) => (START_SRAM, &data[..]),
+        Start::SaveState(_) => return Err(MovieError::Unsupported("starts from a save state")),
+    };
+    let rom = cartridge.rom();
+    let rom_crc32 = crc32(rom);
+    if rom_crc32 != movie.rom_crc32 {
+        return Err(MovieError::RomMismatch {
+            expected: movie.rom_crc32,
+            found: rom_crc32,
+        });
+    }
+    let mut options = OPTION_SKIP_BIOS | OPTION_LAG_REDUCTION;
+    if movie.bios.is_some() {
+        options |= OPTION_BIOS_FILE;
+    }
+    if cartridge.gpio().is_some_and(|gpio| gpio.rtc.is_some()) {
+        options |= OPTION_RTC;
+    }
+    // VBA's save type setting: 0 automatic, 1 EEPROM, 2 SRAM, 3 flash, 5 none
+    let (save_type, flash_size) = match cartridge.save_type() {
+        SaveType::None => (5, 0),
+        SaveType::Sram => (2, 0),
+        SaveType::Flash(chip) => (3, chip.size() as u32),
+        SaveType::Eeprom(_) => (1, 0),
+    };
+
+    let mut out = vec![0u8; HEADER_SIZE + AUTHOR_SIZE];
+    out[0..4].copy_from_slice(MAGIC);
+    put32(&mut out, 0x04, 1);
+    put32(&mut out, 0x08, movie.rtc_start as u32);
+    put32(&mut out, 0x0C, movie.frames.len() as u32);
+    put32(&mut out, 0x10, movie.rerecords);
+    out[0x14] = start_flags;
+    out[0x15] = 1;
+    out[0x16] = SYSTEM_GBA;
+    out[0x17] = options;
+    put32(&mut out, 0x18, save_type);
+    put32(&mut out, 0x1C, flash_size);
+    out[0x24..0x30].copy_from_slice(&rom[0xA0..0xAC]);
+    out[0x30] = 1;
+    out[0x31] = rom[0xBD];
+    out[0x34..0x38].copy_from_slice(&rom[0xAC..0xB0]);
+    let author = movie.author.as_bytes();
+    let author_len = author.len().min(AUTHOR_SIZE - 1);
+    out[HEADER_SIZE..HEADER_SIZE + author_len].copy_from_slice(&author[..author_len]);
+    if !start.is_empty() {
+        let offset = out.len() as u32;
+        put32(&mut out, 0x38, offset);
+        out.extend_from_slice(start);
+    }
+    let offset = out.len() as u32;
+    put32(&mut out, 0x3C, offset);
+    for keys in &movie.frames {
+        out.extend_from_slice(&(keys & KEYS_MASK).to_le_bytes());
+    }
+    Ok(out)
+}
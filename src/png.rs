@@ -0,0 +1,73 @@
+// src/png.rs
+// Minimal PNG writer for screenshots: 8 bit RGB, one IDAT chunk, the zlib stream made of stored
+// (uncompressed) deflate blocks. A 240x160 screenshot comes out around 116 KB, which is fine for
+// CI artifacts and needs no compressor.
+
+use std::io;
+use std::path::Path;
+
+use crate::savestate::crc32;
+
+const SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        out.push(last as u8);
+        let length = block.len() as u16;
+        out.extend_from_slice(&length.to_le_bytes());
+        out.extend_from_slice(&(!length).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+/// Encodes `rgb`, three bytes a pixel row by row, as a PNG image.
+pub fn encode_rgb(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    let row = width as usize * 3;
+    let mut scanlines = Vec::with_capacity((row + 1) * height as usize);
+    for line in rgb.chunks(row).take(height as usize) {
+        scanlines.push(0); // No filter
+        scanlines.extend_from_slice(line);
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]); // 8 bit, RGB, deflate, no filter, no interlace
+
+    let mut out = SIGNATURE.to_vec();
+    chunk(&mut out, b"IHDR", &header);
+    chunk(&mut out, b"IDAT", &zlib_stored(&scanlines));
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+pub fn write_rgb<P: AsRef<Path>>(path: P, width: u32, height: u32, rgb: &[u8]) -> io::Result<()> {
+    std::fs::write(path, encode_rgb(width, height, rgb))
+}
@@ -0,0 +1,153 @@
+// src/ppu/mod.rs
+// Display timing: DISPSTAT, VCOUNT and the blanking interrupts. Each of the 228 scanlines
+// takes 1232 cycles, 960 drawing and 272 in HBlank; lines 160-227 are VBlank. The picture
+// itself is drawn a line at a time by `render`.
+
+pub mod render;
+
+use crate::bus::Bus;
+use crate::dma::DmaTiming;
+use crate::interrupt::Interrupt;
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+use crate::scheduler::Event;
+
+pub const HDRAW_CYCLES: u64 = 960;
+pub const LINE_CYCLES: u64 = 1232;
+pub const VISIBLE_LINES: u16 = 160;
+pub const TOTAL_LINES: u16 = 228;
+pub const FRAME_CYCLES: u64 = LINE_CYCLES * TOTAL_LINES as u64;
+pub const SCREEN_WIDTH: usize = 240;
+pub const SCREEN_HEIGHT: usize = VISIBLE_LINES as usize;
+
+pub const REG_DISPSTAT: u32 = 0x004;
+pub const REG_VCOUNT: u32 = 0x006;
+
+const DISPSTAT_VBLANK: u16 = 1 << 0;
+const DISPSTAT_HBLANK: u16 = 1 << 1;
+const DISPSTAT_VCOUNT_MATCH: u16 = 1 << 2;
+const DISPSTAT_VBLANK_IRQ: u16 = 1 << 3;
+const DISPSTAT_HBLANK_IRQ: u16 = 1 << 4;
+const DISPSTAT_VCOUNT_IRQ: u16 = 1 << 5;
+const DISPSTAT_READ_ONLY: u16 = DISPSTAT_VBLANK | DISPSTAT_HBLANK | DISPSTAT_VCOUNT_MATCH;
+
+#[derive(Debug, Default)]
+pub struct Ppu {
+    pub dispstat: u16,
+    pub vcount: u16,
+    pub frame: u64,            // Frames completed since power on
+    pub framebuffer: Vec<u16>, // 240x160 BGR555, as the palette stores colors
+}
+
+impl Ppu {
+    pub fn new() -> Self {
+        Ppu {
+            dispstat: 0,
+            vcount: 0,
+            frame: 0,
+            framebuffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+
+    fn vcount_setting(&self) -> u16 {
+        self.dispstat >> 8
+    }
+
+    pub fn in_vblank(&self) -> bool {
+        self.dispstat & DISPSTAT_VBLANK != 0
+    }
+
+    /// The framebuffer as 8 bit RGB, three bytes a pixel, for screenshots.
+    pub fn framebuffer_rgb(&self) -> Vec<u8> {
+        let expand = |value: u16| ((value & 0x1F) << 3 | (value & 0x1F) >> 2) as u8;
+        self.framebuffer
+            .iter()
+            .flat_map(|&color| [expand(color), expand(color >> 5), expand(color >> 10)])
+            .collect()
+    }
+}
+
+impl Bus {
+    pub(crate) fn read_ppu_register(&self, offset: u32) -> u8 {
+        match offset {
+            0x004 => self.ppu.dispstat as u8,
+            0x005 => (self.ppu.dispstat >> 8) as u8,
+            0x006 => self.ppu.vcount as u8,
+            _ => 0,
+        }
+    }
+
+    pub(crate) fn write_ppu_register(&mut self, offset: u32, value: u8) {
+        match offset {
+            0x004 => {
+                let writable = value as u16 & !DISPSTAT_READ_ONLY;
+                self.ppu.dispstat = (self.ppu.dispstat & (0xFF00 | DISPSTAT_READ_ONLY)) | writable;
+            }
+            0x005 => self.ppu.dispstat = (self.ppu.dispstat & 0x00FF) | ((value as u16) << 8),
+            _ => {} // VCOUNT is read only
+        }
+    }
+
+    pub(crate) fn handle_ppu_event(&mut self, event: Event, at: u64) {
+        match event {
+            Event::HBlank => {
+                self.ppu.dispstat |= DISPSTAT_HBLANK;
+                if self.ppu.dispstat & DISPSTAT_HBLANK_IRQ != 0 {
+                    self.request_interrupt(Interrupt::HBlank);
+                }
+                if self.ppu.vcount < VISIBLE_LINES {
+                    self.render_scanline();
+                    self.trigger_dma(DmaTiming::HBlank);
+                }
+                self.scheduler.schedule_at(at + LINE_CYCLES, Event::HBlank);
+            }
+            Event::EndOfLine => {
+                self.ppu.dispstat &= !DISPSTAT_HBLANK;
+                self.ppu.vcount = (self.ppu.vcount + 1) % TOTAL_LINES;
+                match self.ppu.vcount {
+                    VISIBLE_LINES => {
+                        self.ppu.dispstat |= DISPSTAT_VBLANK;
+                        if self.ppu.dispstat & DISPSTAT_VBLANK_IRQ != 0 {
+                            self.request_interrupt(Interrupt::VBlank);
+                        }
+                        self.trigger_dma(DmaTiming::VBlank);
+                    }
+                    // The flag drops a line early, on the last line of the frame
+                    227 => self.ppu.dispstat &= !DISPSTAT_VBLANK,
+                    0 => self.ppu.frame += 1,
+                    _ => {}
+                }
+                if self.ppu.vcount == self.ppu.vcount_setting() {
+                    self.ppu.dispstat |= DISPSTAT_VCOUNT_MATCH;
+                    if self.ppu.dispstat & DISPSTAT_VCOUNT_IRQ != 0 {
+                        self.request_interrupt(Interrupt::VCount);
+                    }
+                } else {
+                    self.ppu.dispstat &= !DISPSTAT_VCOUNT_MATCH;
+                }
+                self.scheduler.schedule_at(at + LINE_CYCLES, Event::EndOfLine);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Snapshot for Ppu {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.dispstat);
+        state.u16(self.vcount);
+        state.u64(self.frame);
+        for color in &self.framebuffer {
+            state.u16(*color);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.dispstat = state.u16()?;
+        self.vcount = state.u16()?;
+        self.frame = state.u64()?;
+        for color in self.framebuffer.iter_mut() {
+            *color = state.u16()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+// src/ppu/render.rs
+// Scanline renderer: draws each visible line into the framebuffer as HBlank starts. Covers the
+// tiled modes 0-2 (text and affine backgrounds), the bitmap modes 3-5 and regular and affine
+// sprites, layered by priority. Windows, blending and mosaic aren't done yet, layers are drawn
+// as if they were off.
+//
+// Affine backgrounds take their reference point from BGxX/BGxY plus PB/PD per line, rather
+// than from the internal registers the hardware latches at VBlank, so mid-frame writes to the
+// reference point only show from the next frame on.
+
+use crate::bus::Bus;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+const REG_DISPCNT: u32 = 0x000;
+const REG_BG0CNT: u32 = 0x008;
+const REG_BG0HOFS: u32 = 0x010;
+const REG_BG2PA: u32 = 0x020;
+
+const DISPCNT_FRAME_SELECT: u16 = 1 << 4;
+const DISPCNT_OBJ_1D: u16 = 1 << 6;
+const DISPCNT_FORCED_BLANK: u16 = 1 << 7;
+const DISPCNT_OBJ: u16 = 1 << 12;
+
+const OBJ_TILES: usize = 0x10000;
+const OBJ_PALETTE: usize = 0x200;
+const BITMAP_FRAME_SIZE: usize = 0xA000;
+const WHITE: u16 = 0x7FFF;
+
+// Sprite sizes in pixels, by shape (square, wide, tall) and size
+const OBJ_SIZES: [[(i32, i32); 4]; 3] = [
+    [(8, 8), (16, 16), (32, 32), (64, 64)],
+    [(16, 8), (32, 8), (32, 16), (64, 32)],
+    [(8, 16), (8, 32), (16, 32), (32, 64)],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Background {
+    Text,
+    Affine,
+    Bitmap,
+}
+
+impl Bus {
+    fn vram_halfword(&self, offset: usize) -> u16 {
+        match self.vram.get(offset..offset + 2) {
+            Some(bytes) => u16::from_le_bytes([bytes[0], bytes[1]]),
+            None => 0,
+        }
+    }
+
+    fn palette_color(&self, offset: usize) -> u16 {
+        u16::from_le_bytes([self.palette[offset], self.palette[offset + 1]]) & 0x7FFF
+    }
+
+    // Affine parameters as signed 8.8 fixed point
+    fn io_signed(&self, offset: u32) -> i32 {
+        self.io_halfword(offset) as i16 as i32
+    }
+
+    // BGxX/BGxY, 28 bit signed 20.8 fixed point
+    fn io_reference(&self, offset: u32) -> i32 {
+        let value = self.io_halfword(offset) as u32 | (self.io_halfword(offset + 2) as u32) << 16;
+        ((value << 4) as i32) >> 4
+    }
+
+    /// Draws line VCOUNT into the framebuffer.
+    pub(crate) fn render_scanline(&mut self) {
+        let y = self.ppu.vcount as usize;
+        if y >= SCREEN_HEIGHT {
+            return;
+        }
+        let dispcnt = self.io_halfword(REG_DISPCNT);
+        let mut line = [WHITE; SCREEN_WIDTH];
+        if dispcnt & DISPCNT_FORCED_BLANK == 0 {
+            self.compose_line(dispcnt, y, &mut line);
+        }
+        self.ppu.framebuffer[y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH].copy_from_slice(&line);
+    }
+
+    fn compose_line(&self, dispcnt: u16, y: usize, line: &mut [u16; SCREEN_WIDTH]) {
+        let mode = dispcnt & 7;
+        let layers: &[(usize, Background)] = match mode {
+            0 => &[
+                (0, Background::Text),
+                (1, Background::Text),
+                (2, Background::Text),
+                (3, Background::Text),
+            ],
+            1 => &[
+                (0, Background::Text),
+                (1, Background::Text),
+                (2, Background::Affine),
+            ],
+            2 => &[(2, Background::Affine), (3, Background::Affine)],
+            3..=5 => &[(2, Background::Bitmap)],
+            _ => &[],
+        };
+        let sprites = if dispcnt & DISPCNT_OBJ != 0 {
+            self.sprite_line(dispcnt, y)
+        } else {
+            [None; SCREEN_WIDTH]
+        };
+
+        line.fill(self.palette_color(0));
+        // Back to front: lower priority values and, between equals, lower numbers end up on top
+        for priority in (0..4).rev() {
+            for &(bg, kind) in layers.iter().rev() {
+                let control = self.io_halfword(REG_BG0CNT + 2 * bg as u32);
+                if dispcnt & (1 << (8 + bg)) == 0 || control & 3 != priority {
+                    continue;
+                }
+                for (x, pixel) in line.iter_mut().enumerate() {
+                    let color = match kind {
+                        Background::Text => self.text_pixel(bg, control, x, y),
+                        Background::Affine => self.affine_pixel(bg, control, x, y),
+                        Background::Bitmap => self.bitmap_pixel(mode, dispcnt, x, y),
+                    };
+                    if let Some(color) = color {
+                        *pixel = color;
+                    }
+                }
+            }
+            for (pixel, sprite) in line.iter_mut().zip(&sprites) {
+                if let Some((color, _)) = sprite.filter(|&(_, p)| p == priority) {
+                    *pixel = color;
+                }
+            }
+        }
+    }
+
+    fn text_pixel(&self, bg: usize, control: u16, x: usize, y: usize) -> Option<u16> {
+        let hofs = self.io_halfword(REG_BG0HOFS + 4 * bg as u32) as usize & 0x1FF;
+        let vofs = self.io_halfword(REG_BG0HOFS + 4 * bg as u32 + 2) as usize & 0x1FF;
+        let size = (control >> 14) as usize;
+        let (width, height) = (256 << (size & 1), 256 << (size >> 1));
+        let (px, py) = ((x + hofs) % width, (y + vofs) % height);
+
+        // 256x256 screen blocks, side by side and then below each other
+        let block = px / 256 + (py / 256) * (width / 256);
+        let screen_base = ((control >> 8) & 0x1F) as usize * 0x800;
+        let entry_offset = screen_base + block * 0x800 + ((py % 256) / 8 * 32 + (px % 256) / 8) * 2;
+        let entry = self.vram_halfword(entry_offset);
+
+        let mut tx = px % 8;
+        let mut ty = py % 8;
+        if entry & (1 << 10) != 0 {
+            tx = 7 - tx;
+        }
+        if entry & (1 << 11) != 0 {
+            ty = 7 - ty;
+        }
+        let char_base = ((control >> 2) & 3) as usize * 0x4000;
+        let tile = (entry & 0x3FF) as usize;
+        if control & (1 << 7) != 0 {
+            let index = *self.vram.get(char_base + tile * 64 + ty * 8 + tx)? as usize;
+            (index != 0).then(|| self.palette_color(index * 2))
+        } else {
+            let byte = *self.vram.get(char_base + tile * 32 + ty * 4 + tx / 2)?;
+            let index = (byte >> ((tx & 1) * 4)) as usize & 0xF;
+            let palette = (entry >> 12) as usize;
+            (index != 0).then(|| self.palette_color((palette * 16 + index) * 2))
+        }
+    }
+
+    // Texture coordinates of screen pixel (x, y) through BG2's or BG3's matrix
+    fn affine_coordinates(&self, bg: usize, x: usize, y: usize) -> (i32, i32) {
+        let base = REG_BG2PA + 0x10 * (bg as u32 - 2);
+        let (pa, pb) = (self.io_signed(base), self.io_signed(base + 2));
+        let (pc, pd) = (self.io_signed(base + 4), self.io_signed(base + 6));
+        let (x0, y0) = (self.io_reference(base + 8), self.io_reference(base + 12));
+        let (x, y) = (x as i32, y as i32);
+        ((x0 + pa * x + pb * y) >> 8, (y0 + pc * x + pd * y) >> 8)
+    }
+
+    fn affine_pixel(&self, bg: usize, control: u16, x: usize, y: usize) -> Option<u16> {
+        let size = 128 << (control >> 14);
+        let (mut tx, mut ty) = self.affine_coordinates(bg, x, y);
+        if control & (1 << 13) != 0 {
+            tx = tx.rem_euclid(size);
+            ty = ty.rem_euclid(size);
+        } else if !(0..size).contains(&tx) || !(0..size).contains(&ty) {
+            return None;
+        }
+        let (tx, ty) = (tx as usize, ty as usize);
+        let screen_base = ((control >> 8) & 0x1F) as usize * 0x800;
+        let tiles_per_row = size as usize / 8;
+        let tile = *self
+            .vram
+            .get(screen_base + ty / 8 * tiles_per_row + tx / 8)? as usize;
+        let char_base = ((control >> 2) & 3) as usize * 0x4000;
+        let index = *self
+            .vram
+            .get(char_base + tile * 64 + (ty % 8) * 8 + tx % 8)? as usize;
+        (index != 0).then(|| self.palette_color(index * 2))
+    }
+
+    fn bitmap_pixel(&self, mode: u16, dispcnt: u16, x: usize, y: usize) -> Option<u16> {
+        let (tx, ty) = self.affine_coordinates(2, x, y);
+        let (width, height) = if mode == 5 { (160, 128) } else { (240, 160) };
+        if !(0..width).contains(&tx) || !(0..height).contains(&ty) {
+            return None;
+        }
+        let pixel = (ty * width + tx) as usize;
+        let frame = if dispcnt & DISPCNT_FRAME_SELECT != 0 {
+            BITMAP_FRAME_SIZE
+        } else {
+            0
+        };
+        match mode {
+            3 => Some(self.vram_halfword(pixel * 2) & 0x7FFF),
+            4 => {
+                let index = self.vram[frame + pixel] as usize;
+                (index != 0).then(|| self.palette_color(index * 2))
+            }
+            _ => Some(self.vram_halfword(frame + pixel * 2) & 0x7FFF),
+        }
+    }
+
+    // The frontmost sprite pixel at each x of line y, with its priority
+    fn sprite_line(&self, dispcnt: u16, y: usize) -> [Option<(u16, u16)>; SCREEN_WIDTH] {
+        let mut line = [None; SCREEN_WIDTH];
+        let bitmap_mode = dispcnt & 7 >= 3;
+        // Lower OAM entries go in front, so they get to fill a pixel first
+        for entry in 0..128 {
+            let attribute = |i: usize| {
+                let offset = entry * 8 + i * 2;
+                u16::from_le_bytes([self.oam[offset], self.oam[offset + 1]])
+            };
+            let (attr0, attr1, attr2) = (attribute(0), attribute(1), attribute(2));
+            let affine = attr0 & (1 << 8) != 0;
+            let double_size = attr0 & (1 << 9) != 0;
+            let mode = (attr0 >> 10) & 3;
+            let shape = (attr0 >> 14) as usize;
+            // Hidden, OBJ window (no windows yet) or prohibited
+            if !affine && double_size || mode >= 2 || shape == 3 {
+                continue;
+            }
+            let (width, height) = OBJ_SIZES[shape][(attr1 >> 14) as usize];
+            let scale = if affine && double_size { 2 } else { 1 };
+            let (box_width, box_height) = (width * scale, height * scale);
+
+            let line_in_sprite = (y as i32 - (attr0 & 0xFF) as i32).rem_euclid(256);
+            if line_in_sprite >= box_height {
+                continue;
+            }
+            let mut left = (attr1 & 0x1FF) as i32;
+            if left >= SCREEN_WIDTH as i32 {
+                left -= 512;
+            }
+
+            let eight_bit = attr0 & (1 << 13) != 0;
+            let tile = (attr2 & 0x3FF) as usize;
+            let priority = (attr2 >> 10) & 3;
+            let palette = (attr2 >> 12) as usize;
+            let matrix = (attr1 >> 9) as usize & 0x1F;
+            let parameter = |i: usize| {
+                let offset = matrix * 32 + 6 + i * 8;
+                i16::from_le_bytes([self.oam[offset], self.oam[offset + 1]]) as i32
+            };
+
+            for sx in 0..box_width {
+                let x = left + sx;
+                if !(0..SCREEN_WIDTH as i32).contains(&x) || line[x as usize].is_some() {
+                    continue;
+                }
+                let (tx, ty) = if affine {
+                    let (dx, dy) = (sx - box_width / 2, line_in_sprite - box_height / 2);
+                    let tx = ((parameter(0) * dx + parameter(1) * dy) >> 8) + width / 2;
+                    let ty = ((parameter(2) * dx + parameter(3) * dy) >> 8) + height / 2;
+                    if !(0..width).contains(&tx) || !(0..height).contains(&ty) {
+                        continue;
+                    }
+                    (tx, ty)
+                } else {
+                    let tx = if attr1 & (1 << 12) != 0 {
+                        width - 1 - sx
+                    } else {
+                        sx
+                    };
+                    let ty = if attr1 & (1 << 13) != 0 {
+                        height - 1 - line_in_sprite
+                    } else {
+                        line_in_sprite
+                    };
+                    (tx, ty)
+                };
+                let (tx, ty) = (tx as usize, ty as usize);
+
+                // 8 bit tiles take two 32 byte tile slots
+                let step = if eight_bit { 2 } else { 1 };
+                let row = if dispcnt & DISPCNT_OBJ_1D != 0 {
+                    ty / 8 * (width as usize / 8) * step
+                } else {
+                    ty / 8 * 32
+                };
+                let tile_index = (tile + row + tx / 8 * step) & 0x3FF;
+                // The bitmap modes need the lower half of sprite VRAM for the picture
+                if bitmap_mode && tile_index < 512 {
+                    continue;
+                }
+                let tile_base = OBJ_TILES + tile_index * 32;
+                let color = if eight_bit {
+                    let index = self.vram[tile_base + (ty % 8) * 8 + tx % 8] as usize;
+                    (index != 0).then(|| self.palette_color(OBJ_PALETTE + index * 2))
+                } else {
+                    let byte = self.vram[tile_base + (ty % 8) * 4 + (tx % 8) / 2];
+                    let index = (byte >> ((tx & 1) * 4)) as usize & 0xF;
+                    (index != 0)
+                        .then(|| self.palette_color(OBJ_PALETTE + (palette * 16 + index) * 2))
+                };
+                if let Some(color) = color {
+                    line[x as usize] = Some((color, priority));
+                }
+            }
+        }
+        line
+    }
+}
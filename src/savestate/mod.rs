@@ -0,0 +1,331 @@
+// src/savestate/mod.rs
+// Save states: the whole machine, CPU, memory, I/O and cartridge state, in one file.
+//
+//   "GBASTATE" | format version u32 | emulator version (u8 length + text) | ROM CRC-32 u32 |
+//   body length u32 | body CRC-32 u32 | RLE compressed body
+//
+// All little-endian. Each part of the machine writes itself into the body through `Snapshot`,
+// in a fixed order, so any change to what gets saved needs a new FORMAT_VERSION. The ROM and
+// BIOS images are not saved, a state only loads on top of the ROM it was made with. Loading
+// checks everything it can before touching the machine. Host-side things, the link cable,
+// the RTC's clock source, debugger and tracer, stay as they are.
+
+pub mod rewind;
+pub mod rle;
+
+use std::fmt;
+use std::path::Path;
+
+use crate::gba::Gba;
+
+const MAGIC: &[u8; 8] = b"GBASTATE";
+pub const FORMAT_VERSION: u32 = 3;
+pub const EMULATOR_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug)]
+pub enum SaveStateError {
+    Io(std::io::Error),
+    NotASaveState,
+    UnsupportedVersion { found: u32, emulator: String }, // Format version and who wrote it
+    RomMismatch { expected: u32, found: u32 },           // ROM CRC-32s, the state's and ours
+    NoCartridge,
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::Io(err) => write!(f, "could not access save state: {}", err),
+            SaveStateError::NotASaveState => write!(f, "not a save state"),
+            SaveStateError::UnsupportedVersion { found, emulator } => write!(
+                f,
+                "save state format {} (written by emulator {}) is not supported, this is format {} (emulator {})",
+                found, emulator, FORMAT_VERSION, EMULATOR_VERSION
+            ),
+            SaveStateError::RomMismatch { expected, found } => write!(
+                f,
+                "save state is for a different ROM: made with CRC-32 {:08X}, loaded ROM is {:08X}",
+                expected, found
+            ),
+            SaveStateError::NoCartridge => write!(f, "no cartridge inserted"),
+            SaveStateError::Corrupt(what) => write!(f, "save state is corrupt: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+impl From<std::io::Error> for SaveStateError {
+    fn from(err: std::io::Error) -> Self {
+        SaveStateError::Io(err)
+    }
+}
+
+/// Something that can be written into a save state and read back.
+pub trait Snapshot {
+    fn save_state(&self, state: &mut StateWriter);
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError>;
+}
+
+#[derive(Debug, Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        StateWriter::default()
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Bytes as they are, for magic numbers.
+    pub fn raw(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn i16(&mut self, value: i16) {
+        self.u16(value as u16);
+    }
+
+    pub fn i64(&mut self, value: i64) {
+        self.u64(value as u64);
+    }
+
+    /// A length-prefixed block, for memories whose size can vary.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn option_u32(&mut self, value: Option<u32>) {
+        self.bool(value.is_some());
+        self.u32(value.unwrap_or(0));
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data, position: 0 }
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.position == self.data.len()
+    }
+
+    pub fn raw(&mut self, count: usize) -> Result<&'a [u8], SaveStateError> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + count)
+            .ok_or(SaveStateError::Corrupt("ends early"))?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.raw(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, SaveStateError> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, SaveStateError> {
+        let bytes = self.raw(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, SaveStateError> {
+        let bytes = self.raw(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, SaveStateError> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.raw(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn i16(&mut self) -> Result<i16, SaveStateError> {
+        Ok(self.u16()? as i16)
+    }
+
+    pub fn i64(&mut self) -> Result<i64, SaveStateError> {
+        Ok(self.u64()? as i64)
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], SaveStateError> {
+        let length = self.u32()? as usize;
+        self.raw(length)
+    }
+
+    /// A block that has to be exactly as long as `into`, like RAM.
+    pub fn bytes_into(&mut self, into: &mut [u8]) -> Result<(), SaveStateError> {
+        let bytes = self.bytes()?;
+        if bytes.len() != into.len() {
+            return Err(SaveStateError::Corrupt("memory size differs"));
+        }
+        into.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    pub fn option_u32(&mut self) -> Result<Option<u32>, SaveStateError> {
+        let present = self.bool()?;
+        let value = self.u32()?;
+        Ok(present.then_some(value))
+    }
+}
+
+/// CRC-32 (IEEE, as zip and PNG use it).
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Header {
+    pub format_version: u32,
+    pub emulator_version: String,
+    pub rom_crc32: u32,
+}
+
+// The header, the body's length and checksum, and where the compressed body starts
+fn parse(data: &[u8]) -> Result<(Header, u32, u32, usize), SaveStateError> {
+    if !data.starts_with(MAGIC) {
+        return Err(SaveStateError::NotASaveState);
+    }
+    let mut reader = StateReader::new(&data[MAGIC.len()..]);
+    let format_version = reader.u32()?;
+    let length = reader.u8()? as usize;
+    let emulator_version = String::from_utf8_lossy(reader.raw(length)?).into_owned();
+    let header = Header {
+        format_version,
+        emulator_version,
+        rom_crc32: reader.u32()?,
+    };
+    let body_length = reader.u32()?;
+    let body_crc32 = reader.u32()?;
+    Ok((header, body_length, body_crc32, MAGIC.len() + reader.position))
+}
+
+/// Reads just the header, to show what a state file is without loading it.
+pub fn read_header(data: &[u8]) -> Result<Header, SaveStateError> {
+    Ok(parse(data)?.0)
+}
+
+fn rom_crc32(gba: &Gba) -> Result<u32, SaveStateError> {
+    let cartridge = gba.bus.cartridge.as_ref().ok_or(SaveStateError::NoCartridge)?;
+    Ok(crc32(cartridge.rom()))
+}
+
+// The machine without header or compression, what rewind keeps
+fn snapshot(gba: &Gba) -> Vec<u8> {
+    let mut body = StateWriter::new();
+    gba.cpu.save_state(&mut body);
+    gba.bus.save_state(&mut body);
+    body.into_bytes()
+}
+
+fn read_body(gba: &mut Gba, body: &[u8]) -> Result<(), SaveStateError> {
+    let mut reader = StateReader::new(body);
+    gba.cpu.load_state(&mut reader)?;
+    gba.bus.load_state(&mut reader)?;
+    if !reader.is_at_end() {
+        return Err(SaveStateError::Corrupt("trailing data"));
+    }
+    Ok(())
+}
+
+// All or nothing: a body that passes its checksum can still turn out bad halfway through, then
+// the machine goes back to how it was.
+fn restore(gba: &mut Gba, body: &[u8]) -> Result<(), SaveStateError> {
+    let backup = snapshot(gba);
+    let result = read_body(gba, body);
+    if result.is_err() {
+        read_body(gba, &backup).expect("a snapshot of the machine loads back into it");
+    }
+    result
+}
+
+/// The whole machine as a save state.
+pub fn save(gba: &Gba) -> Result<Vec<u8>, SaveStateError> {
+    let body = snapshot(gba);
+
+    let mut state = StateWriter::new();
+    state.raw(MAGIC);
+    state.u32(FORMAT_VERSION);
+    state.u8(EMULATOR_VERSION.len() as u8);
+    state.raw(EMULATOR_VERSION.as_bytes());
+    state.u32(rom_crc32(gba)?);
+    state.u32(body.len() as u32);
+    state.u32(crc32(&body));
+    state.raw(&rle::compress(&body));
+    Ok(state.into_bytes())
+}
+
+/// Restores a state made by `save`. A state for another ROM or format version is refused and
+/// leaves the machine untouched, so does one that fails its checksum or turns out corrupt.
+pub fn load(gba: &mut Gba, data: &[u8]) -> Result<(), SaveStateError> {
+    let (header, body_length, body_crc32, start) = parse(data)?;
+    if header.format_version != FORMAT_VERSION {
+        return Err(SaveStateError::UnsupportedVersion {
+            found: header.format_version,
+            emulator: header.emulator_version,
+        });
+    }
+    let rom = rom_crc32(gba)?;
+    if header.rom_crc32 != rom {
+        return Err(SaveStateError::RomMismatch {
+            expected: header.rom_crc32,
+            found: rom,
+        });
+    }
+    let body = rle::decompress(&data[start..], body_length as usize)
+        .ok_or(SaveStateError::Corrupt("bad compressed data"))?;
+    if crc32(&body) != body_crc32 {
+        return Err(SaveStateError::Corrupt("checksum mismatch"));
+    }
+    restore(gba, &body)
+}
+
+pub fn save_to_file<P: AsRef<Path>>(gba: &Gba, path: P) -> Result<(), SaveStateError> {
+    std::fs::write(path, save(gba)?)?;
+    Ok(())
+}
+
+pub fn load_from_file<P: AsRef<Path>>(gba: &mut Gba, path: P) -> Result<(), SaveStateError> {
+    load(gba, &std::fs::read(path)?)
+}
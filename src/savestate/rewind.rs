@@ -0,0 +1,181 @@
+// src/savestate/rewind.rs
+// Rewind: a snapshot of the machine every `interval` frames, kept in a ring of `capacity`.
+//
+// Only the newest snapshot is kept whole. Each older one is stored as the XOR of it and the
+// snapshot after it, with the zero runs squeezed out. Consecutive frames differ in little more
+// than the registers, a few lines of RAM and what the PPU touched, so a delta is mostly zeros
+// and costs bytes to a few KB where a snapshot is 400 KB. Going back one snapshot undoes the
+// newest delta; once the ring is full the oldest delta falls off the end.
+//
+// A delta is a list of (zeros u32, literal length u32, literal bytes). The save state RLE is no
+// good here, it spends two bytes on every 130 zeros.
+
+use std::collections::VecDeque;
+
+use crate::gba::Gba;
+use crate::savestate::{restore, snapshot, SaveStateError};
+
+// Zeros shorter than this stay in the literal, a new packet costs 8 bytes
+const MIN_ZEROS: usize = 8;
+
+// An older snapshot, relative to the one after it
+struct Delta {
+    length: usize,   // Of the older snapshot, they vary with queued events and save types
+    packed: Vec<u8>, // The XOR, over the longer of the two
+}
+
+pub struct Rewind {
+    interval: u32,
+    capacity: usize,
+    frames_since_capture: u32,
+    newest: Option<Vec<u8>>,
+    newest_cycles: u64, // The CPU's cycle count in it, to tell if anything ran since
+    deltas: VecDeque<Delta>, // Oldest first
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut out = vec![0u8; a.len().max(b.len())];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = a.get(i).copied().unwrap_or(0) ^ b.get(i).copied().unwrap_or(0);
+    }
+    out
+}
+
+fn pack(diff: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < diff.len() {
+        let zeros = diff[i..].iter().take_while(|&&byte| byte == 0).count();
+        let start = i + zeros;
+        // The literal runs up to the next stretch of zeros worth a packet of its own
+        let mut end = start;
+        while end < diff.len() {
+            let next_zeros = diff[end..]
+                .iter()
+                .take(MIN_ZEROS)
+                .take_while(|&&byte| byte == 0)
+                .count();
+            if next_zeros == MIN_ZEROS || end + next_zeros == diff.len() {
+                break;
+            }
+            end += next_zeros.max(1);
+        }
+        out.extend_from_slice(&(zeros as u32).to_le_bytes());
+        out.extend_from_slice(&((end - start) as u32).to_le_bytes());
+        out.extend_from_slice(&diff[start..end]);
+        i = end;
+    }
+    out
+}
+
+fn unpack(packed: &[u8], length: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(length);
+    let mut i = 0;
+    let word = |at: &mut usize| -> Option<usize> {
+        let bytes = packed.get(*at..*at + 4)?;
+        *at += 4;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    };
+    while i < packed.len() {
+        let zeros = word(&mut i)?;
+        let literal = word(&mut i)?;
+        if out.len() + zeros + literal > length {
+            return None;
+        }
+        out.resize(out.len() + zeros, 0);
+        out.extend_from_slice(packed.get(i..i + literal)?);
+        i += literal;
+    }
+    out.resize(length, 0);
+    Some(out)
+}
+
+impl Rewind {
+    /// Keeps up to `capacity` snapshots, one every `interval` frames, so with an interval of 1
+    /// the host can step back frame by frame.
+    pub fn new(interval: u32, capacity: usize) -> Self {
+        Rewind {
+            interval: interval.max(1),
+            capacity: capacity.max(1),
+            frames_since_capture: 0,
+            newest: None,
+            newest_cycles: 0,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    /// Call once per emulated frame, takes a snapshot when one is due.
+    pub fn frame(&mut self, gba: &Gba) {
+        self.frames_since_capture += 1;
+        if self.newest.is_none() || self.frames_since_capture >= self.interval {
+            self.capture(gba);
+        }
+    }
+
+    /// Takes a snapshot now, whether one is due or not.
+    pub fn capture(&mut self, gba: &Gba) {
+        let current = snapshot(gba);
+        if let Some(previous) = self.newest.take() {
+            self.deltas.push_back(Delta {
+                length: previous.len(),
+                packed: pack(&xor(&previous, &current)),
+            });
+            if self.deltas.len() >= self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.newest = Some(current);
+        self.newest_cycles = gba.cpu.cycles;
+        self.frames_since_capture = 0;
+    }
+
+    /// Goes back to the newest snapshot, or to the one before it if nothing has run since.
+    /// False when there's nothing further back.
+    pub fn step_back(&mut self, gba: &mut Gba) -> Result<bool, SaveStateError> {
+        if self.newest.is_none() || gba.cpu.cycles == self.newest_cycles && !self.drop_newest()? {
+            return Ok(false);
+        }
+        if let Some(newest) = &self.newest {
+            restore(gba, newest)?;
+        }
+        self.newest_cycles = gba.cpu.cycles;
+        self.frames_since_capture = 0;
+        Ok(true)
+    }
+
+    // Rebuilds the snapshot before the newest one in its place
+    fn drop_newest(&mut self) -> Result<bool, SaveStateError> {
+        let (Some(newest), Some(delta)) = (&self.newest, self.deltas.back()) else {
+            return Ok(false);
+        };
+        let length = newest.len().max(delta.length);
+        let mut older = unpack(&delta.packed, length)
+            .map(|diff| xor(&diff, newest))
+            .ok_or(SaveStateError::Corrupt("bad rewind delta"))?;
+        older.truncate(delta.length);
+        self.newest = Some(older);
+        self.deltas.pop_back();
+        Ok(true)
+    }
+
+    /// How many snapshots there are to go back through, the newest included.
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.newest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    /// Bytes held by the snapshots.
+    pub fn memory_usage(&self) -> usize {
+        let deltas: usize = self.deltas.iter().map(|delta| delta.packed.len()).sum();
+        deltas + self.newest.as_ref().map_or(0, Vec::len)
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.frames_since_capture = 0;
+    }
+}
@@ -0,0 +1,67 @@
+// src/savestate/rle.rs
+// Run-length compression for save state bodies. Most of a state is RAM and VRAM that is
+// largely zeros or repeated fill, which runs catch well enough without a real compressor.
+//
+// A control byte starts every packet: 0x00-0x7F is a literal of 1-128 bytes that follow,
+// 0x80-0xFF repeats the next byte 3-130 times.
+
+const MIN_RUN: usize = 3;
+const MAX_RUN: usize = 130;
+const MAX_LITERAL: usize = 128;
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() / 4);
+    let mut literal_start = 0;
+    let mut i = 0;
+    while i < data.len() {
+        let run = data[i..]
+            .iter()
+            .take(MAX_RUN)
+            .take_while(|&&byte| byte == data[i])
+            .count();
+        if run >= MIN_RUN {
+            flush_literal(&mut out, &data[literal_start..i]);
+            out.push(0x80 | (run - MIN_RUN) as u8);
+            out.push(data[i]);
+            i += run;
+            literal_start = i;
+        } else {
+            i += 1;
+        }
+    }
+    flush_literal(&mut out, &data[literal_start..]);
+    out
+}
+
+fn flush_literal(out: &mut Vec<u8>, literal: &[u8]) {
+    for chunk in literal.chunks(MAX_LITERAL) {
+        out.push((chunk.len() - 1) as u8);
+        out.extend_from_slice(chunk);
+    }
+}
+
+/// None if `data` is malformed or doesn't come out at exactly `length` bytes.
+pub fn decompress(data: &[u8], length: usize) -> Option<Vec<u8>> {
+    // `length` comes from the file, so don't allocate more than `data` could ever expand to.
+    // Every packet is at least 2 bytes and none gives more than a run of MAX_RUN.
+    if length > data.len() / 2 * MAX_RUN {
+        return None;
+    }
+    let mut out = Vec::with_capacity(length);
+    let mut i = 0;
+    while i < data.len() {
+        let control = data[i] as usize;
+        if control & 0x80 != 0 {
+            let byte = *data.get(i + 1)?;
+            out.resize(out.len() + (control & 0x7F) + MIN_RUN, byte);
+            i += 2;
+        } else {
+            out.extend_from_slice(data.get(i + 1..i + 2 + control)?);
+            i += 2 + control;
+        }
+        if out.len() > length {
+            return None;
+        }
+    }
+    (out.len() == length).then_some(out)
+}
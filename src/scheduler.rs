@@ -0,0 +1,102 @@
+// src/scheduler.rs
+// Timed hardware events, in CPU cycles (16.78 MHz) since power on. Lets a halted CPU skip
+// straight to the next thing that can happen instead of ticking cycle by cycle.
+
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Event {
+    HBlank,         // Cycle 960 of a scanline
+    EndOfLine,      // Cycle 1232, the next scanline starts
+    SerialStart,    // SIOCNT start bit set, deferred until the whole store has landed
+    SerialTransfer, // Our side of a serial transfer has shifted all its bits
+    LinkSync,       // Barrier with the other instances on the link cable
+    TimerOverflow(u8), // Timer 0-3 wraps around
+}
+
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    now: u64,
+    events: Vec<(u64, Event)>, // Unordered, there are only ever a handful
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler {
+            now: 0,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn schedule_at(&mut self, at: u64, event: Event) {
+        self.events.push((at, event));
+    }
+
+    pub fn schedule(&mut self, delay: u64, event: Event) {
+        self.schedule_at(self.now + delay, event);
+    }
+
+    pub fn cancel(&mut self, event: Event) {
+        self.events.retain(|(_, pending)| *pending != event);
+    }
+
+    pub fn next_event_at(&self) -> Option<u64> {
+        self.events.iter().map(|(at, _)| *at).min()
+    }
+
+    pub fn advance(&mut self, cycles: u64) {
+        self.now += cycles;
+    }
+
+    /// Removes and returns the earliest event that is due, with the cycle it was due at.
+    pub fn pop_due(&mut self) -> Option<(u64, Event)> {
+        let (index, _) = self
+            .events
+            .iter()
+            .enumerate()
+            .filter(|(_, (at, _))| *at <= self.now)
+            .min_by_key(|(_, (at, _))| *at)?;
+        Some(self.events.swap_remove(index))
+    }
+}
+
+const EVENTS: [Event; 9] = [
+    Event::HBlank,
+    Event::EndOfLine,
+    Event::SerialStart,
+    Event::SerialTransfer,
+    Event::LinkSync,
+    Event::TimerOverflow(0),
+    Event::TimerOverflow(1),
+    Event::TimerOverflow(2),
+    Event::TimerOverflow(3),
+];
+
+impl Snapshot for Scheduler {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u64(self.now);
+        state.u32(self.events.len() as u32);
+        for (at, event) in &self.events {
+            state.u64(*at);
+            state.u8(EVENTS.iter().position(|e| e == event).unwrap_or_default() as u8);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.now = state.u64()?;
+        let count = state.u32()?;
+        self.events.clear();
+        for _ in 0..count {
+            let at = state.u64()?;
+            let event = *EVENTS
+                .get(state.u8()? as usize)
+                .ok_or(SaveStateError::Corrupt("unknown scheduler event"))?;
+            self.events.push((at, event));
+        }
+        Ok(())
+    }
+}
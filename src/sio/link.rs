@@ -0,0 +1,304 @@
+// src/sio/link.rs
+// Link cable between emulator instances over TCP or Unix sockets. Player 0 hosts and the
+// others connect to it, so the cable is a star with the host in the middle. Every instance
+// stops at each SYNC_QUANTUM cycle boundary until all the others got there too, which keeps
+// them within one quantum of each other and makes a transfer land on the same cycle every run.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+pub const MAX_PLAYERS: usize = 4;
+// Cycles between two barriers, one scanline.
+pub const SYNC_QUANTUM: u64 = 1232;
+
+const FRAME_SIZE: usize = 16;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Message {
+    Hello { player: u8, players: u8 }, // Host to client, assigns the player number
+    Sync { cycle: u64 },
+    Transfer { data: u32 }, // Normal or multi-player transfer started by `from`
+    Reply { data: u32 },    // What the receiving end had in its data register
+    Complete { data: [u16; MAX_PLAYERS] }, // SIOMULTI0-3 at the end of a multi-player transfer
+    Uart { byte: u8 },
+}
+
+// Fixed size frames: kind, sending player, two bytes padding and 12 bytes of payload.
+fn encode(from: u8, message: &Message) -> [u8; FRAME_SIZE] {
+    let mut payload = [0u8; FRAME_SIZE - 4];
+    let kind = match *message {
+        Message::Hello { player, players } => {
+            payload[0] = player;
+            payload[1] = players;
+            0
+        }
+        Message::Sync { cycle } => {
+            payload[..8].copy_from_slice(&cycle.to_le_bytes());
+            1
+        }
+        Message::Transfer { data } => {
+            payload[..4].copy_from_slice(&data.to_le_bytes());
+            2
+        }
+        Message::Reply { data } => {
+            payload[..4].copy_from_slice(&data.to_le_bytes());
+            3
+        }
+        Message::Complete { data } => {
+            for (i, value) in data.iter().enumerate() {
+                payload[i * 2..i * 2 + 2].copy_from_slice(&value.to_le_bytes());
+            }
+            4
+        }
+        Message::Uart { byte } => {
+            payload[0] = byte;
+            5
+        }
+    };
+    let mut frame = [kind, from, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    frame[4..].copy_from_slice(&payload);
+    frame
+}
+
+fn decode(frame: &[u8; FRAME_SIZE]) -> io::Result<(u8, Message)> {
+    let payload = &frame[4..];
+    let word = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+    let message = match frame[0] {
+        0 => Message::Hello {
+            player: payload[0],
+            players: payload[1],
+        },
+        1 => {
+            let mut cycle = [0u8; 8];
+            cycle.copy_from_slice(&payload[..8]);
+            Message::Sync {
+                cycle: u64::from_le_bytes(cycle),
+            }
+        }
+        2 => Message::Transfer { data: word },
+        3 => Message::Reply { data: word },
+        4 => {
+            let mut data = [0u16; MAX_PLAYERS];
+            for (i, value) in data.iter_mut().enumerate() {
+                *value = u16::from_le_bytes([payload[i * 2], payload[i * 2 + 1]]);
+            }
+            Message::Complete { data }
+        }
+        5 => Message::Uart { byte: payload[0] },
+        kind => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown link message {}", kind),
+            ))
+        }
+    };
+    Ok((frame[1], message))
+}
+
+/// A connected socket. Reading happens on a thread of its own, so the stream must be clonable.
+pub trait LinkStream: Read + Write + Send {
+    fn try_clone_stream(&self) -> io::Result<Box<dyn LinkStream>>;
+}
+
+impl LinkStream for TcpStream {
+    fn try_clone_stream(&self) -> io::Result<Box<dyn LinkStream>> {
+        Ok(Box::new(self.try_clone()?))
+    }
+}
+
+#[cfg(unix)]
+impl LinkStream for UnixStream {
+    fn try_clone_stream(&self) -> io::Result<Box<dyn LinkStream>> {
+        Ok(Box::new(self.try_clone()?))
+    }
+}
+
+pub struct Link {
+    player: u8,
+    players: u8,
+    peers: Vec<Box<dyn LinkStream>>, // Host: one per client. Client: just the host.
+    incoming: Receiver<io::Result<(usize, Message)>>, // Tagged with the index into `peers`
+    synced: Vec<u64>, // Last barrier each peer reported
+}
+
+impl Link {
+    /// Builds a link from already connected streams. The host passes one stream per client, in
+    /// player order, a client passes the stream to the host.
+    pub fn from_streams(
+        player: u8,
+        players: u8,
+        peers: Vec<Box<dyn LinkStream>>,
+    ) -> io::Result<Self> {
+        let (sender, incoming) = mpsc::channel();
+        for (index, peer) in peers.iter().enumerate() {
+            // The host's peers are players 1 and up, a client's only peer is the host
+            let sender_player = if player == 0 { index as u8 + 1 } else { 0 };
+            spawn_reader(peer.try_clone_stream()?, index, sender_player, sender.clone());
+        }
+        let synced = vec![0; peers.len()];
+        Ok(Link {
+            player,
+            players,
+            peers,
+            incoming,
+            synced,
+        })
+    }
+
+    /// Waits for `clients` players to connect and tells each its player number.
+    pub fn host_streams(mut clients: Vec<Box<dyn LinkStream>>) -> io::Result<Self> {
+        if clients.is_empty() || clients.len() >= MAX_PLAYERS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a link needs 2 to 4 players",
+            ));
+        }
+        let players = clients.len() as u8 + 1;
+        for (i, client) in clients.iter_mut().enumerate() {
+            let hello = Message::Hello {
+                player: i as u8 + 1,
+                players,
+            };
+            client.write_all(&encode(0, &hello))?;
+        }
+        Self::from_streams(0, players, clients)
+    }
+
+    /// Joins the host and waits to be given a player number.
+    pub fn join_stream(mut host: Box<dyn LinkStream>) -> io::Result<Self> {
+        let mut frame = [0u8; FRAME_SIZE];
+        host.read_exact(&mut frame)?;
+        match decode(&frame)? {
+            (_, Message::Hello { player, players }) => Self::from_streams(player, players, vec![host]),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected a hello from the link host",
+            )),
+        }
+    }
+
+    pub fn host_tcp(listener: &TcpListener, clients: usize) -> io::Result<Self> {
+        let mut streams: Vec<Box<dyn LinkStream>> = Vec::new();
+        for _ in 0..clients {
+            let (stream, _) = listener.accept()?;
+            stream.set_nodelay(true)?;
+            streams.push(Box::new(stream));
+        }
+        Self::host_streams(streams)
+    }
+
+    #[cfg(unix)]
+    pub fn host_unix(listener: &UnixListener, clients: usize) -> io::Result<Self> {
+        let mut streams: Vec<Box<dyn LinkStream>> = Vec::new();
+        for _ in 0..clients {
+            let (stream, _) = listener.accept()?;
+            streams.push(Box::new(stream));
+        }
+        Self::host_streams(streams)
+    }
+
+    /// Hosts on `address`, either `host:port` or `unix:<path>`.
+    pub fn host(address: &str, clients: usize) -> io::Result<Self> {
+        #[cfg(unix)]
+        if let Some(path) = address.strip_prefix("unix:") {
+            let _ = std::fs::remove_file(path); // Left over from an earlier run
+            return Self::host_unix(&UnixListener::bind(path)?, clients);
+        }
+        Self::host_tcp(&TcpListener::bind(address)?, clients)
+    }
+
+    /// Connects to a host started with `Link::host`.
+    pub fn connect(address: &str) -> io::Result<Self> {
+        #[cfg(unix)]
+        if let Some(path) = address.strip_prefix("unix:") {
+            return Self::join_stream(Box::new(UnixStream::connect(path)?));
+        }
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        Self::join_stream(Box::new(stream))
+    }
+
+    pub fn player(&self) -> u8 {
+        self.player
+    }
+
+    pub fn players(&self) -> u8 {
+        self.players
+    }
+
+    pub fn is_host(&self) -> bool {
+        self.player == 0
+    }
+
+    /// Sends to every peer, which for a client is only the host.
+    pub fn broadcast(&mut self, message: Message) -> io::Result<()> {
+        let frame = encode(self.player, &message);
+        for peer in &mut self.peers {
+            peer.write_all(&frame)?;
+        }
+        Ok(())
+    }
+
+    /// Sends to one player. Clients can only reach the host.
+    pub fn send_to(&mut self, player: u8, message: Message) -> io::Result<()> {
+        let frame = encode(self.player, &message);
+        let index = if self.is_host() { (player as usize).wrapping_sub(1) } else { 0 };
+        let Some(peer) = self.peers.get_mut(index) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("no player {} on the link", player),
+            ));
+        };
+        peer.write_all(&frame)
+    }
+
+    /// Blocks for the next message from any peer, returning the player that sent it. Sync
+    /// messages are recorded here too, see `peers_synced`.
+    pub fn receive(&mut self) -> io::Result<(u8, Message)> {
+        let (index, message) = self.incoming.recv().map_err(|_| {
+            io::Error::new(io::ErrorKind::ConnectionAborted, "link closed")
+        })??;
+        if let Message::Sync { cycle } = message {
+            self.synced[index] = self.synced[index].max(cycle);
+        }
+        let from = if self.is_host() { index as u8 + 1 } else { 0 };
+        Ok((from, message))
+    }
+
+    /// Every peer has reached the barrier at `cycle`.
+    pub fn peers_synced(&self, cycle: u64) -> bool {
+        self.synced.iter().all(|&synced| synced >= cycle)
+    }
+}
+
+// Reads the frames of peer `index`, which must all come from `player`.
+fn spawn_reader(
+    mut stream: Box<dyn LinkStream>,
+    index: usize,
+    player: u8,
+    sender: Sender<io::Result<(usize, Message)>>,
+) {
+    thread::spawn(move || loop {
+        let mut frame = [0u8; FRAME_SIZE];
+        let result = stream
+            .read_exact(&mut frame)
+            .and_then(|_| decode(&frame))
+            .and_then(|(from, message)| {
+                if from != player {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("message from player {} on player {}'s connection", from, player),
+                    ));
+                }
+                Ok((index, message))
+            });
+        let failed = result.is_err();
+        if sender.send(result).is_err() || failed {
+            return;
+        }
+    });
+}
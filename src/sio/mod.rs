@@ -0,0 +1,455 @@
+// src/sio/mod.rs
+// Serial port (GBATEK "GBA Communication Ports"). RCNT and SIOCNT pick the mode: Normal 8/32
+// bit, Multi-player, UART, JOY BUS or general purpose. Transfers go through a `Link` to other
+// emulator instances, without one the port behaves like nothing is plugged in.
+
+pub mod link;
+
+use std::io;
+
+use crate::bus::Bus;
+use crate::interrupt::Interrupt;
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+use crate::scheduler::Event;
+use link::{Link, Message, MAX_PLAYERS, SYNC_QUANTUM};
+
+pub const SIO_REGISTERS_START: u32 = 0x120;
+pub const SIO_REGISTERS_END: u32 = 0x12B;
+pub const REG_RCNT: u32 = 0x134;
+pub const JOY_REGISTERS_START: u32 = 0x140;
+pub const JOY_REGISTERS_END: u32 = 0x15B;
+
+const CPU_CLOCK: u64 = 16_777_216;
+const MULTI_BAUD_RATES: [u64; 4] = [9600, 38400, 57600, 115200];
+
+const SIOCNT_INTERNAL_CLOCK: u16 = 1 << 0;
+const SIOCNT_FAST_CLOCK: u16 = 1 << 1; // 2 MHz instead of 256 KHz
+const SIOCNT_START: u16 = 1 << 7;
+const SIOCNT_IRQ: u16 = 1 << 14;
+// UART
+const SIOCNT_UART_RECEIVE_EMPTY: u16 = 1 << 5;
+const SIOCNT_UART_SEND_ENABLE: u16 = 1 << 10;
+const SIOCNT_UART_RECEIVE_ENABLE: u16 = 1 << 11;
+// Bits the hardware owns in each mode
+const NORMAL_READ_ONLY: u16 = 1 << 2;
+const MULTI_READ_ONLY: u16 = 0b1_1111 << 2;
+const UART_READ_ONLY: u16 = 0b111 << 4;
+
+const JOYCNT_RESET: u16 = 1 << 0;
+const JOYCNT_RECEIVED: u16 = 1 << 1;
+const JOYCNT_SENT: u16 = 1 << 2;
+const JOYCNT_IRQ: u16 = 1 << 6;
+const JOYSTAT_RECEIVED: u16 = 1 << 1;
+const JOYSTAT_SENT: u16 = 1 << 3;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SioMode {
+    Normal8,
+    Normal32,
+    Multiplayer,
+    Uart,
+    JoyBus,
+    General, // The four pins driven directly through RCNT
+}
+
+pub struct Sio {
+    pub siocnt: u16,
+    pub rcnt: u16,
+    pub data: [u16; 4], // 0x120-0x127, SIODATA32 or SIOMULTI0-3
+    pub data8: u16,     // 0x12A, SIODATA8 or SIOMLT_SEND
+    pub joycnt: u16,
+    pub joy_recv: u32,
+    pub joy_trans: u32,
+    pub joystat: u16,
+    pub link: Option<Link>,
+    pub link_error: Option<io::Error>, // Why the link went away, if it did
+    replies: [Option<u32>; MAX_PLAYERS], // Answers to our own transfer, by player
+}
+
+impl Sio {
+    pub fn new() -> Self {
+        Sio {
+            siocnt: 0,
+            rcnt: 0,
+            data: [0; 4],
+            data8: 0,
+            joycnt: 0,
+            joy_recv: 0,
+            joy_trans: 0,
+            joystat: 0,
+            link: None,
+            link_error: None,
+            replies: [None; MAX_PLAYERS],
+        }
+    }
+
+    pub fn mode(&self) -> SioMode {
+        match self.rcnt >> 14 {
+            0 | 1 => match (self.siocnt >> 12) & 0b11 {
+                0 => SioMode::Normal8,
+                1 => SioMode::Normal32,
+                2 => SioMode::Multiplayer,
+                _ => SioMode::Uart,
+            },
+            2 => SioMode::General,
+            _ => SioMode::JoyBus,
+        }
+    }
+
+    fn player(&self) -> u8 {
+        self.link.as_ref().map_or(0, |link| link.player())
+    }
+
+    fn players(&self) -> u8 {
+        self.link.as_ref().map_or(1, |link| link.players())
+    }
+
+    fn normal_data(&self) -> u32 {
+        match self.mode() {
+            SioMode::Normal32 => self.data[0] as u32 | ((self.data[1] as u32) << 16),
+            _ => self.data8 as u8 as u32,
+        }
+    }
+
+    fn set_normal_data(&mut self, value: u32) {
+        match self.mode() {
+            SioMode::Normal32 => self.data[..2].copy_from_slice(&[value as u16, (value >> 16) as u16]),
+            _ => self.data8 = (self.data8 & 0xFF00) | (value & 0xFF) as u16,
+        }
+    }
+
+    // SIOCNT with the bits the hardware drives filled in.
+    fn siocnt_value(&self) -> u16 {
+        match self.mode() {
+            SioMode::Multiplayer => {
+                let player = self.player() as u16;
+                let child = (player != 0) as u16;
+                let ready = self.link.is_some() as u16;
+                (self.siocnt & !MULTI_READ_ONLY) | (child << 2) | (ready << 3) | (player << 4)
+            }
+            _ => self.siocnt,
+        }
+    }
+
+    fn transfer_cycles(&self) -> u64 {
+        match self.mode() {
+            SioMode::Multiplayer => {
+                // Start bit, 16 data bits and a stop bit for every player in turn
+                let baud = MULTI_BAUD_RATES[(self.siocnt & 0b11) as usize];
+                CPU_CLOCK * 18 * self.players() as u64 / baud
+            }
+            mode => {
+                let bits = if mode == SioMode::Normal32 { 32 } else { 8 };
+                let per_bit = if self.siocnt & SIOCNT_FAST_CLOCK != 0 { 8 } else { 64 };
+                bits * per_bit
+            }
+        }
+    }
+}
+
+impl Default for Sio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus {
+    /// Plugs in the link cable. Every instance has to attach its link before running, the
+    /// barriers fall on the same cycles for everyone. If the connection breaks the cable is
+    /// unplugged and the error kept in `sio.link_error`.
+    pub fn attach_link(&mut self, link: Link) {
+        self.sio.link = Some(link);
+        self.sio.link_error = None;
+        let now = self.scheduler.now();
+        let next_barrier = (now / SYNC_QUANTUM + 1) * SYNC_QUANTUM;
+        self.scheduler.cancel(Event::LinkSync);
+        self.scheduler.schedule_at(next_barrier, Event::LinkSync);
+    }
+
+    pub(crate) fn read_sio_register(&mut self, offset: u32) -> u8 {
+        let halfword = match offset & !1 {
+            0x120..=0x126 => self.sio.data[((offset - 0x120) / 2) as usize],
+            0x128 => self.sio.siocnt_value(),
+            0x12A => self.sio.data8,
+            REG_RCNT => self.sio.rcnt,
+            0x140 => self.sio.joycnt,
+            0x150 => {
+                self.sio.joystat &= !JOYSTAT_RECEIVED;
+                self.sio.joy_recv as u16
+            }
+            0x152 => {
+                self.sio.joystat &= !JOYSTAT_RECEIVED;
+                (self.sio.joy_recv >> 16) as u16
+            }
+            0x154 => self.sio.joy_trans as u16,
+            0x156 => (self.sio.joy_trans >> 16) as u16,
+            0x158 => self.sio.joystat,
+            _ => 0,
+        };
+        (halfword >> ((offset & 1) * 8)) as u8
+    }
+
+    pub(crate) fn write_sio_register(&mut self, offset: u32, value: u8) {
+        let shift = (offset & 1) * 8;
+        let merge = |old: u16| (old & !(0xFF << shift)) | ((value as u16) << shift);
+        match offset & !1 {
+            0x120..=0x126 => {
+                let index = ((offset - 0x120) / 2) as usize;
+                self.sio.data[index] = merge(self.sio.data[index]);
+            }
+            0x128 => self.write_siocnt(merge(self.sio.siocnt)),
+            0x12A => {
+                self.sio.data8 = merge(self.sio.data8);
+                if self.sio.mode() == SioMode::Uart && shift == 0 {
+                    self.send_uart(value);
+                }
+            }
+            REG_RCNT => self.sio.rcnt = merge(self.sio.rcnt),
+            // Writing 1 acknowledges the JOY BUS flags
+            0x140 => {
+                let acknowledged = merge(0) & (JOYCNT_RESET | JOYCNT_RECEIVED | JOYCNT_SENT);
+                let irq = merge(self.sio.joycnt) & JOYCNT_IRQ;
+                self.sio.joycnt = (self.sio.joycnt & !acknowledged & !JOYCNT_IRQ) | irq;
+            }
+            0x154 | 0x156 => {
+                let shift = shift + (offset & 2) * 8;
+                self.sio.joy_trans =
+                    (self.sio.joy_trans & !(0xFF << shift)) | ((value as u32) << shift);
+                self.sio.joystat |= JOYSTAT_SENT;
+            }
+            // Only the general purpose flags in bits 4-5 are writable
+            0x158 if shift == 0 => {
+                self.sio.joystat = (self.sio.joystat & !0x30) | (value as u16 & 0x30)
+            }
+            _ => {}
+        }
+    }
+
+    fn write_siocnt(&mut self, value: u16) {
+        let read_only = match self.sio.mode() {
+            SioMode::Normal8 | SioMode::Normal32 => NORMAL_READ_ONLY,
+            SioMode::Multiplayer => MULTI_READ_ONLY,
+            SioMode::Uart => UART_READ_ONLY,
+            _ => 0,
+        };
+        let old = self.sio.siocnt;
+        self.sio.siocnt = (value & !read_only) | (old & read_only);
+        // The mode lives in the high byte, a halfword store is written low byte first
+        if old & SIOCNT_START == 0 && value & SIOCNT_START != 0 {
+            self.scheduler.schedule(0, Event::SerialStart);
+        }
+    }
+
+    pub(crate) fn start_transfer(&mut self) {
+        if self.sio.siocnt & SIOCNT_START == 0 {
+            return;
+        }
+        let data = match self.sio.mode() {
+            // With the external clock the other end starts the transfer
+            SioMode::Normal8 | SioMode::Normal32 if self.sio.siocnt & SIOCNT_INTERNAL_CLOCK != 0 => {
+                self.sio.normal_data()
+            }
+            // Only the parent can start, children are started by its transfer
+            SioMode::Multiplayer if self.sio.player() == 0 => self.sio.data8 as u32,
+            _ => return,
+        };
+        self.sio.replies = [None; MAX_PLAYERS];
+        self.link_send(None, Message::Transfer { data });
+        let cycles = self.sio.transfer_cycles();
+        self.scheduler.schedule(cycles, Event::SerialTransfer);
+    }
+
+    fn end_transfer(&mut self) {
+        self.sio.siocnt &= !SIOCNT_START;
+        if self.sio.siocnt & SIOCNT_IRQ != 0 {
+            self.request_interrupt(Interrupt::Serial);
+        }
+    }
+
+    // Our own transfer is done, collect what the other players sent back.
+    pub(crate) fn finish_transfer(&mut self) {
+        let players = self.sio.players();
+        let waiting_for: Vec<u8> = match self.sio.mode() {
+            SioMode::Multiplayer => (1..players).collect(),
+            _ if players > 1 => vec![if self.sio.player() == 0 { 1 } else { 0 }],
+            _ => Vec::new(),
+        };
+        self.pump_link(|sio| waiting_for.iter().all(|&player| sio.replies[player as usize].is_some()));
+        match self.sio.mode() {
+            SioMode::Multiplayer => {
+                // Missing players read as 0xFFFF
+                let mut multi = [0xFFFF; MAX_PLAYERS];
+                multi[0] = self.sio.data8;
+                for (value, reply) in multi.iter_mut().zip(self.sio.replies).skip(1) {
+                    if let Some(reply) = reply {
+                        *value = reply as u16;
+                    }
+                }
+                self.sio.data = multi;
+                self.link_send(None, Message::Complete { data: multi });
+            }
+            _ => {
+                let reply = waiting_for.first().and_then(|&player| self.sio.replies[player as usize]);
+                self.sio.set_normal_data(reply.unwrap_or(0xFFFF_FFFF));
+            }
+        }
+        self.end_transfer();
+    }
+
+    fn send_uart(&mut self, byte: u8) {
+        if self.sio.siocnt & SIOCNT_UART_SEND_ENABLE != 0 {
+            self.link_send(None, Message::Uart { byte });
+        }
+    }
+
+    /// Barrier at `cycle`: tells the others we got here and waits until they did too.
+    pub(crate) fn sync_link(&mut self, cycle: u64) {
+        if self.sio.link.is_none() {
+            return;
+        }
+        self.link_send(None, Message::Sync { cycle });
+        self.pump_link(|sio| sio.link.as_ref().is_none_or(|link| link.peers_synced(cycle)));
+        if self.sio.link.is_some() {
+            self.scheduler.schedule_at(cycle + SYNC_QUANTUM, Event::LinkSync);
+        }
+    }
+
+    fn link_send(&mut self, to: Option<u8>, message: Message) {
+        let Some(link) = &mut self.sio.link else {
+            return;
+        };
+        let result = match to {
+            Some(player) => link.send_to(player, message),
+            None => link.broadcast(message),
+        };
+        if let Err(err) = result {
+            self.disconnect_link(err);
+        }
+    }
+
+    // Unplugs the cable, the game sees nothing connected from now on.
+    fn disconnect_link(&mut self, err: io::Error) {
+        self.sio.link = None;
+        self.sio.link_error = Some(err);
+        self.scheduler.cancel(Event::LinkSync);
+    }
+
+    // Handles incoming messages until `done` holds or the link goes away.
+    fn pump_link<F: Fn(&Sio) -> bool>(&mut self, done: F) {
+        while self.sio.link.is_some() && !done(&self.sio) {
+            let received = self.sio.link.as_mut().map(|link| link.receive());
+            match received {
+                Some(Ok((from, message))) => self.handle_link_message(from, message),
+                Some(Err(err)) => self.disconnect_link(err),
+                None => {}
+            }
+        }
+    }
+
+    fn handle_link_message(&mut self, from: u8, message: Message) {
+        match message {
+            Message::Transfer { data } => {
+                let reply = match self.sio.mode() {
+                    SioMode::Normal8 | SioMode::Normal32 => {
+                        let own = self.sio.normal_data();
+                        self.sio.set_normal_data(data);
+                        let waiting = SIOCNT_START | SIOCNT_INTERNAL_CLOCK;
+                        if self.sio.siocnt & waiting == SIOCNT_START {
+                            self.end_transfer();
+                        }
+                        own
+                    }
+                    SioMode::Multiplayer => {
+                        self.sio.siocnt |= SIOCNT_START; // Busy until the parent is done
+                        self.sio.data8 as u32
+                    }
+                    _ => 0xFFFF_FFFF,
+                };
+                self.link_send(Some(from), Message::Reply { data: reply });
+            }
+            Message::Reply { data } => self.sio.replies[from as usize] = Some(data),
+            Message::Complete { data } => {
+                self.sio.data = data;
+                self.end_transfer();
+            }
+            Message::Uart { byte } => {
+                if self.sio.siocnt & SIOCNT_UART_RECEIVE_ENABLE != 0 {
+                    self.sio.data8 = byte as u16;
+                    self.sio.siocnt &= !SIOCNT_UART_RECEIVE_EMPTY;
+                    if self.sio.siocnt & SIOCNT_IRQ != 0 {
+                        self.request_interrupt(Interrupt::Serial);
+                    }
+                }
+            }
+            Message::Hello { .. } | Message::Sync { .. } => {}
+        }
+    }
+
+    /// Sends a JOY BUS command as a GameCube would and returns the GBA's answer. 0xFF resets,
+    /// 0x00 asks for the status, 0x14 reads JOY_TRANS and 0x15 writes `data` to JOY_RECV.
+    pub fn joybus_command(&mut self, command: u8, data: u32) -> Vec<u8> {
+        let status = self.sio.joystat as u8;
+        let response = match command {
+            0xFF | 0x00 => {
+                if command == 0xFF {
+                    self.sio.joycnt |= JOYCNT_RESET;
+                }
+                vec![0x00, 0x04, status] // Device type of a GBA
+            }
+            0x14 => {
+                self.sio.joycnt |= JOYCNT_SENT;
+                self.sio.joystat &= !JOYSTAT_SENT;
+                let mut bytes = self.sio.joy_trans.to_le_bytes().to_vec();
+                bytes.push(status);
+                bytes
+            }
+            0x15 => {
+                self.sio.joy_recv = data;
+                self.sio.joycnt |= JOYCNT_RECEIVED;
+                self.sio.joystat |= JOYSTAT_RECEIVED;
+                vec![self.sio.joystat as u8]
+            }
+            _ => return Vec::new(),
+        };
+        if self.sio.mode() == SioMode::JoyBus && self.sio.joycnt & JOYCNT_IRQ != 0 {
+            self.request_interrupt(Interrupt::Serial);
+        }
+        response
+    }
+}
+
+// The link itself is a connection to other instances and stays as it is.
+impl Snapshot for Sio {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.siocnt);
+        state.u16(self.rcnt);
+        for value in self.data {
+            state.u16(value);
+        }
+        state.u16(self.data8);
+        state.u16(self.joycnt);
+        state.u32(self.joy_recv);
+        state.u32(self.joy_trans);
+        state.u16(self.joystat);
+        for reply in self.replies {
+            state.option_u32(reply);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.siocnt = state.u16()?;
+        self.rcnt = state.u16()?;
+        for value in self.data.iter_mut() {
+            *value = state.u16()?;
+        }
+        self.data8 = state.u16()?;
+        self.joycnt = state.u16()?;
+        self.joy_recv = state.u32()?;
+        self.joy_trans = state.u32()?;
+        self.joystat = state.u16()?;
+        for reply in self.replies.iter_mut() {
+            *reply = state.option_u32()?;
+        }
+        Ok(())
+    }
+}
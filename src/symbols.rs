@@ -0,0 +1,145 @@
+// src/symbols.rs
+// Symbol tables for the debugger, the disassembler and traces: addresses to names and back.
+// Symbol files are no$gba's .sym format, one `ADDRESS NAME` pair per line with the address in
+// hex. Lines starting with `;` are comments, names starting with `.` are no$gba's markers for
+// ARM/Thumb code and data (.arm, .thumb, .byt:NNNN...) rather than symbols. ELF files bring
+// their own table, see `elf`.
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use crate::elf::Elf;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Symbol {
+    pub address: u32,
+    pub size: u32, // 0 when unknown, then it runs up to the next symbol
+    pub name: String,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>, // Sorted by address
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    /// Parses a symbol file. Lines that aren't an address and a name are skipped.
+    pub fn parse(text: &str) -> SymbolTable {
+        let mut table = SymbolTable::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let (Some(address), Some(name)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            if name.starts_with('.') {
+                continue;
+            }
+            let address = address.trim_start_matches("0x");
+            if let Ok(address) = u32::from_str_radix(address, 16) {
+                table.insert(address, name);
+            }
+        }
+        table
+    }
+
+    /// Loads a .sym file, or the symbol table of an ELF file.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<SymbolTable> {
+        let data = std::fs::read(path)?;
+        if data.starts_with(b"\x7FELF") {
+            return Elf::parse(&data)
+                .map(|elf| elf.symbols)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err));
+        }
+        Ok(SymbolTable::parse(&String::from_utf8_lossy(&data)))
+    }
+
+    pub fn insert(&mut self, address: u32, name: &str) {
+        self.insert_sized(address, 0, name)
+    }
+
+    pub fn insert_sized(&mut self, address: u32, size: u32, name: &str) {
+        let index = self
+            .symbols
+            .partition_point(|symbol| symbol.address <= address);
+        self.symbols.insert(
+            index,
+            Symbol {
+                address,
+                size,
+                name: name.to_string(),
+            },
+        );
+    }
+
+    /// Adds every symbol of `other`.
+    pub fn extend(&mut self, other: SymbolTable) {
+        for symbol in other.symbols {
+            self.insert_sized(symbol.address, symbol.size, &symbol.name);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    pub fn address_of(&self, name: &str) -> Option<u32> {
+        self.symbols
+            .iter()
+            .find(|symbol| symbol.name == name)
+            .map(|symbol| symbol.address)
+    }
+
+    /// The closest symbol at or below `address`, and how far past it the address is.
+    pub fn lookup(&self, address: u32) -> Option<(&Symbol, u32)> {
+        let index = self
+            .symbols
+            .partition_point(|symbol| symbol.address <= address);
+        let symbol = self.symbols.get(index.checked_sub(1)?)?;
+        let offset = address - symbol.address;
+        if symbol.size != 0 && offset >= symbol.size {
+            return None;
+        }
+        Some((symbol, offset))
+    }
+
+    /// `name+0x10` style label for `address`, or None without a symbol below it.
+    pub fn label(&self, address: u32) -> Option<Label<'_>> {
+        self.lookup(address).map(|(symbol, offset)| Label {
+            name: &symbol.name,
+            offset,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Label<'a> {
+    pub name: &'a str,
+    pub offset: u32,
+}
+
+impl fmt::Display for Label<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.offset == 0 {
+            f.write_str(self.name)
+        } else {
+            write!(f, "{}+0x{:x}", self.name, self.offset)
+        }
+    }
+}
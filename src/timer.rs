@@ -0,0 +1,151 @@
+// src/timer.rs
+// The four 16 bit timers (GBATEK "GBA Timers"). A running timer isn't ticked, its counter is
+// worked out from the cycle it was last reloaded at and its overflow goes on the scheduler.
+// Timers in count-up mode only move when the timer before them overflows. Timer 0 and 1
+// overflows also clock the DirectSound FIFOs, see `apu`.
+
+use crate::bus::Bus;
+use crate::interrupt::Interrupt;
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+use crate::scheduler::Event;
+
+pub const TIMER_REGISTERS_START: u32 = 0x100;
+pub const TIMER_REGISTERS_END: u32 = 0x10F;
+
+const PRESCALER_SHIFTS: [u32; 4] = [0, 6, 8, 10]; // 1, 64, 256 or 1024 cycles a tick
+const TMCNT_COUNT_UP: u16 = 1 << 2;
+const TMCNT_IRQ: u16 = 1 << 6;
+const TMCNT_ENABLE: u16 = 1 << 7;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Timer {
+    pub reload: u16,
+    pub control: u16,
+    counter: u16,    // As of `started_at`
+    started_at: u64, // Cycle the counter was last set
+}
+
+impl Timer {
+    pub fn is_enabled(&self) -> bool {
+        self.control & TMCNT_ENABLE != 0
+    }
+
+    fn prescaler_shift(&self) -> u32 {
+        PRESCALER_SHIFTS[(self.control & 3) as usize]
+    }
+
+    // Count-up timers are clocked by the previous timer, timer 0 has none
+    fn counts_up(&self, index: usize) -> bool {
+        index > 0 && self.control & TMCNT_COUNT_UP != 0
+    }
+
+    /// The counter at cycle `now`.
+    pub fn counter(&self, index: usize, now: u64) -> u16 {
+        if !self.is_enabled() || self.counts_up(index) {
+            return self.counter;
+        }
+        let ticks = now.saturating_sub(self.started_at) >> self.prescaler_shift();
+        (self.counter as u64 + ticks).min(0xFFFF) as u16
+    }
+
+    // Cycles from a freshly set counter to its overflow
+    fn period(&self) -> u64 {
+        (0x10000 - self.counter as u64) << self.prescaler_shift()
+    }
+}
+
+fn overflow_event(index: usize) -> Event {
+    Event::TimerOverflow(index as u8)
+}
+
+impl Bus {
+    pub(crate) fn read_timer_register(&self, offset: u32) -> u8 {
+        let index = ((offset - TIMER_REGISTERS_START) / 4) as usize;
+        let timer = &self.timers[index];
+        let halfword = match offset & 2 {
+            0 => timer.counter(index, self.scheduler.now()),
+            _ => timer.control,
+        };
+        (halfword >> ((offset & 1) * 8)) as u8
+    }
+
+    pub(crate) fn write_timer_register(&mut self, offset: u32, value: u8) {
+        let index = ((offset - TIMER_REGISTERS_START) / 4) as usize;
+        let shift = (offset & 1) * 8;
+        let merge = |old: u16| (old & !(0xFF << shift)) | ((value as u16) << shift);
+        match offset & 3 {
+            // Writes go to the reload value, the counter picks it up on the next start or overflow
+            0 | 1 => self.timers[index].reload = merge(self.timers[index].reload),
+            2 => self.write_timer_control(index, value as u16),
+            _ => {}
+        }
+    }
+
+    fn write_timer_control(&mut self, index: usize, control: u16) {
+        let now = self.scheduler.now();
+        let timer = &mut self.timers[index];
+        let was_enabled = timer.is_enabled();
+        timer.counter = timer.counter(index, now);
+        timer.started_at = now;
+        timer.control = control & 0xC7;
+        if !was_enabled && timer.is_enabled() {
+            timer.counter = timer.reload;
+        }
+        let timer = *timer;
+        self.scheduler.cancel(overflow_event(index));
+        if timer.is_enabled() && !timer.counts_up(index) {
+            self.scheduler
+                .schedule(timer.period(), overflow_event(index));
+        }
+    }
+
+    /// Timer `index` wrapped around at cycle `at`: reloads it and passes the overflow on.
+    pub(crate) fn timer_overflow(&mut self, index: usize, at: u64) {
+        let timer = &mut self.timers[index];
+        timer.counter = timer.reload;
+        timer.started_at = at;
+        let timer = *timer;
+        if !timer.counts_up(index) {
+            self.scheduler
+                .schedule_at(at + timer.period(), overflow_event(index));
+        }
+        if timer.control & TMCNT_IRQ != 0 {
+            let interrupt = [
+                Interrupt::Timer0,
+                Interrupt::Timer1,
+                Interrupt::Timer2,
+                Interrupt::Timer3,
+            ];
+            self.request_interrupt(interrupt[index]);
+        }
+        if index < 2 {
+            self.clock_sound_fifos(index);
+        }
+
+        if let Some(next) = self.timers.get_mut(index + 1) {
+            if next.is_enabled() && next.counts_up(index + 1) {
+                next.counter = next.counter.wrapping_add(1);
+                if next.counter == 0 {
+                    self.timer_overflow(index + 1, at);
+                }
+            }
+        }
+    }
+}
+
+impl Snapshot for Timer {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.reload);
+        state.u16(self.control);
+        state.u16(self.counter);
+        state.u64(self.started_at);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), SaveStateError> {
+        self.reload = state.u16()?;
+        self.control = state.u16()?;
+        self.counter = state.u16()?;
+        self.started_at = state.u64()?;
+        Ok(())
+    }
+}
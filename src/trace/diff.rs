@@ -0,0 +1,108 @@
+// src/trace/diff.rs
+// Finds where two traces of the same program part ways. A register that differs before step N
+// was written wrong by the instruction at step N - 1, so that one is reported too.
+
+use std::fmt;
+
+use super::format::ParsedStep;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Mismatch {
+    Pc,
+    Opcode,
+    Register(usize),
+    Cpsr,
+    Length, // One trace ended first
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Divergence {
+    pub step: usize, // 0 based index into both traces
+    pub mismatch: Mismatch,
+    pub left: Option<ParsedStep>,
+    pub right: Option<ParsedStep>,
+    pub culprit: Option<ParsedStep>, // The left trace's step before, when there is one
+}
+
+fn compare(left: &ParsedStep, right: &ParsedStep) -> Option<Mismatch> {
+    if left.pc() != right.pc() {
+        return Some(Mismatch::Pc);
+    }
+    if let Some(index) = (0..15).find(|&i| left.registers[i] != right.registers[i]) {
+        return Some(Mismatch::Register(index));
+    }
+    if left.cpsr != right.cpsr {
+        return Some(Mismatch::Cpsr);
+    }
+    match (left.opcode, right.opcode) {
+        (Some(a), Some(b)) if a != b => Some(Mismatch::Opcode),
+        _ => None,
+    }
+}
+
+pub fn first_divergence(left: &[ParsedStep], right: &[ParsedStep]) -> Option<Divergence> {
+    let steps = left.len().max(right.len());
+    for step in 0..steps {
+        let mismatch = match (left.get(step), right.get(step)) {
+            (Some(a), Some(b)) => compare(a, b),
+            _ => Some(Mismatch::Length),
+        };
+        if let Some(mismatch) = mismatch {
+            return Some(Divergence {
+                step,
+                mismatch,
+                left: left.get(step).copied(),
+                right: right.get(step).copied(),
+                culprit: step.checked_sub(1).and_then(|i| left.get(i)).copied(),
+            });
+        }
+    }
+    None
+}
+
+fn describe_step(f: &mut fmt::Formatter, side: &str, step: &Option<ParsedStep>) -> fmt::Result {
+    match step {
+        Some(step) => {
+            write!(f, "  {} line {}: pc {:08X}", side, step.line, step.pc())?;
+            if let Some(opcode) = step.opcode {
+                write!(f, " opcode {:08X}", opcode)?;
+            }
+            writeln!(f)
+        }
+        None => writeln!(f, "  {} trace ended", side),
+    }
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Traces diverge at step {}", self.step)?;
+        describe_step(f, "left ", &self.left)?;
+        describe_step(f, "right", &self.right)?;
+        let (Some(left), Some(right)) = (self.left, self.right) else {
+            return Ok(());
+        };
+        match self.mismatch {
+            Mismatch::Pc => write!(f, "  pc: {:08X} vs {:08X}", left.pc(), right.pc())?,
+            Mismatch::Register(i) => write!(
+                f,
+                "  r{}: {:08X} vs {:08X}",
+                i, left.registers[i], right.registers[i]
+            )?,
+            Mismatch::Cpsr => write!(f, "  cpsr: {:08X} vs {:08X}", left.cpsr, right.cpsr)?,
+            Mismatch::Opcode => write!(
+                f,
+                "  opcode: {:08X} vs {:08X}",
+                left.opcode.unwrap_or(0),
+                right.opcode.unwrap_or(0)
+            )?,
+            Mismatch::Length => {}
+        }
+        if let Some(culprit) = self.culprit {
+            write!(f, "\n  after the instruction at {:08X}", culprit.pc())?;
+            if let Some(opcode) = culprit.opcode {
+                write!(f, " ({:08X})", opcode)?;
+            }
+        }
+        Ok(())
+    }
+}
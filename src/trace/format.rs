@@ -0,0 +1,175 @@
+// src/trace/format.rs
+// Trace lines as mGBA's and NanoBoyAdvance's trace loggers print them, so a run can be diffed
+// line by line against theirs, and a parser that reads back any of the text formats. Both of
+// them show r15 the way the pipeline sees it, the instruction's address + 8 (+ 4 in Thumb).
+//
+//   mGBA: r0 .. r15 cpsr: CPSR | OPCODE: disassembly
+//   NBA:  r0:R0 .. r15:R15 cpsr:CPSR [OPCODE] disassembly
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use super::{read_binary, TraceRecord, BINARY_MAGIC};
+
+const THUMB_BIT: u32 = 1 << 5;
+
+/// The state before one instruction, whichever format it came from. r15 holds the
+/// instruction's address.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ParsedStep {
+    pub line: usize, // 1 based, or the record number of a binary trace
+    pub opcode: Option<u32>,
+    pub registers: [u32; 16],
+    pub cpsr: u32,
+}
+
+impl ParsedStep {
+    pub fn pc(&self) -> u32 {
+        self.registers[15]
+    }
+
+    fn from_record(line: usize, record: &TraceRecord) -> Self {
+        ParsedStep {
+            line,
+            opcode: Some(record.opcode),
+            registers: record.registers,
+            cpsr: record.cpsr,
+        }
+    }
+}
+
+fn pipeline_offset(cpsr: u32) -> u32 {
+    if cpsr & THUMB_BIT != 0 {
+        4
+    } else {
+        8
+    }
+}
+
+fn pipeline_registers(record: &TraceRecord) -> [u32; 16] {
+    let mut registers = record.registers;
+    registers[15] = record.pc.wrapping_add(pipeline_offset(record.cpsr));
+    registers
+}
+
+pub fn mgba_line(record: &TraceRecord) -> String {
+    let mut line = String::new();
+    for value in pipeline_registers(record) {
+        line.push_str(&format!("{:08X} ", value));
+    }
+    line.push_str(&format!(
+        "cpsr: {:08X} | {:08X}: {}",
+        record.cpsr,
+        record.opcode,
+        record.disassembly()
+    ));
+    line
+}
+
+pub fn nba_line(record: &TraceRecord) -> String {
+    let mut line = String::new();
+    for (i, value) in pipeline_registers(record).iter().enumerate() {
+        line.push_str(&format!("r{}:{:08X} ", i, value));
+    }
+    line.push_str(&format!(
+        "cpsr:{:08X} [{:08X}] {}",
+        record.cpsr,
+        record.opcode,
+        record.disassembly()
+    ));
+    line
+}
+
+fn hex(token: &str) -> Option<u32> {
+    u32::from_str_radix(token.trim_end_matches(':'), 16).ok()
+}
+
+fn parse_mgba(line: &str) -> Option<([u32; 16], u32, Option<u32>)> {
+    let (registers_part, rest) = line.split_once("cpsr: ")?;
+    let mut registers = [0u32; 16];
+    let mut tokens = registers_part.split_whitespace();
+    for register in registers.iter_mut() {
+        *register = hex(tokens.next()?)?;
+    }
+    if tokens.next().is_some() {
+        return None;
+    }
+    let (cpsr, instruction) = rest.split_once('|')?;
+    let opcode = instruction.split_whitespace().next().and_then(hex);
+    Some((registers, hex(cpsr.trim())?, opcode))
+}
+
+// Our own text format and NBA's both name every register, rN=VALUE or rN:VALUE
+fn parse_named(line: &str) -> Option<([u32; 16], u32, Option<u32>, bool)> {
+    let mut registers = [None; 16];
+    let mut cpsr = None;
+    let mut opcode = None;
+    for token in line.split_whitespace() {
+        let Some((name, value)) = token.split_once(['=', ':']) else {
+            if let Some(inner) = token.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+                opcode = opcode.or(hex(inner));
+            }
+            continue;
+        };
+        if name == "cpsr" {
+            cpsr = hex(value);
+        } else if let Some(index) = name.strip_prefix('r').and_then(|n| n.parse::<usize>().ok()) {
+            if index < 16 {
+                registers[index] = hex(value);
+            }
+        }
+    }
+    let mut values = [0u32; 16];
+    for (value, register) in values.iter_mut().zip(registers) {
+        *value = register?;
+    }
+    // Ours starts with the cycle count, address and opcode and has r15 unadjusted
+    let native = opcode.is_none() && line.contains("r0=");
+    if native {
+        opcode = line.split_whitespace().nth(2).and_then(hex);
+    }
+    Some((values, cpsr?, opcode, native))
+}
+
+/// Parses a line of our text format, mGBA's or NBA's. Lines that are none of them, log
+/// messages and the like, give `None`.
+pub fn parse_line(line: &str, number: usize) -> Option<ParsedStep> {
+    let (mut registers, cpsr, opcode, native) = match parse_mgba(line) {
+        Some((registers, cpsr, opcode)) => (registers, cpsr, opcode, false),
+        None => parse_named(line)?,
+    };
+    if !native {
+        registers[15] = registers[15].wrapping_sub(pipeline_offset(cpsr));
+    }
+    Some(ParsedStep {
+        line: number,
+        opcode,
+        registers,
+        cpsr,
+    })
+}
+
+/// Reads a trace in any format, binary ones are told apart by their header.
+pub fn read_steps<R: Read>(reader: R) -> io::Result<Vec<ParsedStep>> {
+    let mut reader = BufReader::new(reader);
+    if reader.fill_buf()?.starts_with(BINARY_MAGIC) {
+        let records = read_binary(reader)?;
+        return Ok(records
+            .iter()
+            .enumerate()
+            .map(|(i, record)| ParsedStep::from_record(i + 1, record))
+            .collect());
+    }
+    let mut steps = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        if let Some(step) = parse_line(&line?, i + 1) {
+            steps.push(step);
+        }
+    }
+    Ok(steps)
+}
+
+pub fn load_steps<P: AsRef<Path>>(path: P) -> io::Result<Vec<ParsedStep>> {
+    read_steps(File::open(path)?)
+}
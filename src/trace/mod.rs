@@ -0,0 +1,382 @@
+// src/trace/mod.rs
+// Execution tracing. The CPU hands a record to the tracer before each instruction it runs,
+// records go to a sink as text lines or fixed size binary records. Only categories switched
+// on and, when ranges are given, only addresses inside one of them are traced.
+
+pub mod diff;
+pub mod format;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use crate::cpu_instructions::instruction_decoding::{decode_arm, Instruction};
+use crate::symbols::SymbolTable;
+
+const BINARY_MAGIC: &[u8; 8] = b"GBATRC01";
+const BINARY_RECORD_SIZE: usize = 1 + 4 + 4 + 16 * 4 + 4 + 8;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Category {
+    Instruction = 0, // Every instruction not covered by a category below
+    Branch = 1,      // B, BL, BX and BLX
+    Swi = 2,         // Software interrupts
+    Irq = 3,         // IRQ exception entry, recorded before the handler runs
+}
+
+impl Category {
+    pub const ALL: [Category; 4] =
+        [Category::Instruction, Category::Branch, Category::Swi, Category::Irq];
+
+    pub fn of(instruction: &Instruction) -> Category {
+        match instruction {
+            Instruction::Branch { .. }
+            | Instruction::BranchExchange { .. }
+            | Instruction::BranchLinkExchange { .. } => Category::Branch,
+            Instruction::SoftwareInterrupt { .. } => Category::Swi,
+            _ => Category::Instruction,
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Category> {
+        Category::ALL.get(value as usize).copied()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Instruction => "instruction",
+            Category::Branch => "branch",
+            Category::Swi => "swi",
+            Category::Irq => "irq",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Category> {
+        Category::ALL.into_iter().find(|category| category.name() == name)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TraceRecord {
+    pub category: Category,
+    pub pc: u32,
+    pub opcode: u32,
+    pub registers: [u32; 16], // Before the instruction runs, r15 is the instruction's address
+    pub cpsr: u32,
+    pub cycles: u64, // Cycles run since power on
+}
+
+impl TraceRecord {
+    pub fn instruction(&self) -> Instruction {
+        decode_arm(self.opcode)
+    }
+
+    pub fn disassembly(&self) -> String {
+        match self.category {
+            Category::Irq => String::from("<irq>"),
+            _ => crate::disasm::disassemble_arm(self.opcode, self.pc),
+        }
+    }
+
+    /// One line: cycles, address, opcode, disassembly, then r0-r15 and CPSR.
+    pub fn to_text(&self) -> String {
+        self.text(None)
+    }
+
+    /// `to_text` with `<function+offset>` before the disassembly and after branch targets.
+    pub fn to_text_with_symbols(&self, symbols: &SymbolTable) -> String {
+        self.text(Some(symbols))
+    }
+
+    fn text(&self, symbols: Option<&SymbolTable>) -> String {
+        let mut disassembly = match symbols {
+            Some(symbols) => {
+                let text = crate::disasm::annotate(&self.disassembly(), symbols);
+                match symbols.label(self.pc) {
+                    Some(label) => format!("<{}> {}", label, text),
+                    None => text,
+                }
+            }
+            None => self.disassembly(),
+        };
+        // Calls the HLE BIOS returns from without doing anything
+        if let Instruction::SoftwareInterrupt { comment } = self.instruction() {
+            if self.category == Category::Swi && crate::bios::hle::is_stubbed((comment >> 16) as u8) {
+                disassembly.push_str(" ; HLE BIOS stub");
+            }
+        }
+        let mut line = format!(
+            "{:>12} {:08X} {:08X} {}",
+            self.cycles, self.pc, self.opcode, disassembly
+        );
+        for (i, value) in self.registers.iter().enumerate() {
+            line.push_str(&format!(" r{}={:08X}", i, value));
+        }
+        line.push_str(&format!(" cpsr={:08X}", self.cpsr));
+        line
+    }
+
+    pub fn to_bytes(&self) -> [u8; BINARY_RECORD_SIZE] {
+        let mut bytes = [0u8; BINARY_RECORD_SIZE];
+        bytes[0] = self.category as u8;
+        bytes[1..5].copy_from_slice(&self.pc.to_le_bytes());
+        bytes[5..9].copy_from_slice(&self.opcode.to_le_bytes());
+        for (i, value) in self.registers.iter().enumerate() {
+            bytes[9 + i * 4..13 + i * 4].copy_from_slice(&value.to_le_bytes());
+        }
+        bytes[73..77].copy_from_slice(&self.cpsr.to_le_bytes());
+        bytes[77..85].copy_from_slice(&self.cycles.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; BINARY_RECORD_SIZE]) -> Option<TraceRecord> {
+        let word = |offset: usize| {
+            u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+        };
+        let mut registers = [0u32; 16];
+        for (i, register) in registers.iter_mut().enumerate() {
+            *register = word(9 + i * 4);
+        }
+        let mut cycles = [0u8; 8];
+        cycles.copy_from_slice(&bytes[77..85]);
+        Some(TraceRecord {
+            category: Category::from_u8(bytes[0])?,
+            pc: word(1),
+            opcode: word(5),
+            registers,
+            cpsr: word(73),
+            cycles: u64::from_le_bytes(cycles),
+        })
+    }
+}
+
+pub trait TraceSink: Send {
+    fn record(&mut self, record: &TraceRecord) -> io::Result<()>;
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+    /// Symbols to label addresses with, for sinks that write something readable.
+    fn set_symbols(&mut self, _symbols: SymbolTable) {}
+}
+
+/// One line per record, see `TraceRecord::to_text`, or in another emulator's format.
+pub struct TextSink<W: Write + Send> {
+    writer: W,
+    format: TraceFormat,
+    symbols: Option<SymbolTable>, // Only our own format has room for them
+}
+
+impl<W: Write + Send> TextSink<W> {
+    pub fn new(writer: W) -> Self {
+        TextSink::with_format(writer, TraceFormat::Text)
+    }
+
+    /// `format` is one of the text formats, Binary falls back to Text.
+    pub fn with_format(writer: W, format: TraceFormat) -> Self {
+        TextSink {
+            writer,
+            format,
+            symbols: None,
+        }
+    }
+}
+
+impl<W: Write + Send> TraceSink for TextSink<W> {
+    fn record(&mut self, record: &TraceRecord) -> io::Result<()> {
+        let line = match self.format {
+            TraceFormat::Text | TraceFormat::Binary => match &self.symbols {
+                Some(symbols) => record.to_text_with_symbols(symbols),
+                None => record.to_text(),
+            },
+            // Neither of them logs interrupts as a step of their own
+            _ if record.category == Category::Irq => return Ok(()),
+            TraceFormat::Mgba => format::mgba_line(record),
+            TraceFormat::NanoBoyAdvance => format::nba_line(record),
+        };
+        writeln!(self.writer, "{}", line)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = Some(symbols);
+    }
+}
+
+/// A magic header followed by 85 byte little-endian records, read back with `read_binary`.
+pub struct BinarySink<W: Write + Send> {
+    writer: W,
+    header_written: bool,
+}
+
+impl<W: Write + Send> BinarySink<W> {
+    pub fn new(writer: W) -> Self {
+        BinarySink {
+            writer,
+            header_written: false,
+        }
+    }
+}
+
+impl<W: Write + Send> TraceSink for BinarySink<W> {
+    fn record(&mut self, record: &TraceRecord) -> io::Result<()> {
+        if !self.header_written {
+            self.writer.write_all(BINARY_MAGIC)?;
+            self.header_written = true;
+        }
+        self.writer.write_all(&record.to_bytes())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Keeps records in memory, for tests and tools.
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    pub records: Vec<TraceRecord>,
+}
+
+impl TraceSink for MemorySink {
+    fn record(&mut self, record: &TraceRecord) -> io::Result<()> {
+        self.records.push(*record);
+        Ok(())
+    }
+}
+
+// Lets a caller keep a handle on the records while the tracer owns the sink.
+impl TraceSink for std::sync::Arc<std::sync::Mutex<MemorySink>> {
+    fn record(&mut self, record: &TraceRecord) -> io::Result<()> {
+        self.lock().unwrap().record(record)
+    }
+}
+
+/// Reads a whole binary trace.
+pub fn read_binary<R: Read>(mut reader: R) -> io::Result<Vec<TraceRecord>> {
+    let mut magic = [0u8; 8];
+    match reader.read_exact(&mut magic) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    }
+    if &magic != BINARY_MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a binary trace"));
+    }
+    let mut records = Vec::new();
+    let mut bytes = [0u8; BINARY_RECORD_SIZE];
+    loop {
+        match reader.read_exact(&mut bytes) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(records),
+            Err(err) => return Err(err),
+        }
+        let record = TraceRecord::from_bytes(&bytes)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "bad trace category"))?;
+        records.push(record);
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TraceFormat {
+    Text,
+    Binary,
+    Mgba,
+    NanoBoyAdvance,
+}
+
+impl TraceFormat {
+    pub fn from_name(name: &str) -> Option<TraceFormat> {
+        match name {
+            "text" => Some(TraceFormat::Text),
+            "binary" => Some(TraceFormat::Binary),
+            "mgba" => Some(TraceFormat::Mgba),
+            "nba" => Some(TraceFormat::NanoBoyAdvance),
+            _ => None,
+        }
+    }
+}
+
+pub struct Tracer {
+    categories: u32, // Bit per Category
+    ranges: Vec<RangeInclusive<u32>>, // Empty traces every address
+    sink: Box<dyn TraceSink>,
+}
+
+impl Tracer {
+    /// Traces every category at every address.
+    pub fn new(sink: Box<dyn TraceSink>) -> Self {
+        Tracer {
+            categories: !0,
+            ranges: Vec::new(),
+            sink,
+        }
+    }
+
+    pub fn to_file<P: AsRef<Path>>(path: P, format: TraceFormat) -> io::Result<Self> {
+        let writer = BufWriter::new(File::create(path)?);
+        let sink: Box<dyn TraceSink> = match format {
+            TraceFormat::Binary => Box::new(BinarySink::new(writer)),
+            _ => Box::new(TextSink::with_format(writer, format)),
+        };
+        Ok(Tracer::new(sink))
+    }
+
+    pub fn enable(&mut self, category: Category, enabled: bool) {
+        if enabled {
+            self.categories |= 1 << category as u32;
+        } else {
+            self.categories &= !(1 << category as u32);
+        }
+    }
+
+    /// Traces only the given categories.
+    pub fn set_categories(&mut self, categories: &[Category]) {
+        self.categories = 0;
+        for category in categories {
+            self.enable(*category, true);
+        }
+    }
+
+    pub fn is_enabled(&self, category: Category) -> bool {
+        self.categories & (1 << category as u32) != 0
+    }
+
+    /// Adds an address range to trace, once there is one addresses outside all ranges are skipped.
+    pub fn add_range(&mut self, range: RangeInclusive<u32>) {
+        self.ranges.push(range);
+    }
+
+    pub fn clear_ranges(&mut self) {
+        self.ranges.clear();
+    }
+
+    pub fn wants(&self, category: Category, pc: u32) -> bool {
+        self.is_enabled(category)
+            && (self.ranges.is_empty() || self.ranges.iter().any(|range| range.contains(&pc)))
+    }
+
+    pub fn record(&mut self, record: &TraceRecord) -> io::Result<()> {
+        if self.wants(record.category, record.pc) {
+            self.sink.record(record)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.sink.set_symbols(symbols)
+    }
+}
+
+impl Drop for Tracer {
+    fn drop(&mut self) {
+        let _ = self.sink.flush();
+    }
+}
@@ -0,0 +1,64 @@
+// src/wav.rs
+// Streaming WAV writer for audio dumps: 16 bit signed stereo PCM. The RIFF and data sizes
+// aren't known until the end, they're written as 0 and patched in by `finish`.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const HEADER_SIZE: u32 = 44;
+const CHANNELS: u16 = 2;
+const BYTES_PER_FRAME: u32 = 4; // Two 16 bit samples
+
+pub struct WavWriter<W: Write + Seek> {
+    out: W,
+    frames: u32, // Left/right pairs written
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> io::Result<Self> {
+        WavWriter::new(BufWriter::new(File::create(path)?), sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut out: W, sample_rate: u32) -> io::Result<Self> {
+        out.write_all(b"RIFF")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(b"WAVEfmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?; // PCM
+        out.write_all(&CHANNELS.to_le_bytes())?;
+        out.write_all(&sample_rate.to_le_bytes())?;
+        out.write_all(&(sample_rate * BYTES_PER_FRAME).to_le_bytes())?;
+        out.write_all(&(BYTES_PER_FRAME as u16).to_le_bytes())?;
+        out.write_all(&16u16.to_le_bytes())?;
+        out.write_all(b"data")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        Ok(WavWriter { out, frames: 0 })
+    }
+
+    pub fn write_samples(&mut self, samples: &[[i16; 2]]) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(samples.len() * BYTES_PER_FRAME as usize);
+        for [left, right] in samples {
+            bytes.extend_from_slice(&left.to_le_bytes());
+            bytes.extend_from_slice(&right.to_le_bytes());
+        }
+        self.out.write_all(&bytes)?;
+        self.frames += samples.len() as u32;
+        Ok(())
+    }
+
+    /// Fills in the sizes and flushes. Returns the output.
+    pub fn finish(mut self) -> io::Result<W> {
+        let data_size = self.frames * BYTES_PER_FRAME;
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out
+            .write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&data_size.to_le_bytes())?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
@@ -0,0 +1,56 @@
+// src/y4m.rs
+// YUV4MPEG2 writer for video dumps, the format ffmpeg, mpv and x264 take as raw input. Frames
+// are 4:4:4, so no chroma is lost on the way from RGB, in BT.601 studio range. The frame rate
+// is written as an exact fraction, for the GBA 2^24 / 280896, about 59.7275 fps.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+pub struct Y4mWriter<W: Write> {
+    out: W,
+    width: u32,
+    height: u32,
+}
+
+impl Y4mWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        width: u32,
+        height: u32,
+        rate: (u64, u64),
+    ) -> io::Result<Self> {
+        Y4mWriter::new(BufWriter::new(File::create(path)?), width, height, rate)
+    }
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// `rate` is frames per second as numerator and denominator.
+    pub fn new(mut out: W, width: u32, height: u32, rate: (u64, u64)) -> io::Result<Self> {
+        writeln!(
+            out,
+            "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+            width, height, rate.0, rate.1
+        )?;
+        Ok(Y4mWriter { out, width, height })
+    }
+
+    /// Writes one frame of 8 bit RGB, three bytes a pixel.
+    pub fn write_frame(&mut self, rgb: &[u8]) -> io::Result<()> {
+        let pixels = (self.width * self.height) as usize;
+        let mut planes = vec![0u8; pixels * 3];
+        for (index, pixel) in rgb.chunks_exact(3).take(pixels).enumerate() {
+            let (r, g, b) = (pixel[0] as i32, pixel[1] as i32, pixel[2] as i32);
+            planes[index] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+            planes[pixels + index] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+            planes[2 * pixels + index] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+        }
+        self.out.write_all(b"FRAME\n")?;
+        self.out.write_all(&planes)
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
@@ -0,0 +1,195 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{booted, rom_with_code, spinning_cartridge, TempFile, LOOP};
+    use emulator::apu::{CYCLES_PER_SAMPLE, SAMPLE_RATE};
+    use emulator::gba::Gba;
+    use emulator::wav::WavWriter;
+    use emulator::y4m::Y4mWriter;
+    use std::io::Cursor;
+    use std::process::Command;
+
+    // Master enable on, every PSG channel at full volume on both sides
+    fn sound_on(gba: &mut Gba) {
+        gba.bus.write_halfword(0x04000084, 0x0080);
+        gba.bus.write_halfword(0x04000080, 0xFF77);
+        gba.bus.write_halfword(0x04000082, 0x0002);
+        gba.bus.apu.capture = true;
+    }
+
+    #[test]
+    fn test_timer_counts_and_overflows() {
+        let mut gba = booted(spinning_cartridge());
+        gba.bus.write_halfword(0x04000100, 0xFF00);
+        gba.bus.write_halfword(0x04000102, 0x00C1); // Enabled, IRQ, 64 cycles a tick
+        assert_eq!(gba.bus.read_halfword(0x04000100), 0xFF00);
+        gba.run_cycles(64 * 0x10).unwrap();
+        let counter = gba.bus.read_halfword(0x04000100);
+        assert!((0xFF10..0xFF20).contains(&counter), "{:04X}", counter);
+        assert_eq!(gba.bus.interrupt_flags() & (1 << 3), 0);
+
+        gba.run_cycles(64 * 0x100).unwrap();
+        assert_ne!(gba.bus.interrupt_flags() & (1 << 3), 0);
+        assert!(gba.bus.read_halfword(0x04000100) >= 0xFF00);
+
+        // Stopping freezes the counter
+        gba.bus.write_halfword(0x04000102, 0x0001);
+        let frozen = gba.bus.read_halfword(0x04000100);
+        gba.run_cycles(64 * 0x20).unwrap();
+        assert_eq!(gba.bus.read_halfword(0x04000100), frozen);
+    }
+
+    #[test]
+    fn test_timer_cascade() {
+        let mut gba = booted(spinning_cartridge());
+        gba.bus.write_halfword(0x04000100, 0xFF00); // Overflows every 256 cycles
+        gba.bus.write_halfword(0x04000104, 0xFFFD);
+        gba.bus.write_halfword(0x04000106, 0x00C4); // Count-up, IRQ
+        gba.bus.write_halfword(0x04000102, 0x0080);
+        gba.run_cycles(256 * 2 + 100).unwrap();
+        assert_eq!(gba.bus.read_halfword(0x04000104), 0xFFFF);
+        assert_eq!(gba.bus.interrupt_flags() & (1 << 4), 0);
+        gba.run_cycles(256).unwrap();
+        assert_eq!(gba.bus.read_halfword(0x04000104), 0xFFFD);
+        assert_ne!(gba.bus.interrupt_flags() & (1 << 4), 0);
+    }
+
+    #[test]
+    fn test_square_channel() {
+        let mut gba = booted(spinning_cartridge());
+        sound_on(&mut gba);
+        gba.bus.write_halfword(0x04000062, 0xF080); // Volume 15, 50% duty
+        gba.bus.write_halfword(0x04000064, 0x8000 | 1792); // 512 Hz, restart
+        assert_eq!(gba.bus.read_byte(0x04000084), 0x81);
+        assert_eq!(gba.bus.read_halfword(0x04000064) & 0x8000, 0);
+
+        gba.run_cycles(SAMPLE_RATE as u64 / 8 * CYCLES_PER_SAMPLE)
+            .unwrap();
+        let samples = gba.bus.apu.take_samples();
+        assert!(samples.len() >= SAMPLE_RATE as usize / 8);
+        // 15 * 8 on 10 bits, both sides alike
+        let peak = samples.iter().map(|[left, _]| *left).max().unwrap();
+        assert_eq!(peak, 15 * 8 * 64);
+        assert!(samples.iter().all(|[left, right]| left == right));
+        // 512 Hz at 32768 Hz is 64 samples a cycle, half of them high
+        let high = samples[..640].iter().filter(|[left, _]| *left > 0).count();
+        assert_eq!(high, 320);
+
+        // Master off silences everything and clears the registers
+        gba.bus.write_halfword(0x04000084, 0);
+        assert_eq!(gba.bus.read_halfword(0x04000062), 0);
+        gba.run_cycles(CYCLES_PER_SAMPLE * 4).unwrap();
+        assert!(gba.bus.apu.take_samples().iter().all(|&s| s == [0, 0]));
+    }
+
+    #[test]
+    fn test_length_counter_stops_channel() {
+        let mut gba = booted(spinning_cartridge());
+        sound_on(&mut gba);
+        gba.bus.write_halfword(0x04000068, 0xF03F); // Length 1/256 s
+        gba.bus.write_halfword(0x0400006C, 0xC000 | 1024);
+        assert_eq!(gba.bus.read_byte(0x04000084) & 2, 2);
+        gba.run_cycles(1 << 16).unwrap();
+        assert_eq!(gba.bus.read_byte(0x04000084) & 2, 0);
+    }
+
+    #[test]
+    fn test_direct_sound_fifo_and_dma() {
+        let mut gba = booted(spinning_cartridge());
+        sound_on(&mut gba);
+        // FIFO A at full volume on both sides, clocked by timer 0
+        gba.bus.write_halfword(0x04000082, 0x0B04);
+        for (index, sample) in (0..64u32).enumerate() {
+            gba.bus
+                .write_byte(0x02000000 + index as u32, sample as u8 * 2);
+        }
+        gba.bus.write_word(0x040000BC, 0x02000000);
+        gba.bus.write_word(0x040000C0, 0x040000A0);
+        gba.bus.write_halfword(0x040000C6, 0xB640); // Enabled, sound timing, repeat, words, fixed
+        gba.bus.write_word(0x040000A0, 0x03020100);
+
+        // A sample every 1024 cycles, two output samples each
+        gba.bus.write_halfword(0x04000100, 0xFC00);
+        gba.bus.write_halfword(0x04000102, 0x0080);
+        gba.bus.apu.take_samples();
+        gba.run_cycles(1024 * 8).unwrap();
+        let levels: Vec<i16> = gba
+            .bus
+            .apu
+            .take_samples()
+            .iter()
+            .map(|[left, _]| *left)
+            .collect();
+        let played: Vec<i16> = levels.iter().map(|level| level / 4 / 64).collect();
+        assert!(played.windows(3).any(|window| window == [0, 1, 1]));
+        assert!(played.contains(&2));
+
+        // Half empty after the first pop, DMA1 tops it up from EWRAM
+        assert!(gba.bus.apu.fifos[0].len() > 4);
+        assert!(!gba.bus.apu.fifos[0].is_empty());
+    }
+
+    #[test]
+    fn test_sound_survives_save_state() {
+        let mut gba = booted(spinning_cartridge());
+        sound_on(&mut gba);
+        gba.bus.write_halfword(0x04000078, 0xF700);
+        gba.bus.write_halfword(0x0400007C, 0x8021);
+        gba.bus.write_halfword(0x04000062, 0xA3C0);
+        gba.bus.write_halfword(0x04000064, 0x8000 | 1900);
+        gba.run_frame().unwrap();
+        gba.bus.apu.take_samples();
+
+        let state = gba.save_state().unwrap();
+        gba.run_frame().unwrap();
+        let expected = gba.bus.apu.take_samples();
+        gba.load_state(&state).unwrap();
+        gba.run_frame().unwrap();
+        assert_eq!(gba.bus.apu.take_samples(), expected);
+    }
+
+    #[test]
+    fn test_wav_and_y4m_writers() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), SAMPLE_RATE).unwrap();
+        wav.write_samples(&[[1, -1], [0x1234, 0]]).unwrap();
+        let bytes = wav.finish().unwrap().into_inner();
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(&bytes[4..8], &44u32.to_le_bytes());
+        assert_eq!(&bytes[24..28], &SAMPLE_RATE.to_le_bytes());
+        assert_eq!(&bytes[40..44], &8u32.to_le_bytes());
+        assert_eq!(&bytes[44..48], &[1, 0, 0xFF, 0xFF]);
+
+        let mut y4m = Y4mWriter::new(Vec::new(), 2, 1, (1 << 24, 280896)).unwrap();
+        y4m.write_frame(&[0, 0, 0, 255, 255, 255]).unwrap();
+        let bytes = y4m.finish().unwrap();
+        let header = b"YUV4MPEG2 W2 H1 F16777216:280896 Ip A1:1 C444\nFRAME\n";
+        assert!(bytes.starts_with(header));
+        assert_eq!(&bytes[header.len()..], &[16, 235, 128, 128, 128, 128]);
+    }
+
+    #[test]
+    fn test_headless_dumps_stay_in_sync() {
+        let rom = TempFile::new("apu_dump.gba");
+        let wav = TempFile::new("apu_dump.wav");
+        let y4m = TempFile::new("apu_dump.y4m");
+        std::fs::write(&rom.0, rom_with_code(&[LOOP])).unwrap();
+        let status = Command::new(env!("CARGO_BIN_EXE_headless"))
+            .arg(&rom.0)
+            .args(["--frames", "8", "--wav"])
+            .arg(&wav.0)
+            .arg("--video")
+            .arg(&y4m.0)
+            .status()
+            .unwrap();
+        assert_eq!(status.code(), Some(0));
+
+        // 8 frames of 280896 cycles are 4389 samples of 512, give or take the last instruction
+        let samples = (std::fs::read(&wav.0).unwrap().len() - 44) / 4;
+        assert!((4389..=4390).contains(&samples), "{}", samples);
+        let video = std::fs::read(&y4m.0).unwrap();
+        let header_end = video.iter().position(|&byte| byte == b'\n').unwrap() + 1;
+        assert_eq!(video.len() - header_end, 8 * (6 + 240 * 160 * 3));
+    }
+}
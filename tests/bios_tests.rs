@@ -0,0 +1,324 @@
+#[cfg(test)]
+mod tests {
+    use emulator::bios::hle::{software_interrupt, BIOS_IF, SP_IRQ, SP_SVC, SP_SYS};
+    use emulator::bios::{self, BiosError};
+    use emulator::bus::Bus;
+    use emulator::cpu::{Cpu, Mode};
+    use emulator::error::EmulatorError;
+    use emulator::memory::Memory;
+
+    const EWRAM: u32 = 0x02000000;
+    const HALT: u32 = 0xFFFFFFFF;
+
+    fn swi(cpu: &mut Cpu, bus: &mut Bus, number: u8, args: &[u32]) {
+        for (i, value) in args.iter().enumerate() {
+            cpu.cpu_state.set_register(i, *value).unwrap();
+        }
+        software_interrupt(cpu, bus, number).unwrap();
+    }
+
+    fn write_bytes(bus: &mut Bus, address: u32, bytes: &[u8]) {
+        for (i, byte) in bytes.iter().enumerate() {
+            bus.write_byte(address + i as u32, *byte);
+        }
+    }
+
+    fn read_bytes(bus: &mut Bus, address: u32, length: u32) -> Vec<u8> {
+        (0..length).map(|i| bus.read_byte(address + i)).collect()
+    }
+
+    #[test]
+    fn test_div_register_side_effects() {
+        let (mut cpu, mut bus) = (Cpu::new(), Bus::new());
+        swi(&mut cpu, &mut bus, 0x06, &[-7i32 as u32, 2]);
+        assert_eq!(cpu.cpu_state.get_register(0).unwrap() as i32, -3);
+        assert_eq!(cpu.cpu_state.get_register(1).unwrap() as i32, -1);
+        assert_eq!(cpu.cpu_state.get_register(3).unwrap(), 3);
+
+        // DivArm takes the operands the other way round
+        swi(&mut cpu, &mut bus, 0x07, &[3, 100]);
+        assert_eq!(cpu.cpu_state.get_register(0).unwrap(), 33);
+        assert_eq!(cpu.cpu_state.get_register(1).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_swi_instruction_runs_hle_bios() {
+        let (mut cpu, mut bus) = (Cpu::new(), Bus::new());
+        bus.write_word(EWRAM, 0xEF060000); // SWI 0x06 (Div)
+        bus.write_word(EWRAM + 4, HALT);
+        cpu.cpu_state.set_register(0, 100).unwrap();
+        cpu.cpu_state.set_register(1, 7).unwrap();
+        cpu.cpu_state.set_register(15, EWRAM).unwrap();
+        cpu.run_program(&mut bus).unwrap();
+        assert_eq!(cpu.cpu_state.get_register(0).unwrap(), 14);
+        assert_eq!(cpu.cpu_state.get_register(1).unwrap(), 2);
+        assert_eq!(cpu.cpu_state.get_register(3).unwrap(), 14);
+    }
+
+    #[test]
+    fn test_swi_without_hle_enters_supervisor_mode() {
+        let mut memory = Memory::new(64);
+        memory.write_word(0x08, HALT).unwrap(); // SWI vector
+        memory.write_word(0x10, 0xEF000000).unwrap();
+        let mut cpu = Cpu::new();
+        cpu.hle_bios = false;
+        cpu.cpu_state.set_mode(Mode::System);
+        cpu.cpu_state.set_register(13, 0x1234).unwrap();
+        cpu.cpu_state.set_register(15, 0x10).unwrap();
+        cpu.run_program(&mut memory).unwrap();
+        assert_eq!(cpu.cpu_state.mode(), Mode::Supervisor);
+        assert_eq!(cpu.cpu_state.get_register(14).unwrap(), 0x14);
+        assert_eq!(cpu.cpu_state.SPSR.mode_bits(), Mode::System as u32);
+        assert!(cpu.cpu_state.CPSR.is_irq_disabled());
+        // Back in System mode the old stack pointer is still there
+        cpu.cpu_state.set_mode(Mode::System);
+        assert_eq!(cpu.cpu_state.get_register(13).unwrap(), 0x1234);
+    }
+
+    #[test]
+    fn test_sqrt_and_arctan2() {
+        let (mut cpu, mut bus) = (Cpu::new(), Bus::new());
+        swi(&mut cpu, &mut bus, 0x08, &[1000]);
+        assert_eq!(cpu.cpu_state.get_register(0).unwrap(), 31);
+        swi(&mut cpu, &mut bus, 0x08, &[0xFFFFFFFF]);
+        assert_eq!(cpu.cpu_state.get_register(0).unwrap(), 0xFFFF);
+
+        let cases = [(1, 0, 0x0000), (0, 1, 0x4000), (-1i32, 0, 0x8000), (0, -1i32, 0xC000)];
+        for (x, y, expected) in cases {
+            swi(&mut cpu, &mut bus, 0x0A, &[x as u32, y as u32]);
+            assert_eq!(cpu.cpu_state.get_register(0).unwrap(), expected);
+            assert_eq!(cpu.cpu_state.get_register(3).unwrap(), 0x170);
+        }
+        // The polynomial lands close to, not exactly on, 45 and 225 degrees
+        swi(&mut cpu, &mut bus, 0x0A, &[0x100, 0x100]);
+        assert!((cpu.cpu_state.get_register(0).unwrap() as i32 - 0x2000).abs() < 0x10);
+        swi(&mut cpu, &mut bus, 0x0A, &[-0x100i32 as u32, -0x100i32 as u32]);
+        assert!((cpu.cpu_state.get_register(0).unwrap() as i32 - 0xA000).abs() < 0x10);
+    }
+
+    #[test]
+    fn test_midi_key_to_frequency() {
+        let (mut cpu, mut bus) = (Cpu::new(), Bus::new());
+        // WaveData.freq is the sample rate times 1024, recorded at key 180
+        let cases = [
+            (13379 * 1024, 180, 0, 13379 * 1024),
+            (13379 * 1024, 168, 0, 13379 * 512),
+            (13379 * 1024, 60, 0, 13379),
+            (13379 * 1024, 69, 0, 22500),
+            (13379 * 1024, 72, 128, 27542),
+            (13379 * 1024, 57, 200, 11769),
+            (13379 * 1024, 100, 37, 135982),
+            // Too many bits for an f32
+            (44100 * 1024 + 1, 180, 0, 44100 * 1024 + 1),
+            (44100 * 1024 + 1, 60, 0, 44100),
+        ];
+        for (sample_rate, key, fraction, expected) in cases {
+            bus.write_word(EWRAM + 4, sample_rate);
+            swi(&mut cpu, &mut bus, 0x1F, &[EWRAM, key, fraction]);
+            assert_eq!(cpu.cpu_state.get_register(0).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_cpu_set_copy_and_fill() {
+        let (mut cpu, mut bus) = (Cpu::new(), Bus::new());
+        for i in 0..4 {
+            bus.write_halfword(EWRAM + i * 2, 0x1111 * (i as u16 + 1));
+        }
+        swi(&mut cpu, &mut bus, 0x0B, &[EWRAM, EWRAM + 0x100, 4]);
+        assert_eq!(bus.read_halfword(EWRAM + 0x106), 0x4444);
+
+        bus.write_word(EWRAM, 0xDEADBEEF);
+        swi(&mut cpu, &mut bus, 0x0B, &[EWRAM, EWRAM + 0x200, (1 << 26) | (1 << 24) | 3]);
+        assert_eq!(bus.read_word(EWRAM + 0x208), 0xDEADBEEF);
+        assert_eq!(bus.read_word(EWRAM + 0x20C), 0);
+
+        // CpuFastSet rounds the count up to 8 words
+        swi(&mut cpu, &mut bus, 0x0C, &[EWRAM, EWRAM + 0x300, (1 << 24) | 1]);
+        assert_eq!(bus.read_word(EWRAM + 0x31C), 0xDEADBEEF);
+        assert_eq!(bus.read_word(EWRAM + 0x320), 0);
+    }
+
+    #[test]
+    fn test_lz77_and_run_length() {
+        let (mut cpu, mut bus) = (Cpu::new(), Bus::new());
+        // "ABC", then 6 bytes from 3 back, then "X"
+        write_bytes(&mut bus, EWRAM, &[0x10, 10, 0, 0, 0x10, b'A', b'B', b'C', 0x30, 0x02, b'X']);
+        swi(&mut cpu, &mut bus, 0x11, &[EWRAM, EWRAM + 0x100]);
+        assert_eq!(read_bytes(&mut bus, EWRAM + 0x100, 10), b"ABCABCABCX");
+
+        write_bytes(&mut bus, EWRAM, &[0x30, 6, 0, 0, 0x82, b'A', 0x00, b'B']);
+        swi(&mut cpu, &mut bus, 0x15, &[EWRAM, 0x06000000]);
+        assert_eq!(read_bytes(&mut bus, 0x06000000, 6), b"AAAAAB");
+    }
+
+    #[test]
+    fn test_huffman() {
+        let (mut cpu, mut bus) = (Cpu::new(), Bus::new());
+        // 8 bit data, a root with two data children 'a' (bit 0) and 'b' (bit 1)
+        write_bytes(&mut bus, EWRAM, &[0x28, 4, 0, 0, 1, 0xC0, b'a', b'b']);
+        bus.write_word(EWRAM + 8, 0x6000_0000);
+        swi(&mut cpu, &mut bus, 0x13, &[EWRAM, EWRAM + 0x100]);
+        assert_eq!(read_bytes(&mut bus, EWRAM + 0x100, 4), b"abba");
+    }
+
+    #[test]
+    fn test_diff_unfilter_and_bit_unpack() {
+        let (mut cpu, mut bus) = (Cpu::new(), Bus::new());
+        write_bytes(&mut bus, EWRAM, &[0x81, 4, 0, 0, 10, 1, 1, 0xFF]);
+        swi(&mut cpu, &mut bus, 0x16, &[EWRAM, EWRAM + 0x100]);
+        assert_eq!(read_bytes(&mut bus, EWRAM + 0x100, 4), vec![10, 11, 12, 11]);
+
+        // 1 bit to 4 bit units, adding 1 to the set ones
+        bus.write_byte(EWRAM + 0x10, 0b0000_0101);
+        bus.write_halfword(EWRAM + 0x20, 1);
+        bus.write_byte(EWRAM + 0x22, 1);
+        bus.write_byte(EWRAM + 0x23, 4);
+        bus.write_word(EWRAM + 0x24, 1);
+        swi(&mut cpu, &mut bus, 0x10, &[EWRAM + 0x10, EWRAM + 0x200, EWRAM + 0x20]);
+        assert_eq!(bus.read_word(EWRAM + 0x200), 0x0000_0202);
+    }
+
+    #[test]
+    fn test_pointers_near_4_gib_wrap_around() {
+        let (mut cpu, mut bus) = (Cpu::new(), Bus::new());
+        for number in 0x10..=0x18 {
+            swi(&mut cpu, &mut bus, number, &[0xFFFF_FFFE, 0xFFFF_FFF0, 0xFFFF_FFFC]);
+        }
+        swi(&mut cpu, &mut bus, 0x1F, &[0xFFFF_FFFE, 180, 0]);
+    }
+
+    #[test]
+    fn test_stubbed_and_invalid_swis() {
+        let (mut cpu, mut bus) = (Cpu::new(), Bus::new());
+        // SoundDriverMain, called every frame by games with Nintendo's driver
+        swi(&mut cpu, &mut bus, 0x1C, &[]);
+
+        bus.write_word(EWRAM, 0xEF2B0000); // SWI 0x2B
+        cpu.cpu_state.set_register(15, EWRAM + 4).unwrap();
+        assert_eq!(
+            software_interrupt(&mut cpu, &mut bus, 0x2B),
+            Err(EmulatorError::UnimplementedInstruction {
+                address: EWRAM,
+                opcode: 0xEF2B0000,
+                thumb: false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_affine_set() {
+        let (mut cpu, mut bus) = (Cpu::new(), Bus::new());
+        // Rotate by 90 degrees around screen point (8, 0), texture point (16.0, 0)
+        bus.write_word(EWRAM, 0x1000);
+        bus.write_word(EWRAM + 4, 0);
+        bus.write_halfword(EWRAM + 8, 8);
+        bus.write_halfword(EWRAM + 10, 0);
+        bus.write_halfword(EWRAM + 12, 0x100);
+        bus.write_halfword(EWRAM + 14, 0x100);
+        bus.write_halfword(EWRAM + 16, 0x4000);
+        swi(&mut cpu, &mut bus, 0x0E, &[EWRAM, EWRAM + 0x100, 1]);
+        let matrix: Vec<i16> = (0..4)
+            .map(|i| bus.read_halfword(EWRAM + 0x100 + i * 2) as i16)
+            .collect();
+        assert_eq!(matrix, vec![0, -0x100, 0x100, 0]);
+        assert_eq!(bus.read_word(EWRAM + 0x108) as i32, 0x1000);
+        assert_eq!(bus.read_word(EWRAM + 0x10C) as i32, -0x800);
+
+        // ObjAffineSet, scale 2x, no rotation, OAM stride
+        bus.write_halfword(EWRAM + 0x20, 0x200);
+        bus.write_halfword(EWRAM + 0x22, 0x200);
+        bus.write_halfword(EWRAM + 0x24, 0);
+        swi(&mut cpu, &mut bus, 0x0F, &[EWRAM + 0x20, 0x07000006, 1, 8]);
+        assert_eq!(bus.read_halfword(0x07000006), 0x200);
+        assert_eq!(bus.read_halfword(0x0700000E), 0);
+        assert_eq!(bus.read_halfword(0x0700001E), 0x200);
+    }
+
+    #[test]
+    fn test_soft_reset_sets_banked_stacks() {
+        let (mut cpu, mut bus) = (Cpu::new(), Bus::new());
+        bus.write_word(0x03007F00, 0x1234);
+        swi(&mut cpu, &mut bus, 0x00, &[1, 2, 3]);
+        let state = &mut cpu.cpu_state;
+        assert_eq!(state.mode(), Mode::System);
+        assert_eq!(state.get_register(0).unwrap(), 0);
+        assert_eq!(state.get_register(13).unwrap(), SP_SYS);
+        assert_eq!(state.get_register(15).unwrap(), 0x08000000);
+        assert_eq!(bus.read_word(0x03007F00), 0);
+        state.set_mode(Mode::Irq);
+        assert_eq!(state.get_register(13).unwrap(), SP_IRQ);
+        state.set_mode(Mode::Supervisor);
+        assert_eq!(state.get_register(13).unwrap(), SP_SVC);
+    }
+
+    #[test]
+    fn test_intr_wait_halts_until_flag_is_set() {
+        let (mut cpu, mut bus) = (Cpu::new(), Bus::new());
+        bus.write_halfword(BIOS_IF, 1); // Stale VBlank, discarded
+        cpu.cpu_state.set_register(15, EWRAM + 4).unwrap();
+        swi(&mut cpu, &mut bus, 0x05, &[]);
+        assert_eq!(bus.read_halfword(0x04000208), 1); // IME
+        assert_eq!(bus.read_byte(0x04000301), 0); // HALTCNT written
+        // Runs again from the SWI once woken up
+        assert_eq!(cpu.cpu_state.get_register(15).unwrap(), EWRAM);
+
+        bus.write_halfword(BIOS_IF, 1);
+        cpu.cpu_state.set_register(15, EWRAM + 4).unwrap();
+        swi(&mut cpu, &mut bus, 0x05, &[]);
+        assert_eq!(cpu.cpu_state.get_register(15).unwrap(), EWRAM + 4);
+        assert_eq!(bus.read_halfword(BIOS_IF), 0);
+    }
+
+    fn fake_bios() -> Vec<u8> {
+        let mut image = vec![0u8; 16 * 1024];
+        image[0..4].copy_from_slice(&0xEA000018u32.to_le_bytes()); // B 0x68
+        image[0x68..0x6C].copy_from_slice(&HALT.to_le_bytes());
+        image[0x100..0x104].copy_from_slice(&0x12345678u32.to_le_bytes());
+        image
+    }
+
+    #[test]
+    fn test_load_bios_reports_checksum() {
+        let mut bus = Bus::new();
+        let checksum = bus.load_bios(&fake_bios()).unwrap();
+        assert_eq!(checksum, 0xEA000018u32.wrapping_add(HALT).wrapping_add(0x12345678));
+        assert!(matches!(bus.load_bios(&[0; 100]), Err(BiosError::WrongSize { size: 100 })));
+    }
+
+    #[test]
+    fn test_boot_from_reset_vector_and_read_protection() {
+        let mut bus = Bus::new();
+        bus.load_bios(&fake_bios()).unwrap();
+        let mut cpu = Cpu::new();
+        cpu.hle_bios = false;
+        cpu.reset();
+        assert_eq!(cpu.cpu_state.mode(), Mode::Supervisor);
+        assert!(cpu.cpu_state.CPSR.is_irq_disabled() && cpu.cpu_state.CPSR.is_fiq_disabled());
+        cpu.run_program(&mut bus).unwrap();
+        assert_eq!(cpu.cpu_state.get_register(15).unwrap(), 0x68);
+
+        // Only code running in the BIOS can read it, the rest gets the last fetched opcode
+        bus.write_word(EWRAM, HALT);
+        cpu.cpu_state.set_register(15, EWRAM).unwrap();
+        cpu.run_program(&mut bus).unwrap();
+        assert_eq!(bus.read_word(0x100), HALT);
+        assert_eq!(bus.read_byte(0x101), 0xFF);
+    }
+
+    #[test]
+    fn test_skip_boot_state() {
+        let (mut cpu, mut bus) = (Cpu::new(), Bus::new());
+        bios::skip_boot(&mut cpu, &mut bus);
+        assert_eq!(cpu.cpu_state.mode(), Mode::System);
+        assert_eq!(cpu.cpu_state.get_register(15).unwrap(), 0x08000000);
+        assert_eq!(cpu.cpu_state.get_register(13).unwrap(), SP_SYS);
+        assert!(!cpu.cpu_state.CPSR.is_irq_disabled());
+        cpu.cpu_state.set_mode(Mode::Irq);
+        assert_eq!(cpu.cpu_state.get_register(13).unwrap(), SP_IRQ);
+        assert_eq!(bus.read_halfword(0x04000088), 0x0200);
+        assert_eq!(bus.read_halfword(0x04000020), 0x0100);
+        assert_eq!(bus.read_byte(0x04000300), 1);
+        assert_eq!(bus.read_word(0), bios::POST_BOOT_PREFETCH);
+    }
+}
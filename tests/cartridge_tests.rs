@@ -0,0 +1,78 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::make_rom;
+    use emulator::cartridge::{Cartridge, CartridgeError};
+
+    #[test]
+    fn test_parse_valid_header() {
+        let cartridge = Cartridge::from_bytes(make_rom(0x400)).unwrap();
+        let header = &cartridge.header;
+        assert_eq!(header.title, "TESTGAME");
+        assert_eq!(header.game_code, "ATST");
+        assert_eq!(header.maker_code, "01");
+        assert_eq!(header.version, 1);
+        assert_eq!(header.entry_point, 0xEA00002E);
+        assert_eq!(header.entry_address(), Some(0x080000C0));
+        assert_eq!(cartridge.rom_size(), 0x400);
+    }
+
+    #[test]
+    fn test_truncated_rom() {
+        let rom = make_rom(0x400);
+        match Cartridge::from_bytes(rom[..0x80].to_vec()) {
+            Err(CartridgeError::Truncated { size }) => assert_eq!(size, 0x80),
+            other => panic!("unexpected result: {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let mut rom = make_rom(0x400);
+        let good = rom[0xBD];
+        rom[0xBD] = good.wrapping_add(1);
+        match Cartridge::from_bytes(rom) {
+            Err(CartridgeError::ChecksumMismatch { expected, found }) => {
+                assert_eq!(expected, good);
+                assert_eq!(found, good.wrapping_add(1));
+            }
+            other => panic!("unexpected result: {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_invalid_fixed_value() {
+        let mut rom = make_rom(0x400);
+        rom[0xB2] = 0x00;
+        assert!(matches!(
+            Cartridge::from_bytes(rom),
+            Err(CartridgeError::InvalidFixedValue(0x00))
+        ));
+    }
+
+    #[test]
+    fn test_rom_too_large() {
+        let rom = make_rom(32 * 1024 * 1024 + 4);
+        assert!(matches!(
+            Cartridge::from_bytes(rom),
+            Err(CartridgeError::TooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_missing_file() {
+        assert!(matches!(
+            Cartridge::load("does/not/exist.gba"),
+            Err(CartridgeError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_past_end_returns_open_bus() {
+        let cartridge = Cartridge::from_bytes(make_rom(0x400)).unwrap();
+        // Halfword at 0x1000 reads back as 0x0800 (address >> 1)
+        assert_eq!(cartridge.read_rom_byte(0x1000), 0x00);
+        assert_eq!(cartridge.read_rom_byte(0x1001), 0x08);
+    }
+}
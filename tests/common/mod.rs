@@ -0,0 +1,85 @@
+// Helpers shared by the integration tests.
+#![allow(dead_code)]
+
+use emulator::bios;
+use emulator::cartridge::header::CartridgeHeader;
+use emulator::cartridge::Cartridge;
+use emulator::gba::Gba;
+
+pub const EWRAM: u32 = 0x02000000;
+pub const LOOP: u32 = 0xEAFFFFFE; // B .
+
+// Builds a minimal ROM with a valid header.
+pub fn make_rom(size: usize) -> Vec<u8> {
+    make_rom_with_code(size, b"ATST")
+}
+
+pub fn make_rom_with_code(size: usize, game_code: &[u8; 4]) -> Vec<u8> {
+    let mut rom = vec![0u8; size];
+    // B 0x080000C0
+    rom[0..4].copy_from_slice(&0xEA00002Eu32.to_le_bytes());
+    rom[0xA0..0xA0 + 8].copy_from_slice(b"TESTGAME");
+    rom[0xAC..0xB0].copy_from_slice(game_code);
+    rom[0xB0..0xB2].copy_from_slice(b"01");
+    rom[0xB2] = 0x96;
+    rom[0xBC] = 1;
+    rom[0xBD] = CartridgeHeader::compute_complement(&rom);
+    rom
+}
+
+pub fn make_cartridge() -> Cartridge {
+    Cartridge::from_bytes(make_rom(0x400)).unwrap()
+}
+
+// A ROM running `code` from 0x080000C0, where the header's branch goes.
+pub fn rom_with_code(code: &[u32]) -> Vec<u8> {
+    let mut rom = make_rom(0x400);
+    for (i, opcode) in code.iter().enumerate() {
+        rom[0xC0 + i * 4..0xC4 + i * 4].copy_from_slice(&opcode.to_le_bytes());
+    }
+    rom
+}
+
+// Spins on B . at 0x080000C0.
+pub fn spinning_cartridge() -> Cartridge {
+    Cartridge::from_bytes(rom_with_code(&[LOOP])).unwrap()
+}
+
+// Counts up in r0: ADD r0, r0, #1; B 0x080000C0
+pub fn counting_rom() -> Vec<u8> {
+    rom_with_code(&[0xE2800001, 0xEAFFFFFD])
+}
+
+// Past the boot, about to run the ROM.
+pub fn booted(cartridge: Cartridge) -> Gba {
+    let mut gba = Gba::new(cartridge);
+    bios::skip_boot(&mut gba.cpu, &mut gba.bus);
+    gba
+}
+
+// Booted, running `code` from EWRAM instead.
+pub fn booted_in_ewram(code: &[u32]) -> Gba {
+    let mut gba = booted(make_cartridge());
+    for (i, word) in code.iter().enumerate() {
+        gba.bus.write_word(EWRAM + i as u32 * 4, *word);
+    }
+    gba.cpu.cpu_state.set_register(15, EWRAM).unwrap();
+    gba
+}
+
+// Unique path in the system temp directory, removed when dropped.
+pub struct TempFile(pub std::path::PathBuf);
+
+impl TempFile {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("gba_emulator_{}_{}", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        TempFile(path)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
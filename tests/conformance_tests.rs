@@ -0,0 +1,253 @@
+// Conformance runs against public test ROMs, which can't be checked in, so the test is ignored
+// by default. Point GBA_TEST_ROMS at a directory laid out like this and run
+// `cargo test --test conformance_tests -- --ignored --nocapture`:
+//
+//   gba-tests/arm/arm.gba ...        jsmolka's gba-tests, checked through r12 (0 = all passed)
+//   armwrestler.gba, suite.gba ...   anything else, checked by hashing the screen
+//   expected_hashes.txt              `path hash` lines, the reference screens
+//   <rom>.input                      optional input script, see `movie::script`
+//
+// Screen hash tests without a reference are reported but don't fail. With GBA_TEST_BLESS=1 the
+// current screens are written to expected_hashes.txt as the new reference. The report goes to
+// stdout and to GBA_TEST_REPORT, or conformance-report.txt in Cargo's test temp directory.
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{booted, rom_with_code, TempFile, LOOP};
+    use emulator::cartridge::Cartridge;
+    use emulator::gba::Gba;
+    use emulator::movie::frame_hash;
+    use emulator::movie::script::InputScript;
+    use std::collections::BTreeMap;
+    use std::fmt;
+    use std::path::{Path, PathBuf};
+
+    const HASHES_FILE: &str = "expected_hashes.txt";
+    const DEFAULT_FRAMES: u32 = 600;
+    const SETTLE_FRAMES: u32 = 60; // After the last scripted press, before hashing the screen
+
+    #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+    enum Check {
+        Register(usize), // Passed when the register is 0 once the ROM idles
+        ScreenHash,
+    }
+
+    // The ROMs we know how to judge other than by their screen
+    const SUITES: &[(&str, Check)] = &[
+        ("gba-tests/arm/arm.gba", Check::Register(12)),
+        ("gba-tests/thumb/thumb.gba", Check::Register(12)),
+        ("gba-tests/memory/memory.gba", Check::Register(12)),
+        ("gba-tests/bios/bios.gba", Check::Register(12)),
+        ("gba-tests/nes/nes.gba", Check::Register(12)),
+    ];
+
+    #[derive(Debug, PartialEq, Eq, Clone)]
+    enum Outcome {
+        Pass,
+        Fail(String),
+        Error(String),   // The emulator gave up
+        Unverified(u32), // Screen hash with nothing to compare against
+    }
+
+    impl fmt::Display for Outcome {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Outcome::Pass => write!(f, "pass"),
+                Outcome::Fail(reason) => write!(f, "FAIL   {}", reason),
+                Outcome::Error(reason) => write!(f, "ERROR  {}", reason),
+                Outcome::Unverified(hash) => write!(f, "new    screen {:08X}", hash),
+            }
+        }
+    }
+
+    // Test ROMs park in `B .` once they're done
+    fn is_idle(gba: &mut Gba) -> bool {
+        let pc = gba.cpu.cpu_state.registers[15];
+        if gba.cpu.cpu_state.CPSR.is_thumb_state() {
+            gba.bus.read_halfword(pc) == 0xE7FE
+        } else {
+            gba.bus.read_word(pc) == 0xEAFFFFFE
+        }
+    }
+
+    // Runs a ROM until it idles, the script is over or the frame limit, then judges it.
+    // Screen hashes come back as Unverified, for the caller to compare.
+    fn run_rom(rom: &Path, check: Check, script: &InputScript) -> Outcome {
+        let cartridge = match Cartridge::load(rom) {
+            Ok(cartridge) => cartridge,
+            Err(err) => return Outcome::Error(err.to_string()),
+        };
+        let mut gba = booted(cartridge);
+
+        let frames = match check {
+            Check::Register(_) => DEFAULT_FRAMES,
+            Check::ScreenHash => script.length() + SETTLE_FRAMES,
+        };
+        let mut idle = false;
+        for frame in 0..frames {
+            gba.bus.set_keys(script.keys_at(frame));
+            if let Err(err) = gba.run_frame() {
+                return Outcome::Error(format!("frame {}: {}", frame, err));
+            }
+            idle = is_idle(&mut gba);
+            if idle && frame >= script.length() {
+                break;
+            }
+        }
+
+        match check {
+            Check::Register(index) => {
+                let value = gba.cpu.cpu_state.registers[index];
+                match (idle, value) {
+                    (false, _) => Outcome::Fail(format!("still running after {} frames", frames)),
+                    (true, 0) => Outcome::Pass,
+                    (true, value) => Outcome::Fail(format!("test {} failed (r{})", value, index)),
+                }
+            }
+            Check::ScreenHash => Outcome::Unverified(frame_hash(&gba)),
+        }
+    }
+
+    fn find_roms(dir: &Path, found: &mut Vec<PathBuf>) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.is_dir() {
+                find_roms(&path, found);
+            } else if path.extension().is_some_and(|ext| ext == "gba") {
+                found.push(path);
+            }
+        }
+    }
+
+    fn read_hashes(path: &Path) -> BTreeMap<String, u32> {
+        let text = std::fs::read_to_string(path).unwrap_or_default();
+        text.lines()
+            .filter_map(|line| {
+                let (name, hash) = line.trim().split_once(char::is_whitespace)?;
+                Some((name.to_string(), u32::from_str_radix(hash.trim(), 16).ok()?))
+            })
+            .collect()
+    }
+
+    fn write_hashes(path: &Path, hashes: &BTreeMap<String, u32>) {
+        let text: String = hashes
+            .iter()
+            .map(|(name, hash)| format!("{} {:08X}\n", name, hash))
+            .collect();
+        std::fs::write(path, text).unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs the test ROMs in GBA_TEST_ROMS"]
+    fn test_conformance_roms() {
+        let dir = std::env::var_os("GBA_TEST_ROMS")
+            .map(PathBuf::from)
+            .expect("GBA_TEST_ROMS must point at the test ROMs");
+        let bless = std::env::var_os("GBA_TEST_BLESS").is_some();
+        let mut roms = Vec::new();
+        find_roms(&dir, &mut roms);
+        roms.sort();
+        assert!(!roms.is_empty(), "no .gba files under {}", dir.display());
+
+        let hashes_path = dir.join(HASHES_FILE);
+        let mut hashes = read_hashes(&hashes_path);
+        let mut report = String::new();
+        let mut failed = 0;
+        for rom in &roms {
+            let name = rom
+                .strip_prefix(&dir)
+                .unwrap()
+                .to_string_lossy()
+                .replace('\\', "/");
+            let check = SUITES
+                .iter()
+                .find(|(suite, _)| *suite == name)
+                .map_or(Check::ScreenHash, |&(_, check)| check);
+            let script = match InputScript::load(rom.with_extension("input")) {
+                Ok(script) => script,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => InputScript::default(),
+                Err(err) => panic!("{}: bad input script: {}", name, err),
+            };
+
+            let mut outcome = run_rom(rom, check, &script);
+            if let Outcome::Unverified(hash) = outcome {
+                if bless {
+                    hashes.insert(name.clone(), hash);
+                } else if let Some(&expected) = hashes.get(&name) {
+                    outcome = if hash == expected {
+                        Outcome::Pass
+                    } else {
+                        Outcome::Fail(format!("screen {:08X}, expected {:08X}", hash, expected))
+                    };
+                }
+            }
+            if matches!(outcome, Outcome::Fail(_) | Outcome::Error(_)) {
+                failed += 1;
+            }
+            report.push_str(&format!("{:<40} {}\n", name, outcome));
+        }
+        report.push_str(&format!("{} of {} failed\n", failed, roms.len()));
+
+        print!("{}", report);
+        let report_path = std::env::var_os("GBA_TEST_REPORT")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| {
+                Path::new(env!("CARGO_TARGET_TMPDIR")).join("conformance-report.txt")
+            });
+        std::fs::write(&report_path, &report).unwrap();
+        if bless {
+            write_hashes(&hashes_path, &hashes);
+        }
+        assert_eq!(failed, 0, "see {}", report_path.display());
+    }
+
+    // The harness itself, on ROMs that end the way gba-tests do
+    fn result_rom(name: &str, r12: u8) -> TempFile {
+        let rom = rom_with_code(&[0xE3A0C000 | r12 as u32, LOOP]); // MOV r12, #r12; B .
+        let file = TempFile::new(name);
+        std::fs::write(&file.0, rom).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_harness_judges_result_register_and_screen() {
+        let script = InputScript::default();
+        let passing = result_rom("conformance_pass.gba", 0);
+        assert_eq!(
+            run_rom(&passing.0, Check::Register(12), &script),
+            Outcome::Pass
+        );
+        let failing = result_rom("conformance_fail.gba", 3);
+        assert_eq!(
+            run_rom(&failing.0, Check::Register(12), &script),
+            Outcome::Fail("test 3 failed (r12)".to_string())
+        );
+
+        // Same screen every time, and the script decides how long it runs
+        let script = InputScript::parse("0-9 START").unwrap();
+        let first = run_rom(&passing.0, Check::ScreenHash, &script);
+        assert!(matches!(first, Outcome::Unverified(_)));
+        assert_eq!(run_rom(&passing.0, Check::ScreenHash, &script), first);
+
+        let missing = Path::new("/nonexistent/conformance.gba");
+        assert!(matches!(
+            run_rom(missing, Check::Register(12), &script),
+            Outcome::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_hashes_file_round_trip() {
+        let file = TempFile::new("conformance_hashes.txt");
+        let mut hashes = BTreeMap::new();
+        hashes.insert("armwrestler.gba".to_string(), 0x0123ABCD);
+        hashes.insert("mgba/suite.gba".to_string(), 0xFFFF0000);
+        write_hashes(&file.0, &hashes);
+        assert_eq!(read_hashes(&file.0), hashes);
+        assert!(read_hashes(Path::new("/nonexistent/hashes.txt")).is_empty());
+    }
+}
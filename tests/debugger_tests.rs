@@ -0,0 +1,260 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{booted_in_ewram, EWRAM, LOOP};
+    use emulator::cpu::{Cpu, Mode, Step};
+    use emulator::debugger::io::io_register;
+    use emulator::debugger::{Breakpoint, Condition, InstructionSet, Repl, StopReason, WatchKind};
+    use emulator::interrupt::Interrupt;
+    use emulator::memory::Memory;
+    use emulator::symbols::SymbolTable;
+
+    const REG_IE: u32 = 0x04000200;
+    const REG_IME: u32 = 0x04000208;
+    const REG_HALTCNT: u32 = 0x04000301;
+
+    fn program(words: &[u32]) -> Memory {
+        let mut memory = Memory::new(256);
+        for (i, word) in words.iter().enumerate() {
+            memory.write_word(i as u32 * 4, *word).unwrap();
+        }
+        memory
+    }
+
+    #[test]
+    fn test_conditional_breakpoint() {
+        // MOV r1, #0; MOV r1, #1; B 0
+        let mut memory = program(&[0xE3A01000, 0xE3A01001, 0xEAFFFFFC]);
+        let mut cpu = Cpu::new();
+        let condition = Condition::parse("r1 == 1 && !cpsr.t").unwrap();
+        let id = cpu.debugger.add_breakpoint(Breakpoint {
+            condition: Some(condition),
+            ..Breakpoint::at(0)
+        });
+
+        // r1 is still 0 the first time round
+        for _ in 0..3 {
+            assert!(matches!(cpu.step(&mut memory), Ok(Step::Executed(_))));
+        }
+        let expected = StopReason::Breakpoint { id, address: 0 };
+        assert_eq!(cpu.step(&mut memory), Ok(Step::Break(expected)));
+        assert_eq!(cpu.stop_reason(), Some(expected));
+
+        // Continuing runs the instruction it stopped at
+        assert!(matches!(cpu.step(&mut memory), Ok(Step::Executed(_))));
+        assert_eq!(cpu.stop_reason(), None);
+        assert_eq!(cpu.cpu_state.get_register(1), Ok(0));
+    }
+
+    #[test]
+    fn test_breakpoint_state_and_mode_filters() {
+        let mut memory = program(&[0xE3A01001, LOOP]);
+        let mut cpu = Cpu::new();
+        cpu.debugger.add_breakpoint(Breakpoint {
+            state: Some(InstructionSet::Thumb),
+            ..Breakpoint::at(0)
+        });
+        cpu.debugger.add_breakpoint(Breakpoint {
+            mode: Some(Mode::Irq),
+            ..Breakpoint::at(0)
+        });
+        assert!(matches!(cpu.step(&mut memory), Ok(Step::Executed(_))));
+
+        cpu.debugger.clear();
+        assert!(!cpu.debugger.is_active());
+    }
+
+    #[test]
+    fn test_read_watchpoint_reports_value() {
+        // MOV r2, #0x40; LDR r3, [r2]; B .
+        let mut memory = program(&[0xE3A02040, 0xE5923000, LOOP]);
+        memory.write_word(0x40, 0x1234).unwrap();
+        let mut cpu = Cpu::new();
+        let id = cpu.debugger.add_watchpoint(WatchKind::Read, 0x40, 4, None);
+        // Writes don't trigger a read watchpoint
+        cpu.debugger.add_watchpoint(WatchKind::Write, 0x40, 4, None);
+
+        assert!(matches!(cpu.step(&mut memory), Ok(Step::Executed(_))));
+        let expected = StopReason::Watchpoint {
+            id,
+            pc: 4,
+            address: 0x40,
+            write: false,
+            value: 0x1234,
+        };
+        assert_eq!(cpu.step(&mut memory), Ok(Step::Break(expected)));
+        // The load has happened
+        assert_eq!(cpu.cpu_state.get_register(3), Ok(0x1234));
+
+        assert!(cpu.debugger.remove(id));
+        assert!(!cpu.debugger.remove(id));
+    }
+
+    #[test]
+    fn test_write_watchpoint_condition() {
+        // CpuSet fills 0x02000100.. with the word at 0x02000080, 4 words
+        let mut gba = booted_in_ewram(&[
+            0xE3A00402, // MOV r0, #0x02000000
+            0xE2801C01, // ADD r1, r0, #0x100
+            0xE2800080, // ADD r0, r0, #0x80
+            0xE3A02405, // MOV r2, #0x05000000 (fill, 32-bit)
+            0xE2822004, // ADD r2, r2, #4
+            0xEF0B0000, // SWI 0x0B
+            LOOP,
+        ]);
+        gba.bus.write_word(EWRAM + 0x80, 0xCAFE);
+
+        let condition = Condition::parse("[0x02000104] == 0xCAFE").unwrap();
+        let id =
+            gba.cpu
+                .debugger
+                .add_watchpoint(WatchKind::Write, EWRAM + 0x100, 0x10, Some(condition));
+        let step = (0..20)
+            .map(|_| gba.step().unwrap())
+            .find(|step| matches!(step, Step::Break(_)));
+        // Checked once the whole CpuSet is done, reporting the first write
+        assert_eq!(
+            step,
+            Some(Step::Break(StopReason::Watchpoint {
+                id,
+                pc: EWRAM + 0x14,
+                address: EWRAM + 0x100,
+                write: true,
+                value: 0xCAFE,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_io_write_breakpoint() {
+        // SWI 0x02, Halt, writes HALTCNT
+        let mut gba = booted_in_ewram(&[0xEF020000, LOOP]);
+        assert_eq!(gba.cpu.debugger.add_io_breakpoint(EWRAM, None), None);
+        let id = gba
+            .cpu
+            .debugger
+            .add_io_breakpoint(0x04000300, None)
+            .unwrap();
+        assert_eq!(
+            gba.step(),
+            Ok(Step::Break(StopReason::IoWrite {
+                id,
+                pc: EWRAM,
+                address: REG_HALTCNT,
+                value: 0,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_break_on_irq() {
+        let mut gba = booted_in_ewram(&[LOOP]);
+        gba.bus.write_halfword(REG_IE, 1);
+        gba.bus.write_halfword(REG_IME, 1);
+        gba.cpu.debugger.set_break_on_irq(true);
+        assert!(matches!(gba.step(), Ok(Step::Executed(_))));
+
+        gba.bus.request_interrupt(Interrupt::VBlank);
+        assert_eq!(
+            gba.step(),
+            Ok(Step::Break(StopReason::Irq {
+                return_address: EWRAM
+            }))
+        );
+        assert_eq!(gba.cpu.cpu_state.mode(), Mode::Irq);
+    }
+
+    #[test]
+    fn test_condition_parse_errors() {
+        let error = Condition::parse("r1 == ").unwrap_err();
+        assert_eq!(error.position, 6);
+        assert_eq!(error.to_string(), "expected a value at column 7");
+        assert_eq!(
+            Condition::parse("r16").unwrap_err().message,
+            "unknown name 'r16'"
+        );
+        assert_eq!(Condition::parse("(r1").unwrap_err().message, "expected ')'");
+        assert_eq!(
+            Condition::parse("r1 r2").unwrap_err().message,
+            "unexpected input"
+        );
+        assert_eq!(
+            Condition::parse("r1 = 2").unwrap_err().message,
+            "unexpected '='"
+        );
+        assert_eq!(
+            Condition::parse("[sp + 4] != 0 || (lr & 1)")
+                .unwrap()
+                .source(),
+            "[sp + 4] != 0 || (lr & 1)"
+        );
+    }
+
+    // Runs `commands` through the REPL, returns what it printed
+    fn repl_session(repl: &mut Repl, cpu: &mut Cpu, memory: &mut Memory, commands: &str) -> String {
+        let mut output = Vec::new();
+        repl.run(cpu, memory, commands.as_bytes(), &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_repl_break_step_and_inspect() {
+        // MOV r1, #1; MOV r2, #0x40; LDR r3, [r2]; B .
+        let mut memory = program(&[0xE3A01001, 0xE3A02040, 0xE5923000, LOOP]);
+        memory.write_word(0x40, 0x64636261).unwrap();
+        let mut cpu = Cpu::new();
+        let mut repl = Repl::new();
+        repl.symbols = SymbolTable::parse("00000000 start\n00000008 load\n");
+
+        let output = repl_session(
+            &mut repl,
+            &mut cpu,
+            &mut memory,
+            "break load if r1 == 1\nc\nstep\n\nr\nx 0x40 4\ndis start 2\nbp\nq\nstep\n",
+        );
+        assert!(output.starts_with("=> 0x00000000 <start>:  e3a01001   mov r1, #1\n"));
+        assert!(output.contains("Breakpoint 1 at 0x00000008 <load>\n"));
+        assert!(output.contains("=> 0x0000000C <load+0x4>:  eafffffe   b 0x0000000C <load+0x4>\n"));
+        assert!(output.contains("r0  00000000  r1  00000001  r2  00000040  r3  64636261"));
+        assert!(output.contains("Is zero: false"));
+        assert!(output.contains("00000040  61 62 63 64"));
+        assert!(output.contains("|abcd|"));
+        assert!(output.contains("0x00000004 <start+0x4>:  e3a02040   mov r2, #64"));
+        assert!(output.contains("1   break   0x00000008 <load> if r1 == 1"));
+        // Nothing ran after quit
+        assert_eq!(cpu.cpu_state.get_register(15), Ok(12));
+    }
+
+    #[test]
+    fn test_repl_reports_bad_input() {
+        let mut memory = program(&[LOOP]);
+        let mut cpu = Cpu::new();
+        let output = repl_session(
+            &mut Repl::new(),
+            &mut cpu,
+            &mut memory,
+            "frobnicate\nbreak nowhere\nwatch 0x40 if r1 ==\niobreak 0x02000000\nio dispcnt\n",
+        );
+        assert!(output.contains("Unknown command 'frobnicate', try help"));
+        assert!(output.contains("Bad address 'nowhere': unknown name 'nowhere' at column 1"));
+        assert!(output.contains("Bad condition: expected a value at column 6"));
+        assert!(output.contains("0x02000000 is not an I/O register"));
+        assert!(output.contains("DISPCNT (0x04000000) = 0x"));
+        assert!(!cpu.debugger.is_active());
+    }
+
+    #[test]
+    fn test_symbol_table() {
+        let table = SymbolTable::parse("; comment\n08000100 main\n080000C0 _start\nbroken\n");
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.address_of("main"), Some(0x08000100));
+        assert_eq!(table.label(0x08000104).unwrap().to_string(), "main+0x4");
+        assert_eq!(table.label(0x080000C0).unwrap().to_string(), "_start");
+        assert_eq!(table.label(0x08000000), None);
+
+        assert_eq!(io_register("REG_IME").map(|r| r.address), Some(0x04000208));
+        assert_eq!(io_register("ime").map(|r| r.size), Some(2));
+    }
+}
@@ -8,7 +8,7 @@ mod tests {
 
     #[test]
     fn test_decode_add_immediate() {
-        // ADD r0, r1, #10, setting flags (S bit set)
+        // ADD r0, r1, #10, not setting flags (S bit clear)
         // Expected encoding: 0xE281000A
         let instruction = u32::from_le_bytes([0x0A, 0x00, 0x81, 0xE2]);
         let decoded = decode_arm(instruction);
@@ -18,7 +18,7 @@ mod tests {
                 rd: 0,
                 rn: 1,
                 imm12: 10,
-                set_flags: true
+                set_flags: false
             }
         );
     }
@@ -117,7 +117,7 @@ mod tests {
 
     #[test]
     fn test_decode_mov_immediate() {
-        // MOV r0, #0x55, setting flags
+        // MOV r0, #0x55, not setting flags
         let instruction = u32::from_le_bytes([0x55, 0x00, 0xA0, 0xE3]);
         let decoded = decode_arm(instruction);
         assert_eq!(
@@ -125,7 +125,7 @@ mod tests {
             Instruction::MovImmediate {
                 rd: 0,
                 imm12: 0x55,
-                set_flags: true
+                set_flags: false
             }
         );
     }
@@ -167,7 +167,7 @@ mod tests {
 
     #[test]
     fn test_decode_sub_immediate() {
-        // SUB r0, r1, #10, setting flags.
+        // SUB r0, r1, #10, not setting flags.
         let instruction = u32::from_le_bytes([0x0A, 0x00, 0x41, 0xE2]);
         let decoded = decode_arm(instruction);
         assert_eq!(
@@ -176,14 +176,14 @@ mod tests {
                 rd: 0,
                 rn: 1,
                 imm12: 10,
-                set_flags: true
+                set_flags: false
             }
         );
     }
 
     #[test]
     fn test_decode_and_immediate() {
-        // AND r0, r1, #10, setting flags.
+        // AND r0, r1, #10, not setting flags.
         let instruction = u32::from_le_bytes([0x0A, 0x00, 0x01, 0xE2]);
         let decoded = decode_arm(instruction);
         assert_eq!(
@@ -192,7 +192,7 @@ mod tests {
                 rd: 0,
                 rn: 1,
                 imm12: 10,
-                set_flags: true
+                set_flags: false
             }
         );
     }
@@ -217,7 +217,7 @@ mod tests {
 
     #[test]
     fn test_decode_orr_immediate() {
-        // ORR r0, r1, #10, setting flags.
+        // ORR r0, r1, #10, not setting flags.
         let instruction = u32::from_le_bytes([0x0A, 0x00, 0x81, 0xE3]);
         let decoded = decode_arm(instruction);
         assert_eq!(
@@ -226,7 +226,7 @@ mod tests {
                 rd: 0,
                 rn: 1,
                 imm12: 10,
-                set_flags: true
+                set_flags: false
             }
         );
     }
@@ -259,7 +259,7 @@ mod tests {
                 rd: 0,
                 rn: 1,
                 imm12: 5,
-                set_flags: true
+                set_flags: false
             }
         )
     }
@@ -292,7 +292,7 @@ mod tests {
                 rd: 0,
                 rn: 1,
                 imm12: 10,
-                set_flags: true
+                set_flags: false
             }
         );
     }
@@ -323,15 +323,15 @@ mod tests {
                 rd: 1,
                 rn: 2,
                 imm12: 5,
-                set_flags: true
+                set_flags: false
             }
         );
 
         let mut cpu = Cpu::new();
-        cpu.cpu_state.set_register(2, 0b1010); // R2 = 10
+        cpu.cpu_state.set_register(2, 0b1010).unwrap(); // R2 = 10
         cpu.eor_immediate(1, 2, 5, false); // R1 = R2 ^ 5
 
-        assert_eq!(cpu.cpu_state.get_register(1), 0b1010 ^ 0b0101); // 10 ^ 5 = 15 (0b1111)
+        assert_eq!(cpu.cpu_state.get_register(1).unwrap(), 0b1010 ^ 0b0101); // 10 ^ 5 = 15 (0b1111)
     }
     #[test]
     fn test_eor_register_with_s() {
@@ -350,11 +350,11 @@ mod tests {
         );
 
         let mut cpu = Cpu::new();
-        cpu.cpu_state.set_register(2, 0b1100); // R2 = 12
-        cpu.cpu_state.set_register(3, 0b1010); // R3 = 10
+        cpu.cpu_state.set_register(2, 0b1100).unwrap(); // R2 = 12
+        cpu.cpu_state.set_register(3, 0b1010).unwrap(); // R3 = 10
         cpu.eor_register(1, 2, 3, ShiftType::LSL, 0, true); // R1 = R2 ^ R3
 
-        assert_eq!(cpu.cpu_state.get_register(1), 0b1100 ^ 0b1010); // 12 ^ 10 = 6 (0b0110)
+        assert_eq!(cpu.cpu_state.get_register(1).unwrap(), 0b1100 ^ 0b1010); // 12 ^ 10 = 6 (0b0110)
                                                                     // You'll also need to assert the flags here based on the result (e.g., Z flag)
         assert_eq!(cpu.cpu_state.CPSR.is_zero(), false); // Result is not zero
         assert_eq!(cpu.cpu_state.CPSR.is_negative(), false); // MSB is 0
@@ -374,7 +374,7 @@ mod tests {
                 rd: 0,
                 rn: 1,
                 imm12: 7,
-                set_flags: true
+                set_flags: false
             }
         );
     }
@@ -444,7 +444,7 @@ mod tests {
     }
     #[test]
     fn test_decode_mvn_immediate() {
-        // MVN R0, #5 (setting flags)
+        // MVN R0, #5 (not setting flags)
         let instruction: u32 = u32::from_le_bytes([0x05, 0x00, 0xE0, 0xE3]);
         let decoded = decode_arm(instruction);
         assert_eq!(
@@ -452,7 +452,7 @@ mod tests {
             Instruction::MvnImmediate {
                 rd: 0,
                 imm12: 5,
-                set_flags: true
+                set_flags: false
             }
         );
     }
@@ -524,11 +524,11 @@ mod tests {
 
         let mut cpu = Cpu::new();
         cpu.cpu_state.CPSR.set_carry(true);
-        cpu.cpu_state.set_register(2, 3); // R2 = 3
-        cpu.cpu_state.set_register(3, 10); // R3 = 10
+        cpu.cpu_state.set_register(2, 3).unwrap(); // R2 = 3
+        cpu.cpu_state.set_register(3, 10).unwrap(); // R3 = 10
         cpu.rsc_register(1, 2, 3, ShiftType::LSL, 0, false); // R1 = R3 - R2 - !C = 10 - 3 - 0 = 7
 
-        assert_eq!(cpu.cpu_state.get_register(1), 7);
+        assert_eq!(cpu.cpu_state.get_register(1).unwrap(), 7);
     }
 
     #[test]
@@ -549,11 +549,11 @@ mod tests {
 
         let mut cpu = Cpu::new();
         cpu.cpu_state.CPSR.set_carry(false);
-        cpu.cpu_state.set_register(2, 10); // R2 = 10
-        cpu.cpu_state.set_register(3, 1); // R3 = 1
+        cpu.cpu_state.set_register(2, 10).unwrap(); // R2 = 10
+        cpu.cpu_state.set_register(3, 1).unwrap(); // R3 = 1
         cpu.rsc_register(1, 2, 3, ShiftType::LSL, 0, true); // R1 = R3 - R2 - !C = 1 - 10 - 1 = -10
         cpu.cpu_state.CPSR.display_all_flags();
-        assert_eq!(cpu.cpu_state.get_register(1) as i32, -10);
+        assert_eq!(cpu.cpu_state.get_register(1).unwrap() as i32, -10);
         assert_eq!(cpu.cpu_state.CPSR.is_zero(), false);
         assert_eq!(cpu.cpu_state.CPSR.is_negative(), true);
         assert_eq!(cpu.cpu_state.CPSR.is_carry(), false);
@@ -573,7 +573,7 @@ mod tests {
         //  - imm24: 6 (i.e., 0x000006)
         // In little-endian, the bytes are: [0x06, 0x00, 0x00, 0xEA].
         let branch_instruction: [u8; 4] = [0x06, 0x00, 0x00, 0xEA];
-        memory.write_bytes(0, &branch_instruction);
+        memory.write_bytes(0, &branch_instruction).unwrap();
 
         // Write the halt instruction (0xFFFFFFFF) at the branch target address.
         // As calculated below, for imm24 = 6:
@@ -583,16 +583,16 @@ mod tests {
         // Then target_pc = 4 + 24 + 4 = 32.
         // Write halt (0xFFFFFFFF) starting at address 32.
         let halt_instruction: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
-        memory.write_bytes(32, &halt_instruction);
+        memory.write_bytes(32, &halt_instruction).unwrap();
 
         // Create a new CPU instance.
         let mut cpu = Cpu::new();
 
         // Initialize the PC (register 15) to 0.
-        cpu.cpu_state.set_register(15, 0);
+        cpu.cpu_state.set_register(15, 0).unwrap();
 
         // Run the program.
-        cpu.run_program(&mut memory);
+        cpu.run_program(&mut memory).unwrap();
 
         // When fetching from memory, fetch_instruction increments PC by 4.
         // So when the branch is executed, current PC = 4.
@@ -603,7 +603,7 @@ mod tests {
         // We then assert that PC is 32.
         let expected_pc = 32;
         assert_eq!(
-            cpu.cpu_state.get_register(15),
+            cpu.cpu_state.get_register(15).unwrap(),
             expected_pc,
             "Branch target PC should be {}",
             expected_pc
@@ -619,30 +619,30 @@ mod tests {
         // BL is encoded as 0xEB000006.
         // In little-endian, the bytes are: [0x06, 0x00, 0x00, 0xEB].
         let branch_link_instruction: [u8; 4] = [0x06, 0x00, 0x00, 0xEB];
-        memory.write_bytes(0, &branch_link_instruction);
+        memory.write_bytes(0, &branch_link_instruction).unwrap();
 
         // Write the halt instruction at the expected branch target.
         // Using the same calculation: target PC = 4 (after fetch) + 24 + 4 = 32.
         let halt_instruction: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
-        memory.write_bytes(32, &halt_instruction);
+        memory.write_bytes(32, &halt_instruction).unwrap();
 
         let mut cpu = Cpu::new();
-        cpu.cpu_state.set_register(15, 0);
+        cpu.cpu_state.set_register(15, 0).unwrap();
 
-        cpu.run_program(&mut memory);
+        cpu.run_program(&mut memory).unwrap();
 
         // For BL, in addition to updating PC to 32, the link register (r14) should be set.
         // Convention: r14 receives the PC value before the branch (which is 4).
         let expected_pc = 32;
         let expected_lr = 4;
         assert_eq!(
-            cpu.cpu_state.get_register(15),
+            cpu.cpu_state.get_register(15).unwrap(),
             expected_pc,
             "Branch with link target PC should be {}",
             expected_pc
         );
         assert_eq!(
-            cpu.cpu_state.get_register(14),
+            cpu.cpu_state.get_register(14).unwrap(),
             expected_lr,
             "Link register (r14) should be {} for BL",
             expected_lr
@@ -671,14 +671,14 @@ mod tests {
         let mut cpu = Cpu::new();
         // Set R0 to a target address with the LSB set (indicating Thumb mode).
         // For example, 0x08000001 means the branch target should be 0x08000000 with Thumb mode.
-        cpu.cpu_state.set_register(0, 0x08000001);
+        cpu.cpu_state.set_register(0, 0x08000001).unwrap();
         // Initialize the PC to a dummy starting address.
-        cpu.cpu_state.set_register(15, 0x08000000);
+        cpu.cpu_state.set_register(15, 0x08000000).unwrap();
         // Execute BX using register 0.
         cpu.branch_exchange(0);
         // After executing BX, the PC should be set to 0x08000000 (with the LSB cleared)
         // and the CPSR Thumb flag should be set.
-        assert_eq!(cpu.cpu_state.get_register(15), 0x08000000);
+        assert_eq!(cpu.cpu_state.get_register(15).unwrap(), 0x08000000);
         assert!(cpu.cpu_state.CPSR.is_thumb_state());
     }
 
@@ -688,15 +688,15 @@ mod tests {
         let mut cpu = Cpu::new();
         // Set R1 to a target address with LSB set (to indicate Thumb mode).
         // This means the actual branch target should be 0x08001000.
-        cpu.cpu_state.set_register(1, 0x08001001);
+        cpu.cpu_state.set_register(1, 0x08001001).unwrap();
         // Initialize the PC to a known value.
-        cpu.cpu_state.set_register(15, 0x08000000);
+        cpu.cpu_state.set_register(15, 0x08000000).unwrap();
         // Execute BLX using register 1.
         cpu.branch_link_exchange(1);
         // Verify that the Link Register (R14) is set to the old PC value.
-        assert_eq!(cpu.cpu_state.get_register(14), 0x08000000);
+        assert_eq!(cpu.cpu_state.get_register(14).unwrap(), 0x08000000);
         // Verify that the PC is updated to the target address with LSB cleared (0x08001000).
-        assert_eq!(cpu.cpu_state.get_register(15), 0x08001000);
+        assert_eq!(cpu.cpu_state.get_register(15).unwrap(), 0x08001000);
         // Since the LSB was set, the Thumb mode flag should be on.
         assert!(cpu.cpu_state.CPSR.is_thumb_state());
     }
@@ -707,23 +707,23 @@ mod tests {
         let mut cpu = Cpu::new();
 
         // Set base register R1 = 100.
-        cpu.cpu_state.set_register(1, 100);
+        cpu.cpu_state.set_register(1, 100).unwrap();
 
         // Encode LDR: 0xE5B12008.
         // Instruction at address 0.
-        memory.write_word(0, 0xE5B12008);
+        memory.write_word(0, 0xE5B12008).unwrap();
         // Place HALT at address 4.
-        memory.write_word( 4, HALT);
+        memory.write_word( 4, HALT).unwrap();
 
         // At effective address: 100+8 = 108, store 0xDEADBEEF.
-        memory.write_word( 108, 0xDEADBEEF);
+        memory.write_word( 108, 0xDEADBEEF).unwrap();
 
         // Run program.
-        cpu.run_program(&mut memory);
+        cpu.run_program(&mut memory).unwrap();
 
         // Expect R2 = 0xDEADBEEF, and R1 updated to 108.
-        assert_eq!(cpu.cpu_state.get_register(2), 0xDEADBEEF);
-        assert_eq!(cpu.cpu_state.get_register(1), 108);
+        assert_eq!(cpu.cpu_state.get_register(2).unwrap(), 0xDEADBEEF);
+        assert_eq!(cpu.cpu_state.get_register(1).unwrap(), 108);
     }
 
     // LDR Post-Indexed, add mode with no write-back.
@@ -733,20 +733,20 @@ mod tests {
         let mut cpu = Cpu::new();
 
         // Set base register R1 = 200.
-        cpu.cpu_state.set_register(1, 200);
+        cpu.cpu_state.set_register(1, 200).unwrap();
 
         // Encode LDR: 0xE4912010 (P=0, W=0, offset = 16).
-        memory.write_word(0, 0xE4912010);
-        memory.write_word(4, HALT);
+        memory.write_word(0, 0xE4912010).unwrap();
+        memory.write_word(4, HALT).unwrap();
 
         // In post-index, effective address = base (200). Place 0xCAFEBABE at address 200.
-        memory.write_word( 200, 0xCAFEBABE);
+        memory.write_word( 200, 0xCAFEBABE).unwrap();
 
-        cpu.run_program(&mut memory);
+        cpu.run_program(&mut memory).unwrap();
 
         // Expect R2 = 0xCAFEBABE and R1 remains 200.
-        assert_eq!(cpu.cpu_state.get_register(2), 0xCAFEBABE);
-        assert_eq!(cpu.cpu_state.get_register(1), 200);
+        assert_eq!(cpu.cpu_state.get_register(2).unwrap(), 0xCAFEBABE);
+        assert_eq!(cpu.cpu_state.get_register(1).unwrap(), 200);
     }
 
     // LDM Pre-Indexed, add mode with write-back.
@@ -756,27 +756,27 @@ mod tests {
         let mut cpu = Cpu::new();
 
         // Set base register R5 = 300.
-        cpu.cpu_state.set_register(5, 300);
+        cpu.cpu_state.set_register(5, 300).unwrap();
 
         // Encode LDM: 0xE9B50015.
         // This loads registers as indicated by bits in register_list = 0x0015 (R0, R2, R4).
-        memory.write_word( 0, 0xE9B50015);
-        memory.write_word(4, HALT);
+        memory.write_word( 0, 0xE9B50015).unwrap();
+        memory.write_word(4, HALT).unwrap();
 
         // Pre-index: effective starting address = 300 + 4 = 304.
         // Write values at sequential addresses:
-        memory.write_word(304, 0x11111111); // for R0
-        memory.write_word(308, 0x22222222); // for R2
-        memory.write_word(312, 0x33333333); // for R4
+        memory.write_word(304, 0x11111111).unwrap(); // for R0
+        memory.write_word(308, 0x22222222).unwrap(); // for R2
+        memory.write_word(312, 0x33333333).unwrap(); // for R4
 
-        cpu.run_program(&mut memory);
+        cpu.run_program(&mut memory).unwrap();
 
         // Verify loaded registers.
-        assert_eq!(cpu.cpu_state.get_register(0), 0x11111111);
-        assert_eq!(cpu.cpu_state.get_register(2), 0x22222222);
-        assert_eq!(cpu.cpu_state.get_register(4), 0x33333333);
+        assert_eq!(cpu.cpu_state.get_register(0).unwrap(), 0x11111111);
+        assert_eq!(cpu.cpu_state.get_register(2).unwrap(), 0x22222222);
+        assert_eq!(cpu.cpu_state.get_register(4).unwrap(), 0x33333333);
         // Write-back updates base R5: 300 + (3 * 4) = 312.
-        assert_eq!(cpu.cpu_state.get_register(5), 312);
+        assert_eq!(cpu.cpu_state.get_register(5).unwrap(), 312);
     }
 
     //LDM Post-Indexed, subtract mode with no write-back.
@@ -786,28 +786,28 @@ mod tests {
         let mut cpu = Cpu::new();
 
         // Set base register R6 = 500.
-        cpu.cpu_state.set_register(6, 500);
+        cpu.cpu_state.set_register(6, 500).unwrap();
 
         // Encode LDM (load multiple): 0xE816002A.
         // This chooses post-index (P=0), subtract mode (U=0), no write-back (W=0),
         // and loads registers from register_list = 0x002A (R1, R3, R5).
-        memory.write_word( 0, 0xE816002A);
-        memory.write_word( 4, HALT);
+        memory.write_word( 0, 0xE816002A).unwrap();
+        memory.write_word( 4, HALT).unwrap();
 
         // In post-index mode, effective address is the original base (500).
         // Write memory for registers sequentially:
-        memory.write_word( 500, 0xAAAAAAAA); // for R1
-        memory.write_word(504, 0xBBBBBBBB); // for R3
-        memory.write_word( 508, 0xCCCCCCCC); // for R5
+        memory.write_word( 500, 0xAAAAAAAA).unwrap(); // for R1
+        memory.write_word(504, 0xBBBBBBBB).unwrap(); // for R3
+        memory.write_word( 508, 0xCCCCCCCC).unwrap(); // for R5
 
-        cpu.run_program(&mut memory);
+        cpu.run_program(&mut memory).unwrap();
 
         // Verify registers loaded.
-        assert_eq!(cpu.cpu_state.get_register(1), 0xAAAAAAAA);
-        assert_eq!(cpu.cpu_state.get_register(3), 0xBBBBBBBB);
-        assert_eq!(cpu.cpu_state.get_register(5), 0xCCCCCCCC);
+        assert_eq!(cpu.cpu_state.get_register(1).unwrap(), 0xAAAAAAAA);
+        assert_eq!(cpu.cpu_state.get_register(3).unwrap(), 0xBBBBBBBB);
+        assert_eq!(cpu.cpu_state.get_register(5).unwrap(), 0xCCCCCCCC);
         // Base register R6 remains unchanged.
-        assert_eq!(cpu.cpu_state.get_register(6), 500);
+        assert_eq!(cpu.cpu_state.get_register(6).unwrap(), 500);
     }
 
     // LDRB Pre-Indexed, Add mode with write-back.
@@ -817,7 +817,7 @@ mod tests {
         let mut cpu = Cpu::new();
 
         // Set the base register R1 = 100.
-        cpu.cpu_state.set_register(1, 100);
+        cpu.cpu_state.set_register(1, 100).unwrap();
 
         // The LDRB instruction: 0xED703020 encodes:
         //   - Condition: 0xE,
@@ -830,18 +830,18 @@ mod tests {
         //   - L = 1 (load)
         //   - Rn = 1 and Rt = 2, offset = 0x20.
         // Effective address = 100 + 0x20 = 132.
-        memory.write_word(0, 0xED703020);
-        memory.write_word(4, HALT);
+        memory.write_word(0, 0xED703020).unwrap();
+        memory.write_word(4, HALT).unwrap();
 
         // At effective address 132, place a byte value (e.g., 0xAB).
-        memory.write_bytes(132, &[0xAB]);
+        memory.write_bytes(132, &[0xAB]).unwrap();
 
-        cpu.run_program(&mut memory);
+        cpu.run_program(&mut memory).unwrap();
 
         // Verify: R2 should hold 0x000000AB (zero-extended)
         // and write-back updates R1 to 132.
-        assert_eq!(cpu.cpu_state.get_register(2), 0xAB);
-        assert_eq!(cpu.cpu_state.get_register(1), 132);
+        assert_eq!(cpu.cpu_state.get_register(2).unwrap(), 0xAB);
+        assert_eq!(cpu.cpu_state.get_register(1).unwrap(), 132);
     }
 
     // LDRB Post-Indexed, Add mode with no write-back.
@@ -851,23 +851,23 @@ mod tests {
         let mut cpu = Cpu::new();
 
         // Set the base register R1 = 200.
-        cpu.cpu_state.set_register(1, 200);
+        cpu.cpu_state.set_register(1, 200).unwrap();
 
         // LDRB post-index: same fields as before, but with:
         //   P = 0 (post-index) and W = 0 (no write-back).
         // Encoded instruction: 0xED512020.
         // Effective address = base = 200.
-        memory.write_word(0, 0xED512020);
-        memory.write_word(4, HALT);
+        memory.write_word(0, 0xED512020).unwrap();
+        memory.write_word(4, HALT).unwrap();
 
         // Write a byte (e.g., 0xCD) at address 200.
-        memory.write_bytes(200, &[0xCD]);
+        memory.write_bytes(200, &[0xCD]).unwrap();
 
-        cpu.run_program(&mut memory);
+        cpu.run_program(&mut memory).unwrap();
 
         // Verify: R2 should hold 0x000000CD and R1 remains 200.
-        assert_eq!(cpu.cpu_state.get_register(2), 0xCD);
-        assert_eq!(cpu.cpu_state.get_register(1), 200);
+        assert_eq!(cpu.cpu_state.get_register(2).unwrap(), 0xCD);
+        assert_eq!(cpu.cpu_state.get_register(1).unwrap(), 200);
     }
 
     // LDRD Pre-Indexed, Add mode with write-back.
@@ -877,7 +877,7 @@ mod tests {
         let mut cpu = Cpu::new();
 
         // Set the base register R3 = 200.
-        cpu.cpu_state.set_register(3, 200);
+        cpu.cpu_state.set_register(3, 200).unwrap();
 
         // LDRD instruction: 0xED3340A0 encodes:
         //   - Condition: 0xE,
@@ -886,20 +886,20 @@ mod tests {
         //   - W = 1 (write-back), L = 1 (load),
         //   - Rn = 3, Rt = 4, offset = 0xA0.
         // Effective address = 200 + 0xA0 (160) = 360.
-        memory.write_word(0, 0xED3340A0);
-        memory.write_word(4, HALT);
+        memory.write_word(0, 0xED3340A0).unwrap();
+        memory.write_word(4, HALT).unwrap();
 
         // Write the lower word at address 360 and the upper word at address 364.
-        memory.write_word(360, 0xDEADBEEF);
-        memory.write_word(364, 0xFEEDFACE);
+        memory.write_word(360, 0xDEADBEEF).unwrap();
+        memory.write_word(364, 0xFEEDFACE).unwrap();
 
-        cpu.run_program(&mut memory);
+        cpu.run_program(&mut memory).unwrap();
 
         // Verify: R4 should be 0xDEADBEEF, R5 should be 0xFEEDFACE,
         // and write-back updates R3 to 360.
-        assert_eq!(cpu.cpu_state.get_register(4), 0xDEADBEEF);
-        assert_eq!(cpu.cpu_state.get_register(5), 0xFEEDFACE);
-        assert_eq!(cpu.cpu_state.get_register(3), 360);
+        assert_eq!(cpu.cpu_state.get_register(4).unwrap(), 0xDEADBEEF);
+        assert_eq!(cpu.cpu_state.get_register(5).unwrap(), 0xFEEDFACE);
+        assert_eq!(cpu.cpu_state.get_register(3).unwrap(), 360);
     }
 
     // Test 4: LDRD Post-Indexed, Subtract mode with no write-back.
@@ -909,26 +909,66 @@ mod tests {
         let mut cpu = Cpu::new();
 
         // Set the base register R3 = 400.
-        cpu.cpu_state.set_register(3, 400);
+        cpu.cpu_state.set_register(3, 400).unwrap();
 
         // LDRD post-index: based on our previous LDRD encoding (0xED3340A0),
         // if we clear P (pre-index) and W (write-back) bits we get:
         //   0xED3340A0 - 0x01000000 (P) - 0x00200000 (W) = 0xEC1340A0.
         // Effective address = base = 400.
-        memory.write_word(0, 0xEC1340A0);
-        memory.write_word(4, HALT);
+        memory.write_word(0, 0xEC1340A0).unwrap();
+        memory.write_word(4, HALT).unwrap();
 
         // At address 400, write lower word and at 404, write upper word.
-        memory.write_word(400, 0xAAAAAAAA);
-        memory.write_word(404, 0xBBBBBBBB);
+        memory.write_word(400, 0xAAAAAAAA).unwrap();
+        memory.write_word(404, 0xBBBBBBBB).unwrap();
 
-        cpu.run_program(&mut memory);
+        cpu.run_program(&mut memory).unwrap();
 
         // Verify: R4 should be 0xAAAAAAAA, R5 = 0xBBBBBBBB,
         // and since write-back is disabled, R3 remains 400.
-        assert_eq!(cpu.cpu_state.get_register(4), 0xAAAAAAAA);
-        assert_eq!(cpu.cpu_state.get_register(5), 0xBBBBBBBB);
-        assert_eq!(cpu.cpu_state.get_register(3), 400);
+        assert_eq!(cpu.cpu_state.get_register(4).unwrap(), 0xAAAAAAAA);
+        assert_eq!(cpu.cpu_state.get_register(5).unwrap(), 0xBBBBBBBB);
+        assert_eq!(cpu.cpu_state.get_register(3).unwrap(), 400);
+    }
+
+    #[test]
+    fn test_spsr_only_restored_by_flag_setting_writes_to_pc() {
+        let mut cpu = Cpu::new();
+        cpu.cpu_state.CPSR.value = 0x0000_001F; // System mode, flags clear
+        cpu.cpu_state.SPSR.value = 0xF000_0012; // IRQ mode, NZCV set
+
+        // ADD r0, r1, #1 and MOV pc, lr don't touch CPSR
+        cpu.add_immediate(0, 1, 1, false);
+        assert_eq!(cpu.cpu_state.get_register(0).unwrap(), 1);
+        assert_eq!(cpu.cpu_state.CPSR.value, 0x0000_001F);
+        cpu.cpu_state.set_register(14, 0x100).unwrap();
+        cpu.mov_register(15, 14, ShiftType::LSL, 0, false);
+        assert_eq!(cpu.cpu_state.CPSR.value, 0x0000_001F);
+
+        // MOVS pc, lr returns from an exception
+        cpu.mov_register(15, 14, ShiftType::LSL, 0, true);
+        assert_eq!(cpu.cpu_state.CPSR.value, 0xF000_0012);
+    }
+
+    #[test]
+    fn test_immediate_writes_to_pc_through_step() {
+        let mut memory = Memory::new(256);
+        memory.write_word(0x00, 0xE3A0F040).unwrap(); // MOV pc, #0x40
+        memory.write_word(0x40, 0xE281F080).unwrap(); // ADD pc, r1, #0x80
+        memory.write_word(0x80, 0xE3B0F000).unwrap(); // MOVS pc, #0
+        let mut cpu = Cpu::new();
+        cpu.cpu_state.CPSR.value = 0x0000_0013; // Supervisor mode, flags clear
+        cpu.cpu_state.SPSR.value = 0xF000_001F; // System mode, NZCV set
+
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(cpu.cpu_state.get_register(15).unwrap(), 0x40);
+        assert_eq!(cpu.cpu_state.CPSR.value, 0x0000_0013);
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(cpu.cpu_state.get_register(15).unwrap(), 0x80);
+        assert_eq!(cpu.cpu_state.CPSR.value, 0x0000_0013);
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(cpu.cpu_state.get_register(15).unwrap(), 0);
+        assert_eq!(cpu.cpu_state.CPSR.value, 0xF000_001F);
     }
 
     #[test]
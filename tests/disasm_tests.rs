@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod tests {
+    use emulator::cpu_instructions::instruction_decoding::{decode_arm, Instruction};
+    use emulator::disasm::{disassemble_arm, disassemble_thumb};
+
+    const ROM: u32 = 0x08000000;
+
+    #[test]
+    fn test_arm_data_processing() {
+        let cases = [
+            (0xE3A0507B, "mov r5, #123"),
+            (0xE1A03005, "mov r3, r5"),
+            (0x10912003, "addsne r2, r1, r3"),
+            (0xE0812103, "add r2, r1, r3, lsl #2"),
+            (0xE1A00231, "mov r0, r1, lsr r2"),
+            (0xE1A00061, "mov r0, r1, rrx"),
+            (0xE3530000, "cmp r3, #0"),
+            (0xE3A00301, "mov r0, #0x4000000"),
+            (0xE0020391, "mul r2, r1, r3"),
+            (0xE0C10293, "smull r0, r1, r3, r2"),
+            (0xE10F0000, "mrs r0, cpsr"),
+            (0xE129F000, "msr cpsr_fc, r0"),
+        ];
+        for (opcode, text) in cases {
+            assert_eq!(disassemble_arm(opcode, 0), text, "{:08X}", opcode);
+        }
+        assert_eq!(disassemble_arm(0xE28F0004, 0x100), "add r0, pc, #4 ; 0x0000010C");
+    }
+
+    #[test]
+    fn test_arm_branches_and_transfers() {
+        let cases = [
+            (0xEAFFFFFE, "b 0x08000000"),
+            (0xEB000010, "bl 0x08000048"),
+            (0x0A000000, "beq 0x08000008"),
+            (0xE12FFF1E, "bx lr"),
+            (0xE59F0008, "ldr r0, [pc, #8] ; 0x08000010"),
+            (0xE5912004, "ldr r2, [r1, #4]"),
+            (0xE4912004, "ldr r2, [r1], #4"),
+            (0xE5B12004, "ldr r2, [r1, #4]!"),
+            (0xE5512004, "ldrb r2, [r1, #-4]"),
+            (0xE7912103, "ldr r2, [r1, r3, lsl #2]"),
+            (0xE5812000, "str r2, [r1]"),
+            (0xE1D120B2, "ldrh r2, [r1, #2]"),
+            (0xE1010092, "swp r0, r2, [r1]"),
+            (0xE92D4010, "push {r4, lr}"),
+            (0xE8BD500F, "pop {r0, r1, r2, r3, r12, lr}"),
+            (0xE8910006, "ldm r1, {r1, r2}"),
+            (0xE9A10006, "stmib r1!, {r1, r2}"),
+            (0xEF050000, "svc 0x00050000"),
+            (0xE7F000F0, ".word 0xE7F000F0"),
+        ];
+        for (opcode, text) in cases {
+            assert_eq!(disassemble_arm(opcode, ROM), text, "{:08X}", opcode);
+        }
+    }
+
+    #[test]
+    fn test_thumb() {
+        let cases: [(u16, &str); 15] = [
+            (0x2001, "movs r0, #1"),
+            (0x0088, "lsls r0, r1, #2"),
+            (0x1888, "adds r0, r1, r2"),
+            (0x4348, "muls r0, r1"),
+            (0x4770, "bx lr"),
+            (0x46C0, "nop"),
+            (0x6848, "ldr r0, [r1, #4]"),
+            (0x5888, "ldr r0, [r1, r2]"),
+            (0xB510, "push {r4, lr}"),
+            (0xBD10, "pop {r4, pc}"),
+            (0xB082, "sub sp, #8"),
+            (0xD0FE, "beq 0x08000000"),
+            (0xE7FE, "b 0x08000000"),
+            (0xDF05, "svc #5"),
+            (0xC903, "ldmia r1, {r0, r1}"),
+        ];
+        for (opcode, text) in cases {
+            assert_eq!(disassemble_thumb(opcode, 0, ROM), (text.to_string(), 2), "{:04X}", opcode);
+        }
+        assert_eq!(
+            disassemble_thumb(0x4801, 0, ROM + 2),
+            ("ldr r0, [pc, #4] ; 0x08000008".to_string(), 2)
+        );
+        assert_eq!(disassemble_thumb(0xF000, 0xF808, ROM), ("bl 0x08000014".to_string(), 4));
+        assert_eq!(disassemble_thumb(0xF000, 0x2001, ROM), (".short 0xF000".to_string(), 2));
+    }
+
+    #[test]
+    fn test_instruction_display() {
+        assert_eq!(decode_arm(0xE1A03005).to_string(), "mov r3, r5");
+        assert_eq!(decode_arm(0xEA000006).to_string(), "b .+32");
+        assert_eq!(decode_arm(0xEAFFFFFE).to_string(), "b .+0");
+        assert_eq!(decode_arm(0xE8BD500F).to_string(), "pop {r0, r1, r2, r3, r12, lr}");
+        // The S suffix follows bit 20 for immediate operands too, like disassemble_arm
+        for (opcode, text) in [(0xE2800001, "add r0, r0, #1"), (0xE2900001, "adds r0, r0, #1")] {
+            assert_eq!(decode_arm(opcode).to_string(), text);
+            assert_eq!(disassemble_arm(opcode, 0), text);
+        }
+        let ldr = Instruction::Ldr {
+            rt: 2,
+            rn: 1,
+            offset: 4,
+            pre_index: false,
+            add: false,
+            write_back: false,
+        };
+        assert_eq!(ldr.to_string(), "ldr r2, [r1], #-4");
+        assert_eq!(Instruction::Unknown(0xE5812000).to_string(), "str r2, [r1]");
+    }
+}
@@ -0,0 +1,151 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{make_cartridge, TempFile};
+    use emulator::bus::Bus;
+    use emulator::cartridge::eeprom::EepromSize;
+    use emulator::cartridge::save::SaveType;
+
+    const BUFFER: u32 = 0x02000000;
+    const EEPROM: u32 = 0x0D000000;
+
+    fn bus_with_eeprom(size: EepromSize) -> Bus {
+        let mut cartridge = make_cartridge();
+        cartridge.set_save_type(SaveType::Eeprom(size));
+        Bus::with_cartridge(cartridge)
+    }
+
+    // DMA3, 16 bit units, both addresses incrementing, immediate start.
+    fn dma3(bus: &mut Bus, source: u32, destination: u32, count: u16) {
+        bus.write_word(0x040000D4, source);
+        bus.write_word(0x040000D8, destination);
+        bus.write_halfword(0x040000DC, count);
+        bus.write_halfword(0x040000DE, 0x8000);
+    }
+
+    fn push_bits(bits: &mut Vec<u16>, value: u64, width: u32) {
+        for i in (0..width).rev() {
+            bits.push(((value >> i) & 1) as u16);
+        }
+    }
+
+    fn write_buffer(bus: &mut Bus, bits: &[u16]) {
+        for (i, bit) in bits.iter().enumerate() {
+            bus.write_halfword(BUFFER + i as u32 * 2, *bit);
+        }
+    }
+
+    fn eeprom_write(bus: &mut Bus, address: u64, address_bits: u32, data: u64) {
+        let mut bits = vec![];
+        push_bits(&mut bits, 0b10, 2);
+        push_bits(&mut bits, address, address_bits);
+        push_bits(&mut bits, data, 64);
+        bits.push(0);
+        write_buffer(bus, &bits);
+        dma3(bus, BUFFER, EEPROM, bits.len() as u16);
+    }
+
+    fn eeprom_read(bus: &mut Bus, address: u64, address_bits: u32) -> u64 {
+        let mut bits = vec![];
+        push_bits(&mut bits, 0b11, 2);
+        push_bits(&mut bits, address, address_bits);
+        bits.push(0);
+        write_buffer(bus, &bits);
+        dma3(bus, BUFFER, EEPROM, bits.len() as u16);
+
+        let reply = BUFFER + 0x1000;
+        dma3(bus, EEPROM, reply, 68);
+        let mut value = 0u64;
+        for i in 4..68 {
+            value = (value << 1) | (bus.read_halfword(reply + i * 2) & 1) as u64;
+        }
+        value
+    }
+
+    #[test]
+    fn test_eeprom_4k_write_then_read() {
+        let mut bus = bus_with_eeprom(EepromSize::Kbit4);
+        eeprom_write(&mut bus, 3, 6, 0x0123456789ABCDEF);
+        assert_eq!(eeprom_read(&mut bus, 3, 6), 0x0123456789ABCDEF);
+        // Other blocks stay erased
+        assert_eq!(eeprom_read(&mut bus, 4, 6), u64::MAX);
+    }
+
+    #[test]
+    fn test_eeprom_busy_then_ready_after_write() {
+        let mut bus = bus_with_eeprom(EepromSize::Kbit64);
+        eeprom_write(&mut bus, 0x10, 14, 42);
+        assert_eq!(bus.read_halfword(EEPROM) & 1, 0);
+        let mut polls = 0;
+        while bus.read_halfword(EEPROM) & 1 == 0 {
+            polls += 1;
+            assert!(polls < 1000, "EEPROM never became ready");
+        }
+        assert_eq!(eeprom_read(&mut bus, 0x10, 14), 42);
+    }
+
+    #[test]
+    fn test_size_detected_from_first_dma() {
+        let mut bus = bus_with_eeprom(EepromSize::Unknown);
+        eeprom_write(&mut bus, 1, 6, 0xAA);
+        let cartridge = bus.cartridge.as_ref().unwrap();
+        assert_eq!(cartridge.save_type(), SaveType::Eeprom(EepromSize::Kbit4));
+        assert_eq!(cartridge.save_data().len(), 512);
+
+        let mut bus = bus_with_eeprom(EepromSize::Unknown);
+        eeprom_read(&mut bus, 1, 14);
+        let cartridge = bus.cartridge.as_ref().unwrap();
+        assert_eq!(cartridge.save_type(), SaveType::Eeprom(EepromSize::Kbit64));
+        assert_eq!(cartridge.save_data().len(), 8192);
+    }
+
+    #[test]
+    fn test_eeprom_sav_layout() {
+        let file = TempFile::new("eeprom.sav");
+        {
+            let mut bus = bus_with_eeprom(EepromSize::Kbit4);
+            bus.cartridge.as_mut().unwrap().attach_save_file(&file.0).unwrap();
+            eeprom_write(&mut bus, 2, 6, 0x1122334455667788);
+        }
+        let bytes = std::fs::read(&file.0).unwrap();
+        assert_eq!(bytes.len(), 512);
+        // Block 2 starts at byte 16, first bit sent is the MSB of the first byte
+        assert_eq!(&bytes[16..24], &[0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88]);
+
+        // A 512 byte file settles the size on load
+        let mut cartridge = make_cartridge();
+        cartridge.set_save_type(SaveType::Eeprom(EepromSize::Unknown));
+        cartridge.attach_save_file(&file.0).unwrap();
+        assert_eq!(cartridge.save_type(), SaveType::Eeprom(EepromSize::Kbit4));
+    }
+
+    #[test]
+    fn test_dma_copies_words() {
+        let mut bus = Bus::new();
+        for i in 0..4 {
+            bus.write_word(0x02000000 + i * 4, 0x1000 + i);
+        }
+        bus.write_word(0x040000D4, 0x02000000);
+        bus.write_word(0x040000D8, 0x03000000);
+        bus.write_halfword(0x040000DC, 4);
+        bus.write_halfword(0x040000DE, 0x8400); // 32 bit units
+        assert_eq!(bus.read_word(0x03000008), 0x1002);
+        // Non repeating channels disable themselves
+        assert_eq!(bus.read_halfword(0x040000DE) & 0x8000, 0);
+    }
+
+    #[test]
+    fn test_dma0_count_is_14_bits() {
+        let mut bus = Bus::new();
+        for i in 0..4 {
+            bus.write_halfword(0x02000000 + i * 2, 0x100 + i as u16);
+        }
+        bus.write_word(0x040000B0, 0x02000000);
+        bus.write_word(0x040000B4, 0x03000000);
+        bus.write_halfword(0x040000B8, 0x8002); // Bit 15 isn't part of the count
+        bus.write_halfword(0x040000BA, 0x8000);
+        assert_eq!(bus.read_halfword(0x03000002), 0x101);
+        assert_eq!(bus.read_halfword(0x03000004), 0);
+    }
+}
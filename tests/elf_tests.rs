@@ -0,0 +1,171 @@
+#[cfg(test)]
+mod tests {
+    use emulator::disasm::annotate;
+    use emulator::elf::{Elf, ElfError};
+    use emulator::symbols::SymbolTable;
+    use emulator::trace::{Category, TraceRecord};
+
+    const IWRAM: u32 = 0x03000000;
+
+    fn put16(data: &mut [u8], offset: usize, value: u16) {
+        data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    // A devkitARM-like ELF: 0x100 bytes of ROM with Thumb code at 0x080000C0, a word of
+    // IWRAM data and a symbol table, header not fixed up by gbafix.
+    fn make_elf(rom_address: u32) -> Vec<u8> {
+        let mut elf = vec![0u8; 0x2F0];
+        elf[0..4].copy_from_slice(b"\x7FELF");
+        elf[4] = 1; // 32-bit
+        elf[5] = 1; // Little-endian
+        elf[6] = 1;
+        put16(&mut elf, 0x10, 2); // Executable
+        put16(&mut elf, 0x12, 40); // ARM
+        put32(&mut elf, 0x18, 0x080000C1);
+        put32(&mut elf, 0x1C, 0x34);
+        put32(&mut elf, 0x20, 0x270);
+        put16(&mut elf, 0x28, 52);
+        put16(&mut elf, 0x2A, 32);
+        put16(&mut elf, 0x2C, 2);
+        put16(&mut elf, 0x2E, 40);
+        put16(&mut elf, 0x30, 3);
+        put16(&mut elf, 0x32, 2);
+
+        // PT_LOAD ROM and IWRAM
+        for (i, (offset, address, size)) in [(0x100, rom_address, 0x100), (0x200, IWRAM, 4)]
+            .into_iter()
+            .enumerate()
+        {
+            let header = 0x34 + i * 32;
+            put32(&mut elf, header, 1);
+            put32(&mut elf, header + 4, offset);
+            put32(&mut elf, header + 8, address);
+            put32(&mut elf, header + 12, address);
+            put32(&mut elf, header + 16, size);
+            put32(&mut elf, header + 20, size);
+        }
+        put16(&mut elf, 0x100 + 0xC0, 0x2001); // MOVS r0, #1
+        put32(&mut elf, 0x200, 0xDEADBEEF);
+
+        // Symbols: null, main (Thumb function), counter (object), $t (mapping symbol)
+        let symbols = [
+            (0, 0, 0, 0),
+            (1, 0x080000C1, 8, 0x12),
+            (6, IWRAM, 4, 0x11),
+            (14, 0x080000C0, 0, 0),
+        ];
+        for (i, (name, value, size, info)) in symbols.into_iter().enumerate() {
+            let symbol = 0x210 + i * 16;
+            put32(&mut elf, symbol, name);
+            put32(&mut elf, symbol + 4, value);
+            put32(&mut elf, symbol + 8, size);
+            elf[symbol + 12] = info;
+            put16(&mut elf, symbol + 14, if name == 0 { 0 } else { 1 });
+        }
+        elf[0x250..0x250 + 17].copy_from_slice(b"\0main\0counter\0$t\0");
+
+        // Sections: null, .symtab linked to .strtab
+        put32(&mut elf, 0x270 + 40 + 4, 2);
+        put32(&mut elf, 0x270 + 40 + 16, 0x210);
+        put32(&mut elf, 0x270 + 40 + 20, 64);
+        put32(&mut elf, 0x270 + 40 + 24, 2);
+        put32(&mut elf, 0x270 + 80 + 4, 3);
+        put32(&mut elf, 0x270 + 80 + 16, 0x250);
+        put32(&mut elf, 0x270 + 80 + 20, 17);
+        elf
+    }
+
+    #[test]
+    fn test_parse_segments_and_symbols() {
+        let elf = Elf::parse(&make_elf(0x08000000)).unwrap();
+        assert_eq!(elf.entry, 0x080000C1);
+        assert_eq!(elf.segments.len(), 2);
+        assert_eq!(elf.segments[1].address, IWRAM);
+        assert_eq!(elf.segments[1].data, 0xDEADBEEFu32.to_le_bytes());
+
+        // Thumb bit cleared, mapping symbols left out
+        assert_eq!(elf.symbols.len(), 2);
+        assert_eq!(elf.symbols.address_of("main"), Some(0x080000C0));
+        assert_eq!(
+            elf.symbols.label(0x080000C6).unwrap().to_string(),
+            "main+0x6"
+        );
+        // Past the end of a sized symbol
+        assert_eq!(elf.symbols.label(0x080000C8), None);
+        assert_eq!(elf.symbols.label(IWRAM).unwrap().to_string(), "counter");
+    }
+
+    #[test]
+    fn test_build_gba_loads_segments_and_entry() {
+        let elf = Elf::parse(&make_elf(0x08000000)).unwrap();
+        assert_eq!(elf.rom_image().unwrap().len(), 0x100);
+        let mut gba = elf.build_gba().unwrap();
+        assert_eq!(gba.bus.read_halfword(0x080000C0), 0x2001);
+        assert_eq!(gba.bus.read_word(IWRAM), 0xDEADBEEF);
+        assert_eq!(gba.cpu.cpu_state.get_register(15), Ok(0x080000C0));
+        assert!(gba.cpu.cpu_state.CPSR.is_thumb_state());
+    }
+
+    #[test]
+    fn test_elf_errors() {
+        assert!(matches!(Elf::parse(b"not an elf"), Err(ElfError::NotElf)));
+        assert!(matches!(
+            Elf::parse(&make_elf(0x08000000)[..0x40]),
+            Err(ElfError::Truncated)
+        ));
+        assert!(matches!(
+            Elf::parse(&make_elf(0x05000000)),
+            Err(ElfError::BadSegment {
+                address: 0x05000000,
+                size: 0x100
+            })
+        ));
+        let mut big_endian = make_elf(0x08000000);
+        big_endian[5] = 2;
+        assert_eq!(
+            Elf::parse(&big_endian).unwrap_err().to_string(),
+            "unsupported ELF file: not little-endian"
+        );
+    }
+
+    #[test]
+    fn test_nocash_sym_file() {
+        let table = SymbolTable::parse(
+            "08000000 .arm\n080000C0 .thumb\n080000C0 main\n08000200 .byt:0010\n08000200 table\n",
+        );
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.label(0x08000204).unwrap().to_string(), "table+0x4");
+    }
+
+    #[test]
+    fn test_symbols_in_disassembly_and_traces() {
+        let table = SymbolTable::parse("08000100 main\n08000200 helper\n08000300 data\n");
+        assert_eq!(annotate("bl 0x08000200", &table), "bl 0x08000200 <helper>");
+        assert_eq!(
+            annotate("ldr r0, [pc, #4] ; 0x08000308", &table),
+            "ldr r0, [pc, #4] ; 0x08000308 <data+0x8>"
+        );
+        assert_eq!(
+            annotate("bic r0, r0, #0x08000000", &table),
+            "bic r0, r0, #0x08000000"
+        );
+        assert_eq!(annotate("b 0x00000010", &table), "b 0x00000010");
+
+        let record = TraceRecord {
+            category: Category::Branch,
+            pc: 0x08000104,
+            opcode: 0xEB00003D, // BL 0x08000200
+            registers: [0; 16],
+            cpsr: 0x1F,
+            cycles: 7,
+        };
+        let line = record.to_text_with_symbols(&table);
+        assert!(line
+            .starts_with("           7 08000104 EB00003D <main+0x4> bl 0x08000200 <helper> r0="));
+        assert!(!record.to_text().contains('<'));
+    }
+}
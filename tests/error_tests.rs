@@ -0,0 +1,64 @@
+#[cfg(test)]
+mod tests {
+    use emulator::cpu::Cpu;
+    use emulator::error::EmulatorError;
+    use emulator::memory::Memory;
+
+    const HALT: u32 = 0xFFFFFFFF;
+
+    #[test]
+    fn test_invalid_register_index() {
+        let mut cpu = Cpu::new();
+        assert_eq!(
+            cpu.cpu_state.get_register(16),
+            Err(EmulatorError::InvalidRegister { index: 16 })
+        );
+        assert!(cpu.cpu_state.set_register(99, 1).is_err());
+        cpu.cpu_state.set_register(15, 0x100).unwrap();
+        assert_eq!(cpu.cpu_state.get_register(15), Ok(0x100));
+    }
+
+    #[test]
+    fn test_memory_out_of_bounds_is_an_error() {
+        let mut memory = Memory::new(16);
+        assert_eq!(
+            memory.read_word(14),
+            Err(EmulatorError::UnmappedAccess { address: 14, write: false })
+        );
+        assert_eq!(
+            memory.write_bytes(15, &[1, 2]),
+            Err(EmulatorError::UnmappedAccess { address: 15, write: true })
+        );
+    }
+
+    #[test]
+    fn test_load_from_unmapped_address_stops_program() {
+        let mut memory = Memory::new(64);
+        memory.write_word(0, 0xE3A01C01).unwrap(); // MOV r1, #0x100
+        memory.write_word(4, 0xE5912000).unwrap(); // LDR r2, [r1]
+        memory.write_word(8, HALT).unwrap();
+        let mut cpu = Cpu::new();
+        let err = cpu.run_program(&mut memory).unwrap_err();
+        assert!(matches!(err, EmulatorError::UnmappedAccess { write: false, .. }));
+    }
+
+    #[test]
+    fn test_undefined_and_unimplemented_instructions() {
+        let mut memory = Memory::new(64);
+        memory.write_word(0, 0xE7F000F0).unwrap(); // Permanently undefined
+        let mut cpu = Cpu::new();
+        assert_eq!(
+            cpu.run_program(&mut memory),
+            Err(EmulatorError::UndefinedInstruction { address: 0, opcode: 0xE7F000F0 })
+        );
+
+        memory.write_word(0, 0xE5812000).unwrap(); // STR r2, [r1], stores are not in yet
+        let mut cpu = Cpu::new();
+        let err = cpu.run_program(&mut memory).unwrap_err();
+        assert_eq!(
+            err,
+            EmulatorError::UnimplementedInstruction { address: 0, opcode: 0xE5812000, thumb: false }
+        );
+        assert_eq!(err.to_string(), "unimplemented ARM instruction 0xE5812000 (str r2, [r1]) at 0x00000000");
+    }
+}
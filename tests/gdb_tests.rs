@@ -0,0 +1,180 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::process::Command;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::common::{rom_with_code, TempFile, LOOP};
+
+    use emulator::cpu::Cpu;
+    use emulator::gdb::GdbStub;
+    use emulator::memory::Memory;
+
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+        fn send(&mut self, data: &str) {
+            let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+            write!(self.stream, "${}#{:02x}", data, checksum).unwrap();
+            let mut ack = [0u8; 1];
+            self.stream.read_exact(&mut ack).unwrap();
+            assert_eq!(ack[0], b'+');
+        }
+
+        fn receive(&mut self) -> String {
+            let mut byte = [0u8; 1];
+            let mut data = Vec::new();
+            loop {
+                self.stream.read_exact(&mut byte).unwrap();
+                if byte[0] == b'$' {
+                    break;
+                }
+            }
+            loop {
+                self.stream.read_exact(&mut byte).unwrap();
+                if byte[0] == b'#' {
+                    break;
+                }
+                data.push(byte[0]);
+            }
+            let mut checksum = [0u8; 2];
+            self.stream.read_exact(&mut checksum).unwrap();
+            self.stream.write_all(b"+").unwrap();
+            String::from_utf8(data).unwrap()
+        }
+
+        fn request(&mut self, data: &str) -> String {
+            self.send(data);
+            self.receive()
+        }
+    }
+
+    // MOV r1, #1; MOV r2, #0x40; LDR r3, [r2]; B .
+    fn start() -> (Client, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut memory = Memory::new(256);
+            for (i, word) in [0xE3A01001u32, 0xE3A02040, 0xE5923000, 0xEAFFFFFE].iter().enumerate() {
+                memory.write_word(i as u32 * 4, *word).unwrap();
+            }
+            let mut cpu = Cpu::new();
+            let (stream, _) = listener.accept().unwrap();
+            GdbStub::new(stream).run(&mut cpu, &mut memory).unwrap();
+        });
+        let stream = TcpStream::connect(address).unwrap();
+        (Client { stream }, server)
+    }
+
+    #[test]
+    fn test_handshake_and_target_description() {
+        let (mut gdb, server) = start();
+        let features = gdb.request("qSupported:multiprocess+;swbreak+;hwbreak+");
+        assert!(features.contains("qXfer:features:read+"));
+        let xml = gdb.request("qXfer:features:read:target.xml:0,fff");
+        assert!(xml.starts_with("l<?xml"));
+        assert!(xml.contains("org.gnu.gdb.arm.core"));
+        assert!(xml.contains("<reg name=\"cpsr\" bitsize=\"32\" regnum=\"16\""));
+        assert!(xml.contains("name=\"spsr_irq\""));
+        assert_eq!(gdb.request("?"), "S05");
+        assert_eq!(gdb.request("vMustReplyEmpty"), "");
+        assert_eq!(gdb.request("D"), "OK");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_registers_memory_and_breakpoints() {
+        let (mut gdb, server) = start();
+        gdb.request("qSupported:swbreak+");
+        assert_eq!(gdb.request("Z0,8,4"), "OK");
+        assert_eq!(gdb.request("c"), "T05swbreak:;");
+        assert_eq!(gdb.request("pf"), "08000000");
+        let registers = gdb.request("g");
+        assert_eq!(&registers[8..16], "01000000"); // r1
+        assert_eq!(registers.len(), 8 * 45);
+
+        assert_eq!(gdb.request("P0=78563412"), "OK");
+        assert_eq!(gdb.request("p0"), "78563412");
+        // r13_irq is banked, sp is not touched
+        assert_eq!(gdb.request("P20=00800003"), "OK");
+        assert_eq!(gdb.request("p20"), "00800003");
+        assert_eq!(gdb.request("pd"), "00000000");
+
+        assert_eq!(gdb.request("M40,4:aabbccdd"), "OK");
+        assert_eq!(gdb.request("m40,4"), "aabbccdd");
+        assert_eq!(gdb.request("m1000,4"), "E0e");
+
+        assert_eq!(gdb.request("z0,8,4"), "OK");
+        assert_eq!(gdb.request("Z3,40,4"), "OK");
+        assert_eq!(gdb.request("c"), "T05rwatch:40;");
+        assert_eq!(gdb.request("p3"), "aabbccdd");
+        assert_eq!(gdb.request("pf"), "0c000000");
+        assert_eq!(gdb.request("s"), "S05");
+        assert_eq!(gdb.request("pf"), "0c000000"); // B .
+
+        // Running forever until Ctrl-C
+        assert_eq!(gdb.request("z3,40,4"), "OK");
+        gdb.send("c");
+        gdb.stream.write_all(&[0x03]).unwrap();
+        assert_eq!(gdb.receive(), "S02");
+        gdb.send("k");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_bad_port_is_rejected() {
+        for port in ["gdb", "70000"] {
+            let output = Command::new(env!("CARGO_BIN_EXE_emulator"))
+                .args(["--gdb", port, "missing.gba"])
+                .output()
+                .unwrap();
+            assert_eq!(output.status.code(), Some(1));
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(stderr.contains(&format!("Bad port '{}'", port)));
+            assert!(!String::from_utf8_lossy(&output.stdout).contains("Waiting for GDB"));
+        }
+    }
+
+    #[test]
+    fn test_trace_while_under_gdb() {
+        let rom = TempFile::new("gdb_trace.gba");
+        std::fs::write(&rom.0, rom_with_code(&[0xE3A01001, LOOP])).unwrap(); // MOV r1, #1
+        let symbols = TempFile::new("gdb_trace.sym");
+        std::fs::write(&symbols.0, "080000C0 main\n").unwrap();
+        let trace = TempFile::new("gdb_trace.txt");
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut child = Command::new(env!("CARGO_BIN_EXE_emulator"))
+            .arg("--trace")
+            .arg(&trace.0)
+            .arg("--symbols")
+            .arg(&symbols.0)
+            .args(["--gdb", &port.to_string()])
+            .arg(&rom.0)
+            .spawn()
+            .unwrap();
+        let stream = (0..100)
+            .find_map(|_| {
+                TcpStream::connect(("127.0.0.1", port))
+                    .map_err(|_| thread::sleep(Duration::from_millis(50)))
+                    .ok()
+            })
+            .expect("emulator listens for GDB");
+        let mut gdb = Client { stream };
+        // The header's branch, then MOV r1, #1 at main
+        for _ in 0..2 {
+            assert_eq!(gdb.request("s"), "S05");
+        }
+        gdb.send("k");
+        assert!(child.wait().unwrap().success());
+
+        let text = std::fs::read_to_string(&trace.0).unwrap();
+        assert_eq!(text.lines().count(), 2);
+        assert!(text.lines().nth(1).unwrap().contains("080000C0 E3A01001 <main>"));
+    }
+}
@@ -0,0 +1,246 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{booted, counting_rom, rom_with_code, spinning_cartridge, TempFile, LOOP};
+    use emulator::gba::Gba;
+    use emulator::movie::script::{parse_keys, InputScript, Press};
+    use emulator::png;
+    use emulator::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+    use std::process::Command;
+
+    const RED: u16 = 0x001F;
+    const GREEN: u16 = 0x03E0;
+    const BLUE: u16 = 0x7C00;
+
+    fn pixel(gba: &Gba, x: usize, y: usize) -> u16 {
+        gba.bus.ppu.framebuffer[y * SCREEN_WIDTH + x]
+    }
+
+    #[test]
+    fn test_render_bitmap_mode_3() {
+        let mut gba = booted(spinning_cartridge());
+        gba.bus.write_halfword(0x04000000, 0x0403); // Mode 3, BG2 on
+        gba.bus
+            .write_halfword(0x06000000 + (20 * 240 + 10) * 2, RED);
+        gba.bus
+            .write_halfword(0x06000000 + (159 * 240 + 239) * 2, BLUE);
+        gba.run_frame().unwrap();
+        assert_eq!(pixel(&gba, 10, 20), RED);
+        assert_eq!(pixel(&gba, 239, 159), BLUE);
+        assert_eq!(pixel(&gba, 11, 20), 0);
+
+        // Forced blank shows white
+        gba.bus.write_halfword(0x04000000, 0x0483);
+        gba.run_frame().unwrap();
+        assert!(gba.bus.ppu.framebuffer.iter().all(|&color| color == 0x7FFF));
+    }
+
+    #[test]
+    fn test_render_text_background_and_sprite() {
+        let mut gba = booted(spinning_cartridge());
+        gba.bus.write_halfword(0x04000000, 0x1140); // Mode 0, BG0 and sprites, 1D mapping
+        gba.bus.write_halfword(0x04000008, 0x1F00); // BG0: char base 0, screen base 31
+        gba.bus.write_halfword(0x05000000, BLUE); // Backdrop
+        gba.bus.write_halfword(0x05000002, RED);
+        gba.bus.write_halfword(0x05000204, GREEN); // Sprite palette 0, color 2
+
+        // BG tile 1 is all color 1, in the top left corner of the map
+        for offset in (0..32).step_by(2) {
+            gba.bus.write_halfword(0x06000020 + offset, 0x1111);
+            gba.bus.write_halfword(0x06010000 + offset, 0x2222);
+        }
+        gba.bus.write_halfword(0x0600F800, 0x0001);
+
+        // Sprite 0, 8x8 at (4, 0) over the BG, the rest hidden
+        gba.bus.write_halfword(0x07000000, 0x0000);
+        gba.bus.write_halfword(0x07000002, 0x0004);
+        gba.bus.write_halfword(0x07000004, 0x0000);
+        for entry in 1..128 {
+            gba.bus.write_halfword(0x07000000 + entry * 8, 0x0200);
+        }
+        gba.run_frame().unwrap();
+
+        assert_eq!(pixel(&gba, 0, 0), RED);
+        assert_eq!(pixel(&gba, 4, 7), GREEN);
+        assert_eq!(pixel(&gba, 11, 0), GREEN);
+        assert_eq!(pixel(&gba, 12, 0), BLUE);
+        assert_eq!(pixel(&gba, 0, 8), BLUE);
+
+        // BG0 in front once the sprite has a lower priority
+        gba.bus.write_halfword(0x07000004, 0x0400);
+        gba.run_frame().unwrap();
+        assert_eq!(pixel(&gba, 4, 0), RED);
+        assert_eq!(pixel(&gba, 8, 0), GREEN);
+    }
+
+    #[test]
+    fn test_png_header() {
+        let rgb = vec![0x80; 3 * 4 * 2];
+        let image = png::encode_rgb(4, 2, &rgb);
+        assert!(image.starts_with(b"\x89PNG\r\n\x1a\n"));
+        assert_eq!(&image[12..16], b"IHDR");
+        assert_eq!(&image[16..20], &4u32.to_be_bytes());
+        assert_eq!(&image[20..24], &2u32.to_be_bytes());
+        assert_eq!(&image[24..26], &[8, 2]);
+        assert!(image.ends_with(&[b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]));
+    }
+
+    #[test]
+    fn test_input_script() {
+        let script = InputScript::parse(
+            "# comment\n\
+             10 START\n\
+             \n\
+             20-22 a+right # walk\n\
+             21 B\n",
+        )
+        .unwrap();
+        assert_eq!(
+            script.presses[0],
+            Press {
+                first: 10,
+                last: 10,
+                keys: 0x0008
+            }
+        );
+        assert_eq!(script.keys_at(9), 0);
+        assert_eq!(script.keys_at(10), 0x0008);
+        assert_eq!(script.keys_at(21), 0x0013);
+        assert_eq!(script.keys_at(23), 0);
+        assert_eq!(script.length(), 23);
+
+        assert_eq!(parse_keys("L+R"), Some(0x0300));
+        assert_eq!(parse_keys("none"), Some(0));
+        assert_eq!(parse_keys("TURBO"), None);
+
+        let error = InputScript::parse("1 A\n5-3 B").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert_eq!(error.to_string(), "line 2: range 5-3 ends before it starts");
+        assert_eq!(
+            InputScript::parse("x A").unwrap_err().message,
+            "bad frame 'x'"
+        );
+        assert_eq!(
+            InputScript::parse("3").unwrap_err().message,
+            "expected a frame and buttons"
+        );
+    }
+
+    fn headless(rom: &[u8], name: &str, args: &[&str]) -> (Option<i32>, String) {
+        let file = TempFile::new(name);
+        std::fs::write(&file.0, rom).unwrap();
+        let output = Command::new(env!("CARGO_BIN_EXE_headless"))
+            .arg(&file.0)
+            .args(args)
+            .output()
+            .unwrap();
+        (
+            output.status.code(),
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+        )
+    }
+
+    #[test]
+    fn test_headless_frames_and_screenshot() {
+        let png = TempFile::new("headless_shot.png");
+        let png_path = png.0.to_str().unwrap();
+        let (code, stdout) = headless(
+            &rom_with_code(&[LOOP]),
+            "headless_frames.gba",
+            &["--frames", "3", "--png", png_path, "--hash", "--registers"],
+        );
+        assert_eq!(code, Some(0));
+        assert!(stdout.contains("Framebuffer hash: "));
+        assert!(stdout.contains("r15"));
+
+        let image = std::fs::read(&png.0).unwrap();
+        assert_eq!(&image[16..20], &(SCREEN_WIDTH as u32).to_be_bytes());
+        assert_eq!(&image[20..24], &(SCREEN_HEIGHT as u32).to_be_bytes());
+    }
+
+    #[test]
+    fn test_headless_bios_dump() {
+        let bios = TempFile::new("headless_bios.bin");
+        let mut image = vec![0u8; 16 * 1024];
+        image[0..4].copy_from_slice(&0xE3A01042u32.to_le_bytes()); // MOV r1, #0x42
+        image[4..8].copy_from_slice(&0xE3A0F302u32.to_le_bytes()); // MOV pc, #0x08000000
+        image[8..12].copy_from_slice(&0xE1B0F00Eu32.to_le_bytes()); // SWI vector: MOVS pc, lr
+        std::fs::write(&bios.0, image).unwrap();
+        let bios_path = bios.0.to_str().unwrap();
+        // MOV r0, #49; SWI 0x08 (Sqrt); B .
+        let rom = rom_with_code(&[0xE3A00031, 0xEF080000, LOOP]);
+        let args = ["--bios", bios_path, "--frames", "2"];
+
+        // The boot is skipped unless asked for and SWIs still go to the HLE BIOS
+        let skipping = [&args[..], &["--until", "r0 == 7 && r1 != 0x42"]].concat();
+        let (code, _) = headless(&rom, "headless_skip.gba", &skipping);
+        assert_eq!(code, Some(0));
+        let booting = [&args[..], &["--boot-bios", "--until", "r0 == 49 && r1 == 0x42"]].concat();
+        let (code, _) = headless(&rom, "headless_bios.gba", &booting);
+        assert_eq!(code, Some(0));
+        let (code, _) = headless(&rom, "headless_bad_bios.gba", &["--bios", "/nonexistent"]);
+        assert_eq!(code, Some(2));
+    }
+
+    #[test]
+    fn test_headless_link_cable() {
+        let image = rom_with_code(&[LOOP]);
+        let rom = TempFile::new("headless_link.gba");
+        std::fs::write(&rom.0, &image).unwrap();
+        let socket = TempFile::new("headless_link.sock");
+        let address = format!("unix:{}", socket.0.to_str().unwrap());
+        let run = |args: &[&str]| {
+            Command::new(env!("CARGO_BIN_EXE_headless"))
+                .arg(&rom.0)
+                .args(["--frames", "3"])
+                .args(args)
+                .spawn()
+                .unwrap()
+        };
+        let mut host = run(&["--link-host", &address, "1"]);
+        for _ in 0..500 {
+            if socket.0.exists() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let mut client = run(&["--link", &address]);
+        assert_eq!(client.wait().unwrap().code(), Some(0));
+        assert_eq!(host.wait().unwrap().code(), Some(0));
+
+        let (code, _) = headless(&image, "headless_no_link.gba", &["--link", "unix:/nonexistent"]);
+        assert_eq!(code, Some(2));
+        let players = ["--link-host", &address, "many"];
+        let (code, _) = headless(&image, "headless_bad_link.gba", &players);
+        assert_eq!(code, Some(2));
+    }
+
+    #[test]
+    fn test_headless_exit_codes() {
+        let rom = counting_rom();
+        let (code, stdout) = headless(&rom, "headless_until.gba", &["--until", "r0 > 1000"]);
+        assert_eq!(code, Some(0));
+        assert!(stdout.contains("Stopped after 1 frames"));
+
+        let (code, _) = headless(&rom, "headless_pc.gba", &["--until-pc", "0x080000C4"]);
+        assert_eq!(code, Some(0));
+
+        let (code, _) = headless(
+            &rom,
+            "headless_timeout.gba",
+            &["--frames", "2", "--until", "r1 == 1"],
+        );
+        assert_eq!(code, Some(1));
+
+        let (code, _) = headless(&rom, "headless_usage.gba", &["--frames", "many"]);
+        assert_eq!(code, Some(2));
+        let (code, _) = headless(&rom, "headless_condition.gba", &["--until", "r0 =="]);
+        assert_eq!(code, Some(2));
+
+        // Undefined instruction right away
+        let broken = rom_with_code(&[0xE7F000F0]);
+        let (code, _) = headless(&broken, "headless_error.gba", &["--frames", "2"]);
+        assert_eq!(code, Some(3));
+    }
+}
@@ -0,0 +1,141 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{booted_in_ewram, EWRAM, LOOP};
+    use emulator::cpu::{Mode, Step};
+    use emulator::error::EmulatorError;
+    use emulator::gba::Gba;
+    use emulator::interrupt::{Interrupt, PowerState};
+    use emulator::ppu::{FRAME_CYCLES, LINE_CYCLES, VISIBLE_LINES};
+
+    const IWRAM: u32 = 0x03000000;
+    const REG_DISPSTAT: u32 = 0x04000004;
+    const REG_KEYINPUT: u32 = 0x04000130;
+    const REG_KEYCNT: u32 = 0x04000132;
+    const REG_IE: u32 = 0x04000200;
+    const REG_IF: u32 = 0x04000202;
+    const REG_IME: u32 = 0x04000208;
+    const REG_POSTFLG: u32 = 0x04000300;
+    const REG_HALTCNT: u32 = 0x04000301;
+
+    // Booted game code spinning in EWRAM, in System mode with IRQs enabled in the CPSR.
+    fn spinning_gba() -> Gba {
+        booted_in_ewram(&[LOOP])
+    }
+
+    #[test]
+    fn test_if_is_acknowledged_by_writing_ones() {
+        let mut gba = spinning_gba();
+        gba.bus.request_interrupt(Interrupt::VBlank);
+        gba.bus.request_interrupt(Interrupt::Dma3);
+        assert_eq!(gba.bus.read_halfword(REG_IF), 0x0801);
+        gba.bus.write_halfword(REG_IF, 0x0001);
+        assert_eq!(gba.bus.read_halfword(REG_IF), 0x0800);
+
+        // POSTFLG only has one bit, KEYINPUT cannot be written
+        gba.bus.write_byte(REG_POSTFLG, 0xFF);
+        assert_eq!(gba.bus.read_byte(REG_POSTFLG), 1);
+        gba.bus.write_halfword(REG_KEYINPUT, 0);
+        assert_eq!(gba.bus.read_halfword(REG_KEYINPUT), 0x03FF);
+    }
+
+    #[test]
+    fn test_halt_fast_forwards_to_vblank() {
+        let mut gba = spinning_gba();
+        gba.bus.write_halfword(REG_DISPSTAT, 1 << 3); // VBlank IRQ
+        gba.bus.write_halfword(REG_IE, 1);
+        gba.bus.write_byte(REG_HALTCNT, 0);
+        assert_eq!(gba.bus.power_state, PowerState::Halted);
+
+        // Every step jumps a whole event ahead, no instruction runs while halted
+        let mut steps = 0;
+        while gba.bus.power_state == PowerState::Halted {
+            gba.step().unwrap();
+            steps += 1;
+        }
+        assert!(steps <= 2 * VISIBLE_LINES as u32);
+        assert_eq!(gba.bus.scheduler.now(), VISIBLE_LINES as u64 * LINE_CYCLES);
+        assert_eq!(gba.bus.ppu.vcount, VISIBLE_LINES);
+        // IME is off, so the CPU wakes up without taking the interrupt
+        assert_eq!(gba.cpu.cpu_state.get_register(15).unwrap(), EWRAM);
+        assert_eq!(gba.cpu.cpu_state.mode(), Mode::System);
+    }
+
+    #[test]
+    fn test_irq_runs_game_handler_through_hle_bios() {
+        let mut gba = spinning_gba();
+        gba.bus.write_word(IWRAM, 0xE3A05001); // MOV r5, #1
+        gba.bus.write_word(IWRAM + 4, 0xE12FFF1E); // BX LR
+        gba.bus.write_word(0x03007FFC, IWRAM); // Handler pointer, mirrored at 0x03FFFFFC
+        gba.cpu.cpu_state.set_register(0, 0x1234).unwrap();
+        gba.bus.write_halfword(REG_DISPSTAT, 1 << 3);
+        gba.bus.write_halfword(REG_IE, 1);
+        gba.bus.write_halfword(REG_IME, 1);
+
+        while gba.cpu.cpu_state.get_register(5).unwrap() == 0 {
+            gba.step().unwrap();
+        }
+        assert_eq!(gba.cpu.cpu_state.mode(), Mode::Irq);
+        assert_eq!(gba.cpu.cpu_state.get_register(0).unwrap(), 0x04000000);
+        // The handler cannot store to IF yet, acknowledge for it
+        gba.bus.write_halfword(REG_IF, 1);
+        gba.run_cycles(2).unwrap();
+        assert_eq!(gba.cpu.cpu_state.get_register(0).unwrap(), 0x1234);
+        assert_eq!(gba.cpu.cpu_state.get_register(13).unwrap(), 0x03007F00);
+        assert_eq!(gba.cpu.cpu_state.mode(), Mode::System);
+        assert_eq!(gba.cpu.cpu_state.get_register(15).unwrap(), EWRAM);
+    }
+
+    #[test]
+    fn test_stop_ignores_vblank_and_wakes_on_keypad() {
+        let mut gba = spinning_gba();
+        gba.bus.write_halfword(REG_DISPSTAT, 1 << 3);
+        gba.bus.write_halfword(REG_IE, (1 << 0) | (1 << 12));
+        gba.bus.write_halfword(REG_KEYCNT, (1 << 14) | 1); // Any of A
+        gba.bus.write_byte(REG_HALTCNT, 0x80);
+        assert_eq!(gba.bus.power_state, PowerState::Stopped);
+
+        assert_eq!(gba.step().unwrap(), Step::Stopped);
+        gba.run_cycles(FRAME_CYCLES).unwrap();
+        assert_eq!(gba.bus.power_state, PowerState::Stopped);
+        assert_eq!(gba.bus.scheduler.now(), 0);
+
+        gba.bus.set_keys(1);
+        assert_eq!(gba.bus.read_halfword(REG_KEYINPUT), 0x03FE);
+        gba.step().unwrap();
+        assert_eq!(gba.bus.power_state, PowerState::Running);
+        assert_eq!(gba.bus.interrupt_flags(), 1 << 12);
+    }
+
+    #[test]
+    fn test_keycnt_and_condition() {
+        let mut gba = spinning_gba();
+        gba.bus.write_halfword(REG_KEYCNT, (1 << 15) | (1 << 14) | 0b11); // A and B
+        gba.bus.set_keys(0b01);
+        assert_eq!(gba.bus.interrupt_flags(), 0);
+        gba.bus.set_keys(0b11);
+        assert_eq!(gba.bus.interrupt_flags(), 1 << 12);
+    }
+
+    #[test]
+    fn test_dma_end_interrupt() {
+        let mut gba = spinning_gba();
+        gba.bus.write_word(0x040000D4, EWRAM); // DMA3 source
+        gba.bus.write_word(0x040000D8, EWRAM + 0x100);
+        gba.bus.write_halfword(0x040000DC, 1);
+        gba.bus.write_halfword(0x040000DE, 0x8000 | (1 << 14));
+        assert_eq!(gba.bus.interrupt_flags(), 1 << 11);
+    }
+
+    #[test]
+    fn test_thumb_code_stops_instead_of_panicking() {
+        let mut gba = spinning_gba();
+        gba.cpu.cpu_state.CPSR.set_thumb_state(true);
+        assert!(matches!(
+            gba.step(),
+            Err(EmulatorError::UnimplementedInstruction { address: EWRAM, thumb: true, .. })
+        ));
+        assert!(gba.run_cycles(FRAME_CYCLES).is_err());
+    }
+}
@@ -0,0 +1,183 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{booted_in_ewram, LOOP};
+    use emulator::gba::Gba;
+    use emulator::ppu::FRAME_CYCLES;
+    use emulator::sio::link::{Link, LinkStream, Message};
+    use std::io::{ErrorKind, Write};
+    use std::net::TcpListener;
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+
+    const REG_SIODATA32: u32 = 0x04000120;
+    const REG_SIOMULTI: u32 = 0x04000120;
+    const REG_SIOCNT: u32 = 0x04000128;
+    const REG_SIOMLT_SEND: u32 = 0x0400012A;
+    const REG_RCNT: u32 = 0x04000134;
+    const REG_JOY_RECV: u32 = 0x04000150;
+    const REG_JOY_TRANS: u32 = 0x04000154;
+    const REG_JOYSTAT: u32 = 0x04000158;
+    const SERIAL_IRQ: u16 = 1 << 7;
+
+    const NORMAL_32: u16 = 1 << 12;
+    const MULTIPLAYER: u16 = 2 << 12;
+    const INTERNAL_CLOCK: u16 = 1 << 0;
+    const START: u16 = 1 << 7;
+    const IRQ: u16 = 1 << 14;
+
+    fn spinning_gba() -> Gba {
+        let mut gba = booted_in_ewram(&[LOOP]);
+        gba.bus.write_halfword(REG_RCNT, 0); // Serial instead of general purpose
+        gba
+    }
+
+    // Host plus `clients` players connected through socket pairs.
+    fn linked(clients: usize) -> Vec<Gba> {
+        let mut host_ends: Vec<Box<dyn LinkStream>> = Vec::new();
+        let mut client_ends = Vec::new();
+        for _ in 0..clients {
+            let (host_end, client_end) = UnixStream::pair().unwrap();
+            host_ends.push(Box::new(host_end));
+            client_ends.push(client_end);
+        }
+        let mut host = spinning_gba();
+        host.bus.attach_link(Link::host_streams(host_ends).unwrap());
+        let mut gbas = vec![host];
+        for client_end in client_ends {
+            let mut client = spinning_gba();
+            client.bus.attach_link(Link::join_stream(Box::new(client_end)).unwrap());
+            gbas.push(client);
+        }
+        gbas
+    }
+
+    // Runs every instance on its own thread, they wait for each other at the barriers.
+    fn run_all(gbas: Vec<Gba>, cycles: u64) -> Vec<Gba> {
+        let handles: Vec<_> = gbas
+            .into_iter()
+            .map(|mut gba| {
+                thread::spawn(move || {
+                    gba.run_cycles(cycles).unwrap();
+                    gba
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_normal_32_bit_exchange() {
+        let mut gbas = linked(1);
+        gbas[0].bus.write_word(REG_SIODATA32, 0x11223344);
+        gbas[1].bus.write_word(REG_SIODATA32, 0xAABBCCDD);
+        // The slave waits on the external clock, the master starts shifting
+        gbas[1].bus.write_halfword(REG_SIOCNT, NORMAL_32 | START | IRQ);
+        gbas[0].bus.write_halfword(REG_SIOCNT, NORMAL_32 | INTERNAL_CLOCK | START | IRQ);
+
+        let mut gbas = run_all(gbas, FRAME_CYCLES);
+        assert_eq!(gbas[0].bus.read_word(REG_SIODATA32), 0xAABBCCDD);
+        assert_eq!(gbas[1].bus.read_word(REG_SIODATA32), 0x11223344);
+        for gba in &mut gbas {
+            assert_eq!(gba.bus.read_halfword(REG_SIOCNT) & START, 0);
+            assert_eq!(gba.bus.interrupt_flags() & SERIAL_IRQ, SERIAL_IRQ);
+        }
+    }
+
+    #[test]
+    fn test_multiplayer_three_players() {
+        let mut gbas = linked(2);
+        for (player, gba) in gbas.iter_mut().enumerate() {
+            gba.bus.write_halfword(REG_SIOCNT, MULTIPLAYER | IRQ | 3);
+            gba.bus.write_halfword(REG_SIOMLT_SEND, 0x1111 * (player as u16 + 1));
+            // Player number in bits 4-5, children have SI high
+            let siocnt = gba.bus.read_halfword(REG_SIOCNT);
+            assert_eq!((siocnt >> 4) & 3, player as u16);
+            assert_eq!((siocnt >> 2) & 1, (player != 0) as u16);
+        }
+        gbas[0].bus.write_halfword(REG_SIOCNT, MULTIPLAYER | IRQ | 3 | START);
+
+        let mut gbas = run_all(gbas, FRAME_CYCLES);
+        for gba in &mut gbas {
+            let multi: Vec<u16> = (0..4).map(|i| gba.bus.read_halfword(REG_SIOMULTI + i * 2)).collect();
+            assert_eq!(multi, vec![0x1111, 0x2222, 0x3333, 0xFFFF]);
+            assert_eq!(gba.bus.read_halfword(REG_SIOCNT) & START, 0);
+            assert_eq!(gba.bus.interrupt_flags() & SERIAL_IRQ, SERIAL_IRQ);
+        }
+    }
+
+    #[test]
+    fn test_transfer_without_cable() {
+        let mut gba = spinning_gba();
+        gba.bus.write_word(REG_SIODATA32, 0x12345678);
+        gba.bus.write_halfword(REG_SIOCNT, NORMAL_32 | INTERNAL_CLOCK | START | IRQ);
+        gba.run_cycles(32 * 64 + 1).unwrap();
+        assert_eq!(gba.bus.read_word(REG_SIODATA32), 0xFFFFFFFF);
+        assert_eq!(gba.bus.interrupt_flags() & SERIAL_IRQ, SERIAL_IRQ);
+
+        gba.bus.write_halfword(REG_SIOMLT_SEND, 0x4242);
+        gba.bus.write_halfword(REG_SIOCNT, MULTIPLAYER | START);
+        gba.run_cycles(FRAME_CYCLES).unwrap();
+        assert_eq!(gba.bus.read_halfword(REG_SIOMULTI), 0x4242);
+        assert_eq!(gba.bus.read_halfword(REG_SIOMULTI + 2), 0xFFFF);
+    }
+
+    #[test]
+    fn test_dropped_peer_unplugs_the_cable() {
+        let mut gbas = linked(1);
+        drop(gbas.pop());
+        let mut host = gbas.pop().unwrap();
+        assert!(host.bus.sio.link_error.is_none());
+        host.run_cycles(FRAME_CYCLES).unwrap();
+        assert!(host.bus.sio.link.is_none());
+        assert!(host.bus.sio.link_error.is_some());
+    }
+
+    #[test]
+    fn test_joybus_commands() {
+        let mut gba = spinning_gba();
+        gba.bus.write_halfword(REG_RCNT, 0xC000);
+        gba.bus.write_halfword(0x04000140, 1 << 6); // JOYCNT IRQ
+        assert_eq!(gba.bus.joybus_command(0xFF, 0), vec![0x00, 0x04, 0x00]);
+        assert_eq!(gba.bus.interrupt_flags() & SERIAL_IRQ, SERIAL_IRQ);
+
+        gba.bus.joybus_command(0x15, 0xCAFEBABE);
+        assert_eq!(gba.bus.read_halfword(REG_JOYSTAT) & 2, 2);
+        assert_eq!(gba.bus.read_word(REG_JOY_RECV), 0xCAFEBABE);
+        assert_eq!(gba.bus.read_halfword(REG_JOYSTAT) & 2, 0);
+
+        gba.bus.write_word(REG_JOY_TRANS, 0x01020304);
+        assert_eq!(gba.bus.read_halfword(REG_JOYSTAT) & 8, 8);
+        assert_eq!(gba.bus.joybus_command(0x14, 0), vec![4, 3, 2, 1, 8]);
+        assert_eq!(gba.bus.read_halfword(REG_JOYSTAT) & 8, 0);
+    }
+
+    #[test]
+    fn test_bad_player_numbers_are_errors() {
+        let (host_end, mut client_end) = UnixStream::pair().unwrap();
+        let mut host = Link::host_streams(vec![Box::new(host_end)]).unwrap();
+        for player in [0, 2] {
+            let err = host.send_to(player, Message::Uart { byte: 1 }).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+
+        // A sync claiming to come from the host itself
+        let mut frame = [0u8; 16];
+        frame[0] = 1;
+        client_end.write_all(&frame).unwrap();
+        assert_eq!(host.receive().unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_tcp_link_assigns_players() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let host = thread::spawn(move || Link::host_tcp(&listener, 1).unwrap());
+        let client = Link::connect(&address).unwrap();
+        let host = host.join().unwrap();
+        assert!(host.is_host());
+        assert_eq!((client.player(), client.players()), (1, 2));
+        assert_eq!(host.players(), 2);
+    }
+}
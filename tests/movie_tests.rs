@@ -0,0 +1,212 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{make_rom_with_code, rom_with_code, TempFile};
+    use emulator::bios;
+    use emulator::cartridge::save::SaveType;
+    use emulator::cartridge::Cartridge;
+    use emulator::gba::Gba;
+    use emulator::movie::{vbm, Checkpoint, Movie, MovieError, Start};
+    use std::process::Command;
+
+    // Adds KEYINPUT into r0 over and over, so r0 depends on every key press
+    fn key_summing_cartridge() -> Cartridge {
+        let rom = rom_with_code(&[
+            0xE3A02301, // MOV r2, #0x04000000
+            0xE2822E13, // ADD r2, r2, #0x130
+            0xE5921000, // LDR r1, [r2]
+            0xE0800001, // ADD r0, r0, r1
+            0xEAFFFFFC, // B 0x080000C8
+        ]);
+        Cartridge::from_bytes(rom).unwrap()
+    }
+
+    fn record(keys: &[u16]) -> (Movie, Gba) {
+        let cartridge = key_summing_cartridge();
+        let mut movie = Movie::new(&cartridge);
+        movie.rtc_start = 1_000_000_000;
+        let mut gba = movie.boot(cartridge, None).unwrap();
+        for &pressed in keys {
+            movie.record_frame(&mut gba, pressed).unwrap();
+        }
+        movie.add_checkpoint(&gba);
+        (movie, gba)
+    }
+
+    #[test]
+    fn test_record_and_replay_bit_exact() {
+        let keys = [0, 0x0001, 0x0001, 0x0008, 0, 0x0300, 0, 0];
+        let (movie, recorded) = record(&keys);
+        assert_eq!(movie.frames, keys);
+        assert_eq!(movie.checkpoints[0].frame, 8);
+
+        let movie = Movie::parse(&movie.to_bytes()).unwrap();
+        let mut gba = movie.boot(key_summing_cartridge(), None).unwrap();
+        movie.play(&mut gba).unwrap();
+        assert_eq!(
+            gba.cpu.cpu_state.registers,
+            recorded.cpu.cpu_state.registers
+        );
+        assert_eq!(gba.cpu.cycles, recorded.cpu.cycles);
+
+        // Other input, other result
+        let (_, other) = record(&[0, 0x0001, 0x0001, 0x0008, 0, 0x0200, 0, 0]);
+        assert_ne!(
+            other.cpu.cpu_state.registers[0],
+            recorded.cpu.cpu_state.registers[0]
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_desync() {
+        let (mut movie, _) = record(&[0; 4]);
+        movie.checkpoints.push(Checkpoint { frame: 2, hash: 0 });
+        let mut gba = movie.boot(key_summing_cartridge(), None).unwrap();
+        let err = movie.play(&mut gba).unwrap_err();
+        assert!(matches!(
+            err,
+            MovieError::Desync {
+                frame: 2,
+                expected: 0,
+                ..
+            }
+        ));
+        assert!(err.to_string().starts_with("desync at frame 2"));
+    }
+
+    #[test]
+    fn test_start_from_save_state_and_rerecord() {
+        let (mut movie, mut gba) = record(&[0x0001; 3]);
+        let state = gba.save_state().unwrap();
+        let r0 = gba.cpu.cpu_state.registers[0];
+        movie.record_frame(&mut gba, 0x0002).unwrap();
+
+        // Back to frame 3 and take it another way
+        gba.load_state(&state).unwrap();
+        movie.rerecord_from(3);
+        movie.record_frame(&mut gba, 0x0004).unwrap();
+        assert_eq!(movie.frames, [0x0001, 0x0001, 0x0001, 0x0004]);
+        assert_eq!(movie.rerecords, 1);
+
+        let mut from_state = movie.clone();
+        from_state.start = Start::SaveState(state);
+        from_state.frames = vec![0x0004];
+        from_state.checkpoints.clear();
+        let mut replay = from_state.boot(key_summing_cartridge(), None).unwrap();
+        assert_eq!(replay.cpu.cpu_state.registers[0], r0);
+        from_state.play(&mut replay).unwrap();
+        assert_eq!(replay.cpu.cpu_state.registers, gba.cpu.cpu_state.registers);
+    }
+
+    #[test]
+    fn test_movie_rejects_other_rom_and_bad_files() {
+        let (movie, _) = record(&[0; 2]);
+        let other = Cartridge::from_bytes(make_rom_with_code(0x400, b"BOTH")).unwrap();
+        assert!(matches!(
+            movie.boot(other, None),
+            Err(MovieError::RomMismatch { .. })
+        ));
+        assert!(matches!(
+            movie.boot(key_summing_cartridge(), Some(&[0u8; 0x4000])),
+            Err(MovieError::BiosMismatch { expected: None, .. })
+        ));
+
+        let bytes = movie.to_bytes();
+        assert!(matches!(
+            Movie::parse(b"GBASTATE"),
+            Err(MovieError::NotAMovie)
+        ));
+        let mut newer = bytes.clone();
+        newer[8] = 3;
+        assert!(matches!(
+            Movie::parse(&newer),
+            Err(MovieError::UnsupportedVersion { found: 3 })
+        ));
+        assert!(matches!(
+            Movie::parse(&bytes[..bytes.len() - 3]),
+            Err(MovieError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn test_movie_made_with_bios_dump() {
+        let image = vec![0u8; 0x4000];
+        let (mut movie, _) = record(&[0; 2]);
+        movie.bios = Some(bios::checksum(&image));
+        let gba = movie.boot(key_summing_cartridge(), Some(&image)).unwrap();
+        assert!(gba.cpu.hle_bios);
+        assert_eq!(gba.cpu.cpu_state.get_register(15), Ok(0x08000000));
+        movie.skip_bios = false;
+        let mut gba = movie.boot(key_summing_cartridge(), Some(&image)).unwrap();
+        assert_eq!(gba.cpu.cpu_state.get_register(15), Ok(0));
+        assert_eq!(gba.bus.read_byte(0x04000300), 0); // POSTFLG, first boot
+
+        // The CLI plays it with the dump given by --bios
+        movie.skip_bios = true;
+        let (rom, bios_file, movie_file) = (
+            TempFile::new("bios_movie.gba"),
+            TempFile::new("bios_movie.bin"),
+            TempFile::new("bios_movie.gbm"),
+        );
+        std::fs::write(&rom.0, key_summing_cartridge().rom()).unwrap();
+        std::fs::write(&bios_file.0, &image).unwrap();
+        movie.save(movie_file.0.to_str().unwrap()).unwrap();
+        let output = Command::new(env!("CARGO_BIN_EXE_emulator"))
+            .arg("--bios")
+            .arg(&bios_file.0)
+            .arg("--movie")
+            .args([&movie_file.0, &rom.0])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("Played 2 frames"));
+    }
+
+    #[test]
+    fn test_save_data_start() {
+        let mut cartridge = key_summing_cartridge();
+        cartridge.set_save_type(SaveType::Sram);
+        let mut movie = Movie::new(&cartridge);
+        movie.start = Start::SaveData(vec![0x42; 0x8000]);
+        let mut gba = movie.boot(cartridge, None).unwrap();
+        assert_eq!(gba.bus.read_byte(0x0E000123), 0x42);
+    }
+
+    #[test]
+    fn test_vbm_export_and_import() {
+        let (mut movie, _) = record(&[0, 0x0001, 0x0201, 0x03FF]);
+        movie.author = "tester".to_string();
+        movie.rerecords = 7;
+        let cartridge = key_summing_cartridge();
+        let vbm = vbm::export(&movie, &cartridge).unwrap();
+        assert!(vbm.starts_with(vbm::MAGIC));
+        assert_eq!(vbm.len(), 0x100 + 4 * 2);
+
+        let imported = vbm::import(&vbm, &cartridge).unwrap();
+        assert_eq!(imported.frames, movie.frames);
+        assert_eq!(imported.rom_crc32, movie.rom_crc32);
+        assert_eq!(imported.author, "tester");
+        assert_eq!(imported.rerecords, 7);
+        assert_eq!(imported.rtc_start, 1_000_000_000);
+
+        let other = Cartridge::from_bytes(make_rom_with_code(0x400, b"BOTH")).unwrap();
+        assert!(matches!(
+            vbm::import(&vbm, &other),
+            Err(MovieError::GameMismatch { .. })
+        ));
+        // A power cycle in the middle
+        let mut reset = vbm.clone();
+        reset[0x100 + 3] = 0x04;
+        assert!(matches!(
+            vbm::import(&reset, &cartridge),
+            Err(MovieError::Unsupported("resets during the movie"))
+        ));
+        let mut snapshot = vbm.clone();
+        snapshot[0x14] = 1;
+        assert!(matches!(
+            vbm::import(&snapshot, &cartridge),
+            Err(MovieError::Unsupported(_))
+        ));
+    }
+}
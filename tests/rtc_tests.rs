@@ -0,0 +1,166 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{make_cartridge, TempFile};
+    use emulator::bus::Bus;
+    use emulator::cartridge::detect::CartridgeHardware;
+    use emulator::cartridge::rtc::{DateTime, FakeClock};
+    use emulator::cartridge::CartridgeError;
+
+    const GPIO_DATA: u32 = 0x080000C4;
+    const GPIO_DIRECTION: u32 = 0x080000C6;
+    const GPIO_CONTROL: u32 = 0x080000C8;
+
+    // 2024-02-29 13:45:30 UTC, a Thursday
+    const START_TIME: u64 = 1709214330;
+
+    fn bus_with_rtc(clock: &FakeClock) -> Bus {
+        let mut cartridge = make_cartridge();
+        cartridge.set_hardware(CartridgeHardware::RTC);
+        cartridge.set_rtc_clock(Box::new(clock.clone()));
+        let mut bus = Bus::with_cartridge(cartridge);
+        bus.write_halfword(GPIO_CONTROL, 1);
+        bus
+    }
+
+    // Same bit banging sequence games use: commands go out MSB first, data LSB first.
+    fn begin(bus: &mut Bus, command: u8) {
+        bus.write_halfword(GPIO_DIRECTION, 7);
+        bus.write_halfword(GPIO_DATA, 1);
+        bus.write_halfword(GPIO_DATA, 5);
+        for i in (0..8).rev() {
+            let bit = ((command >> i) & 1) as u16;
+            bus.write_halfword(GPIO_DATA, 4 | (bit << 1));
+            bus.write_halfword(GPIO_DATA, 5 | (bit << 1));
+        }
+    }
+
+    fn send_byte(bus: &mut Bus, value: u8) {
+        for i in 0..8 {
+            let bit = ((value >> i) & 1) as u16;
+            bus.write_halfword(GPIO_DATA, 4 | (bit << 1));
+            bus.write_halfword(GPIO_DATA, 5 | (bit << 1));
+        }
+    }
+
+    fn receive_byte(bus: &mut Bus) -> u8 {
+        bus.write_halfword(GPIO_DIRECTION, 5);
+        let mut value = 0;
+        for i in 0..8 {
+            bus.write_halfword(GPIO_DATA, 4);
+            bus.write_halfword(GPIO_DATA, 5);
+            value |= (((bus.read_halfword(GPIO_DATA) >> 1) & 1) as u8) << i;
+        }
+        value
+    }
+
+    fn end(bus: &mut Bus) {
+        bus.write_halfword(GPIO_DIRECTION, 7);
+        bus.write_halfword(GPIO_DATA, 1);
+    }
+
+    fn read_datetime(bus: &mut Bus) -> Vec<u8> {
+        begin(bus, 0x65);
+        let bytes = (0..7).map(|_| receive_byte(bus)).collect();
+        end(bus);
+        bytes
+    }
+
+    #[test]
+    fn test_read_datetime_in_bcd() {
+        let clock = FakeClock::new(START_TIME);
+        let mut bus = bus_with_rtc(&clock);
+        // 24 hour mode is the power-on default here, PM flag set past noon
+        assert_eq!(read_datetime(&mut bus), vec![0x24, 0x02, 0x29, 0x04, 0x93, 0x45, 0x30]);
+
+        clock.advance(61);
+        begin(&mut bus, 0x67);
+        let time: Vec<u8> = (0..3).map(|_| receive_byte(&mut bus)).collect();
+        end(&mut bus);
+        assert_eq!(time, vec![0x93, 0x46, 0x31]);
+    }
+
+    #[test]
+    fn test_control_register_and_12_hour_mode() {
+        let clock = FakeClock::new(START_TIME);
+        let mut bus = bus_with_rtc(&clock);
+        begin(&mut bus, 0x62);
+        send_byte(&mut bus, 0x00); // 12 hour mode
+        end(&mut bus);
+
+        begin(&mut bus, 0x63);
+        assert_eq!(receive_byte(&mut bus), 0x00);
+        end(&mut bus);
+        assert_eq!(read_datetime(&mut bus)[4], 0x81); // 1 PM
+    }
+
+    #[test]
+    fn test_datetime_write_moves_game_clock() {
+        let clock = FakeClock::new(START_TIME);
+        let mut bus = bus_with_rtc(&clock);
+        begin(&mut bus, 0x64);
+        for byte in [0x05, 0x12, 0x24, 0x00, 0x23, 0x59, 0x50] {
+            send_byte(&mut bus, byte);
+        }
+        end(&mut bus);
+
+        clock.advance(15);
+        let now = bus.cartridge.as_mut().unwrap().rtc_mut().unwrap().now();
+        assert_eq!(
+            (now.year, now.month, now.day, now.hour, now.minute, now.second),
+            (2005, 12, 25, 0, 0, 5)
+        );
+        assert_eq!(now.weekday, 0); // Christmas 2005 was a Sunday
+    }
+
+    #[test]
+    fn test_registers_write_only_without_control_bit() {
+        let clock = FakeClock::new(START_TIME);
+        let mut bus = bus_with_rtc(&clock);
+        bus.write_halfword(GPIO_CONTROL, 0);
+        // Reads now see the ROM underneath
+        assert_eq!(bus.read_halfword(GPIO_DIRECTION), 0);
+    }
+
+    #[test]
+    fn test_rtc_state_persisted_next_to_save() {
+        let file = TempFile::new("rtc_game.sav");
+        let rtc_file = TempFile(file.0.with_extension("rtc"));
+        let clock = FakeClock::new(START_TIME);
+        {
+            let mut bus = bus_with_rtc(&clock);
+            bus.cartridge.as_mut().unwrap().attach_save_file(&file.0).unwrap();
+            begin(&mut bus, 0x64);
+            for byte in [0x10, 0x01, 0x01, 0x05, 0x08, 0x00, 0x00] {
+                send_byte(&mut bus, byte);
+            }
+            end(&mut bus);
+        }
+        assert!(rtc_file.0.exists());
+
+        let mut cartridge = make_cartridge();
+        cartridge.set_hardware(CartridgeHardware::RTC);
+        cartridge.set_rtc_clock(Box::new(clock.clone()));
+        cartridge.attach_save_file(&file.0).unwrap();
+        let now = cartridge.rtc_mut().unwrap().now();
+        assert_eq!((now.year, now.month, now.day, now.hour), (2010, 1, 1, 8));
+
+        // A cut short .rtc file is an error, not a clock reset
+        let state = std::fs::read(&rtc_file.0).unwrap();
+        std::fs::write(&rtc_file.0, &state[..4]).unwrap();
+        let mut cartridge = make_cartridge();
+        cartridge.set_hardware(CartridgeHardware::RTC);
+        assert!(matches!(
+            cartridge.attach_save_file(&file.0),
+            Err(CartridgeError::InvalidRtcState)
+        ));
+    }
+
+    #[test]
+    fn test_datetime_conversion_roundtrip() {
+        let datetime = DateTime::from_unix(START_TIME as i64);
+        assert_eq!(datetime.to_unix(), START_TIME as i64);
+        assert_eq!(DateTime::from_unix(0).weekday, 4);
+    }
+}
@@ -0,0 +1,204 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{make_cartridge, make_rom, make_rom_with_code, TempFile};
+    use emulator::bus::Bus;
+    use emulator::cartridge::detect::{detect_save_type, scan_signature};
+    use emulator::cartridge::eeprom::EepromSize;
+    use emulator::cartridge::flash::FlashChip;
+    use emulator::cartridge::save::SaveType;
+    use emulator::cartridge::{Cartridge, AUTOSAVE_DELAY_FRAMES};
+
+    fn bus_with_save(save_type: SaveType) -> Bus {
+        let mut cartridge = make_cartridge();
+        cartridge.set_save_type(save_type);
+        Bus::with_cartridge(cartridge)
+    }
+
+    fn flash_command(bus: &mut Bus, command: u8) {
+        bus.write_byte(0x0E005555, 0xAA);
+        bus.write_byte(0x0E002AAA, 0x55);
+        bus.write_byte(0x0E005555, command);
+    }
+
+    #[test]
+    fn test_sram_read_write_and_mirror() {
+        let mut bus = bus_with_save(SaveType::Sram);
+        bus.write_byte(0x0E000010, 0x42);
+        assert_eq!(bus.read_byte(0x0E000010), 0x42);
+        // 32 KB mirror
+        assert_eq!(bus.read_byte(0x0E008010), 0x42);
+        // 8 bit bus, wider reads repeat the byte
+        assert_eq!(bus.read_halfword(0x0E000010), 0x4242);
+        assert_eq!(bus.read_word(0x0E000010), 0x42424242);
+    }
+
+    #[test]
+    fn test_sram_word_write_uses_address_lane() {
+        let mut bus = bus_with_save(SaveType::Sram);
+        bus.write_word(0x0E000001, 0x44332211);
+        assert_eq!(bus.read_byte(0x0E000001), 0x22);
+    }
+
+    #[test]
+    fn test_flash_id_mode() {
+        let mut bus = bus_with_save(SaveType::Flash(FlashChip::Macronix128));
+        flash_command(&mut bus, 0x90);
+        assert_eq!(bus.read_byte(0x0E000000), 0xC2);
+        assert_eq!(bus.read_byte(0x0E000001), 0x09);
+        flash_command(&mut bus, 0xF0);
+        assert_eq!(bus.read_byte(0x0E000000), 0xFF);
+    }
+
+    #[test]
+    fn test_flash_chip_ids() {
+        let chips = [
+            (FlashChip::Sst, 0xBF, 0xD4),
+            (FlashChip::Macronix64, 0xC2, 0x1C),
+            (FlashChip::Panasonic, 0x32, 0x1B),
+            (FlashChip::Atmel, 0x1F, 0x3D),
+            (FlashChip::Sanyo, 0x62, 0x13),
+        ];
+        for (chip, manufacturer, device) in chips {
+            assert_eq!(chip.manufacturer_id(), manufacturer);
+            assert_eq!(chip.device_id(), device);
+        }
+    }
+
+    #[test]
+    fn test_flash_program_and_sector_erase() {
+        let mut bus = bus_with_save(SaveType::Flash(FlashChip::Panasonic));
+        flash_command(&mut bus, 0xA0);
+        bus.write_byte(0x0E001234, 0x5A);
+        assert_eq!(bus.read_byte(0x0E001234), 0x5A);
+        // A write without the command sequence is ignored
+        bus.write_byte(0x0E001235, 0x00);
+        assert_eq!(bus.read_byte(0x0E001235), 0xFF);
+
+        flash_command(&mut bus, 0x80);
+        bus.write_byte(0x0E005555, 0xAA);
+        bus.write_byte(0x0E002AAA, 0x55);
+        bus.write_byte(0x0E001000, 0x30);
+        assert_eq!(bus.read_byte(0x0E001234), 0xFF);
+    }
+
+    #[test]
+    fn test_flash_chip_erase() {
+        let mut bus = bus_with_save(SaveType::Flash(FlashChip::Sst));
+        flash_command(&mut bus, 0xA0);
+        bus.write_byte(0x0E00F000, 0x00);
+        flash_command(&mut bus, 0x80);
+        flash_command(&mut bus, 0x10);
+        assert_eq!(bus.read_byte(0x0E00F000), 0xFF);
+    }
+
+    #[test]
+    fn test_flash_bank_switch() {
+        let mut bus = bus_with_save(SaveType::Flash(FlashChip::Sanyo));
+        flash_command(&mut bus, 0xB0);
+        bus.write_byte(0x0E000000, 1);
+        flash_command(&mut bus, 0xA0);
+        bus.write_byte(0x0E000020, 0x77);
+        assert_eq!(bus.read_byte(0x0E000020), 0x77);
+
+        flash_command(&mut bus, 0xB0);
+        bus.write_byte(0x0E000000, 0);
+        assert_eq!(bus.read_byte(0x0E000020), 0xFF);
+        let data = bus.cartridge.as_ref().unwrap().save_data();
+        assert_eq!(data.len(), 128 * 1024);
+        assert_eq!(data[0x10020], 0x77);
+    }
+
+    #[test]
+    fn test_atmel_page_program() {
+        let mut bus = bus_with_save(SaveType::Flash(FlashChip::Atmel));
+        flash_command(&mut bus, 0xA0);
+        for i in 0..128 {
+            bus.write_byte(0x0E000080 + i, i as u8);
+        }
+        assert_eq!(bus.read_byte(0x0E000080 + 5), 5);
+        assert_eq!(bus.read_byte(0x0E0000FF), 127);
+    }
+
+    #[test]
+    fn test_save_file_roundtrip() {
+        let file = TempFile::new("roundtrip.sav");
+        {
+            let mut cartridge = make_cartridge();
+            cartridge.set_save_type(SaveType::Sram);
+            cartridge.attach_save_file(&file.0).unwrap();
+            cartridge.write_save(0x100, 0xAB);
+            // Dropping the cartridge flushes the save
+        }
+        let bytes = std::fs::read(&file.0).unwrap();
+        assert_eq!(bytes.len(), 32 * 1024);
+        assert_eq!(bytes[0x100], 0xAB);
+
+        let mut cartridge = make_cartridge();
+        cartridge.set_save_type(SaveType::Sram);
+        cartridge.attach_save_file(&file.0).unwrap();
+        assert_eq!(cartridge.read_save(0x100), 0xAB);
+    }
+
+    #[test]
+    fn test_autosave_waits_for_idle_frames() {
+        let file = TempFile::new("autosave.sav");
+        let mut cartridge = make_cartridge();
+        cartridge.set_save_type(SaveType::Sram);
+        cartridge.attach_save_file(&file.0).unwrap();
+        cartridge.write_save(0, 1);
+        for _ in 0..AUTOSAVE_DELAY_FRAMES - 1 {
+            cartridge.autosave_tick().unwrap();
+        }
+        assert!(!file.0.exists());
+        cartridge.autosave_tick().unwrap();
+        assert!(file.0.exists());
+    }
+
+    fn rom_with_signature(signature: &[u8]) -> Vec<u8> {
+        let mut rom = make_rom(0x1000);
+        rom[0x800..0x800 + signature.len()].copy_from_slice(signature);
+        rom
+    }
+
+    #[test]
+    fn test_detect_from_library_strings() {
+        let cases: [(&[u8], SaveType); 6] = [
+            (b"EEPROM_V124", SaveType::Eeprom(EepromSize::Unknown)),
+            (b"SRAM_V113", SaveType::Sram),
+            (b"SRAM_F_V103", SaveType::Sram),
+            (b"FLASH_V126", SaveType::Flash(FlashChip::Panasonic)),
+            (b"FLASH512_V131", SaveType::Flash(FlashChip::Panasonic)),
+            (b"FLASH1M_V103", SaveType::Flash(FlashChip::Sanyo)),
+        ];
+        for (signature, expected) in cases {
+            let cartridge = Cartridge::from_bytes(rom_with_signature(signature)).unwrap();
+            assert_eq!(cartridge.save_type(), expected);
+        }
+        assert_eq!(scan_signature(&make_rom(0x1000)), None);
+        assert_eq!(make_cartridge().save_type(), SaveType::None);
+    }
+
+    #[test]
+    fn test_override_table_wins_over_signature() {
+        // Pokemon Emerald is detected as 128 KB Flash even if the string says otherwise
+        let mut rom = make_rom_with_code(0x1000, b"BPEE");
+        rom[0x800..0x80A].copy_from_slice(b"FLASH_V126");
+        assert_eq!(
+            detect_save_type(&rom, "BPEE"),
+            SaveType::Flash(FlashChip::Sanyo)
+        );
+        // Iridion II has a stray string but no save chip
+        let mut rom = make_rom_with_code(0x1000, b"AI2E");
+        rom[0x800..0x808].copy_from_slice(b"EEPROM_V");
+        assert_eq!(Cartridge::from_bytes(rom).unwrap().save_type(), SaveType::None);
+    }
+
+    #[test]
+    fn test_forced_save_type() {
+        let mut cartridge = Cartridge::from_bytes(rom_with_signature(b"SRAM_V113")).unwrap();
+        cartridge.set_save_type(SaveType::Flash(FlashChip::Macronix128));
+        assert_eq!(cartridge.save_type(), SaveType::Flash(FlashChip::Macronix128));
+    }
+}
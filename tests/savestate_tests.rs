@@ -0,0 +1,241 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{booted, counting_rom, make_rom_with_code, spinning_cartridge, TempFile};
+    use emulator::cartridge::flash::FlashChip;
+    use emulator::cartridge::save::SaveType;
+    use emulator::cartridge::Cartridge;
+    use emulator::cpu::{Cpsr, Mode};
+    use emulator::gba::Gba;
+    use emulator::savestate::rewind::Rewind;
+    use emulator::savestate::{self, crc32, rle, SaveStateError, FORMAT_VERSION};
+
+    fn counting_cartridge() -> Cartridge {
+        Cartridge::from_bytes(counting_rom()).unwrap()
+    }
+
+    fn flash_write(gba: &mut Gba, address: u32, value: u8) {
+        gba.bus.write_byte(0x0E005555, 0xAA);
+        gba.bus.write_byte(0x0E002AAA, 0x55);
+        gba.bus.write_byte(0x0E005555, 0xA0);
+        gba.bus.write_byte(address, value);
+    }
+
+    #[test]
+    fn test_round_trip_restores_machine() {
+        let mut cartridge = spinning_cartridge();
+        cartridge.set_save_type(SaveType::Flash(FlashChip::Macronix128));
+        let mut gba = booted(cartridge);
+        gba.run_cycles(5000).unwrap();
+        gba.bus.write_word(0x02000100, 0x12345678);
+        gba.bus.write_word(0x03000200, 0xCAFEBABE);
+        gba.bus.write_halfword(0x05000002, 0x7FFF);
+        flash_write(&mut gba, 0x0E000010, 0x5A);
+        let state = &mut gba.cpu.cpu_state;
+        state.set_register(3, 0xDEADBEEF).unwrap();
+        state.set_mode_register(Mode::Irq, 13, 0x03007FA0);
+        state.set_mode_spsr(Mode::Supervisor, Cpsr { value: 0x6000001F });
+
+        let saved = gba.save_state().unwrap();
+        let registers = gba.cpu.cpu_state.registers;
+        let cycles = gba.cpu.cycles;
+        let vcount = gba.bus.ppu.vcount;
+
+        // Run on and scribble over everything, then go back
+        gba.run_cycles(100_000).unwrap();
+        gba.bus.write_word(0x02000100, 0);
+        gba.bus.write_word(0x03000200, 0);
+        gba.bus.write_halfword(0x05000002, 0);
+        flash_write(&mut gba, 0x0E000020, 0x11);
+        gba.cpu.cpu_state.set_mode_register(Mode::Irq, 13, 0);
+        gba.load_state(&saved).unwrap();
+
+        assert_eq!(gba.cpu.cpu_state.registers, registers);
+        assert_eq!(gba.cpu.cycles, cycles);
+        assert_eq!(gba.bus.ppu.vcount, vcount);
+        assert_eq!(gba.bus.read_word(0x02000100), 0x12345678);
+        assert_eq!(gba.bus.read_word(0x03000200), 0xCAFEBABE);
+        assert_eq!(gba.bus.read_halfword(0x05000002), 0x7FFF);
+        assert_eq!(gba.bus.read_byte(0x0E000010), 0x5A);
+        assert_eq!(gba.bus.read_byte(0x0E000020), 0xFF);
+        let state = &gba.cpu.cpu_state;
+        assert_eq!(state.mode_register(Mode::Irq, 13), 0x03007FA0);
+        assert_eq!(state.mode_spsr(Mode::Supervisor).value, 0x6000001F);
+
+        // Saving again gives the same state, nothing was left behind
+        assert_eq!(gba.save_state().unwrap(), saved);
+    }
+
+    #[test]
+    fn test_state_file_and_header() {
+        let mut gba = booted(spinning_cartridge());
+        gba.run_cycles(1000).unwrap();
+        let file = TempFile::new("state.ss1");
+        savestate::save_to_file(&gba, &file.0).unwrap();
+
+        let header = savestate::read_header(&std::fs::read(&file.0).unwrap()).unwrap();
+        assert_eq!(header.format_version, FORMAT_VERSION);
+        assert_eq!(header.emulator_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            header.rom_crc32,
+            crc32(gba.bus.cartridge.as_ref().unwrap().rom())
+        );
+
+        let pc = gba.cpu.cpu_state.registers[15];
+        gba.run_cycles(1000).unwrap();
+        savestate::load_from_file(&mut gba, &file.0).unwrap();
+        assert_eq!(gba.cpu.cpu_state.registers[15], pc);
+    }
+
+    #[test]
+    fn test_rejects_other_rom() {
+        let gba = booted(spinning_cartridge());
+        let saved = gba.save_state().unwrap();
+        let other = Cartridge::from_bytes(make_rom_with_code(0x400, b"BOTH")).unwrap();
+        let mut other = booted(other);
+        other.bus.write_word(0x02000000, 0x11111111);
+        assert!(matches!(
+            other.load_state(&saved),
+            Err(SaveStateError::RomMismatch { .. })
+        ));
+        // Untouched
+        assert_eq!(other.bus.read_word(0x02000000), 0x11111111);
+    }
+
+    #[test]
+    fn test_rejects_other_version_and_garbage() {
+        let mut gba = booted(spinning_cartridge());
+        let saved = gba.save_state().unwrap();
+
+        let mut newer = saved.clone();
+        newer[8..12].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        let err = gba.load_state(&newer).unwrap_err();
+        assert!(
+            matches!(err, SaveStateError::UnsupportedVersion { found, .. } if found == FORMAT_VERSION + 1)
+        );
+        assert!(err.to_string().contains("is not supported"));
+
+        assert!(matches!(
+            gba.load_state(b"not a save state"),
+            Err(SaveStateError::NotASaveState)
+        ));
+        assert!(matches!(
+            gba.load_state(&saved[..saved.len() - 1]),
+            Err(SaveStateError::Corrupt(_))
+        ));
+        let mut flipped = saved.clone();
+        let last = flipped.len() - 1;
+        flipped[last] ^= 0x01;
+        assert!(matches!(
+            gba.load_state(&flipped),
+            Err(SaveStateError::Corrupt(_))
+        ));
+        gba.load_state(&saved).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_body_leaves_machine_untouched() {
+        let mut gba = booted(spinning_cartridge());
+        let saved = gba.save_state().unwrap();
+
+        // Same state with a trailing byte in the body, under a valid length and checksum, so it only
+        // fails once the cpu and bus have been read back
+        let start = 13 + saved[12] as usize + 12;
+        let length = u32::from_le_bytes(saved[start - 8..start - 4].try_into().unwrap()) as usize;
+        let mut body = rle::decompress(&saved[start..], length).unwrap();
+        body.push(0);
+        let mut bad = saved[..start - 8].to_vec();
+        bad.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        bad.extend_from_slice(&crc32(&body).to_le_bytes());
+        bad.extend_from_slice(&rle::compress(&body));
+
+        gba.cpu.cpu_state.registers[0] = 0x1234;
+        gba.bus.write_word(0x02000000, 0xCAFEF00D);
+        let before = gba.save_state().unwrap();
+        assert!(matches!(
+            gba.load_state(&bad),
+            Err(SaveStateError::Corrupt("trailing data"))
+        ));
+        assert_eq!(gba.save_state().unwrap(), before);
+        assert_eq!(gba.cpu.cpu_state.registers[0], 0x1234);
+    }
+
+    #[test]
+    fn test_rle_and_crc32() {
+        let mut data = vec![0u8; 1000];
+        data.extend_from_slice(b"abcabcabc");
+        data.extend(std::iter::repeat_n(7, 131));
+        data.extend((0..=255u8).cycle().take(300));
+        let packed = rle::compress(&data);
+        assert!(packed.len() < data.len());
+        assert_eq!(rle::decompress(&packed, data.len()), Some(data.clone()));
+        assert_eq!(rle::decompress(&packed, data.len() + 1), None);
+        assert_eq!(
+            rle::decompress(&packed[..packed.len() - 1], data.len()),
+            None
+        );
+        assert_eq!(rle::compress(&[]), Vec::<u8>::new());
+        // A length from the file that the data can't reach is refused before allocating it
+        assert_eq!(rle::decompress(&[0x80, 0], usize::MAX), None);
+
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn test_rewind_steps_back_frame_by_frame() {
+        let mut gba = booted(counting_cartridge());
+        let mut rewind = Rewind::new(1, 60);
+        let mut counts = Vec::new();
+        for _ in 0..10 {
+            gba.run_frame().unwrap();
+            gba.bus
+                .write_word(0x02000000, gba.cpu.cpu_state.registers[0]);
+            rewind.frame(&gba);
+            counts.push(gba.cpu.cpu_state.registers[0]);
+        }
+        assert_eq!(rewind.len(), 10);
+        // One whole snapshot plus nine small deltas
+        let mut single = Rewind::new(1, 1);
+        single.capture(&gba);
+        assert!(single.memory_usage() > 0x60000);
+        assert!(rewind.memory_usage() < single.memory_usage() + 9 * 0x100);
+
+        // Half a frame on, the first step goes back to the start of this frame
+        gba.run_cycles(100_000).unwrap();
+        assert!(rewind.step_back(&mut gba).unwrap());
+        assert_eq!(gba.cpu.cpu_state.registers[0], counts[9]);
+        for i in (0..9).rev() {
+            assert!(rewind.step_back(&mut gba).unwrap());
+            assert_eq!(gba.cpu.cpu_state.registers[0], counts[i]);
+            assert_eq!(gba.bus.read_word(0x02000000), counts[i]);
+        }
+        assert!(!rewind.step_back(&mut gba).unwrap());
+        assert_eq!(rewind.len(), 1);
+
+        // Running on from a rewound frame is the same as it was the first time
+        gba.run_frame().unwrap();
+        assert_eq!(gba.cpu.cpu_state.registers[0], counts[1]);
+    }
+
+    #[test]
+    fn test_rewind_interval_and_capacity() {
+        let mut gba = booted(counting_cartridge());
+        let mut rewind = Rewind::new(4, 3);
+        for _ in 0..20 {
+            gba.run_frame().unwrap();
+            rewind.frame(&gba);
+        }
+        // Frames 1, 5, 9, 13 and 17 were captured, the ring keeps the last three
+        assert_eq!(rewind.len(), 3);
+        let mut steps = 0;
+        while rewind.step_back(&mut gba).unwrap() {
+            steps += 1;
+        }
+        assert_eq!(steps, 3);
+        assert_eq!(gba.bus.ppu.frame, 9);
+        rewind.clear();
+        assert!(rewind.is_empty());
+        assert!(!rewind.step_back(&mut gba).unwrap());
+    }
+}
@@ -0,0 +1,108 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{make_cartridge, make_rom_with_code};
+    use emulator::bus::Bus;
+    use emulator::cartridge::detect::CartridgeHardware;
+    use emulator::cartridge::Cartridge;
+
+    const GPIO_DATA: u32 = 0x080000C4;
+    const GPIO_DIRECTION: u32 = 0x080000C6;
+    const GPIO_CONTROL: u32 = 0x080000C8;
+
+    fn bus_with_hardware(hardware: CartridgeHardware) -> Bus {
+        let mut cartridge = make_cartridge();
+        cartridge.set_hardware(hardware);
+        let mut bus = Bus::with_cartridge(cartridge);
+        bus.write_halfword(GPIO_CONTROL, 1);
+        bus
+    }
+
+    // Boktai's routine: reset the counter, then clock until the sensor flag comes up.
+    fn solar_clocks(bus: &mut Bus) -> u32 {
+        bus.write_halfword(GPIO_DIRECTION, 7);
+        bus.write_halfword(GPIO_DATA, 2);
+        bus.write_halfword(GPIO_DATA, 0);
+        let mut clocks = 0;
+        while bus.read_halfword(GPIO_DATA) & 8 == 0 {
+            bus.write_halfword(GPIO_DATA, 1);
+            bus.write_halfword(GPIO_DATA, 0);
+            clocks += 1;
+        }
+        clocks
+    }
+
+    #[test]
+    fn test_solar_sensor_brighter_trips_sooner() {
+        let mut bus = bus_with_hardware(CartridgeHardware::RTC_SOLAR);
+        bus.cartridge.as_mut().unwrap().set_light_level(0x40);
+        let dim = solar_clocks(&mut bus);
+        bus.cartridge.as_mut().unwrap().set_light_level(0xC0);
+        let bright = solar_clocks(&mut bus);
+        assert_eq!(dim, 0xFF - 0x40);
+        assert_eq!(bright, 0xFF - 0xC0);
+    }
+
+    #[test]
+    fn test_tilt_sensor_in_save_region() {
+        let mut bus = bus_with_hardware(CartridgeHardware::TILT);
+        bus.cartridge.as_mut().unwrap().set_tilt(0x20, -0x10);
+        bus.write_byte(0x0E008000, 0x55);
+        bus.write_byte(0x0E008100, 0xAA);
+        let x = bus.read_byte(0x0E008200) as u16 | ((bus.read_byte(0x0E008300) as u16) << 8);
+        let y = bus.read_byte(0x0E008400) as u16 | ((bus.read_byte(0x0E008500) as u16) << 8);
+        // Bit 15 of x flags the finished sample
+        assert_eq!(x, 0x8000 | (0x3A0 - 0x20));
+        assert_eq!(y, 0x3A0 + 0x10);
+    }
+
+    #[test]
+    fn test_tilt_needs_both_latch_writes() {
+        let mut bus = bus_with_hardware(CartridgeHardware::TILT);
+        bus.cartridge.as_mut().unwrap().set_tilt(0x100, 0);
+        bus.write_byte(0x0E008100, 0xAA);
+        assert_eq!(bus.read_byte(0x0E008200), 0xA0); // Still the level reading
+    }
+
+    #[test]
+    fn test_gyro_shifts_out_sample() {
+        let mut bus = bus_with_hardware(CartridgeHardware::GYRO_RUMBLE);
+        bus.cartridge.as_mut().unwrap().set_gyro_rate(-0x40);
+        bus.write_halfword(GPIO_DIRECTION, 0xB);
+        bus.write_halfword(GPIO_DATA, 1);
+        bus.write_halfword(GPIO_DATA, 0);
+        let mut sample = 0u16;
+        for _ in 0..16 {
+            bus.write_halfword(GPIO_DATA, 2);
+            bus.write_halfword(GPIO_DATA, 0);
+            sample = (sample << 1) | ((bus.read_halfword(GPIO_DATA) >> 2) & 1);
+        }
+        assert_eq!(sample, 0x6C0 - 0x40);
+    }
+
+    #[test]
+    fn test_rumble_follows_pin_3() {
+        let mut bus = bus_with_hardware(CartridgeHardware::GYRO_RUMBLE);
+        bus.write_halfword(GPIO_DIRECTION, 0xB);
+        bus.write_halfword(GPIO_DATA, 8);
+        assert!(bus.cartridge.as_ref().unwrap().rumble_active());
+        bus.write_halfword(GPIO_DATA, 0);
+        assert!(!bus.cartridge.as_ref().unwrap().rumble_active());
+    }
+
+    #[test]
+    fn test_hardware_detected_from_game_code() {
+        let cases = [
+            (b"U3IE", CartridgeHardware::RTC_SOLAR),
+            (b"KYGE", CartridgeHardware::TILT),
+            (b"RZWE", CartridgeHardware::GYRO_RUMBLE),
+            (b"V49E", CartridgeHardware::RUMBLE),
+            (b"ATST", CartridgeHardware::NONE),
+        ];
+        for (code, expected) in cases {
+            let cartridge = Cartridge::from_bytes(make_rom_with_code(0x400, code)).unwrap();
+            assert_eq!(cartridge.hardware(), expected);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{booted_in_ewram, LOOP};
+    use emulator::cpu::{Cpu, Step, StepInfo};
+    use emulator::cpu_instructions::instruction_decoding::decode_arm;
+    use emulator::memory::Memory;
+    use emulator::ppu::{FRAME_CYCLES, HDRAW_CYCLES};
+
+
+    fn program(words: &[u32]) -> Memory {
+        let mut memory = Memory::new(256);
+        for (i, word) in words.iter().enumerate() {
+            memory.write_word(i as u32 * 4, *word).unwrap();
+        }
+        memory
+    }
+
+    #[test]
+    fn test_step_reports_executed_instruction() {
+        let mut memory = program(&[0xE3A0507B, LOOP]); // MOV r5, #123
+        let mut cpu = Cpu::new();
+        let step = cpu.step(&mut memory).unwrap();
+        assert_eq!(
+            step,
+            Step::Executed(StepInfo {
+                address: 0,
+                opcode: 0xE3A0507B,
+                instruction: decode_arm(0xE3A0507B),
+                cycles: 1,
+            })
+        );
+        assert_eq!(cpu.cpu_state.get_register(15), Ok(4));
+        assert_eq!(cpu.cpu_state.get_register(5), Ok(123));
+    }
+
+    #[test]
+    fn test_step_advances_past_all_ones_word() {
+        // 0xFFFFFFFF is only a halt marker for run_program, stepping moves on like anywhere else
+        let mut memory = program(&[0xFFFFFFFF, LOOP]);
+        let mut cpu = Cpu::new();
+        assert!(cpu.step(&mut memory).is_ok()); // Condition NV, never runs
+        assert_eq!(cpu.cpu_state.get_register(15), Ok(4));
+    }
+
+    #[test]
+    fn test_run_until_pc_and_for_cycles() {
+        // MOV r1, #1; MOV r2, #2; B .
+        let mut memory = program(&[0xE3A01001, 0xE3A02002, LOOP]);
+        let mut cpu = Cpu::new();
+        assert_eq!(cpu.run_until_pc(&mut memory, 8, 100), Ok(true));
+        assert_eq!(cpu.cpu_state.get_register(2), Ok(2));
+        // Already there, nothing runs
+        assert_eq!(cpu.run_until_pc(&mut memory, 8, 100), Ok(true));
+        assert_eq!(cpu.run_until_pc(&mut memory, 4, 100), Ok(false));
+
+        assert_eq!(cpu.run_for_cycles(&mut memory, 10), Ok(10));
+        assert_eq!(cpu.cpu_state.get_register(15), Ok(8));
+    }
+
+    #[test]
+    fn test_branch_targets_are_relative_to_pc_plus_8() {
+        // B 0x10 at 0, then BL 0x04 at 0x10
+        let mut memory = program(&[0xEA000002, LOOP, 0, 0, 0xEBFFFFFB]);
+        let mut cpu = Cpu::new();
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(cpu.cpu_state.get_register(15), Ok(0x10));
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(cpu.cpu_state.get_register(15), Ok(0x04));
+        assert_eq!(cpu.cpu_state.get_register(14), Ok(0x14));
+        // B . stays put
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(cpu.cpu_state.get_register(15), Ok(0x04));
+    }
+
+    #[test]
+    fn test_run_frame_stops_at_frame_boundary() {
+        let mut gba = booted_in_ewram(&[LOOP]);
+
+        assert_eq!(gba.run_frame(), Ok(FRAME_CYCLES));
+        assert_eq!(gba.bus.ppu.frame, 1);
+        assert_eq!(gba.bus.ppu.vcount, 0);
+        // Halted, the next frame is skipped to event by event
+        gba.bus.write_byte(0x04000301, 0);
+        assert_eq!(gba.step(), Ok(Step::Halted { cycles: HDRAW_CYCLES }));
+        assert_eq!(gba.run_frame(), Ok(FRAME_CYCLES - HDRAW_CYCLES));
+        assert_eq!(gba.bus.ppu.frame, 2);
+    }
+}
@@ -0,0 +1,127 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use emulator::cpu::Cpu;
+    use emulator::memory::Memory;
+    use emulator::trace::diff::{first_divergence, Mismatch};
+    use emulator::trace::format::{self, parse_line};
+    use emulator::trace::{self, BinarySink, Category, MemorySink, TextSink, TraceRecord, Tracer};
+
+    const HALT: u32 = 0xFFFFFFFF;
+
+    // MOV r1, #1; B +0 (to 12); MOV r2, #2; MOV r3, #3; halt
+    fn program() -> Memory {
+        let mut memory = Memory::new(64);
+        for (i, word) in [0xE3A01001, 0xEA000000, 0xE3A02002, 0xE3A03003, HALT].iter().enumerate() {
+            memory.write_word(i as u32 * 4, *word).unwrap();
+        }
+        memory
+    }
+
+    fn run(tracer: impl FnOnce(&mut Tracer)) -> Vec<TraceRecord> {
+        let sink = Arc::new(Mutex::new(MemorySink::default()));
+        let mut cpu = Cpu::new();
+        let mut t = Tracer::new(Box::new(sink.clone()));
+        tracer(&mut t);
+        cpu.tracer = Some(t);
+        cpu.run_program(&mut program()).unwrap();
+        let records = sink.lock().unwrap().records.clone();
+        records
+    }
+
+    #[test]
+    fn test_records_state_before_each_instruction() {
+        let records = run(|_| {});
+        let pcs: Vec<u32> = records.iter().map(|r| r.pc).collect();
+        assert_eq!(pcs, vec![0, 4, 12]);
+        assert_eq!(records[1].category, Category::Branch);
+        assert_eq!(records[1].opcode, 0xEA000000);
+        assert_eq!(records[1].registers[1], 1);
+        assert_eq!(records[1].registers[15], 4);
+        assert_eq!(records[2].registers[2], 0); // Skipped by the branch
+        assert_eq!(records.iter().map(|r| r.cycles).collect::<Vec<_>>(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_category_and_range_filters() {
+        let records = run(|t| t.set_categories(&[Category::Branch]));
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].pc, 4);
+
+        let records = run(|t| {
+            t.add_range(8..=0x20);
+            t.enable(Category::Branch, false);
+        });
+        assert_eq!(records.iter().map(|r| r.pc).collect::<Vec<_>>(), vec![12]);
+    }
+
+    #[test]
+    fn test_binary_round_trip_and_text_format() {
+        let records = run(|_| {});
+        let mut bytes = Vec::new();
+        let mut sink = BinarySink::new(&mut bytes);
+        for record in &records {
+            emulator::trace::TraceSink::record(&mut sink, record).unwrap();
+        }
+        assert_eq!(trace::read_binary(&bytes[..]).unwrap(), records);
+        assert!(trace::read_binary(&b"not a trace file"[..]).is_err());
+
+        let mut text = Vec::new();
+        let mut sink = TextSink::new(&mut text);
+        emulator::trace::TraceSink::record(&mut sink, &records[0]).unwrap();
+        let line = String::from_utf8(text).unwrap();
+        assert!(line.starts_with("           1 00000000 E3A01001 mov r1, #1 r0="));
+        assert!(line.trim_end().ends_with("r15=00000000 cpsr=00000000"));
+
+        // SWIs the HLE BIOS only stubs out say so
+        let sound_driver = TraceRecord {
+            category: Category::Swi,
+            opcode: 0xEF1C0000,
+            ..records[0]
+        };
+        assert!(sound_driver.to_text().contains("svc 0x001C0000 ; HLE BIOS stub r0="));
+    }
+
+    #[test]
+    fn test_mgba_and_nba_lines_parse_back() {
+        let records = run(|_| {});
+        let record = records[1];
+        let mgba = format::mgba_line(&record);
+        assert!(mgba.starts_with("00000000 00000001 00000000"));
+        assert!(mgba.contains("0000000C cpsr: 00000000 | EA000000: "));
+        let nba = format::nba_line(&record);
+        assert!(nba.starts_with("r0:00000000 r1:00000001"));
+        assert!(nba.contains("r15:0000000C cpsr:00000000 [EA000000]"));
+
+        for line in [mgba, nba, record.to_text()] {
+            let step = parse_line(&line, 7).unwrap();
+            assert_eq!(step.line, 7);
+            assert_eq!(step.registers, record.registers); // r15 back to the instruction address
+            assert_eq!(step.opcode, Some(0xEA000000));
+        }
+        assert_eq!(parse_line("Emulation stopped", 1), None);
+    }
+
+    #[test]
+    fn test_diff_reports_first_divergent_register() {
+        let records = run(|_| {});
+        let left: Vec<String> = records.iter().map(format::mgba_line).collect();
+        let mut right: Vec<String> = records.iter().map(format::nba_line).collect();
+        let steps = |lines: &[String]| {
+            format::read_steps(lines.join("\n").as_bytes()).unwrap()
+        };
+        assert_eq!(first_divergence(&steps(&left), &steps(&right)), None);
+
+        right[2] = right[2].replace("r1:00000001", "r1:00000002");
+        let divergence = first_divergence(&steps(&left), &steps(&right)).unwrap();
+        assert_eq!(divergence.step, 2);
+        assert_eq!(divergence.mismatch, Mismatch::Register(1));
+        assert_eq!(divergence.culprit.unwrap().pc(), 4);
+        assert!(divergence.to_string().contains("r1: 00000001 vs 00000002"));
+
+        right.truncate(2);
+        let divergence = first_divergence(&steps(&left), &steps(&right)).unwrap();
+        assert_eq!((divergence.step, divergence.mismatch), (2, Mismatch::Length));
+    }
+}